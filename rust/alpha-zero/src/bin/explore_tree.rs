@@ -0,0 +1,180 @@
+use std::io::{stdout, Stdout, Write};
+
+use clap::Parser;
+use crossterm::cursor::MoveTo;
+use crossterm::event::{read, Event, KeyCode};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType};
+use crossterm::queue;
+use itertools::Itertools;
+
+use board_game::games::chess::ChessBoard;
+use board_game::wdl::{Flip, OutcomeWDL};
+
+use alpha_zero::network::dummy::DummyNetwork;
+use alpha_zero::non_solve_zero::NoPositionHasher;
+use alpha_zero::oracle::DummyOracle;
+use alpha_zero::zero::node::ZeroValues;
+use alpha_zero::zero::step::{zero_step_apply, zero_step_gather, FpuMode};
+use alpha_zero::zero::tree::Tree;
+
+/// Walk a freshly-searched `Tree<ChessBoard>` node-by-node from the terminal, the way
+/// `thin_explore` lets you walk a corrupt btree from a node path on the command line: `Left`/`Right`
+/// ascend/descend an edge, `Up`/`Down` move the highlighted child, `p` follows the principal
+/// variation (repeatedly selecting the max-visit child), and `q` quits.
+#[derive(Debug, clap::Parser)]
+struct Opts {
+    /// Number of root visits to search before opening the explorer.
+    #[clap(long, default_value = "1000")]
+    visits: u64,
+
+    #[clap(long, default_value = "1.0")]
+    exploration_weight: f32,
+
+    /// Node path to jump to on startup, as a sequence of child indices (not move indices) counted
+    /// from the root, e.g. `--path 3,0,1`.
+    #[clap(long, value_delimiter = ',')]
+    path: Vec<usize>,
+}
+
+fn main() -> std::io::Result<()> {
+    let opts: Opts = Opts::parse();
+
+    let board = ChessBoard::default();
+    let tree = build_tree(&board, opts.visits, opts.exploration_weight);
+
+    let mut node = 0;
+    for &child_index in &opts.path {
+        let children = tree[node].children.expect("--path descends past a node without children");
+        node = children.get(child_index);
+    }
+
+    enable_raw_mode()?;
+    let result = run(&tree, node, opts.exploration_weight);
+    disable_raw_mode()?;
+
+    result
+}
+
+/// Build a small search tree to explore, using [`DummyNetwork`]/[`DummyOracle`] so this binary has
+/// no GPU or trained-network dependency; swap in a real [`Network`](alpha_zero::network::Network)
+/// to inspect an actual search.
+fn build_tree(board: &ChessBoard, visits: u64, exploration_weight: f32) -> Tree<ChessBoard> {
+    let mut tree = Tree::new(board.clone());
+    let mut network = DummyNetwork;
+
+    while tree.root_visits() < visits {
+        let request = zero_step_gather(
+            &mut tree,
+            &DummyOracle,
+            &NoPositionHasher,
+            None,
+            exploration_weight,
+            false,
+            FpuMode::Parent,
+        );
+
+        if let Some(request) = request {
+            let eval = network.evaluate(&request.board);
+            zero_step_apply(&mut tree, request.respond(eval));
+        }
+    }
+
+    tree
+}
+
+fn run(tree: &Tree<ChessBoard>, mut node: usize, exploration_weight: f32) -> std::io::Result<()> {
+    let mut out = stdout();
+    let mut selected_child = 0;
+
+    loop {
+        render(&mut out, tree, node, selected_child, exploration_weight)?;
+
+        match read()? {
+            Event::Key(key) => match key.code {
+                KeyCode::Char('q') => break,
+                KeyCode::Right => {
+                    if let Some(children) = tree[node].children {
+                        // `render` lists the children sorted by visits, so pick the highlighted one in that order
+                        let mut rows = children.iter().collect_vec();
+                        rows.sort_by_key(|&c| std::cmp::Reverse(tree[c].complete_visits));
+                        node = rows[selected_child.min(rows.len() - 1)];
+                        selected_child = 0;
+                    }
+                }
+                KeyCode::Left => {
+                    if let Some(&parent) = tree[node].parents.first() {
+                        node = parent;
+                        selected_child = 0;
+                    }
+                }
+                KeyCode::Up => {
+                    selected_child = selected_child.saturating_sub(1);
+                }
+                KeyCode::Down => {
+                    if let Some(children) = tree[node].children {
+                        selected_child = (selected_child + 1).min(children.length as usize - 1);
+                    }
+                }
+                KeyCode::Char('p') => {
+                    node = follow_pv(tree, node);
+                    selected_child = 0;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Repeatedly descend into the most-visited child, the way `best_move` would pick a line to play.
+fn follow_pv(tree: &Tree<ChessBoard>, mut node: usize) -> usize {
+    while let Some(children) = tree[node].children {
+        let best = children.iter().max_by_key(|&c| tree[c].complete_visits);
+        match best {
+            Some(best) => node = best,
+            None => break,
+        }
+    }
+    node
+}
+
+fn render(out: &mut Stdout, tree: &Tree<ChessBoard>, node: usize, selected_child: usize, exploration_weight: f32) -> std::io::Result<()> {
+    queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+
+    let curr = &tree[node];
+    writeln!(
+        out,
+        "node {}  move={}  visits={}+{}  net={:?}\r",
+        node,
+        curr.last_move.map_or("<root>".to_owned(), |mv| format!("{:?}", mv)),
+        curr.complete_visits, curr.virtual_visits,
+        curr.net_values.unwrap_or(ZeroValues::nan()),
+    )?;
+    writeln!(out, "policy={:.3}\r\n", curr.net_policy)?;
+
+    if let Some(children) = curr.children {
+        let parent_total_visits = curr.total_visits();
+        let fpu = if curr.complete_visits > 0 { curr.values().flip() } else { ZeroValues::from_outcome(OutcomeWDL::Draw).flip() };
+
+        let mut rows = children.iter()
+            .map(|c| &tree[c])
+            .collect_vec();
+        rows.sort_by_key(|n| std::cmp::Reverse(n.complete_visits));
+
+        for (index, child) in rows.into_iter().enumerate() {
+            let uct = child.uct(parent_total_visits, fpu, false);
+            let marker = if index == selected_child { ">" } else { " " };
+
+            writeln!(
+                out,
+                "{} {:>4?}  visits={:<6} prior={:.3}  uct(v={:.3} u={:.3})={:.3}\r",
+                marker, child.last_move, child.complete_visits, child.net_policy,
+                uct.v, uct.u, uct.total(exploration_weight),
+            )?;
+        }
+    }
+
+    out.flush()
+}
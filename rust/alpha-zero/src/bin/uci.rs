@@ -0,0 +1,5 @@
+use alpha_zero::uci::run_uci;
+
+fn main() {
+    run_uci();
+}
@@ -1,23 +1,78 @@
 use std::cmp::max;
+use std::collections::VecDeque;
 use std::fmt::Debug;
 
 use board_game::games::chess::ChessBoard;
 use chess::{ALL_FILES, ALL_RANKS, ChessMove, Color, File, Piece, Rank, Square};
+use lazy_static::lazy_static;
+use rand::Rng;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 use crate::mapping::{InputMapper, PolicyMapper};
 use crate::util::IndexOf;
 
 //TODO try different embeddings discussed in Discord
-//TODO AlphaZero also adds history, why?
 #[derive(Debug, Copy, Clone)]
 pub struct ChessStdMapper;
 
-const INPUT_CHANNELS: usize = 2 + (2 * 6) + 1 + (2 * 2) + 4;
+/// The number of past positions stacked into the input, including the current one.
+/// This matches the `T=8` used by AlphaZero, and lets the network see repetitions and captures
+/// instead of just the current static position.
+pub const HISTORY_LENGTH: usize = 8;
+
+/// The planes contributed by a single stacked position: 6 own + 6 opponent piece planes,
+/// plus 2 repetition planes.
+const FRAME_CHANNELS: usize = (2 * 6) + 2;
+
+const INPUT_CHANNELS: usize = (FRAME_CHANNELS * HISTORY_LENGTH) + 2 + 1 + (2 * 2) + 2;
+
+/// A bounded ring buffer of the most recently played [`ChessBoard`]s, used to build the
+/// `HISTORY_LENGTH`-step history planes expected by [`ChessStdMapper`].
+///
+/// The generator is responsible for pushing every played position onto this buffer as the game
+/// progresses; the current board itself is *not* stored here, only the ones before it.
+#[derive(Debug, Clone)]
+pub struct ChessHistory {
+    // oldest first, most recent last, never containing the current board
+    past: VecDeque<ChessBoard>,
+}
+
+impl Default for ChessHistory {
+    fn default() -> Self {
+        ChessHistory { past: VecDeque::with_capacity(HISTORY_LENGTH - 1) }
+    }
+}
+
+impl ChessHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `board` was just played, pushing out the oldest entry once the buffer is full.
+    pub fn push(&mut self, board: ChessBoard) {
+        if self.past.len() == HISTORY_LENGTH - 1 {
+            self.past.pop_front();
+        }
+        self.past.push_back(board);
+    }
+
+    /// The `HISTORY_LENGTH` positions ending at (and including) `current`, most recent first.
+    /// Slots for which there is no recorded history yet are `None`.
+    fn frames<'a>(&'a self, current: &'a ChessBoard) -> [Option<&'a ChessBoard>; HISTORY_LENGTH] {
+        let mut frames = [None; HISTORY_LENGTH];
+        frames[0] = Some(current);
+        for (i, board) in self.past.iter().rev().enumerate() {
+            frames[i + 1] = Some(board);
+        }
+        frames
+    }
+}
 
 impl InputMapper<ChessBoard> for ChessStdMapper {
     const INPUT_SHAPE: [usize; 3] = [INPUT_CHANNELS, 8, 8];
 
-    fn append_board_to(&self, result: &mut Vec<f32>, board: &ChessBoard) {
+    fn append_board_to(&self, result: &mut Vec<f32>, board: &ChessBoard, history: &ChessHistory) {
         let inner = board.inner();
 
         //TODO maybe remove this? is the game indeed fully symmetric after the pov stuff below?
@@ -31,16 +86,13 @@ impl InputMapper<ChessBoard> for ChessStdMapper {
         let pov_colors = [inner.side_to_move(), !inner.side_to_move()];
         let pov_ranks = if inner.side_to_move() == Color::White { &ALL_RANKS } else { &ALL_RANKS_REV };
 
-        //pieces
-        for &color in &pov_colors {
-            for piece in chess::ALL_PIECES {
-                for &rank in pov_ranks {
-                    for file in ALL_FILES {
-                        let square = Square::make_square(rank, file);
-                        let value = inner.color_on(square) == Some(color) && inner.piece_on(square) == Some(piece);
-                        result.push(value as u8 as f32);
-                    }
-                }
+        // stacked history: the current position and up to HISTORY_LENGTH - 1 positions before it,
+        // all viewed from the current side-to-move's POV, most recent first, zero-filled once the
+        // game is shorter than the history length
+        for frame in history.frames(board) {
+            match frame {
+                Some(frame_board) => Self::append_frame_planes(result, frame_board, &pov_colors, pov_ranks),
+                None => result.extend(std::iter::repeat(0.0).take(FRAME_CHANNELS * 8 * 8)),
             }
         }
 
@@ -59,17 +111,43 @@ impl InputMapper<ChessBoard> for ChessStdMapper {
             result.extend(std::iter::repeat((rights.has_queenside()) as u8 as f32).take(8 * 8));
         }
 
-        //TODO this is sketch since cclr data has games with more than 2 repetitions
-        //repetitions (as a binary vector)
-        result.extend(std::iter::repeat((board.repetitions & 1) as f32).take(8 * 8));
-        result.extend(std::iter::repeat((board.repetitions / 2) as f32).take(8 * 8));
-
         //move counters (as simple integers)
         result.extend(std::iter::repeat(board.game_length as f32).take(8 * 8));
         result.extend(std::iter::repeat(board.non_pawn_or_capture_moves as f32).take(8 * 8));
     }
 }
 
+impl ChessStdMapper {
+    /// Append the piece and repetition planes for a single stacked position, from `pov_colors[0]`'s
+    /// point of view (with the rank flip in `pov_ranks` already applied for Black).
+    fn append_frame_planes(
+        result: &mut Vec<f32>,
+        frame_board: &ChessBoard,
+        pov_colors: &[Color; 2],
+        pov_ranks: &[Rank; 8],
+    ) {
+        let inner = frame_board.inner();
+
+        //pieces
+        for &color in pov_colors {
+            for piece in chess::ALL_PIECES {
+                for &rank in pov_ranks {
+                    for file in ALL_FILES {
+                        let square = Square::make_square(rank, file);
+                        let value = inner.color_on(square) == Some(color) && inner.piece_on(square) == Some(piece);
+                        result.push(value as u8 as f32);
+                    }
+                }
+            }
+        }
+
+        //TODO this is sketch since cclr data has games with more than 2 repetitions
+        //repetitions (as a binary vector)
+        result.extend(std::iter::repeat((frame_board.repetitions & 1) as f32).take(8 * 8));
+        result.extend(std::iter::repeat((frame_board.repetitions / 2) as f32).take(8 * 8));
+    }
+}
+
 impl PolicyMapper<ChessBoard> for ChessStdMapper {
     const POLICY_SHAPE: [usize; 3] = [POLICY_CHANNELS, 8, 8];
 
@@ -105,6 +183,178 @@ impl PolicyMapper<ChessBoard> for ChessStdMapper {
     }
 }
 
+impl ChessStdMapper {
+    /// Zobrist hash of `board`, suitable for keying a transposition-aware evaluation cache or for
+    /// merging search-tree nodes reached via different move orders: equal hashes (almost always)
+    /// mean equal positions, which a plain move-path search would miss.
+    ///
+    /// This recomputes the hash from scratch; once a search has a starting hash, prefer updating
+    /// it incrementally with [`Self::board_hash_after_move`] as moves are played.
+    pub fn board_hash(&self, board: &ChessBoard) -> u64 {
+        let inner = board.inner();
+        let mut hash = 0;
+
+        for square in chess::ALL_SQUARES {
+            if let (Some(piece), Some(color)) = (inner.piece_on(square), inner.color_on(square)) {
+                hash ^= ZOBRIST.piece(color, piece, square);
+            }
+        }
+
+        if inner.side_to_move() == Color::Black {
+            hash ^= ZOBRIST.side_to_move;
+        }
+
+        for color in chess::ALL_COLORS {
+            let rights = inner.castle_rights(color);
+            if rights.has_kingside() {
+                hash ^= ZOBRIST.castling(color, true);
+            }
+            if rights.has_queenside() {
+                hash ^= ZOBRIST.castling(color, false);
+            }
+        }
+
+        if let Some(square) = inner.en_passant() {
+            hash ^= ZOBRIST.en_passant_file[square.get_file().to_index()];
+        }
+
+        hash
+    }
+
+    /// Update a hash previously returned by [`Self::board_hash`] (or this function) for `mv` being
+    /// played on `board_before`, in O(1) instead of rehashing the whole resulting board.
+    pub fn board_hash_after_move(&self, hash: u64, board_before: &ChessBoard, mv: ChessMove) -> u64 {
+        let inner = board_before.inner();
+        let mover = inner.side_to_move();
+        let mut hash = hash;
+
+        let from = mv.get_source();
+        let to = mv.get_dest();
+        let moving_piece = inner.piece_on(from).expect("no piece on move source square");
+
+        // remove the moving piece from its origin square
+        hash ^= ZOBRIST.piece(mover, moving_piece, from);
+
+        // a captured piece (including en passant) disappears from the board
+        if let Some(captured_piece) = inner.piece_on(to) {
+            hash ^= ZOBRIST.piece(!mover, captured_piece, to);
+        } else if moving_piece == Piece::Pawn && from.get_file() != to.get_file() && Some(to) == inner.en_passant() {
+            let captured_square = Square::make_square(from.get_rank(), to.get_file());
+            hash ^= ZOBRIST.piece(!mover, Piece::Pawn, captured_square);
+        }
+
+        // place the (possibly promoted) piece on the destination square
+        hash ^= ZOBRIST.piece(mover, mv.get_promotion().unwrap_or(moving_piece), to);
+
+        // castling also moves the rook
+        if moving_piece == Piece::King {
+            let back_rank = from.get_rank();
+            if to == Square::make_square(back_rank, File::G) {
+                hash ^= ZOBRIST.piece(mover, Piece::Rook, Square::make_square(back_rank, File::H));
+                hash ^= ZOBRIST.piece(mover, Piece::Rook, Square::make_square(back_rank, File::F));
+            } else if to == Square::make_square(back_rank, File::C) {
+                hash ^= ZOBRIST.piece(mover, Piece::Rook, Square::make_square(back_rank, File::A));
+                hash ^= ZOBRIST.piece(mover, Piece::Rook, Square::make_square(back_rank, File::D));
+            }
+        }
+
+        // side to move always flips
+        hash ^= ZOBRIST.side_to_move;
+
+        // castling rights are only ever lost, never regained, so only the "before" state matters
+        let rights_before = inner.castle_rights(mover);
+        let king_or_h_rook_moved = moving_piece == Piece::King || (moving_piece == Piece::Rook && from.get_file() == File::H);
+        let king_or_a_rook_moved = moving_piece == Piece::King || (moving_piece == Piece::Rook && from.get_file() == File::A);
+        if rights_before.has_kingside() && king_or_h_rook_moved {
+            hash ^= ZOBRIST.castling(mover, true);
+        }
+        if rights_before.has_queenside() && king_or_a_rook_moved {
+            hash ^= ZOBRIST.castling(mover, false);
+        }
+
+        // the previous en passant file (if any) stops being capturable after this move
+        if let Some(square) = inner.en_passant() {
+            hash ^= ZOBRIST.en_passant_file[square.get_file().to_index()];
+        }
+        // a fresh two-square pawn push opens up a new en passant file
+        let rank_delta = (to.get_rank().to_index() as isize) - (from.get_rank().to_index() as isize);
+        if moving_piece == Piece::Pawn && rank_delta.abs() == 2 {
+            hash ^= ZOBRIST.en_passant_file[to.get_file().to_index()];
+        }
+
+        hash
+    }
+}
+
+lazy_static! {
+    static ref ZOBRIST: ZobristKeys = ZobristKeys::new();
+}
+
+/// The random keys backing [`ChessStdMapper::board_hash`], generated once from a fixed seed so
+/// hashes stay stable across runs.
+struct ZobristKeys {
+    // indexed by [color][piece][square]
+    pieces: [[[u64; 64]; 6]; 2],
+    side_to_move: u64,
+    // white kingside, white queenside, black kingside, black queenside
+    castling: [u64; 4],
+    en_passant_file: [u64; 8],
+}
+
+impl ZobristKeys {
+    fn new() -> Self {
+        let mut rng = StdRng::seed_from_u64(0x5a0b_5157_0000_0001);
+
+        let mut pieces = [[[0u64; 64]; 6]; 2];
+        for color in &mut pieces {
+            for piece in color {
+                for key in piece {
+                    *key = rng.gen();
+                }
+            }
+        }
+
+        let mut en_passant_file = [0u64; 8];
+        for key in &mut en_passant_file {
+            *key = rng.gen();
+        }
+
+        ZobristKeys {
+            pieces,
+            side_to_move: rng.gen(),
+            castling: [rng.gen(), rng.gen(), rng.gen(), rng.gen()],
+            en_passant_file,
+        }
+    }
+
+    fn piece(&self, color: Color, piece: Piece, square: Square) -> u64 {
+        self.pieces[color_index(color)][piece_index(piece)][square.to_index()]
+    }
+
+    fn castling(&self, color: Color, kingside: bool) -> u64 {
+        let side = if kingside { 0 } else { 1 };
+        self.castling[color_index(color) * 2 + side]
+    }
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+fn piece_index(piece: Piece) -> usize {
+    match piece {
+        Piece::Pawn => 0,
+        Piece::Knight => 1,
+        Piece::Bishop => 2,
+        Piece::Rook => 3,
+        Piece::Queen => 4,
+        Piece::King => 5,
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum ClassifiedPovMove {
     Queen { direction: usize, distance_m1: usize },
@@ -0,0 +1,100 @@
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use board_game::board::Board;
+use crossbeam::channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use tokio::sync::oneshot;
+
+use crate::network::{Network, ZeroEvaluation};
+use crate::util::zip_eq_exact;
+
+/// The asynchronous counterpart to [`Network`]: a single-board evaluation any number of concurrent
+/// callers can `.await` independently, the way `test-async`'s `GpuClient`/`gpu_main` prototype
+/// demonstrated coalescing many such requests into one GPU batch over a crossbeam channel plus
+/// per-request `tokio::oneshot` replies. [`BatchingNetwork`] is that idea made real: it wraps an actual
+/// [`Network`] instead of the prototype's `x + 1` stand-in, so concurrently-awaited calls get coalesced
+/// into real batches instead of each caller evaluating one board at a time.
+pub trait AsyncNetwork<B: Board>: Send + Sync {
+    /// Submits `board` for evaluation and waits for its result. Many callers awaiting this
+    /// concurrently get coalesced into as few underlying batches as the implementation can manage.
+    fn evaluate_async(&self, board: &B) -> impl std::future::Future<Output=ZeroEvaluation<'static>> + Send;
+}
+
+struct Job<B> {
+    board: B,
+    reply: oneshot::Sender<ZeroEvaluation<'static>>,
+}
+
+/// Wraps a synchronous [`Network`] with a background worker thread that turns it into an
+/// [`AsyncNetwork`]: every [`evaluate_async`](AsyncNetwork::evaluate_async) call submits its board over
+/// a channel and awaits a [`oneshot`] reply, while the worker thread drains that channel into batches
+/// of up to `max_batch_size` boards (or whatever arrived within `max_wait` of the first one, whichever
+/// limit is hit first), runs one `N::evaluate_batch` pass, and fans the results back out. This is what
+/// lets hundreds of concurrent MCTS search tasks share one GPU evaluator without each one having to
+/// assemble its own batch.
+pub struct BatchingNetwork<B: Board, N> {
+    job_tx: Sender<Job<B>>,
+    ph: PhantomData<N>,
+}
+
+impl<B: Board + Send + 'static, N: Network<B> + Send + 'static> BatchingNetwork<B, N> {
+    pub fn new(network: N, max_batch_size: usize, max_wait: Duration) -> Self {
+        assert!(max_batch_size > 0, "need at least one board per batch");
+
+        // generously sized so producers essentially never block on a full channel while the worker
+        // is mid-batch; the worker itself is what actually bounds how much work is in flight
+        let (job_tx, job_rx) = bounded::<Job<B>>(max_batch_size * 4);
+
+        std::thread::spawn(move || run_worker(network, job_rx, max_batch_size, max_wait));
+
+        BatchingNetwork { job_tx, ph: PhantomData }
+    }
+}
+
+fn run_worker<B: Board, N: Network<B>>(mut network: N, job_rx: Receiver<Job<B>>, max_batch_size: usize, max_wait: Duration) {
+    loop {
+        // block for the first board of the next batch; once every `BatchingNetwork` handle (and thus
+        // every `job_tx` clone) is dropped, `recv` failing is the signal to shut the worker down
+        let first = match job_rx.recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        let mut jobs = vec![first];
+        let deadline = Instant::now() + max_wait;
+
+        while jobs.len() < max_batch_size {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+
+            match job_rx.recv_timeout(remaining) {
+                Ok(job) => jobs.push(job),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        let boards = jobs.iter().map(|job| &job.board).collect::<Vec<_>>();
+        let evals = network.evaluate_batch(&boards);
+
+        for (job, eval) in zip_eq_exact(jobs, evals) {
+            // the awaiting task may have been cancelled and dropped its receiver already; that's fine,
+            // there's simply nobody left to deliver this result to
+            job.reply.send(eval).ok();
+        }
+    }
+}
+
+impl<B: Board + Send + Sync + 'static, N: Network<B> + Send + 'static> AsyncNetwork<B> for BatchingNetwork<B, N> {
+    fn evaluate_async(&self, board: &B) -> impl std::future::Future<Output=ZeroEvaluation<'static>> + Send {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let job = Job { board: board.clone(), reply: reply_tx };
+        let send_result = self.job_tx.send(job);
+
+        async move {
+            send_result.expect("BatchingNetwork worker thread has exited");
+            reply_rx.await.expect("BatchingNetwork worker thread dropped the reply sender without answering")
+        }
+    }
+}
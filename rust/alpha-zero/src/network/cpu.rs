@@ -4,6 +4,7 @@ use std::marker::PhantomData;
 
 use board_game::board::Board;
 
+use gemm::Parallelism;
 use nn_graph::cpu::{cpu_execute_graph, ExecutionInfo, Tensor};
 use nn_graph::graph::Graph;
 use nn_graph::ndarray::IxDyn;
@@ -15,16 +16,26 @@ use crate::network::common::{check_graph_shapes, decode_output};
 pub struct CPUNetwork<B: Board, M: BoardMapper<B>> {
     mapper: M,
     graph: Graph,
+    parallelism: Parallelism,
     ph: PhantomData<B>,
 }
 
 impl<B: Board, M: BoardMapper<B>> CPUNetwork<B, M> {
-    pub fn new(mapper: M, graph: Graph) -> Self {
+    /// `thread_count` picks the `gemm` parallelism used for the batched linear-layer matmuls:
+    /// `1` runs the single-threaded kernel, anything higher spreads each matmul over that many threads.
+    pub fn new(mapper: M, graph: Graph, thread_count: usize) -> Self {
         check_graph_shapes(mapper, &graph);
 
+        let parallelism = if thread_count <= 1 {
+            Parallelism::None
+        } else {
+            Parallelism::Rayon(thread_count)
+        };
+
         CPUNetwork {
             mapper,
             graph,
+            parallelism,
             ph: Default::default(),
         }
     }
@@ -46,7 +57,7 @@ impl<B: Board, M: BoardMapper<B>> CPUNetwork<B, M> {
             .unwrap_or_else(|_| panic!("Incompatible shapes: ({}) -> {:?}", input_len, input_shape));
 
         // evaluate the graph
-        cpu_execute_graph(&self.graph, batch_size, &[&input])
+        cpu_execute_graph(&self.graph, batch_size, &[&input], self.parallelism)
     }
 
     pub fn mapper(&self) -> M {
@@ -1,14 +1,19 @@
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::fmt::{Debug, Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 use std::marker::PhantomData;
 use std::num::NonZeroUsize;
 use std::ops::{Index, IndexMut};
+use std::path::Path;
 
 use decorum::N32;
 use internal_iterator::InternalIterator;
 use itertools::{Itertools, zip_eq};
 use rand::Rng;
 use rand_distr::Distribution;
+use serde::{Deserialize, Serialize};
 
 use board_game::ai::Bot;
 use board_game::board::{Board, Outcome};
@@ -16,21 +21,158 @@ use board_game::symmetry::{Symmetry, SymmetryDistribution};
 use board_game::wdl::{Flip, POV, WDL};
 
 use crate::network::{Network, ZeroEvaluation};
+use crate::opening_book::{BookHeuristic, OpeningBook};
+
+/// A cheap, hand-written evaluation consulted when a node is expanded, so its children and
+/// provisional value are seeded with domain knowledge instead of a uniform prior while the real
+/// network evaluation for that node is still in flight.
+pub trait Heuristic<B: Board>: Debug {
+    /// The policy must have one entry per `board.available_moves()`, in that order.
+    fn evaluate(&self, board: &B) -> ZeroEvaluation;
+}
+
+/// The trivial heuristic: uniform policy and no value information, matching the old behavior of
+/// not providing any prior before the network responds.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ZeroHeuristic;
+
+impl<B: Board> Heuristic<B> for ZeroHeuristic {
+    fn evaluate(&self, board: &B) -> ZeroEvaluation {
+        let move_count = board.available_moves().count();
+        ZeroEvaluation { wdl: WDL::default(), policy: vec![1.0; move_count] }
+    }
+}
+
+/// A hash of a board position, used to key the search tree's optional transposition table (see
+/// [`ZeroSettings::transpositions`]). Two boards that hash equal are treated as the same position
+/// and have their statistics merged, so an unsound implementation (hash collisions between
+/// distinct positions, or a hash that ignores state that affects legal moves or the outcome, e.g.
+/// castling rights or repetition counters) will silently corrupt the tree.
+pub trait PositionHasher<B: Board>: Debug {
+    fn hash(&self, board: &B) -> u64;
+}
+
+/// The hasher used when transpositions are disabled. The search only calls it when
+/// [`ZeroSettings::transpositions`] is set; [`save_tree`], [`load_tree`] and [`OpeningBook`] call
+/// it unconditionally, and it panics when called.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NoPositionHasher;
+
+impl<B: Board> PositionHasher<B> for NoPositionHasher {
+    fn hash(&self, _board: &B) -> u64 {
+        unreachable!("NoPositionHasher is never called, check ZeroSettings::transpositions")
+    }
+}
+
+/// A predicate evaluated after each backup in [`ZeroState::run_until_result_from_root`], used to
+/// end a search early based on the state of the tree instead of only a wall-clock/iteration budget
+/// like `stop_cond`. Compose multiple wards with OR using the `(A, C)` tuple impl below.
+pub trait Ward<B: Board>: Debug {
+    /// `remaining_iterations` is `target_iterations - tree[0].visits` at the time of the call.
+    fn should_stop(&mut self, tree: &Tree<B>, remaining_iterations: u64) -> bool;
+}
+
+/// The default ward: never requests an early stop, matching the old behavior where only
+/// `stop_cond` could end a search.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NoWard;
+
+impl<B: Board> Ward<B> for NoWard {
+    fn should_stop(&mut self, _tree: &Tree<B>, _remaining_iterations: u64) -> bool {
+        false
+    }
+}
+
+impl<B: Board, A: Ward<B>, C: Ward<B>> Ward<B> for (A, C) {
+    fn should_stop(&mut self, tree: &Tree<B>, remaining_iterations: u64) -> bool {
+        self.0.should_stop(tree, remaining_iterations) || self.1.should_stop(tree, remaining_iterations)
+    }
+}
 
+/// Stops once the most-visited root child has enough of a lead over the runner-up that it cannot
+/// be overtaken even if every remaining iteration's visit went to the runner-up, so the search
+/// doesn't keep spending iterations confirming a move that's already decided.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct VisitMarginWard;
+
+impl<B: Board> Ward<B> for VisitMarginWard {
+    fn should_stop(&mut self, tree: &Tree<B>, remaining_iterations: u64) -> bool {
+        let children = match tree[0].children {
+            Some(children) => children,
+            None => return false,
+        };
+
+        let (best, runner_up) = children.iter()
+            .map(|c| tree[c].visits)
+            .fold((0, 0), |(best, runner_up), visits| {
+                if visits > best { (visits, best) } else { (best, runner_up.max(visits)) }
+            });
+
+        best - runner_up > remaining_iterations
+    }
+}
+
+/// Stops once the root's `wdl().value()` crosses a decisive bound in either direction, giving a
+/// principled early resignation/adjudication signal that `best_move` alone can't express.
 #[derive(Debug, Copy, Clone)]
+pub struct ValueThresholdWard {
+    pub threshold: f32,
+}
+
+impl<B: Board> Ward<B> for ValueThresholdWard {
+    fn should_stop(&mut self, tree: &Tree<B>, _remaining_iterations: u64) -> bool {
+        tree.len() > 1 && tree.wdl().value().abs() >= self.threshold
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ZeroSettings {
     pub batch_size: usize,
-    pub exploration_weight: f32,
+    pub uct_weights: UctWeights,
     pub random_symmetries: bool,
+    /// Keep a position-hash -> node index table so a position reached through a different move
+    /// order than the one that first expanded it links to the existing node's subtree instead of
+    /// being searched again from scratch. Requires the tree's [`PositionHasher`] to be sound.
+    pub transpositions: bool,
 }
 
 impl ZeroSettings {
-    pub fn new(batch_size: usize, exploration_weight: f32, random_symmetries: bool) -> Self {
-        ZeroSettings { batch_size, exploration_weight, random_symmetries }
+    pub fn new(batch_size: usize, uct_weights: UctWeights, random_symmetries: bool, transpositions: bool) -> Self {
+        ZeroSettings { batch_size, uct_weights, random_symmetries, transpositions }
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// The weights that turn a node's raw stats into a selection score in [`Node::uct`].
+///
+/// Follows the AlphaZero/MuZero PUCT formula `q + c * p * sqrt(parent_visits) / (1 + visits)`,
+/// with `c` itself scaled by visit count instead of fixed: `c = log((parent_visits + c_base + 1)
+/// / c_base) + c_init`. `fpu_reduction` implements first-play urgency: an unvisited child uses
+/// the parent's value (from the child's POV) minus `fpu_reduction` as its `q`, instead of the 0
+/// a never-visited node's `wdl()` would otherwise give, so unvisited moves aren't overvalued
+/// relative to moves the search has already disproven. `extra_weight` scales an optional
+/// caller-supplied bonus term (e.g. from a heuristic), on top of the usual value and prior terms.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UctWeights {
+    pub c_init: f32,
+    pub c_base: f32,
+    pub fpu_reduction: f32,
+    pub extra_weight: f32,
+}
+
+impl Default for UctWeights {
+    fn default() -> Self {
+        // values taken from the AlphaZero/MuZero papers
+        UctWeights { c_init: 1.25, c_base: 19652.0, fpu_reduction: 0.0, extra_weight: 0.0 }
+    }
+}
+
+impl UctWeights {
+    pub(crate) fn c(&self, parent_visits: u64) -> f32 {
+        ((parent_visits as f32 + self.c_base + 1.0) / self.c_base).ln() + self.c_init
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct IdxRange {
     pub start: NonZeroUsize,
     pub length: u8,
@@ -66,7 +208,10 @@ impl IntoIterator for IdxRange {
 
 #[derive(Debug, Clone)]
 pub struct Node<M> {
-    pub parent: usize,
+    /// The nodes that select into this one. Usually a single entry, except when transpositions
+    /// are enabled (see [`ZeroSettings::transpositions`]) and this node was reached and merged
+    /// through more than one move order; empty for the root.
+    pub parents: Vec<usize>,
     pub last_move: Option<M>,
     pub children: Option<IdxRange>,
 
@@ -86,7 +231,7 @@ pub struct Node<M> {
 impl<N> Node<N> {
     fn new(parent: usize, last_move: Option<N>, p: f32) -> Self {
         Node {
-            parent,
+            parents: vec![parent],
             last_move,
             children: None,
 
@@ -108,10 +253,21 @@ impl<N> Node<N> {
         }
     }
 
-    pub fn uct(&self, exploration_weight: f32, parent_visits: u64) -> f32 {
-        let q = self.wdl().value();
-        let u = self.net_policy * (parent_visits as f32).sqrt() / (1 + self.visits) as f32;
-        q + exploration_weight * u
+    /// The selection score used to pick the best child of a node during the selection phase.
+    ///
+    /// `parent_q` is the parent's value from this child's POV (i.e. the parent's `wdl()` flipped),
+    /// used as this node's first-play-urgency value when it hasn't been visited yet. `extra` is an
+    /// optional caller-supplied bonus, scaled by `weights.extra_weight`.
+    pub fn uct(&self, weights: UctWeights, parent_visits: u64, parent_q: f32, extra: f32) -> f32 {
+        let q = if self.visits == 0 {
+            parent_q - weights.fpu_reduction
+        } else {
+            self.wdl().value()
+        };
+
+        let u = weights.c(parent_visits) * self.net_policy * (parent_visits as f32).sqrt() / (1 + self.visits) as f32;
+
+        q + u + weights.extra_weight * extra
     }
 }
 
@@ -120,6 +276,10 @@ impl<N> Node<N> {
 pub struct Tree<B: Board> {
     root_board: B,
     nodes: Vec<Node<B::Move>>,
+    /// Maps a position hash to the index of the first node expanded for that position, so
+    /// `ZeroState::run_until_result_from_root` can link a transposed position to the existing
+    /// node's subtree instead of re-searching it. `None` when transpositions are disabled.
+    transposition_table: Option<HashMap<u64, usize>>,
 }
 
 impl<B: Board> Index<usize> for Tree<B> {
@@ -143,11 +303,19 @@ pub enum KeepResult<B: Board> {
 }
 
 impl<B: Board> Tree<B> {
-    pub fn new(root_board: B) -> Self {
+    pub fn new(root_board: B, transpositions: bool) -> Self {
         assert!(!root_board.is_done(), "Cannot build tree for done board");
 
-        let root = Node::new(0, None, f32::NAN);
-        Tree { root_board, nodes: vec![root] }
+        let mut root = Node::new(0, None, f32::NAN);
+        // the root has no real incoming edge; the `0` passed above is just a placeholder parent
+        // index, never read since propagation always stops at node 0 before consulting `parents`
+        root.parents.clear();
+
+        Tree {
+            root_board,
+            nodes: vec![root],
+            transposition_table: transpositions.then(HashMap::new),
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -159,20 +327,33 @@ impl<B: Board> Tree<B> {
     }
 
     //TODO flip at the start or not?
-    fn propagate_wdl(&mut self, node: usize, mut wdl: WDL<f32>, count_visit: bool) {
-        let mut curr_index = node;
+    fn propagate_wdl(&mut self, node: usize, wdl: WDL<f32>, count_visit: bool) {
+        let mut visited = HashSet::new();
+        self.propagate_wdl_to(node, wdl, count_visit, &mut visited);
+    }
+
+    /// Backs up `wdl` from `node` to every node reachable by following `parents` edges, flipping
+    /// POV once per step. Transpositions can give a node multiple parents, and can even turn a
+    /// repetition into a cycle through `parents`, so `visited` guards against backing up into the
+    /// same node twice within one call to [`Self::propagate_wdl`].
+    fn propagate_wdl_to(&mut self, node: usize, wdl: WDL<f32>, count_visit: bool, visited: &mut HashSet<usize>) {
+        if !visited.insert(node) {
+            return;
+        }
 
-        loop {
-            wdl = wdl.flip();
+        let wdl = wdl.flip();
 
-            let curr_node = &mut self[curr_index];
-            if count_visit {
-                curr_node.visits += 1;
-            }
-            curr_node.total_wdl += wdl;
+        let curr_node = &mut self[node];
+        if count_visit {
+            curr_node.visits += 1;
+        }
+        curr_node.total_wdl += wdl;
 
-            if curr_index == 0 { break; };
-            curr_index = curr_node.parent;
+        if node == 0 { return; }
+
+        let parents = self[node].parents.clone();
+        for parent in parents {
+            self.propagate_wdl_to(parent, wdl, count_visit, visited);
         }
     }
 
@@ -204,6 +385,12 @@ impl<B: Board> Tree<B> {
 
     /// Return a new tree containing the nodes that are still relevant after playing the given move.
     /// Effectively this copies the part of the tree starting from the selected child.
+    ///
+    /// With transpositions enabled a kept node can have been reachable through more than one
+    /// parent in `self`; since this walk renumbers nodes into a single contiguous `Vec` it cannot
+    /// preserve that sharing in general, so each copy only keeps the one parent edge that reached
+    /// it during this walk. Any further sharing has to be rediscovered through the transposition
+    /// table as the new tree grows.
     pub fn keep_move(&self, mv: B::Move) -> KeepResult<B> {
         assert!(self.len() > 1, "Must have run for at least 1 iteration");
 
@@ -219,6 +406,7 @@ impl<B: Board> Tree<B> {
 
         let old_nodes = &self.nodes;
         let mut new_nodes = vec![old_nodes[picked_child].clone()];
+        new_nodes[0].parents.clear();
 
         let mut i = 0;
 
@@ -227,7 +415,11 @@ impl<B: Board> Tree<B> {
                 None => {}
                 Some(old_children) => {
                     let new_start = new_nodes.len();
-                    new_nodes.extend(old_children.iter().map(|c| old_nodes[c].clone()));
+                    for old_child in old_children.iter() {
+                        let mut child = old_nodes[old_child].clone();
+                        child.parents = vec![i];
+                        new_nodes.push(child);
+                    }
                     let new_end = new_nodes.len();
                     new_nodes[i].children = Some(IdxRange::new(new_start, new_end));
                 }
@@ -236,7 +428,11 @@ impl<B: Board> Tree<B> {
             i += 1;
         }
 
-        let tree = Tree { root_board: new_root_board, nodes: new_nodes };
+        let tree = Tree {
+            root_board: new_root_board,
+            nodes: new_nodes,
+            transposition_table: self.transposition_table.as_ref().map(|_| HashMap::new()),
+        };
         KeepResult::Tree(tree)
     }
 
@@ -245,6 +441,230 @@ impl<B: Board> Tree<B> {
         let parent_visits = self[0].visits;
         TreeDisplay { tree: self, node: 0, curr_depth: 0, max_depth, parent_visits, full }
     }
+
+    /// Build a tree directly from already-searched nodes, used by [`crate::zero_parallel`] to hand
+    /// off a finished parallel search into the regular single-threaded `Tree` API (`best_move`,
+    /// `policy`, `display`, ...). `nodes` must form a valid tree rooted at index 0, as produced by
+    /// repeatedly calling [`Node::new`]; transpositions are not supported here, since the parallel
+    /// search doesn't maintain a transposition table.
+    pub(crate) fn from_parts(root_board: B, nodes: Vec<Node<B::Move>>) -> Self {
+        Tree { root_board, nodes, transposition_table: None }
+    }
+}
+
+/// A WDL value in the plain `(win, draw, loss)` tuple form serde can derive for, since `WDL` itself
+/// comes from `board_game` and isn't ours to add derives to.
+pub(crate) type SerializedWdl = (f32, f32, f32);
+
+fn wdl_to_tuple(wdl: WDL<f32>) -> SerializedWdl {
+    (wdl.win, wdl.draw, wdl.loss)
+}
+
+pub(crate) fn wdl_from_tuple((win, draw, loss): SerializedWdl) -> WDL<f32> {
+    WDL { win, draw, loss }
+}
+
+/// The on-disk mirror of [`Node`], substituting [`SerializedWdl`] for `WDL<f32>` since the latter
+/// has no `Serialize`/`Deserialize` impl.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedNode<M> {
+    parents: Vec<usize>,
+    last_move: Option<M>,
+    children: Option<IdxRange>,
+    net_wdl: Option<SerializedWdl>,
+    net_policy: f32,
+    visits: u64,
+    total_wdl: SerializedWdl,
+}
+
+impl<M: Clone> From<&Node<M>> for SavedNode<M> {
+    fn from(node: &Node<M>) -> Self {
+        SavedNode {
+            parents: node.parents.clone(),
+            last_move: node.last_move.clone(),
+            children: node.children,
+            net_wdl: node.net_wdl.map(wdl_to_tuple),
+            net_policy: node.net_policy,
+            visits: node.visits,
+            total_wdl: wdl_to_tuple(node.total_wdl),
+        }
+    }
+}
+
+impl<M> From<SavedNode<M>> for Node<M> {
+    fn from(saved: SavedNode<M>) -> Self {
+        Node {
+            parents: saved.parents,
+            last_move: saved.last_move,
+            children: saved.children,
+            net_wdl: saved.net_wdl.map(wdl_from_tuple),
+            net_policy: saved.net_policy,
+            visits: saved.visits,
+            total_wdl: wdl_from_tuple(saved.total_wdl),
+        }
+    }
+}
+
+/// The on-disk representation of a [`Tree`], keyed by `root_hash` (the root board hashed with the
+/// [`PositionHasher`] passed to [`save_tree`]/[`load_tree`]) and `settings` so a tree saved for one
+/// position or search configuration can't accidentally be loaded for another.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "B: Serialize, B::Move: Serialize",
+    deserialize = "B: Deserialize<'de>, B::Move: Deserialize<'de>",
+))]
+struct SavedTree<B: Board> {
+    root_hash: u64,
+    settings: ZeroSettings,
+    root_board: B,
+    nodes: Vec<SavedNode<B::Move>>,
+}
+
+/// Why [`load_tree`] refused to load a saved tree.
+#[derive(Debug)]
+pub enum LoadTreeError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    /// The saved tree was built for a different root board (including a different symmetry of it).
+    BoardMismatch,
+    /// The saved tree was built with different [`ZeroSettings`].
+    SettingsMismatch,
+}
+
+/// Persist a finished search so it can later be reloaded with [`load_tree`] instead of being
+/// searched again from scratch, e.g. as a pre-baked opening book entry (see [`OpeningBook`]) or
+/// simply to resume analysis of a position across runs.
+pub fn save_tree<B: Board + Serialize>(
+    path: impl AsRef<Path>,
+    tree: &Tree<B>,
+    settings: ZeroSettings,
+    hasher: &impl PositionHasher<B>,
+) -> std::io::Result<()>
+    where B::Move: Serialize
+{
+    let saved = SavedTree {
+        root_hash: hasher.hash(tree.root_board()),
+        settings,
+        root_board: tree.root_board.clone(),
+        nodes: tree.nodes.iter().map(SavedNode::from).collect(),
+    };
+
+    let file = File::create(path)?;
+    serde_json::to_writer(BufWriter::new(file), &saved).expect("failed to serialize tree");
+    Ok(())
+}
+
+/// Load a tree previously written by [`save_tree`], refusing to return one that wasn't built for
+/// `expected_board` (as hashed by `hasher`) and `expected_settings`.
+pub fn load_tree<B: Board + for<'de> Deserialize<'de>>(
+    path: impl AsRef<Path>,
+    expected_board: &B,
+    expected_settings: ZeroSettings,
+    hasher: &impl PositionHasher<B>,
+) -> Result<Tree<B>, LoadTreeError>
+    where B::Move: for<'de> Deserialize<'de>
+{
+    let file = File::open(path).map_err(LoadTreeError::Io)?;
+    let saved: SavedTree<B> = serde_json::from_reader(BufReader::new(file)).map_err(LoadTreeError::Format)?;
+
+    if saved.settings != expected_settings {
+        return Err(LoadTreeError::SettingsMismatch);
+    }
+    if saved.root_hash != hasher.hash(expected_board) {
+        return Err(LoadTreeError::BoardMismatch);
+    }
+
+    let nodes = saved.nodes.into_iter().map(Node::from).collect();
+    Ok(Tree::from_parts(saved.root_board, nodes))
+}
+
+/// The order in which [`Tree::visit`] decides whether to keep descending into a child, or to call
+/// [`NodeVisitor::visit`] on it once and stop there. In every case a node's own visit always
+/// happens before its children's.
+#[derive(Debug, Copy, Clone)]
+pub enum VisitOrder {
+    /// Recurse into every child, down to the traversal's `max_depth`.
+    FullDepth,
+    /// Recurse past a child only if it is the most-visited child of its parent (ties broken
+    /// towards the last child, matching [`Node::uct`]'s own selection); every other child is still
+    /// visited once but its subtree is skipped. Matches the old `display(_, full: false)`, which
+    /// shows the principal variation in full while collapsing side lines to one line each.
+    BestFirst,
+    /// Recurse past a child only if its visit count is at least `min_visits`; prunes subtrees the
+    /// search barely touched, regardless of depth.
+    VisitThreshold { min_visits: u64 },
+}
+
+/// The depth/visit budget passed to [`Tree::visit`], so a large tree doesn't fully unfold into the
+/// visitor's output.
+#[derive(Debug, Copy, Clone)]
+pub struct TraversalSettings {
+    pub max_depth: usize,
+    pub order: VisitOrder,
+}
+
+/// Callback invoked once per node reached by [`Tree::visit`], in the order chosen by its
+/// [`TraversalSettings`]. `path` is the sequence of node indices from the node the walk started at
+/// (the root for [`Tree::visit`], inclusive) down to this node, and `board` is the position obtained by replaying `last_move` down that path from
+/// `root_board`, the same way the selection loop in [`ZeroState::run_until_result_from_root`]
+/// derives `curr_board` as it walks down the tree.
+pub trait NodeVisitor<B: Board> {
+    fn visit(&mut self, path: &[usize], node: &Node<B::Move>, board: &B);
+}
+
+impl<B: Board> Tree<B> {
+    /// Walk the whole tree starting at the root, calling `visitor` once per node reached.
+    pub fn visit(&self, settings: TraversalSettings, visitor: &mut impl NodeVisitor<B>) {
+        self.visit_from(0, self.root_board.clone(), settings, visitor)
+    }
+
+    /// Walk the tree starting at `node` (whose position is `board`), calling `visitor` once per
+    /// node reached. Useful to export or print a subtree without first calling [`Self::keep_move`].
+    pub fn visit_from(&self, node: usize, board: B, settings: TraversalSettings, visitor: &mut impl NodeVisitor<B>) {
+        let mut path = vec![node];
+        self.visit_recurse(board, settings, 0, &mut path, visitor);
+    }
+
+    fn visit_recurse(
+        &self,
+        board: B,
+        settings: TraversalSettings,
+        depth: usize,
+        path: &mut Vec<usize>,
+        visitor: &mut impl NodeVisitor<B>,
+    ) {
+        let node = *path.last().unwrap();
+        visitor.visit(path, &self[node], &board);
+
+        if depth == settings.max_depth { return; }
+
+        let children = match self[node].children {
+            Some(children) => children,
+            None => return,
+        };
+
+        let best_child = children.iter().max_by_key(|&c| self[c].visits);
+
+        for child in children {
+            let recurse = match settings.order {
+                VisitOrder::FullDepth => true,
+                VisitOrder::BestFirst => Some(child) == best_child,
+                VisitOrder::VisitThreshold { min_visits } => self[child].visits >= min_visits,
+            };
+
+            let mut child_board = board.clone();
+            child_board.play(self[child].last_move.unwrap());
+            path.push(child);
+
+            if recurse {
+                self.visit_recurse(child_board, settings, depth + 1, path, visitor);
+            } else {
+                visitor.visit(path, &self[child], &child_board);
+            }
+
+            path.pop();
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -259,64 +679,135 @@ pub struct TreeDisplay<'a, B: Board> {
 
 impl<B: Board> Display for TreeDisplay<'_, B> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        if self.curr_depth == 0 {
-            writeln!(f, "move: visits zero(w/d/l, policy) net(w/d/l, policy)")?;
+        let order = if self.full { VisitOrder::FullDepth } else { VisitOrder::BestFirst };
+        let settings = TraversalSettings { max_depth: self.max_depth, order };
+
+        let mut visitor = DisplayVisitor { tree: self.tree, root_depth: self.curr_depth, root_parent_visits: self.parent_visits, output: String::new() };
+        self.tree.visit_from(self.node, self.tree.root_board.clone(), settings, &mut visitor);
+        write!(f, "{}", visitor.output)
+    }
+}
+
+/// Reimplements the old hand-written [`TreeDisplay::fmt`] walk as a [`NodeVisitor`].
+struct DisplayVisitor<'a, B: Board> {
+    tree: &'a Tree<B>,
+    /// The depth `Tree::visit_from` was started at, so indentation and the header line match the
+    /// subtree being printed rather than always assuming node 0 is the root.
+    root_depth: usize,
+    root_parent_visits: u64,
+    output: String,
+}
+
+impl<B: Board> NodeVisitor<B> for DisplayVisitor<'_, B> {
+    fn visit(&mut self, path: &[usize], node: &Node<B::Move>, _board: &B) {
+        let depth = self.root_depth + (path.len() - 1);
+
+        if depth == 0 {
+            self.output.push_str("move: visits zero(w/d/l, policy) net(w/d/l, policy)\n");
         }
 
-        let node = &self.tree[self.node];
+        for _ in 0..depth { self.output.push_str("  ") }
 
-        for _ in 0..self.curr_depth { write!(f, "  ")? }
+        let parent_visits = if path.len() == 1 {
+            self.root_parent_visits
+        } else {
+            self.tree[path[path.len() - 2]].visits
+        };
 
         let node_wdl = node.wdl();
         let net_wdl = node.net_wdl.unwrap_or(WDL::nan()).flip();
 
-        writeln!(
-            f,
-            "{:?}: {} zero({:.3}/{:.3}/{:.3}, {:.3}) net({:.3}/{:.3}/{:.3}, {:.3})",
+        self.output.push_str(&format!(
+            "{:?}: {} zero({:.3}/{:.3}/{:.3}, {:.3}) net({:.3}/{:.3}/{:.3}, {:.3})\n",
             node.last_move, node.visits,
             node_wdl.win, node_wdl.draw, node_wdl.loss,
-            (node.visits as f32) / (self.parent_visits as f32),
+            (node.visits as f32) / (parent_visits as f32),
             net_wdl.win, net_wdl.draw, net_wdl.loss,
             node.net_policy,
-        )?;
+        ));
+    }
+}
 
-        if self.curr_depth == self.max_depth { return Ok(()); }
+/// Exports a tree (or subtree) to Graphviz DOT source, one node per `digraph` vertex and one edge
+/// per parent/child link walked by [`Tree::visit`].
+#[derive(Debug, Default)]
+pub struct DotVisitor {
+    body: String,
+}
 
-        if let Some(children) = node.children {
-            let best_child_index = children.iter()
-                .position_max_by_key(|&c| self.tree[c].visits)
-                .unwrap();
-            let best_child = children.get(best_child_index);
+impl DotVisitor {
+    pub fn new() -> Self {
+        DotVisitor { body: String::new() }
+    }
 
-            for child in children {
-                let next_max_depth = if self.full || child == best_child {
-                    self.max_depth
-                } else {
-                    self.curr_depth + 1
-                };
+    /// Wrap the visited nodes and edges into a complete `digraph tree { ... }` source string.
+    pub fn finish(self) -> String {
+        format!("digraph tree {{\n{}}}\n", self.body)
+    }
+}
 
-                let child_display = TreeDisplay {
-                    tree: self.tree,
-                    node: child,
-                    curr_depth: self.curr_depth + 1,
-                    max_depth: next_max_depth,
-                    parent_visits: node.visits,
-                    full: self.full,
-                };
-                write!(f, "{}", child_display)?;
-            }
+impl<B: Board> NodeVisitor<B> for DotVisitor {
+    fn visit(&mut self, path: &[usize], node: &Node<B::Move>, _board: &B) {
+        let id = *path.last().unwrap();
+
+        self.body.push_str(&format!(
+            "  n{} [label=\"{:?}\\nvisits={} q={:.3}\"];\n",
+            id, node.last_move, node.visits, node.wdl().value(),
+        ));
+
+        if path.len() >= 2 {
+            let parent = path[path.len() - 2];
+            self.body.push_str(&format!("  n{} -> n{};\n", parent, id));
         }
+    }
+}
+
+/// Exports a flat CSV of `(move, visits, q, net_policy)` rows, one per node visited, for offline
+/// analysis in a spreadsheet or notebook. The root node has no `last_move` and is skipped.
+#[derive(Debug)]
+pub struct CsvVisitor {
+    body: String,
+}
 
-        Ok(())
+impl Default for CsvVisitor {
+    fn default() -> Self {
+        CsvVisitor { body: "move,visits,q,net_policy\n".to_string() }
+    }
+}
+
+impl CsvVisitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish(self) -> String {
+        self.body
+    }
+}
+
+impl<B: Board> NodeVisitor<B> for CsvVisitor {
+    fn visit(&mut self, _path: &[usize], node: &Node<B::Move>, _board: &B) {
+        let mv = match &node.last_move {
+            Some(mv) => mv,
+            None => return,
+        };
+
+        self.body.push_str(&format!(
+            "{:?},{},{:.6},{:.6}\n",
+            mv, node.visits, node.wdl().value(), node.net_policy,
+        ));
     }
 }
 
 /// A coroutine-style implementation that yields `Request`s instead of immediately calling a network.
 #[derive(Debug, Clone)]
-pub struct ZeroState<B: Board> {
+pub struct ZeroState<B: Board, H: Heuristic<B> = ZeroHeuristic, P: PositionHasher<B> = NoPositionHasher, W: Ward<B> = NoWard> {
     pub tree: Tree<B>,
     pub target_iterations: u64,
     settings: ZeroSettings,
+    heuristic: H,
+    hasher: P,
+    ward: W,
     expected_nodes: Vec<usize>,
 }
 
@@ -357,12 +848,15 @@ pub struct SubResponse<B: Board> {
 
 //TODO why? to discourage future exploration? but it's fine if it happens anyway!
 //  try "draw" and "nothing" too (although "nothing" makes the visit counts inconsistent)
-const VIRTUAL_WDL: WDL<f32> = WDL { win: 0.0, draw: 0.0, loss: 1.0 };
-
-impl<B: Board> ZeroState<B> {
-    /// Create a new state that will expand the given tree until its root node has been visited `iterations` times.
-    pub fn new(tree: Tree<B>, target_iterations: u64, settings: ZeroSettings) -> ZeroState<B> {
-        Self { tree, target_iterations, settings, expected_nodes: vec![] }
+pub(crate) const VIRTUAL_WDL: WDL<f32> = WDL { win: 0.0, draw: 0.0, loss: 1.0 };
+
+impl<B: Board, H: Heuristic<B>, P: PositionHasher<B>, W: Ward<B>> ZeroState<B, H, P, W> {
+    /// Create a new state that will expand the given tree until its root node has been visited
+    /// `iterations` times, consulting `heuristic` whenever a node is expanded, `hasher` to detect
+    /// and merge transposed positions (if `settings.transpositions` is set), and `ward` to end the
+    /// search early based on tree state after each backup.
+    pub fn new(tree: Tree<B>, target_iterations: u64, settings: ZeroSettings, heuristic: H, hasher: P, ward: W) -> Self {
+        Self { tree, target_iterations, settings, heuristic, hasher, ward, expected_nodes: vec![] }
     }
 
     /// Run until finished or a network evaluation is needed.
@@ -393,6 +887,41 @@ impl<B: Board> ZeroState<B> {
         }
     }
 
+    /// If `settings.transpositions` is set and `curr_board` hashes to an already-resolved node,
+    /// adopt that node's children and evaluation for `curr_node` instead of expanding it again, so
+    /// the two occurrences share the rest of the search from here on. Returns `None` (and, the
+    /// first time a hash is seen, registers `curr_node` as its canonical node) when `curr_node`
+    /// should be expanded normally instead: either this is a new position, or the existing node
+    /// for it hasn't been resolved yet itself (still awaiting its own network response), in which
+    /// case aliasing to it now would just mean duplicating that pending request.
+    fn try_link_transposition(&mut self, curr_node: usize, curr_board: &B) -> Option<IdxRange> {
+        if self.tree.transposition_table.is_none() {
+            return None;
+        }
+
+        let hash = self.hasher.hash(curr_board);
+        let existing = self.tree.transposition_table.as_ref().unwrap().get(&hash).copied();
+
+        match existing {
+            Some(existing) if self.tree[existing].net_wdl.is_some() && !self.expected_nodes.contains(&existing) => {
+                let children = self.tree[existing].children
+                    .expect("a node with a net_wdl has already been expanded");
+
+                for child in children {
+                    self.tree[child].parents.push(curr_node);
+                }
+                self.tree[curr_node].children = Some(children);
+                self.tree[curr_node].net_wdl = self.tree[existing].net_wdl;
+
+                Some(children)
+            }
+            _ => {
+                self.tree.transposition_table.as_mut().unwrap().entry(hash).or_insert(curr_node);
+                None
+            }
+        }
+    }
+
     /// Continue running, starting from the selection phase at the root of the tree.
     fn run_until_result_from_root(
         &mut self,
@@ -421,16 +950,32 @@ impl<B: Board> ZeroState<B> {
 
                 //get the children or call the network if this is the first time we visit this node
                 let children = match self.tree[curr_node].children {
+                    None if self.try_link_transposition(curr_node, &curr_board).is_some() => {
+                        // a transposition: curr_node now shares an already-resolved node's
+                        // children and evaluation, so there's nothing left to request and we can
+                        // fall straight through to selecting among those children below
+                        self.tree[curr_node].children.unwrap()
+                    }
                     None => {
-                        // initialize the children with uniform policy
+                        // consult the heuristic to seed a prior for the new children and a
+                        // provisional value for this node, overwritten (not double-counted) once
+                        // the real network evaluation requested below arrives in apply_eval, and
+                        // (if transpositions are enabled) registered above as this position's
+                        // canonical node for any later transposition into it
+                        let heuristic_eval = self.heuristic.evaluate(&curr_board);
+                        assert_eq!(
+                            heuristic_eval.policy.len(), curr_board.available_moves().count(),
+                            "Heuristic policy length must match the number of available moves",
+                        );
+
                         let start = self.tree.len();
-                        curr_board.available_moves().for_each(|mv| {
-                            self.tree.nodes.push(Node::new(curr_node, Some(mv), 1.0));
+                        curr_board.available_moves().enumerate().for_each(|(i, mv)| {
+                            self.tree.nodes.push(Node::new(curr_node, Some(mv), heuristic_eval.policy[i]));
                         });
                         let end = self.tree.len();
 
                         self.tree[curr_node].children = Some(IdxRange::new(start, end));
-                        self.tree[curr_node].net_wdl = None;
+                        self.tree[curr_node].net_wdl = Some(heuristic_eval.wdl);
 
                         //add virtual loss
                         self.tree.propagate_wdl(curr_node, VIRTUAL_WDL, true);
@@ -446,9 +991,14 @@ impl<B: Board> ZeroState<B> {
                 };
 
                 //continue selecting, pick the best child
+                // the parent's value is from the parent's own POV, so flip it to get the FPU
+                // baseline from the child's POV; this still applies even when parent_visits == 0
+                // (all children freshly expanded), since the prior term still scales with it
                 let parent_visits = self.tree[curr_node].visits;
+                let parent_q = self.tree[curr_node].wdl().flip().value();
+
                 let selected = children.iter().max_by_key(|&child| {
-                    N32::from(self.tree[child].uct(self.settings.exploration_weight, parent_visits))
+                    N32::from(self.tree[child].uct(self.settings.uct_weights, parent_visits, parent_q, 0.0))
                 }).expect("Board is not done, this node should have a child");
 
                 curr_node = selected;
@@ -456,6 +1006,13 @@ impl<B: Board> ZeroState<B> {
             };
 
             self.tree.propagate_wdl(curr_node, wdl, true);
+
+            // give the ward a chance to end the search early based on the tree state this backup
+            // just produced, e.g. because the best move can no longer be overtaken
+            let remaining_iterations = self.target_iterations.saturating_sub(self.tree[0].visits);
+            if self.ward.should_stop(&self.tree, remaining_iterations) {
+                return RunResult::Done;
+            }
         }
 
         // return the requests if any, otherwise we're done
@@ -479,7 +1036,7 @@ impl<B: Board> ZeroState<B> {
             // safety check: is this actually our request?
             assert_eq!(expected_node, curr_node, "Received response for wrong node");
 
-            assert!(tree[curr_node].net_wdl.is_none(), "Node already has net_wdl");
+            // overwrites the heuristic's provisional net_wdl set when this node was expanded
             tree[curr_node].net_wdl = Some(wdl);
 
             for_each_original_move_and_policy(&curr_board, sym, &sym_policy, |i, _, p| {
@@ -495,7 +1052,7 @@ impl<B: Board> ZeroState<B> {
 
 /// Visit the available (move, policy) pairs of the given board,
 /// assuming sym_policy is the policy evaluated on `board.map(sym)`.
-fn for_each_original_move_and_policy<B: Board>(
+pub(crate) fn for_each_original_move_and_policy<B: Board>(
     board: &B,
     sym: B::Symmetry,
     sym_policy: &Vec<f32>,
@@ -520,15 +1077,19 @@ fn for_each_original_move_and_policy<B: Board>(
 }
 
 /// Build a new evaluation tree search from scratch for the given `board`.
-pub fn zero_build_tree<B: Board>(
+pub fn zero_build_tree<B: Board, H: Heuristic<B>, P: PositionHasher<B>, W: Ward<B>>(
     board: &B,
     iterations: u64,
     settings: ZeroSettings,
+    heuristic: H,
+    hasher: P,
+    ward: W,
     network: &mut impl Network<B>,
     rng: &mut impl Rng,
     mut stop_cond: impl FnMut() -> bool,
 ) -> Tree<B> {
-    let mut state = ZeroState::new(Tree::new(board.clone()), iterations, settings);
+    let tree = Tree::new(board.clone(), settings.transpositions);
+    let mut state = ZeroState::new(tree, iterations, settings, heuristic, hasher, ward);
 
     let mut response = None;
 
@@ -559,33 +1120,57 @@ pub fn zero_build_tree<B: Board>(
     return state.tree;
 }
 
-pub struct ZeroBot<B: Board, N: Network<B>, R: Rng> {
+pub struct ZeroBot<B: Board, N: Network<B>, R: Rng, H: Heuristic<B> = ZeroHeuristic, P: PositionHasher<B> = NoPositionHasher, W: Ward<B> = NoWard> {
     iterations: u64,
     settings: ZeroSettings,
+    heuristic: H,
+    hasher: P,
+    ward: W,
+    /// Consulted at the start of every [`Self::build_tree`] to warm-start the root from a pre-baked
+    /// search instead of a uniform prior, and by [`Self::select_move`] to skip the search entirely
+    /// on a book hit. See [`OpeningBook`].
+    book: Option<OpeningBook>,
     network: N,
     rng: R,
     ph: PhantomData<*const B>,
 }
 
-impl<B: Board, N: Network<B>, R: Rng> Debug for ZeroBot<B, N, R> {
+impl<B: Board, N: Network<B>, R: Rng, H: Heuristic<B>, P: PositionHasher<B>, W: Ward<B>> Debug for ZeroBot<B, N, R, H, P, W> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "ZeroBot {{ iterations: {:?}, settings: {:?}, network: {:?} }}", self.iterations, self.settings, self.network)
+        write!(
+            f, "ZeroBot {{ iterations: {:?}, settings: {:?}, heuristic: {:?}, ward: {:?}, book: {:?}, network: {:?} }}",
+            self.iterations, self.settings, self.heuristic, self.ward, self.book.is_some(), self.network,
+        )
     }
 }
 
-impl<B: Board, N: Network<B>, R: Rng> ZeroBot<B, N, R> {
-    pub fn new(iterations: u64, settings: ZeroSettings, network: N, rng: R) -> Self {
-        ZeroBot { iterations, settings, network, rng, ph: PhantomData }
+impl<B: Board, N: Network<B>, R: Rng, H: Heuristic<B> + Clone, P: PositionHasher<B> + Clone, W: Ward<B> + Clone> ZeroBot<B, N, R, H, P, W> {
+    pub fn new(iterations: u64, settings: ZeroSettings, heuristic: H, hasher: P, ward: W, network: N, rng: R) -> Self {
+        ZeroBot { iterations, settings, heuristic, hasher, ward, book: None, network, rng, ph: PhantomData }
+    }
+
+    /// Consult `book` to warm-start searches and short-circuit [`Self::select_move`] on a hit.
+    pub fn with_book(mut self, book: OpeningBook) -> Self {
+        self.book = Some(book);
+        self
     }
 
     /// Utility function that builds a tree with the settings of this bot.
     pub fn build_tree(&mut self, board: &B) -> Tree<B> {
-        zero_build_tree(board, self.iterations, self.settings, &mut self.network, &mut self.rng, || false)
+        let heuristic = BookHeuristic::new(self.book.as_ref(), self.hasher.clone(), self.heuristic.clone());
+        zero_build_tree(
+            board, self.iterations, self.settings, heuristic, self.hasher.clone(), self.ward.clone(),
+            &mut self.network, &mut self.rng, || false,
+        )
     }
 }
 
-impl<B: Board, N: Network<B>, R: Rng> Bot<B> for ZeroBot<B, N, R> {
+impl<B: Board, N: Network<B>, R: Rng, H: Heuristic<B> + Clone, P: PositionHasher<B> + Clone, W: Ward<B> + Clone> Bot<B> for ZeroBot<B, N, R, H, P, W> {
     fn select_move(&mut self, board: &B) -> B::Move {
+        if let Some(mv) = self.book.as_ref().and_then(|book| book.best_move(&self.hasher, board)) {
+            return mv;
+        }
+
         self.build_tree(board).best_move()
     }
 }
\ No newline at end of file
@@ -0,0 +1,133 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use decorum::N32;
+use itertools::zip_eq;
+use serde::{Deserialize, Serialize};
+
+use board_game::board::Board;
+
+use crate::network::ZeroEvaluation;
+use crate::non_solve_zero::{wdl_from_tuple, Heuristic, PositionHasher, Tree, ZeroHeuristic};
+
+/// A single pre-baked position: the policy [`Tree::policy`] settled on and the root [`Tree::wdl`],
+/// keyed elsewhere in [`OpeningBook`] by the hash of the board that produced them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BookEntry {
+    policy: Vec<f32>,
+    wdl: (f32, f32, f32),
+    /// The root visit count the entry was saved with, used by [`OpeningBook::merge`] to keep
+    /// whichever of two conflicting entries did more search.
+    visits: u64,
+}
+
+/// A collection of finished searches, keyed by position hash, that [`crate::non_solve_zero::ZeroBot`]
+/// can consult before running a real search: either to answer [`Bot::select_move`] directly, or to
+/// seed the root of a fresh search via [`BookHeuristic`] so it doesn't start from a uniform prior.
+/// Entries are added with [`Self::insert`] as searches complete and can be merged and persisted
+/// incrementally with [`Self::merge`], [`Self::save`] and [`Self::load`].
+///
+/// [`Bot::select_move`]: board_game::ai::Bot::select_move
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpeningBook {
+    entries: HashMap<u64, BookEntry>,
+}
+
+impl OpeningBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        let book = serde_json::from_reader(BufReader::new(file)).expect("failed to parse opening book");
+        Ok(book)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self).expect("failed to serialize opening book");
+        Ok(())
+    }
+
+    /// Add every entry from `other`, keeping the existing entry on a hash collision unless `other`'s
+    /// was built from more root visits, so repeatedly merging books from many self-play runs only
+    /// ever strengthens an entry instead of overwriting it with a shallower search.
+    pub fn merge(&mut self, other: OpeningBook) {
+        for (hash, entry) in other.entries {
+            self.entries.entry(hash)
+                .and_modify(|existing| if entry.visits > existing.visits { *existing = entry.clone(); })
+                .or_insert(entry);
+        }
+    }
+
+    /// Record the completed search `tree`, so a future search starting from an equal position can
+    /// reuse it via [`Self::best_move`] or [`BookHeuristic`] instead of searching from scratch.
+    pub fn insert<B: Board>(&mut self, hasher: &impl PositionHasher<B>, tree: &Tree<B>) {
+        let hash = hasher.hash(tree.root_board());
+        let wdl = tree.wdl();
+
+        self.entries.insert(hash, BookEntry {
+            policy: tree.policy().collect(),
+            wdl: (wdl.win, wdl.draw, wdl.loss),
+            visits: tree[0].visits,
+        });
+    }
+
+    fn lookup<B: Board>(&self, hasher: &impl PositionHasher<B>, board: &B) -> Option<&BookEntry> {
+        self.entries.get(&hasher.hash(board))
+    }
+
+    /// If `board` is in the book, the move with the highest stored policy, without running any
+    /// search at all.
+    pub fn best_move<B: Board>(&self, hasher: &impl PositionHasher<B>, board: &B) -> Option<B::Move> {
+        let entry = self.lookup(hasher, board)?;
+
+        zip_eq(board.available_moves(), entry.policy.iter().copied())
+            .max_by_key(|&(_, p)| N32::from(p))
+            .map(|(mv, _)| mv)
+    }
+}
+
+/// A [`Heuristic`] that warm-starts the root of a new search from a pre-baked [`OpeningBook`]: the
+/// first call to [`Self::evaluate`] (always the root, since a tree's root is the first node
+/// expanded) returns the book's stored policy and value instead of `fallback`'s if `root_board` is
+/// in the book; every later call (always for some descendant of the root) goes straight to
+/// `fallback`, same as if no book had been given at all.
+///
+/// Unlike [`OpeningBook::best_move`] this doesn't skip the search, it only seeds it, so the
+/// resulting tree still gets genuine visit statistics for `ZeroBot::build_tree` callers that need
+/// more than just the immediate best move (e.g. training data generation).
+#[derive(Debug, Clone)]
+pub struct BookHeuristic<'a, B: Board, P: PositionHasher<B>, H: Heuristic<B> = ZeroHeuristic> {
+    book: Option<&'a OpeningBook>,
+    hasher: P,
+    consulted_root: Cell<bool>,
+    fallback: H,
+}
+
+impl<'a, B: Board, P: PositionHasher<B>, H: Heuristic<B>> BookHeuristic<'a, B, P, H> {
+    pub fn new(book: Option<&'a OpeningBook>, hasher: P, fallback: H) -> Self {
+        BookHeuristic { book, hasher, consulted_root: Cell::new(false), fallback }
+    }
+}
+
+impl<B: Board, P: PositionHasher<B>, H: Heuristic<B>> Heuristic<B> for BookHeuristic<'_, B, P, H> {
+    fn evaluate(&self, board: &B) -> ZeroEvaluation {
+        if !self.consulted_root.replace(true) {
+            if let Some(book) = self.book {
+                if let Some(entry) = book.lookup(&self.hasher, board) {
+                    return ZeroEvaluation {
+                        wdl: wdl_from_tuple(entry.wdl),
+                        policy: entry.policy.clone(),
+                    };
+                }
+            }
+        }
+
+        self.fallback.evaluate(board)
+    }
+}
@@ -1,13 +1,14 @@
-use std::fs::create_dir_all;
-use std::io::{BufWriter, Write};
+use std::fs::{create_dir_all, remove_file, File};
+use std::io::{BufReader, BufWriter, Write};
 use std::time::Instant;
 
 use board_game::board::Board;
 use crossbeam::channel::Receiver;
+use flate2::write::GzEncoder;
 
 use crate::mapping::binary_output::BinaryOutput;
 use crate::mapping::BoardMapper;
-use crate::selfplay::protocol::{GeneratorUpdate, ServerUpdate};
+use crate::selfplay::protocol::{Compression, GeneratorUpdate, ServerUpdate};
 
 pub fn collector_main<B: Board>(
     game: &str,
@@ -15,11 +16,14 @@ pub fn collector_main<B: Board>(
     games_per_file: usize,
     first_gen: u32,
     output_folder: &str,
+    compression: Option<Compression>,
     mapper: impl BoardMapper<B>,
     update_receiver: Receiver<GeneratorUpdate<B>>,
 ) {
+    let output_path = |gen: u32| format!("{}/games_{}", output_folder, gen);
+
     let new_output = |gen: u32| {
-        let path = format!("{}/games_{}", output_folder, gen);
+        let path = output_path(gen);
         println!("Collector: start writing to {}", path);
         BinaryOutput::new(path, game, mapper)
             .expect("Error while creating output files")
@@ -49,18 +53,25 @@ pub fn collector_main<B: Board>(
                         .expect("Error while finishing output file");
 
                     let prev_i = curr_gen;
+                    let (raw_byte_count, compressed_byte_count) =
+                        compress_finished_file(&output_path(prev_i), compression);
+
                     curr_gen += 1;
                     curr_game_count = 0;
                     curr_output = new_output(curr_gen);
 
-                    let message = ServerUpdate::FinishedFile { index: prev_i };
+                    let message = ServerUpdate::FinishedFile {
+                        index: prev_i,
+                        raw_byte_count,
+                        compressed_byte_count,
+                    };
                     writer.write_all(serde_json::to_string(&message).unwrap().as_bytes()).unwrap();
                     writer.write(&[b'\n']).unwrap();
                     writer.flush().unwrap();
                 }
             }
-            GeneratorUpdate::Progress { cached_evals, real_evals, moves } => {
-                estimator.update(real_evals, cached_evals, moves);
+            GeneratorUpdate::Progress { cached_evals, real_evals, moves, resigned_games, playthrough_games, resign_false_positives } => {
+                estimator.update(real_evals, cached_evals, moves, resigned_games, playthrough_games, resign_false_positives);
             }
         }
     }
@@ -70,6 +81,45 @@ pub fn collector_main<B: Board>(
     writer.flush().unwrap()
 }
 
+/// Deflate a just-finished output file in place, so completed generations take up less disk space.
+///
+/// `BinaryOutput` writes its own file straight through, so rather than threading a gzip encoder
+/// through its internals this re-streams the finished file into a `.gz` sibling and removes the
+/// uncompressed original; since that only happens once a whole file is done (the same point a
+/// crash-safe prefix already has to survive at), a crash mid-generation still leaves either a
+/// complete raw file or a complete compressed one behind (a crash while compressing can leave a
+/// partial `.gz` next to the complete raw file).
+///
+/// Returns `(raw_byte_count, compressed_byte_count)`; with `compression` set to `None` the file is
+/// left untouched and the two counts are equal.
+fn compress_finished_file(path: &str, compression: Option<Compression>) -> (u64, u64) {
+    let raw_byte_count = std::fs::metadata(path)
+        .expect("Error while reading finished output file metadata")
+        .len();
+
+    let compression = match compression {
+        Some(compression) => compression,
+        None => return (raw_byte_count, raw_byte_count),
+    };
+
+    let Compression::Gzip { level } = compression;
+    let gz_path = format!("{}.gz", path);
+
+    let mut input = BufReader::new(File::open(path).expect("Error while reopening finished output file"));
+    let output = File::create(&gz_path).expect("Error while creating compressed output file");
+    let mut encoder = GzEncoder::new(output, flate2::Compression::new(level));
+    std::io::copy(&mut input, &mut encoder).expect("Error while compressing finished output file");
+    let compressed_byte_count = encoder.finish()
+        .expect("Error while flushing compressed output file")
+        .metadata()
+        .expect("Error while reading compressed output file metadata")
+        .len();
+
+    remove_file(path).expect("Error while removing uncompressed output file");
+
+    (raw_byte_count, compressed_byte_count)
+}
+
 struct ThroughputEstimator {
     last_print_time: Instant,
     real_evals: u64,
@@ -78,6 +128,12 @@ struct ThroughputEstimator {
     games: u64,
     total_moves: u64,
     total_games: u64,
+    resigned_games: u64,
+    playthrough_games: u64,
+    resign_false_positives: u64,
+    total_resigned_games: u64,
+    total_playthrough_games: u64,
+    total_resign_false_positives: u64,
 }
 
 impl ThroughputEstimator {
@@ -90,6 +146,12 @@ impl ThroughputEstimator {
             games: 0,
             total_moves: 0,
             total_games: 0,
+            resigned_games: 0,
+            playthrough_games: 0,
+            resign_false_positives: 0,
+            total_resigned_games: 0,
+            total_playthrough_games: 0,
+            total_resign_false_positives: 0,
         }
     }
 
@@ -98,12 +160,19 @@ impl ThroughputEstimator {
         self.total_games += 1;
     }
 
-    fn update(&mut self, real_evals: u64, cached_evals: u64, moves: u64) {
+    fn update(&mut self, real_evals: u64, cached_evals: u64, moves: u64, resigned_games: u64, playthrough_games: u64, resign_false_positives: u64) {
         self.real_evals += real_evals;
         self.cached_evals += cached_evals;
         self.moves += moves;
         self.total_moves += moves;
 
+        self.resigned_games += resigned_games;
+        self.playthrough_games += playthrough_games;
+        self.resign_false_positives += resign_false_positives;
+        self.total_resigned_games += resigned_games;
+        self.total_playthrough_games += playthrough_games;
+        self.total_resign_false_positives += resign_false_positives;
+
         let now = Instant::now();
         let delta = (now - self.last_print_time).as_secs_f32();
 
@@ -120,10 +189,31 @@ impl ThroughputEstimator {
             );
             println!("   cache hit rate: {}", cached_eval_throughput / (cached_eval_throughput + real_eval_throughput));
 
+            // `adjudicated_games` never exceeds 0 in this checkout: nothing populates
+            // `GeneratorUpdate::Progress`'s resign/playthrough counts, since the generator that would
+            // make the resign decision isn't present here (see `ResignSettings`'s doc comment).
+            let adjudicated_games = self.resigned_games + self.playthrough_games;
+            if adjudicated_games > 0 {
+                let playthrough_fraction = self.playthrough_games as f32 / adjudicated_games as f32;
+                println!(
+                    "   resigned {}/{} games, playthrough fraction: {:.3}, false positive rate: {:.3} ({} total resigned, {} total playthrough, {} total false positives)",
+                    self.resigned_games,
+                    adjudicated_games,
+                    playthrough_fraction,
+                    self.resign_false_positives as f32 / self.playthrough_games.max(1) as f32,
+                    self.total_resigned_games,
+                    self.total_playthrough_games,
+                    self.total_resign_false_positives,
+                );
+            }
+
             self.real_evals = 0;
             self.cached_evals = 0;
             self.moves = 0;
             self.games = 0;
+            self.resigned_games = 0;
+            self.playthrough_games = 0;
+            self.resign_false_positives = 0;
         }
     }
 }
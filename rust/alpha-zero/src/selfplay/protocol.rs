@@ -13,6 +13,37 @@ pub struct StartupSettings {
     pub games_per_gen: usize,
     pub first_gen: u32,
     pub reorder_games: bool,
+    pub resign_settings: ResignSettings,
+    pub compression: Option<Compression>,
+}
+
+/// How a generation's output file is compressed as it is streamed to disk.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum Compression {
+    /// Deflate with the given level (0-9, see [flate2::Compression::new]).
+    Gzip { level: u32 },
+}
+
+/// Settings for AlphaZero-style early game termination, fixed for the whole run so the
+/// false-positive rate measured on playthrough games stays comparable across generations.
+///
+/// **Not implemented, blocked rather than wired up:** `generator.rs`/`commander.rs`, where the actual
+/// per-move resign/playthrough decision would have to live, aren't present in this checkout (only
+/// their `use` declarations in `server.rs` are), so there is no code anywhere that reads these fields.
+/// They're serialized over the wire and round-tripped through [`GeneratorUpdate::Progress`]'s
+/// `resigned_games`/`playthrough_games`/`resign_false_positives` counters and the collector's
+/// reporting below it, but those counters never become nonzero in this tree.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct ResignSettings {
+    /// resign once the root value (from the POV of the side to move) stays below this for both
+    /// players for `resign_move_count` moves in a row
+    pub resign_value: f32,
+    /// the number of consecutive moves the value must stay below `resign_value` before resigning
+    pub resign_move_count: u32,
+    /// this fraction of games ignore resignation and are played to the true end instead, so the
+    /// false-positive rate (how often a "resigned" game would actually have been won or drawn) can
+    /// be measured and the threshold kept calibrated
+    pub resign_disabled_fraction: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +74,14 @@ pub enum GeneratorUpdate<B: Board> {
         real_evals: u64,
         // the number of moves played
         moves: u64,
+        // the number of games that were cut short by resignation
+        // not populated by anything in this checkout, see ResignSettings's doc comment
+        resigned_games: u64,
+        // the number of games played to the true end with resignation disabled, to calibrate the threshold
+        playthrough_games: u64,
+        // of those playthrough games, the number that would have been resigned but did not actually end
+        // in a loss for the resigning side
+        resign_false_positives: u64,
     },
 }
 
@@ -50,7 +89,11 @@ pub enum GeneratorUpdate<B: Board> {
 #[serde(deny_unknown_fields)]
 pub enum ServerUpdate {
     Stopped,
-    FinishedFile { index: u32 },
+    FinishedFile {
+        index: u32,
+        raw_byte_count: u64,
+        compressed_byte_count: u64,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,6 +119,12 @@ pub struct Settings {
 
     // performance
     pub cache_size: usize,
+    /// Pre-bound executor batch sizes the evaluation coalescer rounds requests up to, smallest
+    /// first, ending in the executor's max batch size (see `network::cudnn::default_batch_buckets`).
+    pub batch_buckets: Vec<usize>,
+    /// How long the coalescer waits for more requests to arrive before flushing a partially
+    /// filled bucket, so a quiet generator still gets its evaluations back promptly.
+    pub batch_flush_timeout_millis: u64,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -10,7 +10,6 @@ use board_game::games::ttt::TTTBoard;
 use board_game::symmetry::SymmetryDistribution;
 use board_game::util::board_gen::random_board_with_moves;
 use crossbeam::channel;
-use itertools::Itertools;
 use rand::{Rng, thread_rng};
 
 use cuda_nn_eval::Device;
@@ -146,6 +145,7 @@ fn selfplay_start<B: Board>(
                 startup.games_per_gen,
                 startup.first_gen,
                 &startup.output_folder,
+                startup.compression,
                 mapper,
                 update_receiver,
                 thread_count,
@@ -186,4 +186,4 @@ impl Display for Game {
             Game::Ataxx { size } => write!(f, "ataxx-{}", size),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,126 @@
+/// A minimal Brigadier-style command tree: each [`Node`] is either a fixed [`literal`] keyword or a
+/// parsed [`argument`]/[`remainder`] slot, and [`CommandDispatcher::execute`] walks a whitespace-split
+/// input line down the tree until it reaches a node with an [`Node::executes`] handler. New commands
+/// are added declaratively with [`CommandDispatcher::register`] instead of hand-rolling a parser for
+/// each one.
+pub struct CommandDispatcher<C> {
+    roots: Vec<Node<C>>,
+}
+
+/// An error produced while walking the command tree: an unknown literal, a dangling command with no
+/// handler, or a typed argument ([`parse_arg`]) that failed to parse.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CommandError(pub String);
+
+enum Kind {
+    Literal(String),
+    Argument(String),
+    /// Captures every remaining token, joined back with single spaces, as one argument. Used for
+    /// trailing free-form input like a UCI move list or a FEN string.
+    Remainder(String),
+}
+
+pub struct Node<C> {
+    kind: Kind,
+    children: Vec<Node<C>>,
+    executor: Option<Box<dyn Fn(&mut C, &[String]) -> Result<(), CommandError>>>,
+}
+
+/// Start a literal (fixed-keyword) node, e.g. `literal("go")`.
+pub fn literal<C>(name: &str) -> Node<C> {
+    Node { kind: Kind::Literal(name.to_owned()), children: Vec::new(), executor: None }
+}
+
+/// Start a single-token argument node; the matched token is appended to the handler's `args`.
+pub fn argument<C>(name: &str) -> Node<C> {
+    Node { kind: Kind::Argument(name.to_owned()), children: Vec::new(), executor: None }
+}
+
+/// Start a greedy argument node that consumes the rest of the line as one joined argument. Always a
+/// leaf: any children registered on it are unreachable and ignored.
+pub fn remainder<C>(name: &str) -> Node<C> {
+    Node { kind: Kind::Remainder(name.to_owned()), children: Vec::new(), executor: None }
+}
+
+impl<C> Node<C> {
+    /// Register a child to descend into after this node matches.
+    pub fn then(mut self, child: Node<C>) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Mark this node as a valid command ending, invoked with every argument captured on the path
+    /// down to it, in the order they were matched.
+    pub fn executes(mut self, handler: impl Fn(&mut C, &[String]) -> Result<(), CommandError> + 'static) -> Self {
+        self.executor = Some(Box::new(handler));
+        self
+    }
+
+    fn run(&self, context: &mut C, tokens: &[&str], args: &mut Vec<String>) -> Result<(), CommandError> {
+        match tokens.split_first() {
+            None => match &self.executor {
+                Some(executor) => executor(context, args),
+                None => Err(CommandError("incomplete command".to_owned())),
+            },
+            Some((&next, rest)) => {
+                if let Some(child) = self.children.iter().find(|c| matches!(&c.kind, Kind::Literal(name) if name == next)) {
+                    return child.run(context, rest, args);
+                }
+                if let Some(child) = self.children.iter().find(|c| matches!(c.kind, Kind::Argument(_))) {
+                    args.push(next.to_owned());
+                    return child.run(context, rest, args);
+                }
+                if let Some(child) = self.children.iter().find(|c| matches!(c.kind, Kind::Remainder(_))) {
+                    args.push(tokens.join(" "));
+                    return match &child.executor {
+                        Some(executor) => executor(context, args),
+                        None => Err(CommandError("incomplete command".to_owned())),
+                    };
+                }
+                Err(CommandError(format!("unexpected argument '{}'", next)))
+            }
+        }
+    }
+}
+
+impl<C> Default for CommandDispatcher<C> {
+    fn default() -> Self {
+        CommandDispatcher { roots: Vec::new() }
+    }
+}
+
+impl<C> CommandDispatcher<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a top-level command, built up with [`literal`]/[`argument`]/[`remainder`] and
+    /// [`Node::then`]/[`Node::executes`].
+    pub fn register(&mut self, node: Node<C>) {
+        assert!(matches!(node.kind, Kind::Literal(_)), "a top-level command must start with a literal");
+        self.roots.push(node);
+    }
+
+    /// Parse and run one input line against the registered commands. Blank lines are ignored.
+    pub fn execute(&self, context: &mut C, line: &str) -> Result<(), CommandError> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (&first, rest) = match tokens.split_first() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+
+        match self.roots.iter().find(|r| matches!(&r.kind, Kind::Literal(name) if name == first)) {
+            Some(root) => root.run(context, rest, &mut Vec::new()),
+            None => Err(CommandError(format!("unknown command '{}'", first))),
+        }
+    }
+}
+
+/// Parse a previously-captured [`argument`] by index, the way a Brigadier `IntegerArgumentType`
+/// would inside a handler.
+pub fn parse_arg<T: std::str::FromStr>(args: &[String], index: usize, name: &str) -> Result<T, CommandError> {
+    args.get(index)
+        .ok_or_else(|| CommandError(format!("missing argument '{}'", name)))?
+        .parse()
+        .map_err(|_| CommandError(format!("'{}' is not a valid {}", name, std::any::type_name::<T>())))
+}
@@ -0,0 +1,307 @@
+use std::io::{stdin, stdout, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use board_game::board::Board;
+use board_game::games::chess::ChessBoard;
+use chess::ChessMove;
+use internal_iterator::InternalIterator;
+
+use crate::network::dummy::DummyNetwork;
+use crate::non_solve_zero::{wdl_from_tuple, NoPositionHasher};
+use crate::oracle::DummyOracle;
+use crate::uci::dispatcher::{argument, literal, parse_arg, remainder, CommandDispatcher, CommandError};
+use crate::zero::node::ZeroValues;
+use crate::zero::step::{zero_step_apply, zero_step_gather, FpuMode};
+use crate::zero::tree::Tree;
+
+/// A safety net for `go` when no `stop` ever arrives, so a forgotten GUI doesn't leave the search
+/// thread spinning forever.
+const MAX_VISITS_WITHOUT_STOP: u64 = 1_000_000;
+
+/// Tunables changed with `setoption`, read by [`search_thread`] at the start of every `go`.
+struct Options {
+    exploration_weight: f32,
+    use_value: bool,
+    fpu_mode: FpuMode,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options { exploration_weight: 2.0, use_value: false, fpu_mode: FpuMode::Parent }
+    }
+}
+
+/// The handle of an in-flight `go`: the flag `stop` sets and the thread `stop` joins to get the
+/// finished [`Tree`] back.
+struct Search {
+    stop_flag: Arc<AtomicBool>,
+    thread: JoinHandle<Tree<ChessBoard>>,
+}
+
+/// The state threaded through every dispatched command.
+struct UciState {
+    tree: Option<Tree<ChessBoard>>,
+    options: Options,
+    search: Option<Search>,
+    quit: bool,
+}
+
+impl UciState {
+    fn new() -> Self {
+        UciState {
+            tree: Some(Tree::new(ChessBoard::default())),
+            options: Options::default(),
+            search: None,
+            quit: false,
+        }
+    }
+
+    fn set_board(&mut self, board: ChessBoard) {
+        self.tree = Some(Tree::new(board));
+    }
+
+    /// Block until any running search stops and hand its tree back, so commands other than `stop`
+    /// never have to reason about a search being in flight.
+    fn join_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop_flag.store(true, Ordering::Relaxed);
+            self.tree = Some(search.thread.join().expect("search thread panicked"));
+        }
+    }
+}
+
+/// Read UCI commands from stdin until `quit`, writing responses to stdout. Uses [`DummyNetwork`] and
+/// [`DummyOracle`] instead of a loaded network, so this binary has no GPU/training-pipeline
+/// dependency to drive a real analysis session with a GUI's own evaluation overlays disabled.
+pub fn run_uci() {
+    let dispatcher = build_dispatcher();
+    let mut state = UciState::new();
+
+    for line in stdin().lines() {
+        let line = line.expect("failed to read stdin");
+        if let Err(CommandError(message)) = dispatcher.execute(&mut state, &line) {
+            println!("info string {}", message);
+        }
+        stdout().flush().expect("failed to flush stdout");
+
+        if state.quit {
+            break;
+        }
+    }
+}
+
+fn build_dispatcher() -> CommandDispatcher<UciState> {
+    let mut dispatcher = CommandDispatcher::new();
+
+    dispatcher.register(literal("uci").executes(cmd_uci));
+    dispatcher.register(literal("isready").executes(cmd_isready));
+    dispatcher.register(literal("ucinewgame").executes(cmd_ucinewgame));
+    dispatcher.register(literal("quit").executes(cmd_quit));
+
+    dispatcher.register(
+        literal("position")
+            .then(
+                literal("startpos")
+                    .then(literal("moves").then(remainder("moves").executes(cmd_position_startpos_moves)))
+                    .executes(cmd_position_startpos),
+            )
+            .then(literal("fen").then(remainder("fen").executes(cmd_position_fen))),
+    );
+
+    dispatcher.register(literal("go").executes(cmd_go));
+    dispatcher.register(literal("stop").executes(cmd_stop));
+
+    dispatcher.register(
+        literal("setoption").then(
+            literal("name")
+                .then(literal("exploration_weight").then(literal("value").then(argument("value").executes(cmd_set_exploration_weight))))
+                .then(literal("use_value").then(literal("value").then(argument("value").executes(cmd_set_use_value))))
+                .then(
+                    literal("fpu_mode").then(
+                        literal("value")
+                            .then(literal("parent").executes(cmd_set_fpu_parent))
+                            .then(literal("fixed").then(
+                                argument("win").then(
+                                    argument("draw").then(argument("loss").executes(cmd_set_fpu_fixed)),
+                                ),
+                            )),
+                    ),
+                ),
+        ),
+    );
+
+    dispatcher.register(literal("eval").executes(cmd_eval));
+    dispatcher.register(literal("tree").executes(cmd_tree));
+
+    dispatcher
+}
+
+fn cmd_uci(_state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    println!("id name alpha-zero");
+    println!("id author KarelPeeters");
+    println!("uciok");
+    Ok(())
+}
+
+fn cmd_isready(_state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    println!("readyok");
+    Ok(())
+}
+
+fn cmd_ucinewgame(state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    state.join_search();
+    state.set_board(ChessBoard::default());
+    Ok(())
+}
+
+fn cmd_quit(state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    state.join_search();
+    state.quit = true;
+    Ok(())
+}
+
+fn cmd_position_startpos(state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    state.join_search();
+    state.set_board(ChessBoard::default());
+    Ok(())
+}
+
+fn cmd_position_startpos_moves(state: &mut UciState, args: &[String]) -> Result<(), CommandError> {
+    state.join_search();
+    let mut board = ChessBoard::default();
+    apply_move_list(&mut board, &args[0])?;
+    state.set_board(board);
+    Ok(())
+}
+
+fn cmd_position_fen(state: &mut UciState, args: &[String]) -> Result<(), CommandError> {
+    state.join_search();
+    let board = ChessBoard::from_str(&args[0])
+        .map_err(|_| CommandError(format!("'{}' is not a valid FEN", args[0])))?;
+    state.set_board(board);
+    Ok(())
+}
+
+fn apply_move_list(board: &mut ChessBoard, moves: &str) -> Result<(), CommandError> {
+    for token in moves.split_whitespace() {
+        let mv = parse_move(board, token)?;
+        board.play(mv);
+    }
+    Ok(())
+}
+
+fn parse_move(board: &ChessBoard, token: &str) -> Result<ChessMove, CommandError> {
+    let mv = ChessMove::from_str(token).map_err(|_| CommandError(format!("'{}' is not a move", token)))?;
+    board.available_moves().find(|&available| available == mv)
+        .ok_or_else(|| CommandError(format!("'{}' is not legal here", token)))
+}
+
+fn cmd_go(state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    state.join_search();
+
+    let tree = state.tree.take().expect("tree missing while no search is running");
+    let exploration_weight = state.options.exploration_weight;
+    let use_value = state.options.use_value;
+    let fpu_mode = state.options.fpu_mode;
+
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let thread_stop_flag = stop_flag.clone();
+
+    let thread = std::thread::spawn(move || search_thread(tree, thread_stop_flag, exploration_weight, use_value, fpu_mode));
+    state.search = Some(Search { stop_flag, thread });
+
+    Ok(())
+}
+
+fn search_thread(mut tree: Tree<ChessBoard>, stop_flag: Arc<AtomicBool>, exploration_weight: f32, use_value: bool, fpu_mode: FpuMode) -> Tree<ChessBoard> {
+    let mut network = DummyNetwork;
+
+    while !stop_flag.load(Ordering::Relaxed) && tree.root_visits() < MAX_VISITS_WITHOUT_STOP {
+        let request = zero_step_gather(&mut tree, &DummyOracle, &NoPositionHasher, None, exploration_weight, use_value, fpu_mode);
+
+        if let Some(request) = request {
+            let eval = network.evaluate(&request.board);
+            zero_step_apply(&mut tree, request.respond(eval));
+        }
+    }
+
+    tree
+}
+
+fn cmd_stop(state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    state.join_search();
+
+    let tree = state.tree.as_ref().expect("tree missing after join_search");
+    let best = tree[0].children
+        .and_then(|children| children.iter().max_by_key(|&c| tree[c].complete_visits))
+        .and_then(|best| tree[best].last_move);
+
+    match best {
+        Some(mv) => println!("bestmove {:?}", mv),
+        None => println!("bestmove 0000"),
+    }
+
+    Ok(())
+}
+
+fn cmd_set_exploration_weight(state: &mut UciState, args: &[String]) -> Result<(), CommandError> {
+    state.options.exploration_weight = parse_arg(args, 0, "value")?;
+    Ok(())
+}
+
+fn cmd_set_use_value(state: &mut UciState, args: &[String]) -> Result<(), CommandError> {
+    let value: String = args.first().cloned().ok_or_else(|| CommandError("missing argument 'value'".to_owned()))?;
+    state.options.use_value = match value.as_str() {
+        "true" => true,
+        "false" => false,
+        _ => return Err(CommandError(format!("'{}' is not 'true' or 'false'", value))),
+    };
+    Ok(())
+}
+
+fn cmd_set_fpu_parent(state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    state.options.fpu_mode = FpuMode::Parent;
+    Ok(())
+}
+
+fn cmd_set_fpu_fixed(state: &mut UciState, args: &[String]) -> Result<(), CommandError> {
+    let win: f32 = parse_arg(args, 0, "win")?;
+    let draw: f32 = parse_arg(args, 1, "draw")?;
+    let loss: f32 = parse_arg(args, 2, "loss")?;
+    state.options.fpu_mode = FpuMode::Fixed(ZeroValues::new(wdl_from_tuple((win, draw, loss))));
+    Ok(())
+}
+
+fn cmd_eval(state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    state.join_search();
+    let tree = state.tree.as_ref().expect("tree missing after join_search");
+
+    match tree[0].net_values {
+        Some(values) => println!("info string eval {:?}", values),
+        None => println!("info string eval <not yet evaluated, run 'go' then 'stop' first>"),
+    }
+
+    Ok(())
+}
+
+fn cmd_tree(state: &mut UciState, _args: &[String]) -> Result<(), CommandError> {
+    state.join_search();
+    let tree = state.tree.as_ref().expect("tree missing after join_search");
+
+    if let Some(children) = tree[0].children {
+        for child in children.iter() {
+            let node = &tree[child];
+            println!(
+                "info string {} visits={} policy={:.3}",
+                node.last_move.map_or("<root>".to_owned(), |mv| format!("{:?}", mv)),
+                node.complete_visits,
+                node.net_policy,
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,4 @@
+pub mod dispatcher;
+mod engine;
+
+pub use engine::run_uci;
@@ -1,9 +1,12 @@
+use std::collections::{HashMap, HashSet};
+
 use board_game::board::Board;
 use board_game::wdl::{Flip, OutcomeWDL, POV};
 use decorum::N32;
 use internal_iterator::InternalIterator;
 
 use crate::network::ZeroEvaluation;
+use crate::non_solve_zero::PositionHasher;
 use crate::oracle::Oracle;
 use crate::util::zip_eq_exact;
 use crate::zero::node::{Node, ZeroValues};
@@ -29,6 +32,54 @@ pub enum FpuMode {
     Parent,
 }
 
+/// A subtree's exact game-theoretic value, from its own mover's perspective, once the solver has
+/// proven it beyond doubt. Set directly on a terminal leaf by [`zero_step_gather`] (or seeded early by
+/// [`solve_bounded_minimax`]), then backed up the rest of the way to the root by
+/// [`tree_propagate_proven`]. `distance` counts plies along the line that realizes the value: the
+/// shortest one for a win or a draw, the longest one for a loss. [`best_move_solved`] uses it to play
+/// the fastest forced win; it does not look at proven losses or draws.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProvenValue {
+    Win(u32),
+    Draw(u32),
+    Loss(u32),
+}
+
+impl ProvenValue {
+    fn from_outcome(outcome: OutcomeWDL) -> Self {
+        match outcome {
+            OutcomeWDL::Win => ProvenValue::Win(0),
+            OutcomeWDL::Draw => ProvenValue::Draw(0),
+            OutcomeWDL::Loss => ProvenValue::Loss(0),
+        }
+    }
+
+    fn outcome(self) -> OutcomeWDL {
+        match self {
+            ProvenValue::Win(_) => OutcomeWDL::Win,
+            ProvenValue::Draw(_) => OutcomeWDL::Draw,
+            ProvenValue::Loss(_) => OutcomeWDL::Loss,
+        }
+    }
+
+    fn distance(self) -> u32 {
+        match self {
+            ProvenValue::Win(d) | ProvenValue::Draw(d) | ProvenValue::Loss(d) => d,
+        }
+    }
+
+    /// Reinterprets this value one ply further back, from the perspective of whoever moved into it:
+    /// a forced win for the mover who achieves it is a forced loss for the side that allowed it, and
+    /// vice versa, with the proof one ply longer either way.
+    fn flip(self) -> Self {
+        match self {
+            ProvenValue::Win(d) => ProvenValue::Loss(d + 1),
+            ProvenValue::Draw(d) => ProvenValue::Draw(d + 1),
+            ProvenValue::Loss(d) => ProvenValue::Win(d + 1),
+        }
+    }
+}
+
 /// The first half of a step, walks down the tree until either:
 /// * a **terminal** node is reached.
 /// The resulting wdl value is immediately propagated back to the root, the `visit` counters are incremented
@@ -37,12 +88,31 @@ pub enum FpuMode {
 /// The reached node and its board is returned in a [ZeroRequest],
 /// and all involved nodes end up with their `virtual_visits` counter incremented.
 ///
+/// If `transpositions` is `Some`, newly-reached positions are hashed with `hasher` and looked up in
+/// the table first: a hit links `curr_node` to the existing node's children and evaluation instead
+/// of expanding a fresh subtree, turning the tree into a DAG. `hasher` must fold in every part of
+/// `B`'s state that affects legal moves or game-specific outcomes (castling rights, repetition
+/// counters, ...), exactly like the [`PositionHasher`] contract requires, or positions that only
+/// look equal will get merged into the same node.
+///
+/// A node the solver has already proven (see [`ProvenValue`]) is treated the same as a terminal
+/// board: its known value is backed up immediately and selection never descends any further into it.
+/// Among the children of an unproven node, any child already proven a loss for the side to move here
+/// is skipped during selection, since it's a dead branch that further visits can't improve on; `expect`
+/// below relies on [`tree_propagate_proven`] having already turned such a node itself into a proven
+/// loss/draw once *every* child is dead, so this filter can never remove every candidate.
+/// `solver_depth` bounds a [`solve_bounded_minimax`] lookahead run on freshly-expanded boards, letting
+/// short endgame lines get proven outright without waiting for MCTS to expand them move by move; `0`
+/// disables it.
 pub fn zero_step_gather<B: Board>(
     tree: &mut Tree<B>,
     oracle: &impl Oracle<B>,
+    hasher: &impl PositionHasher<B>,
+    mut transpositions: Option<&mut HashMap<u64, usize>>,
     exploration_weight: f32,
     use_value: bool,
     fpu_mode: FpuMode,
+    solver_depth: u32,
 ) -> Option<ZeroRequest<B>> {
     let mut curr_node = 0;
     let mut curr_board = tree.root_board().clone();
@@ -53,29 +123,59 @@ pub fn zero_step_gather<B: Board>(
         // count each node as visited
         tree[curr_node].virtual_visits += 1;
 
+        // a node already proven by the solver is a known value, so there's nothing left to gain from
+        // asking the oracle or descending any further
+        if let Some(proven) = tree[curr_node].proven {
+            tree_propagate_values(tree, curr_node, ZeroValues::from_outcome(proven.outcome()));
+            return None;
+        }
+
         // if the board is done backpropagate the real value
         if let Some(outcome) = oracle.best_outcome(&curr_board) {
             let outcome = outcome.pov(curr_board.next_player());
+            tree[curr_node].proven = Some(ProvenValue::from_outcome(outcome));
+            tree_propagate_proven(tree, curr_node);
             tree_propagate_values(tree, curr_node, ZeroValues::from_outcome(outcome));
             return None;
         }
 
         let children = match tree[curr_node].children {
+            Some(children) => children,
             None => {
-                // initialize the children with uniform policy
-                let start = tree.len();
-                curr_board.available_moves().for_each(|mv| {
-                    tree.nodes.push(Node::new(Some(curr_node), Some(mv), 1.0));
-                });
-                let end = tree.len();
-
-                tree[curr_node].children = Some(IdxRange::new(start, end));
-                tree[curr_node].net_values = None;
-
-                // return the request
-                return Some(ZeroRequest { board: curr_board, node: curr_node });
+                let linked = match transpositions.as_deref_mut() {
+                    Some(table) => try_link_transposition(tree, table, hasher, curr_node, &curr_board),
+                    None => None,
+                };
+
+                match linked {
+                    Some(children) => children,
+                    None => {
+                        // try to seed a proven value straight away, without spending a network
+                        // evaluation on a line the solver can already resolve outright
+                        if solver_depth > 0 {
+                            if let Some(value) = solve_bounded_minimax(&curr_board, oracle, solver_depth) {
+                                tree[curr_node].proven = Some(value);
+                                tree_propagate_proven(tree, curr_node);
+                                tree_propagate_values(tree, curr_node, ZeroValues::from_outcome(value.outcome()));
+                                return None;
+                            }
+                        }
+
+                        // initialize the children with uniform policy
+                        let start = tree.len();
+                        curr_board.available_moves().for_each(|mv| {
+                            tree.nodes.push(Node::new(vec![curr_node], Some(mv), 1.0));
+                        });
+                        let end = tree.len();
+
+                        tree[curr_node].children = Some(IdxRange::new(start, end));
+                        tree[curr_node].net_values = None;
+
+                        // return the request
+                        return Some(ZeroRequest { board: curr_board, node: curr_node });
+                    }
+                }
             }
-            Some(children) => children,
         };
 
         // update fpu
@@ -84,14 +184,16 @@ pub fn zero_step_gather<B: Board>(
         }
         fpu = fpu.flip();
 
-        // continue selecting, pick the best child
+        // continue selecting, pick the best child, skipping any child already proven lost for us
         let parent_total_visits = tree[curr_node].total_visits();
 
-        let selected = children.iter().max_by_key(|&child| {
-            let x = tree[child].uct(parent_total_visits, fpu_mode.select(fpu), use_value)
-                .total(exploration_weight);
-            N32::from_inner(x)
-        }).expect("Board is not done, this node should have a child");
+        let selected = children.iter()
+            .filter(|&child| !matches!(tree[child].proven.map(ProvenValue::flip), Some(ProvenValue::Loss(_))))
+            .max_by_key(|&child| {
+                let x = tree[child].uct(parent_total_visits, fpu_mode.select(fpu), use_value)
+                    .total(exploration_weight);
+                N32::from_inner(x)
+            }).expect("Board is not done, this node should have a child");
 
         curr_node = selected;
         curr_board.play(tree[curr_node].last_move.unwrap());
@@ -118,25 +220,204 @@ pub fn zero_step_apply<B: Board>(tree: &mut Tree<B>, response: ZeroResponse<B>)
     }
 }
 
-/// Propagate the given `wdl` up to the root.
-fn tree_propagate_values<B: Board>(tree: &mut Tree<B>, node: usize, mut values: ZeroValues) {
-    let mut curr_index = node;
+/// If `curr_board` hashes to an already-expanded node, adopt that node's children and evaluation
+/// for `curr_node` instead of expanding it again, so the two occurrences share the rest of the
+/// search from here on. Returns `None` (and, the first time a hash is seen, registers `curr_node`
+/// as its canonical node) when `curr_node` should be expanded normally instead: either this is a
+/// new position, or the existing node for it hasn't been evaluated yet itself, in which case
+/// aliasing to it now would just mean duplicating that pending request.
+fn try_link_transposition<B: Board>(
+    tree: &mut Tree<B>,
+    transpositions: &mut HashMap<u64, usize>,
+    hasher: &impl PositionHasher<B>,
+    curr_node: usize,
+    curr_board: &B,
+) -> Option<IdxRange> {
+    let hash = hasher.hash(curr_board);
+    let existing = transpositions.get(&hash).copied();
 
-    loop {
-        values = values.parent();
+    match existing {
+        Some(existing) if tree[existing].net_values.is_some() => {
+            let children = tree[existing].children
+                .expect("a node with net_values has already been expanded");
 
-        let curr_node = &mut tree[curr_index];
-        assert!(curr_node.virtual_visits > 0);
+            for child in children {
+                tree[child].parents.push(curr_node);
+            }
+            tree[curr_node].children = Some(children);
+            tree[curr_node].net_values = tree[existing].net_values;
 
-        curr_node.complete_visits += 1;
-        curr_node.virtual_visits -= 1;
-        curr_node.sum_values += values;
+            Some(children)
+        }
+        _ => {
+            transpositions.entry(hash).or_insert(curr_node);
+            None
+        }
+    }
+}
 
-        curr_index = match curr_node.parent {
-            Some(parent) => parent,
-            None => break,
-        };
+/// Propagate the given `values` up to the root, following every incoming edge of each node reached.
+///
+/// With transpositions enabled a node can have more than one parent, and can even turn a repeated
+/// position into a cycle through `parents`; `visited` guards against backing up into the same node
+/// twice within one call. Every distinct parent edge still receives the same backed-up `values`
+/// unscaled (not divided by the number of parents), matching how a real move played through either
+/// edge would be credited.
+fn tree_propagate_values<B: Board>(tree: &mut Tree<B>, node: usize, values: ZeroValues) {
+    let mut visited = HashSet::new();
+    tree_propagate_values_to(tree, node, values, &mut visited);
+}
+
+fn tree_propagate_values_to<B: Board>(
+    tree: &mut Tree<B>,
+    node: usize,
+    values: ZeroValues,
+    visited: &mut HashSet<usize>,
+) {
+    if !visited.insert(node) {
+        return;
     }
+
+    let values = values.parent();
+
+    let curr_node = &mut tree[node];
+    assert!(curr_node.virtual_visits > 0);
+
+    curr_node.complete_visits += 1;
+    curr_node.virtual_visits -= 1;
+    curr_node.sum_values += values;
+
+    let parents = curr_node.parents.clone();
+    for parent in parents {
+        tree_propagate_values_to(tree, parent, values, visited);
+    }
+}
+
+/// Recomputes `node`'s proven value from its children and, if it changed, keeps propagating to every
+/// parent, following every incoming edge exactly like [`tree_propagate_values`]. A parent becomes a
+/// proven win the moment *any* child is a proven loss for that child's own mover (no need to wait on
+/// the others: one refutation is enough); it only becomes a proven loss or draw once *every* child is
+/// proven, since an unproven child might still turn out to be the win that saves it.
+fn tree_propagate_proven<B: Board>(tree: &mut Tree<B>, node: usize) {
+    let mut visited = HashSet::new();
+    tree_propagate_proven_to(tree, node, &mut visited);
+}
+
+fn tree_propagate_proven_to<B: Board>(tree: &mut Tree<B>, node: usize, visited: &mut HashSet<usize>) {
+    if !visited.insert(node) {
+        return;
+    }
+
+    let children = match tree[node].children {
+        Some(children) => children,
+        // a childless node only gets a proven value directly, set at the call site; pass it on upward
+        None => {
+            let parents = tree[node].parents.clone();
+            for parent in parents {
+                tree_propagate_proven_to(tree, parent, visited);
+            }
+            return;
+        }
+    };
+
+    let mut child_values = vec![];
+    for child in children {
+        match tree[child].proven {
+            Some(proven) => child_values.push(proven.flip()),
+            None => {}
+        }
+    }
+
+    let forced_win = child_values.iter().copied()
+        .filter(|value| matches!(value, ProvenValue::Win(_)))
+        .min_by_key(|value| value.distance());
+
+    let new_proven = match forced_win {
+        Some(win) => Some(win),
+        None if child_values.len() == children.length as usize => {
+            // every child is proven and none of them is a win for us: we're either drawn or lost,
+            // whichever every child agrees we are
+            if child_values.iter().any(|value| matches!(value, ProvenValue::Draw(_))) {
+                child_values.into_iter().filter(|value| matches!(value, ProvenValue::Draw(_))).min_by_key(|value| value.distance())
+            } else {
+                // a forced loss: stall as long as possible by picking the slowest one
+                child_values.into_iter().max_by_key(|value| value.distance())
+            }
+        }
+        None => None,
+    };
+
+    if new_proven.is_some() && new_proven != tree[node].proven {
+        tree[node].proven = new_proven;
+
+        let parents = tree[node].parents.clone();
+        for parent in parents {
+            tree_propagate_proven_to(tree, parent, visited);
+        }
+    }
+}
+
+/// A plain bounded exact search directly on `B` (no alpha-beta pruning, just depth-limited full
+/// enumeration), used by [`zero_step_gather`] to seed [`ProvenValue`]s near the end of the game without
+/// waiting for MCTS to expand every remaining line one ply at a time. Returns `None` if `depth` plies
+/// isn't enough to resolve every branch down to a terminal outcome.
+fn solve_bounded_minimax<B: Board>(board: &B, oracle: &impl Oracle<B>, depth: u32) -> Option<ProvenValue> {
+    if let Some(outcome) = oracle.best_outcome(board) {
+        return Some(ProvenValue::from_outcome(outcome.pov(board.next_player())));
+    }
+    if depth == 0 {
+        return None;
+    }
+
+    let mut child_values = vec![];
+    let mut fully_resolved = true;
+
+    board.available_moves().for_each(|mv| {
+        let mut child_board = board.clone();
+        child_board.play(mv);
+
+        match solve_bounded_minimax(&child_board, oracle, depth - 1) {
+            Some(value) => child_values.push(value.flip()),
+            None => fully_resolved = false,
+        }
+    });
+
+    if !fully_resolved {
+        return None;
+    }
+
+    let forced_win = child_values.iter().copied()
+        .filter(|value| matches!(value, ProvenValue::Win(_)))
+        .min_by_key(|value| value.distance());
+
+    match forced_win {
+        Some(win) => Some(win),
+        None if child_values.iter().any(|value| matches!(value, ProvenValue::Draw(_))) => {
+            child_values.into_iter().filter(|value| matches!(value, ProvenValue::Draw(_))).min_by_key(|value| value.distance())
+        }
+        None => child_values.into_iter().max_by_key(|value| value.distance()),
+    }
+}
+
+/// Like [`Tree::best_move`], but lets a proven result override raw visit counts: if any root child is a
+/// proven win for the side to move, the shortest such win is played immediately, since no amount of
+/// further search can beat an already-forced win. Without one, falls back to the usual most-visited
+/// child, so an unsolved position behaves exactly as before.
+pub fn best_move_solved<B: Board>(tree: &Tree<B>) -> B::Move {
+    let children = tree[0].children.expect("Root node must have children");
+
+    let forced_win = children.iter()
+        .filter_map(|child| tree[child].proven.map(|proven| (child, proven.flip())))
+        .filter(|&(_, proven)| matches!(proven, ProvenValue::Win(_)))
+        .min_by_key(|&(_, proven)| proven.distance());
+
+    let best_child = match forced_win {
+        Some((child, _)) => child,
+        None => children.iter().rev().max_by_key(|&child| tree[child].complete_visits)
+            .expect("Root node must have non-empty children"),
+    };
+
+    tree[best_child].last_move.unwrap()
 }
 
 impl FpuMode {
@@ -0,0 +1,333 @@
+//! A batched, multi-worker counterpart to [`crate::zero::step`]'s sequential
+//! `zero_step_gather`/`zero_step_apply` loop, gathering several leaves concurrently so one network
+//! call can evaluate a whole batch instead of a single board at a time. Mirrors the relationship
+//! between [`crate::non_solve_zero::ZeroBot`] and [`crate::zero_parallel::ParZeroBot`]. As in
+//! [`crate::zero_parallel::ParNode`], the per-node visit counters are atomics, since
+//! [`Node`][crate::zero::node::Node]'s `virtual_visits` and `complete_visits` are plain `u64`
+//! counters well-suited to lock-free updates. `ZeroValues`
+//! doesn't have a known fixed-width bit pattern the way a plain `WDL<f32>` does, so `sum_values`
+//! falls back to a mutex instead of a compare-and-swap loop, the same allowance the lock-free
+//! design makes for accumulators that can't support native atomics.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use board_game::board::Board;
+use board_game::wdl::{Flip, OutcomeWDL, POV};
+use crossbeam::channel::bounded;
+use decorum::N32;
+use internal_iterator::InternalIterator;
+use itertools::zip_eq;
+
+use crate::network::{Network, ZeroEvaluation};
+use crate::oracle::Oracle;
+use crate::zero::node::{Node, ZeroValues};
+use crate::zero::range::IdxRange;
+use crate::zero::step::FpuMode;
+use crate::zero::tree::Tree;
+
+/// Settings for [`zero_build_tree_batched`]. Unlike the sequential loop, which issues exactly one
+/// [`crate::zero::step::ZeroRequest`] before evaluating it, `leaves_per_batch` workers each gather
+/// a leaf before the whole batch is handed to the network in one forward pass, and
+/// `max_outstanding_batches` caps how many such batches may be queued for evaluation at once.
+#[derive(Debug, Copy, Clone)]
+pub struct BatchedZeroSettings {
+    pub worker_threads: usize,
+    pub leaves_per_batch: usize,
+    pub max_outstanding_batches: usize,
+    pub exploration_weight: f32,
+    pub use_value: bool,
+    pub fpu_mode: FpuMode,
+}
+
+/// A node in a [`BatchTree`]. `virtual_visits` and `complete_visits` are lock-free atomics updated
+/// by every worker descending through this node. `sum_values` falls back to a short-lived mutex
+/// instead of a true compare-and-swap loop: unlike the plain `WDL<f32>` that
+/// [`crate::zero_parallel::AtomicWdl`] bit-casts into a trio of `AtomicU32`s, `ZeroValues` isn't a
+/// fixed, known-width POD here, so there's no sound bit pattern to compare-and-swap on. `children`,
+/// `net_values` and `net_policy` are each behind their own mutex since they're only ever written
+/// once, by whichever worker wins `expansion_claimed`.
+struct BatchNode<M> {
+    parents: Mutex<Vec<usize>>,
+    last_move: Option<M>,
+    /// Gates expansion with a single atomic compare-and-swap: the worker whose `compare_exchange`
+    /// flips this from `false` to `true` is the one that allocates this node's children and issues
+    /// the `ZeroRequest`; every other worker that reaches this node first spins on `children`
+    /// instead of expanding it a second time.
+    expansion_claimed: AtomicBool,
+    children: Mutex<Option<IdxRange>>,
+    net_values: Mutex<Option<ZeroValues>>,
+    net_policy: Mutex<f32>,
+    virtual_visits: AtomicU64,
+    complete_visits: AtomicU64,
+    sum_values: Mutex<ZeroValues>,
+}
+
+impl<M> BatchNode<M> {
+    fn new(parent: usize, last_move: Option<M>, p: f32) -> Self {
+        BatchNode {
+            parents: Mutex::new(vec![parent]),
+            last_move,
+            expansion_claimed: AtomicBool::new(false),
+            children: Mutex::new(None),
+            net_values: Mutex::new(None),
+            net_policy: Mutex::new(p),
+            virtual_visits: AtomicU64::new(0),
+            complete_visits: AtomicU64::new(0),
+            sum_values: Mutex::new(ZeroValues::default()),
+        }
+    }
+
+    fn total_visits(&self) -> u64 {
+        self.virtual_visits.load(Ordering::Relaxed) + self.complete_visits.load(Ordering::Relaxed)
+    }
+
+    fn values(&self) -> ZeroValues {
+        let complete_visits = self.complete_visits.load(Ordering::Relaxed);
+        if complete_visits == 0 {
+            ZeroValues::default()
+        } else {
+            *self.sum_values.lock().unwrap() / complete_visits as f32
+        }
+    }
+}
+
+/// The result of one worker descending a [`BatchTree`] to a leaf: either the game was already
+/// decided along the way (backed up immediately), or a genuinely new position was reached and
+/// still needs a real network evaluation.
+enum Leaf<B: Board> {
+    Terminal { node: usize },
+    Expand { node: usize, board: B },
+}
+
+/// A search tree shared (via `&BatchTree`) across worker threads by [`zero_build_tree_batched`].
+/// Growing the node arena takes a short-lived write lock; the read-locked selection steps that
+/// dominate the search never block each other.
+pub struct BatchTree<B: Board> {
+    root_board: B,
+    nodes: RwLock<Vec<Box<BatchNode<B::Move>>>>,
+}
+
+impl<B: Board> BatchTree<B> {
+    pub fn new(root_board: B) -> Self {
+        let root = BatchNode::new(0, None, f32::NAN);
+        BatchTree { root_board, nodes: RwLock::new(vec![Box::new(root)]) }
+    }
+
+    pub fn root_visits(&self) -> u64 {
+        self.node(0).complete_visits.load(Ordering::Relaxed)
+    }
+
+    /// A stable reference to node `index`. Safe because nodes live behind a `Box` inside the `Vec`:
+    /// appending further nodes can relocate the `Vec`'s storage of `Box` pointers, but never the
+    /// heap allocation a `Box` points to, and nodes are never removed once pushed. So the returned
+    /// reference stays valid for as long as `self` does, even past the read lock taken to fetch it.
+    fn node(&self, index: usize) -> &BatchNode<B::Move> {
+        let nodes = self.nodes.read().unwrap();
+        let ptr: *const BatchNode<B::Move> = &*nodes[index];
+        unsafe { &*ptr }
+    }
+
+    fn push_children(&self, parent: usize, board: &B) -> IdxRange {
+        let mut nodes = self.nodes.write().unwrap();
+        let start = nodes.len();
+        board.available_moves().for_each(|mv| {
+            nodes.push(Box::new(BatchNode::new(parent, Some(mv), 1.0)));
+        });
+        let end = nodes.len();
+
+        IdxRange::new(start, end)
+    }
+
+    /// Back up `values` to the root along every recorded parent edge, counting a completed visit
+    /// and releasing the virtual loss added when the leaf was claimed. Walks every entry of
+    /// `parents` and guards against revisiting a node twice within the same call, like the
+    /// sequential `tree_propagate_values`; `push_children` only ever records a single parent.
+    fn propagate_values(&self, node: usize, values: ZeroValues) {
+        let mut visited = std::collections::HashSet::new();
+        self.propagate_values_to(node, values, &mut visited);
+    }
+
+    fn propagate_values_to(&self, node: usize, values: ZeroValues, visited: &mut std::collections::HashSet<usize>) {
+        if !visited.insert(node) {
+            return;
+        }
+
+        let values = values.parent();
+        let curr = self.node(node);
+
+        curr.virtual_visits.fetch_sub(1, Ordering::Relaxed);
+        curr.complete_visits.fetch_add(1, Ordering::Relaxed);
+        *curr.sum_values.lock().unwrap() += values;
+
+        let parents = curr.parents.lock().unwrap().clone();
+        for parent in parents {
+            self.propagate_values_to(parent, values, visited);
+        }
+    }
+
+    /// Select down from the root, applying virtual loss to every node visited along the way so
+    /// concurrently running workers diverge onto other children instead of racing for the same
+    /// path. Returns either a terminal position (already backed up by this function) or a leaf
+    /// that still needs a real network evaluation.
+    fn select_leaf(&self, oracle: &impl Oracle<B>, settings: &BatchedZeroSettings) -> Leaf<B> {
+        let mut curr_node = 0;
+        let mut curr_board = self.root_board.clone();
+        let mut fpu = ZeroValues::from_outcome(OutcomeWDL::Draw);
+
+        loop {
+            self.node(curr_node).virtual_visits.fetch_add(1, Ordering::Relaxed);
+
+            if let Some(outcome) = oracle.best_outcome(&curr_board) {
+                let outcome = outcome.pov(curr_board.next_player());
+                self.propagate_values(curr_node, ZeroValues::from_outcome(outcome));
+                return Leaf::Terminal { node: curr_node };
+            }
+
+            let node = self.node(curr_node);
+            let children = *node.children.lock().unwrap();
+
+            let children = match children {
+                Some(children) => children,
+                None => {
+                    if node.expansion_claimed.compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+                        let range = self.push_children(curr_node, &curr_board);
+                        *node.children.lock().unwrap() = Some(range);
+                        return Leaf::Expand { node: curr_node, board: curr_board };
+                    }
+
+                    // another worker is already expanding this node; spin until its children appear
+                    loop {
+                        if let Some(children) = *node.children.lock().unwrap() {
+                            break children;
+                        }
+                        std::hint::spin_loop();
+                    }
+                }
+            };
+
+            if node.complete_visits.load(Ordering::Relaxed) > 0 {
+                fpu = node.values();
+            }
+            fpu = fpu.flip();
+
+            let parent_total_visits = node.total_visits();
+            let selected = children.iter().max_by_key(|&child| {
+                let child_node = self.node(child);
+                N32::from_inner(uct(child_node, parent_total_visits, settings.fpu_mode.select(fpu), settings.use_value, settings.exploration_weight))
+            }).expect("Board is not done, this node should have a child");
+
+            curr_node = selected;
+            curr_board.play(self.node(curr_node).last_move.clone().unwrap());
+        }
+    }
+
+    /// Apply a network evaluation to a previously claimed [`Leaf::Expand`], releasing the virtual
+    /// loss added when it was claimed and seeding the policy of every child it just expanded.
+    fn apply_eval(&self, node: usize, board: &B, eval: ZeroEvaluation) {
+        let node_ref = self.node(node);
+        *node_ref.net_values.lock().unwrap() = Some(eval.values);
+
+        let children = node_ref.children.lock().unwrap().expect("node must already be expanded when its eval arrives");
+        assert_eq!(children.length as usize, eval.policy.len(), "Wrong children length");
+        for (c, &p) in zip_eq(children, eval.policy.as_ref()) {
+            *self.node(c).net_policy.lock().unwrap() = p;
+        }
+
+        self.propagate_values(node, eval.values);
+    }
+
+    /// Consume the batched tree into the regular, single-threaded [`Tree`], so the result of a
+    /// batched search can be used with `best_move`, `policy`, `display`, etc. like any other tree.
+    fn into_tree(self) -> Tree<B> {
+        let nodes = self.nodes.into_inner().unwrap();
+
+        let nodes = nodes.into_iter().map(|node| Node {
+            parents: node.parents.into_inner().unwrap(),
+            last_move: node.last_move,
+            children: node.children.into_inner().unwrap(),
+            net_values: node.net_values.into_inner().unwrap(),
+            net_policy: node.net_policy.into_inner().unwrap(),
+            virtual_visits: node.virtual_visits.into_inner(),
+            complete_visits: node.complete_visits.into_inner(),
+            sum_values: node.sum_values.into_inner().unwrap(),
+        }).collect();
+
+        Tree::from_parts(self.root_board, nodes)
+    }
+}
+
+fn uct<M>(node: &BatchNode<M>, parent_total_visits: u64, fpu: ZeroValues, use_value: bool, exploration_weight: f32) -> f32 {
+    let complete_visits = node.complete_visits.load(Ordering::Relaxed);
+    let net_policy = *node.net_policy.lock().unwrap();
+
+    let q = if complete_visits == 0 { fpu } else { node.values() };
+    let q = if use_value { q.value } else { q.wdl.value() };
+
+    // count in-flight (virtual) visits too, so a child another worker is already descending through scores lower
+    let u = exploration_weight * net_policy * (parent_total_visits as f32).sqrt() / (1 + node.total_visits()) as f32;
+    q + u
+}
+
+/// Run a batched search that shares one [`BatchTree`] across `settings.worker_threads` selection
+/// threads and one evaluator thread. Each worker selects down to a leaf, applies virtual loss so
+/// concurrently running workers diverge instead of piling onto the same leaf, and once it has
+/// gathered `settings.leaves_per_batch` leaves hands them to the evaluator over a channel bounded
+/// to `settings.max_outstanding_batches` in-flight batches, capping memory use and queue depth
+/// regardless of worker count. Backup happens as each batch's evaluation returns, so later
+/// iterations can already build on earlier ones from other workers.
+pub fn zero_build_tree_batched<B: Board, N: Network<B> + Send>(
+    board: &B,
+    target_iterations: u64,
+    settings: BatchedZeroSettings,
+    oracle: &(impl Oracle<B> + Sync),
+    mut network: N,
+) -> Tree<B> {
+    assert!(settings.worker_threads > 0, "need at least one worker thread");
+    assert!(settings.leaves_per_batch > 0, "need at least one leaf per batch");
+
+    let tree = BatchTree::new(board.clone());
+    let (job_tx, job_rx) = bounded::<(Vec<B>, crossbeam::channel::Sender<Vec<ZeroEvaluation>>)>(settings.max_outstanding_batches);
+
+    crossbeam::scope(|s| {
+        s.builder().name("zero-batch-eval".to_owned()).spawn(|_| {
+            for (boards, reply) in job_rx {
+                let evals = network.evaluate_batch(&boards);
+                reply.send(evals).ok();
+            }
+        }).unwrap();
+
+        for w in 0..settings.worker_threads {
+            let job_tx = job_tx.clone();
+            let tree = &tree;
+
+            s.builder().name(format!("zero-batch-worker-{}", w)).spawn(move |_| {
+                while tree.root_visits() < target_iterations {
+                    let mut leaves = vec![];
+
+                    while leaves.len() < settings.leaves_per_batch && tree.root_visits() < target_iterations {
+                        match tree.select_leaf(oracle, &settings) {
+                            Leaf::Terminal { .. } => {}
+                            Leaf::Expand { node, board } => leaves.push((node, board)),
+                        }
+                    }
+
+                    if leaves.is_empty() { continue; }
+
+                    let boards = leaves.iter().map(|(_, board)| board.clone()).collect();
+                    let (reply_tx, reply_rx) = bounded(1);
+                    job_tx.send((boards, reply_tx)).unwrap();
+
+                    let evals = reply_rx.recv().expect("evaluator thread shut down unexpectedly");
+                    for ((node, board), eval) in zip_eq(leaves, evals) {
+                        tree.apply_eval(node, &board, eval);
+                    }
+                }
+            }).unwrap();
+        }
+
+        drop(job_tx);
+    }).unwrap();
+
+    tree.into_tree()
+}
@@ -1,3 +1,5 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{Debug, Formatter};
 use std::marker::PhantomData;
 
@@ -5,9 +7,11 @@ use board_game::ai::Bot;
 use board_game::board::Board;
 use itertools::Itertools;
 
-use crate::network::Network;
+use crate::network::{Network, ZeroEvaluation};
+use crate::non_solve_zero::PositionHasher;
+use crate::oracle::Oracle;
 use crate::util::zip_eq_exact;
-use crate::zero::step::{zero_step_apply, zero_step_gather};
+use crate::zero::step::{zero_step_apply, zero_step_gather, FpuMode, ZeroRequest};
 use crate::zero::tree::Tree;
 
 #[derive(Debug, Copy, Clone)]
@@ -70,6 +74,129 @@ impl ZeroSettings {
     }
 }
 
+/// Tags one in-flight [`NetworkClient::submit_batch`] call so a later `poll` can match its evaluations
+/// back to the boards that were submitted. Batches don't need to finish in submission order.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct BatchId(pub u64);
+
+/// The non-blocking counterpart to [`Network`]: `submit_batch` hands a batch off for evaluation
+/// without waiting for it, and `poll` checks whether a previously submitted batch has come back yet.
+/// This is what lets [`PipelinedZeroSettings::expand_tree`] keep several batches in flight at once, so
+/// CPU tree selection for the next batch overlaps GPU inference on the ones before it, instead of
+/// [`ZeroSettings::expand_tree`]'s strict gather-block-apply-repeat alternation.
+pub trait NetworkClient<B: Board> {
+    fn submit_batch(&mut self, boards: &[impl Borrow<B>]) -> BatchId;
+
+    /// `None` if the batch tagged `id` hasn't finished yet. Each `id` is only ever polled to
+    /// completion once.
+    fn poll(&mut self, id: BatchId) -> Option<Vec<ZeroEvaluation<'static>>>;
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PipelinedZeroSettings {
+    pub batch_size: usize,
+    /// How many batches may be submitted to the [`NetworkClient`] before any of them has to come back,
+    /// the knob that controls how far CPU selection can run ahead of GPU inference.
+    pub max_outstanding_batches: usize,
+    pub exploration_weight: f32,
+    pub use_value: bool,
+    pub fpu_mode: FpuMode,
+    /// Forwarded to [`zero_step_gather`]'s bounded-minimax solver seed; `0` disables it.
+    pub solver_depth: u32,
+}
+
+impl PipelinedZeroSettings {
+    pub fn new(
+        batch_size: usize,
+        max_outstanding_batches: usize,
+        exploration_weight: f32,
+        use_value: bool,
+        fpu_mode: FpuMode,
+        solver_depth: u32,
+    ) -> Self {
+        assert!(max_outstanding_batches > 0, "need at least one outstanding batch");
+        PipelinedZeroSettings { batch_size, max_outstanding_batches, exploration_weight, use_value, fpu_mode, solver_depth }
+    }
+
+    /// Like [`ZeroSettings::expand_tree`], but overlaps tree traversal with evaluation: instead of
+    /// gathering one full batch, blocking on `network.evaluate_batch`, and repeating, it keeps up to
+    /// `max_outstanding_batches` batches in flight through `client` at once, applying whichever comes
+    /// back first. `zero_step_gather`'s existing `virtual_visits` bookkeeping already acts as the
+    /// virtual loss that keeps these concurrently-outstanding paths from all piling onto the same leaf.
+    ///
+    /// Whenever `zero_step_gather` returns `None` before a batch fills up (its descent ended on a
+    /// terminal or proven node and backed that value up instead, which is all it can do once every
+    /// leaf is solved), whatever was gathered so far is submitted as a short batch right away
+    /// instead of spinning on `zero_step_gather` forever waiting for a full one.
+    pub fn expand_tree<B: Board>(
+        self,
+        tree: &mut Tree<B>,
+        oracle: &impl Oracle<B>,
+        hasher: &impl PositionHasher<B>,
+        mut transpositions: Option<&mut HashMap<u64, usize>>,
+        client: &mut impl NetworkClient<B>,
+        mut stop: impl FnMut(&Tree<B>) -> bool,
+    ) {
+        let mut in_flight: VecDeque<(BatchId, Vec<ZeroRequest<B>>)> = VecDeque::new();
+
+        loop {
+            // keep filling the pipeline until we've been told to stop or it's saturated
+            while in_flight.len() < self.max_outstanding_batches && !stop(tree) {
+                let mut requests = vec![];
+
+                while requests.len() < self.batch_size {
+                    let request = zero_step_gather(
+                        tree,
+                        oracle,
+                        hasher,
+                        transpositions.as_deref_mut(),
+                        self.exploration_weight,
+                        self.use_value,
+                        self.fpu_mode,
+                        self.solver_depth,
+                    );
+
+                    match request {
+                        Some(request) => requests.push(request),
+                        // the descent hit a terminal or proven node (always the case once every leaf
+                        // is solved): submit whatever we have rather than chasing a full batch
+                        None => break,
+                    }
+                }
+
+                if requests.is_empty() {
+                    // nothing to submit; with nothing in flight either, gather again after re-checking
+                    // `stop` (every `None` already backed up a visit) instead of returning early
+                    if in_flight.is_empty() {
+                        continue;
+                    }
+                    break;
+                }
+
+                let boards = requests.iter().map(|r| &r.board).collect_vec();
+                let id = client.submit_batch(&boards);
+                in_flight.push_back((id, requests));
+            }
+
+            if in_flight.is_empty() {
+                break;
+            }
+
+            // poll the oldest outstanding batch; if it isn't ready yet, cycle it to the back instead of
+            // blocking, so a batch that finishes out of order doesn't stall the ones behind it
+            let (id, requests) = in_flight.pop_front().unwrap();
+            match client.poll(id) {
+                Some(evals) => {
+                    for (request, eval) in zip_eq_exact(requests, evals) {
+                        zero_step_apply(tree, request.respond(eval));
+                    }
+                }
+                None => in_flight.push_back((id, requests)),
+            }
+        }
+    }
+}
+
 pub struct ZeroBot<B: Board, N: Network<B>> {
     network: N,
     settings: ZeroSettings,
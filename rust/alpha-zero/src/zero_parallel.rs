@@ -0,0 +1,399 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use crossbeam::channel::bounded;
+use decorum::N32;
+use itertools::{zip_eq, Itertools};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use board_game::ai::Bot;
+use board_game::board::Board;
+use board_game::symmetry::{Symmetry, SymmetryDistribution};
+use board_game::wdl::{Flip, POV, WDL};
+use rand_distr::Distribution;
+
+use crate::network::{Network, ZeroEvaluation};
+use crate::non_solve_zero::{for_each_original_move_and_policy, Heuristic, IdxRange, Node, Tree, UctWeights, ZeroHeuristic, VIRTUAL_WDL};
+
+/// Settings for [`zero_build_tree_parallel`]. Unlike the single-threaded [`crate::non_solve_zero::ZeroSettings`]
+/// there is no single `batch_size` to gather before calling the network: every worker thread gathers
+/// its own `leaves_per_batch` leaves and hands them to a shared pool of evaluator threads (one per
+/// element of the `networks` passed to [`zero_build_tree_parallel`]), and `max_outstanding_batches`
+/// caps how many of those batches may be in flight at once across the whole pool, so memory use and
+/// GPU queue depth stay bounded no matter how many workers are running.
+#[derive(Debug, Copy, Clone)]
+pub struct ParZeroSettings {
+    pub leaves_per_batch: usize,
+    pub max_outstanding_batches: usize,
+    pub uct_weights: UctWeights,
+    pub random_symmetries: bool,
+}
+
+impl ParZeroSettings {
+    pub fn new(leaves_per_batch: usize, max_outstanding_batches: usize, uct_weights: UctWeights, random_symmetries: bool) -> Self {
+        ParZeroSettings { leaves_per_batch, max_outstanding_batches, uct_weights, random_symmetries }
+    }
+}
+
+/// A lock-free accumulator for a `WDL<f32>` sum, since there is no `AtomicF32` in `std`. Each
+/// component is stored as the bit pattern of its `f32` value and updated with a compare-and-swap
+/// retry loop, the same trick used to implement atomic float addition elsewhere without a lock.
+#[derive(Debug, Default)]
+struct AtomicWdl {
+    win: AtomicU32,
+    draw: AtomicU32,
+    loss: AtomicU32,
+}
+
+impl AtomicWdl {
+    fn fetch_add(&self, delta: WDL<f32>) {
+        Self::add_component(&self.win, delta.win);
+        Self::add_component(&self.draw, delta.draw);
+        Self::add_component(&self.loss, delta.loss);
+    }
+
+    fn add_component(slot: &AtomicU32, delta: f32) {
+        let mut curr = slot.load(Ordering::Relaxed);
+        loop {
+            let next = (f32::from_bits(curr) + delta).to_bits();
+            match slot.compare_exchange_weak(curr, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(observed) => curr = observed,
+            }
+        }
+    }
+
+    fn load(&self) -> WDL<f32> {
+        WDL {
+            win: f32::from_bits(self.win.load(Ordering::Relaxed)),
+            draw: f32::from_bits(self.draw.load(Ordering::Relaxed)),
+            loss: f32::from_bits(self.loss.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// A node in a [`ParTree`]. Mirrors [`Node`], except the hot per-visit counters (`visits`,
+/// `total_wdl`) are atomics updated without any locking, while the structural fields (`children`,
+/// `net_wdl`, `net_policy`) are each behind a small mutex since they're only ever written once, by
+/// whichever worker thread wins the race to expand this node.
+#[derive(Debug)]
+struct ParNode<M> {
+    parent: usize,
+    last_move: Option<M>,
+    children: Mutex<Option<IdxRange>>,
+    net_wdl: Mutex<Option<WDL<f32>>>,
+    net_policy: Mutex<f32>,
+    visits: AtomicU64,
+    total_wdl: AtomicWdl,
+}
+
+impl<M> ParNode<M> {
+    fn new(parent: usize, last_move: Option<M>, p: f32) -> Self {
+        ParNode {
+            parent,
+            last_move,
+            children: Mutex::new(None),
+            net_wdl: Mutex::new(None),
+            net_policy: Mutex::new(p),
+            visits: AtomicU64::new(0),
+            total_wdl: AtomicWdl::default(),
+        }
+    }
+
+    fn uct(&self, weights: UctWeights, parent_visits: u64, parent_q: f32) -> f32 {
+        let visits = self.visits.load(Ordering::Relaxed);
+
+        let q = if visits == 0 {
+            parent_q - weights.fpu_reduction
+        } else {
+            (self.total_wdl.load() / visits as f32).value()
+        };
+
+        let net_policy = *self.net_policy.lock().unwrap();
+        let u = weights.c(parent_visits) * net_policy * (parent_visits as f32).sqrt() / (1 + visits) as f32;
+
+        q + u
+    }
+}
+
+/// The result of one worker stepping down a [`ParTree`] to a leaf ("unfold"): either the game was
+/// already decided along the way, or a genuinely new position was reached and still needs a real
+/// network evaluation before the search can continue past it.
+enum Leaf<B: Board> {
+    Terminal { node: usize, wdl: WDL<f32> },
+    Expand { node: usize, curr_board: B, sym: B::Symmetry },
+}
+
+/// A search tree that can be shared (via `&ParTree`) across worker threads, used by
+/// [`zero_build_tree_parallel`]. See [`ParNode`] for how individual nodes stay safe to mutate
+/// concurrently; growing the node arena itself takes a short-lived write lock, which is rare
+/// relative to the read-locked selection steps that dominate the search.
+pub struct ParTree<B: Board> {
+    root_board: B,
+    nodes: RwLock<Vec<Box<ParNode<B::Move>>>>,
+}
+
+impl<B: Board> ParTree<B> {
+    pub fn new(root_board: B) -> Self {
+        assert!(!root_board.is_done(), "Cannot build tree for done board");
+        let root = ParNode::new(0, None, f32::NAN);
+        ParTree { root_board, nodes: RwLock::new(vec![Box::new(root)]) }
+    }
+
+    pub fn root_visits(&self) -> u64 {
+        self.node(0).visits.load(Ordering::Relaxed)
+    }
+
+    /// Get a stable reference to node `index`. Safe because nodes live behind a `Box` inside the
+    /// `Vec`: appending further nodes can relocate the `Vec`'s storage of `Box` pointers, but never
+    /// the heap allocation a `Box` points to, and nodes are never removed once pushed. So the
+    /// returned reference stays valid for as long as `self` does, even past the read lock taken to
+    /// fetch it.
+    fn node(&self, index: usize) -> &ParNode<B::Move> {
+        let nodes = self.nodes.read().unwrap();
+        let ptr: *const ParNode<B::Move> = &*nodes[index];
+        unsafe { &*ptr }
+    }
+
+    fn push_children(&self, parent: usize, curr_board: &B, policy: &[f32]) -> IdxRange {
+        let mut nodes = self.nodes.write().unwrap();
+        let start = nodes.len();
+        curr_board.available_moves().zip_eq(policy.iter().copied()).for_each(|(mv, p)| {
+            nodes.push(Box::new(ParNode::new(parent, Some(mv), p)));
+        });
+        let end = nodes.len();
+
+        IdxRange::new(start, end)
+    }
+
+    /// Back up `wdl` (from the POV of the player to move at `node`) to the root, flipping POV once
+    /// per step and counting a visit along the way if requested. Mirrors `Tree::propagate_wdl`, but
+    /// since every node here has exactly one parent the walk is an iterative loop instead of a
+    /// recursion guarded against revisiting nodes.
+    fn propagate_wdl(&self, node: usize, wdl: WDL<f32>, count_visit: bool) {
+        let mut wdl = wdl;
+        let mut node = node;
+
+        loop {
+            wdl = wdl.flip();
+
+            let curr = self.node(node);
+            if count_visit {
+                curr.visits.fetch_add(1, Ordering::Relaxed);
+            }
+            curr.total_wdl.fetch_add(wdl);
+
+            if node == 0 { break; }
+            node = curr.parent;
+        }
+    }
+
+    /// Select down from the root, applying `VIRTUAL_WDL` as soon as a leaf is claimed for
+    /// expansion so concurrent workers diverge onto other children instead of racing for the same
+    /// leaf. Returns either a terminal position (backed up immediately by the caller) or a leaf
+    /// that still needs a real network evaluation.
+    fn select_leaf(&self, heuristic: &impl Heuristic<B>, weights: UctWeights, rng: &mut impl Rng, random_symmetries: bool) -> Leaf<B> {
+        let mut curr_node = 0;
+        let mut curr_board = self.root_board.clone();
+
+        loop {
+            if let Some(outcome) = curr_board.outcome() {
+                return Leaf::Terminal { node: curr_node, wdl: outcome.pov(curr_board.next_player()).to_wdl() };
+            }
+
+            let node = self.node(curr_node);
+            let mut children_guard = node.children.lock().unwrap();
+
+            let children = match *children_guard {
+                Some(children) => children,
+                None => {
+                    let heuristic_eval = heuristic.evaluate(&curr_board);
+                    assert_eq!(
+                        heuristic_eval.policy.len(), curr_board.available_moves().count(),
+                        "Heuristic policy length must match the number of available moves",
+                    );
+
+                    let range = self.push_children(curr_node, &curr_board, &heuristic_eval.policy);
+                    *children_guard = Some(range);
+                    *node.net_wdl.lock().unwrap() = Some(heuristic_eval.wdl);
+                    drop(children_guard);
+
+                    self.propagate_wdl(curr_node, VIRTUAL_WDL, true);
+
+                    let sym = if random_symmetries { SymmetryDistribution.sample(rng) } else { B::Symmetry::identity() };
+                    return Leaf::Expand { node: curr_node, curr_board, sym };
+                }
+            };
+            drop(children_guard);
+
+            // the parent's value is from the parent's own POV, so flip it to get the FPU baseline
+            // from the child's POV; mirrors `Tree::run_until_result_from_root`
+            let parent_visits = node.visits.load(Ordering::Relaxed);
+            let parent_wdl = if parent_visits == 0 { WDL::default() } else { node.total_wdl.load() / parent_visits as f32 };
+            let parent_q = parent_wdl.flip().value();
+
+            let selected = children.iter().max_by_key(|&c| {
+                N32::from(self.node(c).uct(weights, parent_visits, parent_q))
+            }).expect("Board is not done, this node should have a child");
+
+            curr_node = selected;
+            curr_board.play(self.node(curr_node).last_move.clone().unwrap());
+        }
+    }
+
+    /// Apply a network evaluation returned for a previously claimed [`Leaf::Expand`], subtracting
+    /// the virtual loss added when the leaf was claimed so it isn't double-counted.
+    fn apply_eval(&self, node: usize, curr_board: &B, sym: B::Symmetry, evaluation: ZeroEvaluation) {
+        let ZeroEvaluation { wdl, policy: sym_policy } = evaluation;
+        let node_ref = self.node(node);
+
+        *node_ref.net_wdl.lock().unwrap() = Some(wdl);
+
+        let children = node_ref.children.lock().unwrap().expect("node must already be expanded when its eval arrives");
+        for_each_original_move_and_policy(curr_board, sym, &sym_policy, |i, _, p| {
+            *self.node(children.get(i)).net_policy.lock().unwrap() = p;
+        });
+
+        self.propagate_wdl(node, wdl - VIRTUAL_WDL, false);
+    }
+
+    /// Consume the parallel tree into the regular, single-threaded [`Tree`], so the result of a
+    /// parallel search can be used with `best_move`, `policy`, `display`, etc. like any other tree.
+    fn into_tree(self) -> Tree<B> {
+        let nodes = self.nodes.into_inner().unwrap();
+
+        let nodes = nodes.into_iter().enumerate().map(|(i, node)| {
+            let parent = node.parent;
+            Node {
+                parents: if i == 0 { vec![] } else { vec![parent] },
+                last_move: node.last_move,
+                children: node.children.into_inner().unwrap(),
+                net_wdl: node.net_wdl.into_inner().unwrap(),
+                net_policy: node.net_policy.into_inner().unwrap(),
+                visits: node.visits.into_inner(),
+                total_wdl: node.total_wdl.load(),
+            }
+        }).collect();
+
+        Tree::from_parts(self.root_board, nodes)
+    }
+}
+
+/// A batch of leaves waiting on the same network evaluation call, passed from a worker thread to
+/// an evaluator thread over a bounded channel (see [`zero_build_tree_parallel`]).
+struct EvalJob<B: Board> {
+    boards: Vec<B>,
+    reply: crossbeam::channel::Sender<Vec<ZeroEvaluation>>,
+}
+
+/// Run a parallel tree search that shares one [`ParTree`] across `worker_threads` selection
+/// threads and a pool of evaluator threads, one per entry of `networks` (typically one
+/// `CudnnNetwork` per CUDA device). Each worker performs selection down to a leaf, applies `VIRTUAL_WDL` so
+/// concurrently running workers diverge instead of piling onto the same leaf, and once it has
+/// gathered `settings.leaves_per_batch` leaves hands them to the evaluator pool over a channel
+/// bounded to `settings.max_outstanding_batches` in-flight batches, which keeps memory use and GPU
+/// queue depth capped regardless of how many workers are running. Backup happens as each batch's
+/// evaluation returns, so later iterations can already build on earlier ones from other workers.
+pub fn zero_build_tree_parallel<B: Board, H: Heuristic<B> + Sync, N: Network<B> + Send>(
+    board: &B,
+    target_iterations: u64,
+    settings: ParZeroSettings,
+    heuristic: H,
+    networks: Vec<N>,
+    worker_threads: usize,
+) -> Tree<B> {
+    assert!(!networks.is_empty(), "need at least one network executor");
+    assert!(worker_threads > 0, "need at least one worker thread");
+
+    let tree = ParTree::new(board.clone());
+    let (job_tx, job_rx) = bounded::<EvalJob<B>>(settings.max_outstanding_batches);
+
+    crossbeam::scope(|s| {
+        for (i, mut network) in networks.into_iter().enumerate() {
+            let job_rx = job_rx.clone();
+            s.builder().name(format!("par-zero-eval-{}", i)).spawn(move |_| {
+                for job in job_rx {
+                    let evals = network.evaluate_batch(&job.boards);
+                    job.reply.send(evals).ok();
+                }
+            }).unwrap();
+        }
+        drop(job_rx);
+
+        for w in 0..worker_threads {
+            let job_tx = job_tx.clone();
+            let tree = &tree;
+            let heuristic = &heuristic;
+
+            s.builder().name(format!("par-zero-worker-{}", w)).spawn(move |_| {
+                let mut rng = SmallRng::from_entropy();
+
+                while tree.root_visits() < target_iterations {
+                    let mut leaves = vec![];
+
+                    while leaves.len() < settings.leaves_per_batch && tree.root_visits() < target_iterations {
+                        match tree.select_leaf(heuristic, settings.uct_weights, &mut rng, settings.random_symmetries) {
+                            Leaf::Terminal { node, wdl } => tree.propagate_wdl(node, wdl, true),
+                            Leaf::Expand { node, curr_board, sym } => leaves.push((node, curr_board, sym)),
+                        }
+                    }
+
+                    if leaves.is_empty() { continue; }
+
+                    let boards = leaves.iter().map(|(_, curr_board, sym)| curr_board.map(*sym)).collect_vec();
+                    let (reply_tx, reply_rx) = bounded(1);
+                    job_tx.send(EvalJob { boards, reply: reply_tx }).unwrap();
+
+                    let evals = reply_rx.recv().expect("evaluator pool shut down unexpectedly");
+                    for ((node, curr_board, sym), eval) in zip_eq(leaves, evals) {
+                        tree.apply_eval(node, &curr_board, sym, eval);
+                    }
+                }
+            }).unwrap();
+        }
+
+        drop(job_tx);
+    }).unwrap();
+
+    tree.into_tree()
+}
+
+/// A bot backed by [`zero_build_tree_parallel`], for saturating several evaluator devices at once
+/// instead of the single in-flight batch `ZeroBot` is limited to.
+pub struct ParZeroBot<B: Board, N: Network<B>> {
+    target_iterations: u64,
+    settings: ParZeroSettings,
+    heuristic: ZeroHeuristic,
+    networks: Vec<N>,
+    worker_threads: usize,
+    _board: std::marker::PhantomData<B>,
+}
+
+impl<B: Board, N: Network<B> + Send> ParZeroBot<B, N> {
+    pub fn new(target_iterations: u64, settings: ParZeroSettings, networks: Vec<N>, worker_threads: usize) -> Self {
+        ParZeroBot {
+            target_iterations,
+            settings,
+            heuristic: ZeroHeuristic,
+            networks,
+            worker_threads,
+            _board: std::marker::PhantomData,
+        }
+    }
+
+    pub fn build_tree(&mut self, board: &B) -> Tree<B> {
+        zero_build_tree_parallel(
+            board, self.target_iterations, self.settings, self.heuristic,
+            std::mem::take(&mut self.networks), self.worker_threads,
+        )
+    }
+}
+
+impl<B: Board, N: Network<B> + Send> Bot<B> for ParZeroBot<B, N> {
+    fn select_move(&mut self, board: &B) -> B::Move {
+        // `build_tree` drains `self.networks`, so a `ParZeroBot` is single-use; that's fine for the
+        // benchmark/analysis call sites this is intended for, which build one bot per search.
+        self.build_tree(board).best_move()
+    }
+}
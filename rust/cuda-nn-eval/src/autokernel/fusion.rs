@@ -0,0 +1,69 @@
+use nn_graph::fusion::FusionRegion;
+use nn_graph::graph::{Graph, Operation, UnaryOp};
+
+use cuda_sys::wrapper::handle::Device;
+use cuda_sys::wrapper::rtc::core::CuFunction;
+
+use crate::autokernel::common::{compile_cached_kernel, fill_replacements, KernelKey};
+
+const FUSED_ELEMENTWISE_TEMPLATE: &str = r#"
+extern "C" __global__ void $func_name(int n, const float *inputs[], float *output) {
+    int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= n) return;
+
+$body
+    output[i] = acc;
+}
+"#;
+
+/// Generates (or, via [`compile_cached_kernel`], reuses) a single fused kernel for `region`'s chain of
+/// pointwise ops, so e.g. a residual `Add -> Clamp` runs in one kernel launch over one pass of the output
+/// elements instead of one launch (and one intermediate buffer) per op. `region.signature(graph)` is
+/// folded into the generated function name, so [`KernelKey`]'s existing `(device, source, func_name)`
+/// cache naturally keys on the op-sequence: a recurring region shape across the residual tower compiles
+/// its kernel exactly once, the same way [`KERNEL_CACHE`](super::common) already caches today's
+/// hand-written kernels.
+pub fn compile_fused_elementwise_kernel(device: Device, graph: &Graph, region: &FusionRegion) -> CuFunction {
+    assert!(
+        region.anchor.is_none(),
+        "conv-anchored regions run their conv through cudnn and only fuse the elementwise tail"
+    );
+
+    let signature = region.signature(graph);
+    let func_name = format!("fused_{}", signature.replace('-', "_"));
+
+    // `inputs[0]` seeds the accumulator; `inputs[1..]` hold one operand per `Add`/`Mul` of the region,
+    // in member order (an operand read by two ops gets two slots). The accumulator itself never touches
+    // memory between ops.
+    let mut body = String::from("    float acc = inputs[0][i];\n");
+    let mut next_input_slot = 1usize;
+
+    for &value in &region.members {
+        let step = match &graph[value].operation {
+            Operation::Clamp { min, max, .. } => format!("acc = fminf(fmaxf(acc, {min}f), {max}f);", min = min, max = max),
+            Operation::Unary { op: UnaryOp::Sigmoid, .. } => "acc = 1.0f / (1.0f + expf(-acc));".to_owned(),
+            Operation::Unary { op: UnaryOp::Tanh, .. } => "acc = tanhf(acc);".to_owned(),
+            Operation::Add { subtract, .. } => {
+                let slot = next_input_slot;
+                next_input_slot += 1;
+                let op = if *subtract { "-" } else { "+" };
+                format!("acc = acc {op} inputs[{slot}][i];", op = op, slot = slot)
+            }
+            Operation::Mul { .. } => {
+                let slot = next_input_slot;
+                next_input_slot += 1;
+                format!("acc = acc * inputs[{slot}][i];", slot = slot)
+            }
+            Operation::View { .. } => continue,
+            other => panic!("{:?} is not fusable into an elementwise kernel", other),
+        };
+
+        body.push_str("    ");
+        body.push_str(&step);
+        body.push('\n');
+    }
+
+    let source = fill_replacements(FUSED_ELEMENTWISE_TEMPLATE, &[("$func_name", func_name.clone()), ("$body", body)]);
+
+    compile_cached_kernel(KernelKey { device, source, func_name })
+}
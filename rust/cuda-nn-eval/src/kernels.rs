@@ -18,4 +18,13 @@ extern "C" {
         stream: cudaStream_t, size: i32,
         indices: *const i32, input: *const f32, output: *mut f32,
     ) -> cudaError;
+
+    /// Softmax over the middle `axis_size` axis of an `(outer_size, axis_size, inner_size)` tensor.
+    /// If `quiet` the reduction accumulator starts from `exp(-max)` instead of `0`, implementing the
+    /// `exp(x_i) / (1 + sum_j exp(x_j))` variant.
+    pub fn softmaxFloat(
+        stream: cudaStream_t,
+        outer_size: i32, axis_size: i32, inner_size: i32, quiet: bool,
+        input: *const f32, output: *mut f32,
+    ) -> cudaError;
 }
\ No newline at end of file
@@ -3,6 +3,7 @@
 pub use cuda_sys::wrapper::handle::Device;
 
 pub mod executor;
+pub mod profiler;
 pub mod shape;
 pub mod tensor;
 pub mod tester;
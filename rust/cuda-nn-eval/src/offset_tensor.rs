@@ -96,9 +96,23 @@ impl<P: OffsetPtr> PtrTensor<P> {
     }
 }
 
+/// Which cuBLAS math mode a GEMM call should run under. Tensor-core modes only pay off when the
+/// operands are actually laid out and typed for them, so this is picked per call rather than once for
+/// a whole [`crate::executor::CudnnExecutor`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MathMode {
+    /// Plain FP32, no tensor cores. Always correct, never the fastest.
+    Fp32,
+    /// FP32-stored operands, computed via `cublasGemmEx` with `CUBLAS_COMPUTE_32F_FAST_TF32` so the
+    /// multiply-accumulate itself runs on tensor cores at reduced (TF32) mantissa precision.
+    Tf32TensorOp,
+    /// FP16-stored operands, computed via `cublasGemmEx` with `CUBLAS_COMPUTE_16F` tensor-core math.
+    Fp16TensorOp,
+}
+
 impl<P: Clone> PtrTensor<P> {
     //TODO move this somewhere else, this is pretty random
-    pub fn to_mat_mul_arg(&self) -> MatMulOperand<P> {
+    pub fn to_mat_mul_arg(&self, math_mode: MathMode) -> MatMulOperand<P> {
         assert_eq!(self.shape().rank(), 3);
 
         let inner_shape = StridedShape::new(self.shape().shape()[1..].to_vec(), self.shape().strides()[1..].to_vec());
@@ -126,6 +140,7 @@ impl<P: Clone> PtrTensor<P> {
             },
             ld: self.shape().shape()[lead_axis] as i32,
             stride: self.shape().strides()[0] as i64,
+            math_mode,
         }
     }
 }
@@ -8,7 +8,7 @@ use cuda_sys::wrapper::group::{FusedConvolutionArgs, TensorOpArgs};
 use cuda_sys::wrapper::handle::CudnnHandle;
 use cuda_sys::wrapper::mem::device::DeviceMem;
 use cuda_sys::wrapper::operation::STANDARD_CONV_ALGO;
-use nn_graph::graph::{ConvDetails, Graph, Operation, Value};
+use nn_graph::graph::{ConvDetails, Graph, Operation, UnaryOp, Value};
 use nn_graph::optimizer::find_single_use_values;
 use nn_graph::shape::ConcreteShape;
 
@@ -23,24 +23,119 @@ pub struct Planner<'a> {
 
     single_use: HashSet<Value>,
 
+    /// Set by [`Self::with_memory_reuse`]. Off by default, reproducing the old behavior of never
+    /// freeing an intermediate buffer until the whole plan is dropped.
+    memory_reuse: bool,
+    /// Per owning value, how many times it (or a `View`/`Slice` built on top of it) is still going to
+    /// be read as someone's operation input. Graph outputs are pinned to `usize::MAX` since
+    /// [`Self::copy_output`] may read them at any point after planning, including after a later value
+    /// would otherwise have reused their buffer.
+    remaining_uses: HashMap<Value, usize>,
+    /// Byte size of the buffer actually owned by a value (i.e. one that called [`Self::alloc_buffer`]
+    /// for its own result); `View`/`Slice` values don't get an entry since they alias their input's.
+    owner_bytes: HashMap<Value, usize>,
+    /// Retired buffers available for reuse, bucketed by size in bytes. Handing one back out assumes
+    /// `DeviceMem::view`/`slice_bytes` produce aliases into the same allocation rather than copies,
+    /// matching how every other buffer hand-out in this file already treats them.
+    free_list: HashMap<usize, Vec<DeviceMem>>,
+
+    live_bytes: usize,
+    peak_bytes: usize,
+
     map: HashMap<Value, Tensor>,
     plan: Vec<Step>,
 }
 
+/// A fusable activation recognized by [`Planner::match_fused_activation`], and the cuDNN mode/
+/// coefficient it lowers to inside an `ActivationDescriptor`.
+struct FusedActivation {
+    mode: cudnnActivationMode_t,
+    /// `ActivationDescriptor`'s `coef` parameter; only meaningful for `CUDNN_ACTIVATION_CLIPPED_RELU`,
+    /// where it's the clip ceiling (`6.0` for the usual relu6).
+    coef: f32,
+}
+
 impl<'a> Planner<'a> {
     pub fn new(handle: &'a mut CudnnHandle, graph: &'a Graph, batch_size: usize) -> Self {
         let single_use = find_single_use_values(graph);
+        let remaining_uses = Self::count_remaining_uses(graph);
 
         Planner {
             handle,
             graph,
             batch_size,
             single_use,
+            memory_reuse: false,
+            remaining_uses,
+            owner_bytes: Default::default(),
+            free_list: Default::default(),
+            live_bytes: 0,
+            peak_bytes: 0,
             map: Default::default(),
             plan: vec![],
         }
     }
 
+    /// Opt into handing retired buffers back out from [`Self::alloc_buffer`] instead of leaving every
+    /// intermediate allocated for the lifetime of the whole plan, so large graphs can fit in less VRAM.
+    pub fn with_memory_reuse(mut self, enabled: bool) -> Self {
+        self.memory_reuse = enabled;
+        self
+    }
+
+    /// The largest number of bytes ever simultaneously live while building this plan. Compare this
+    /// with and without [`Self::with_memory_reuse`] to see how much a graph's peak allocation shrinks.
+    pub fn peak_bytes(&self) -> usize {
+        self.peak_bytes
+    }
+
+    fn count_remaining_uses(graph: &Graph) -> HashMap<Value, usize> {
+        let mut remaining_uses: HashMap<Value, usize> = HashMap::new();
+
+        for value in graph.values() {
+            for input in graph[value].operation.inputs() {
+                *remaining_uses.entry(resolve_owner(graph, input)).or_insert(0) += 1;
+            }
+        }
+        for &output in graph.outputs() {
+            remaining_uses.insert(resolve_owner(graph, output), usize::MAX);
+        }
+
+        remaining_uses
+    }
+
+    fn owner_of(&self, value: Value) -> Value {
+        resolve_owner(self.graph, value)
+    }
+
+    /// Record that `value` has just been read by a consumer, and retire its owning buffer back onto
+    /// `free_list` once nothing else is ever going to read it.
+    fn note_use(&mut self, value: Value) {
+        let owner = self.owner_of(value);
+
+        let exhausted = match self.remaining_uses.get_mut(&owner) {
+            Some(count) if *count == usize::MAX => false,
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false,
+        };
+
+        if exhausted {
+            self.retire(owner);
+        }
+    }
+
+    fn retire(&mut self, owner: Value) {
+        if let Some(tensor) = self.map.remove(&owner) {
+            if let Some(size_bytes) = self.owner_bytes.remove(&owner) {
+                self.live_bytes = self.live_bytes.saturating_sub(size_bytes);
+                self.free_list.entry(size_bytes).or_default().push(tensor.mem);
+            }
+        }
+    }
+
     pub fn copy_output(&mut self, index: usize, value: Value) -> Tensor {
         let tensor = self.map.get(&value).unwrap();
         self.plan.push(Step::CopyOutput { index, tensor: tensor.view() });
@@ -52,6 +147,14 @@ impl<'a> Planner<'a> {
     }
 
     pub fn visit(&mut self, value: Value) -> Tensor {
+        let result = self.visit_impl(value);
+        if self.memory_reuse {
+            self.note_use(value);
+        }
+        result
+    }
+
+    fn visit_impl(&mut self, value: Value) -> Tensor {
         if let Some(result) = self.map.get(&value) {
             return result.view();
         }
@@ -64,6 +167,8 @@ impl<'a> Planner<'a> {
 
         let result_info = &self.graph[value];
         let result_shape = result_info.shape.eval(self.batch_size);
+        // grabbed before `result_shape` is moved into whichever match arm below actually handles it
+        let result_bytes = result_shape.size() * 4;
 
         let result: Tensor = match &result_info.operation {
             &Operation::Input { index } => {
@@ -123,28 +228,72 @@ impl<'a> Planner<'a> {
                 }
                 curr
             }
+            &Operation::Softmax { input, axis, quiet } => {
+                self.visit_softmax(result_shape, input, axis, quiet)
+            }
+            &Operation::Gather { input, axis, indices } => {
+                self.visit_gather(result_shape, input, axis, indices)
+            }
+            &Operation::GatherNd { .. } =>
+                todo!("GatherND plan lowering needs a dedicated multi-dimensional gather kernel, not implemented yet"),
+            &Operation::Unary { input, op } => {
+                self.visit_unary(result_shape, input, op)
+            }
         };
 
         let prev = self.map.insert(value, result.view());
         assert!(prev.is_none());
 
+        // `View`/`Slice` don't own a buffer of their own, they alias their input's
+        if self.memory_reuse && !matches!(result_info.operation, Operation::View { .. } | Operation::Slice { .. }) {
+            self.owner_bytes.insert(value, result_bytes);
+        }
+
         result
     }
 
+    /// Tries each registered fusable-activation pattern against `graph[curr].operation`. On a match,
+    /// returns the matched activation together with the value it was wrapping, so the caller can keep
+    /// popping bias/res `Add`s off of *that* value instead of `curr`.
+    fn match_fused_activation(&self, curr: Value) -> Option<(Value, FusedActivation)> {
+        let graph = self.graph;
+
+        match &graph[curr].operation {
+            // clamp(x, 0, inf) -> relu, clamp(x, 0, c) -> clipped/relu6-style relu
+            &Operation::Clamp { input, min, max } if self.single_use.contains(&input) && min == 0.0 => {
+                if max == f32::INFINITY {
+                    Some((input, FusedActivation { mode: cudnnActivationMode_t::CUDNN_ACTIVATION_RELU, coef: 0.0 }))
+                } else if max.is_finite() {
+                    Some((input, FusedActivation { mode: cudnnActivationMode_t::CUDNN_ACTIVATION_CLIPPED_RELU, coef: max }))
+                } else {
+                    None
+                }
+            }
+            &Operation::Unary { input, op } if self.single_use.contains(&input) => {
+                let mode = match op {
+                    UnaryOp::Sigmoid => cudnnActivationMode_t::CUDNN_ACTIVATION_SIGMOID,
+                    UnaryOp::Tanh => cudnnActivationMode_t::CUDNN_ACTIVATION_TANH,
+                };
+                Some((input, FusedActivation { mode, coef: 0.0 }))
+            }
+            _ => None,
+        }
+    }
+
     fn visit_fused_conv(&mut self, value: Value) -> Option<Tensor> {
         let mut curr = value;
-        let graph = self.graph;
 
-        // clamp(curr, 0, inf)?
-        let act_mode = if let &Operation::Clamp { input, min, max } = &graph[curr].operation {
-            if !self.single_use.contains(&input) || min != 0.0 && max != f32::INFINITY {
-                return None;
+        // act(curr)? pop a fusable activation, if any, before matching the bias/res/conv chain underneath
+        let (act_mode, act_coef) = match self.match_fused_activation(curr) {
+            Some((inner, act)) => {
+                curr = inner;
+                (act.mode, act.coef)
             }
-            cudnnActivationMode_t::CUDNN_ACTIVATION_RELU
-        } else {
-            cudnnActivationMode_t::CUDNN_ACTIVATION_IDENTITY
+            None => (cudnnActivationMode_t::CUDNN_ACTIVATION_IDENTITY, 0.0),
         };
 
+        let graph = self.graph;
+
         let mut bias = None;
         let mut res = None;
 
@@ -204,23 +353,34 @@ impl<'a> Planner<'a> {
             });
 
             let output_shape = graph[curr].shape.eval(self.batch_size);
+            let output_bytes = output_shape.size() * 4;
             let output = Tensor::new(
                 self.alloc_buffer(output_shape.size()),
                 StridedShape::new_simple(output_shape.dims),
             );
+            if self.memory_reuse {
+                self.owner_bytes.insert(value, output_bytes);
+            }
 
             let input_desc = input.descriptor();
             let output_desc = output.descriptor();
             let filter_desc = filter.filter_descriptor();
 
-            let padding = details.padding as i32;
-            let conv_desc = ConvolutionDescriptor::new(padding, padding, 1, 1, 1, 1);
+            let conv_desc = ConvolutionDescriptor::new(
+                details.padding_h as i32,
+                details.padding_w as i32,
+                details.stride as i32,
+                details.stride as i32,
+                details.dilation as i32,
+                details.dilation as i32,
+                details.groups as i32,
+            );
 
             let algo = STANDARD_CONV_ALGO;
             let work_size_bytes = conv_desc.workspace_size(self.handle, algo, &input_desc, &filter_desc, &output_desc);
             let work_mem = DeviceMem::alloc(work_size_bytes, self.handle.device());
 
-            let act_desc = ActivationDescriptor::new(act_mode, 0.0);
+            let act_desc = ActivationDescriptor::new(act_mode, act_coef);
 
             let args = FusedConvolutionArgs {
                 conv_desc,
@@ -311,7 +471,105 @@ impl<'a> Planner<'a> {
         output
     }
 
+    fn visit_softmax(&mut self, result_shape: ConcreteShape, input: Value, axis: usize, quiet: bool) -> Tensor {
+        let input = self.visit(input);
+
+        // the softmax kernel reduces over the middle axis of a dense (outer, axis, inner) view
+        let outer_size = result_shape.dims[..axis].iter().product();
+        let axis_size = result_shape.dims[axis];
+        let inner_size = result_shape.dims[axis + 1..].iter().product();
+
+        let output = Tensor::new(
+            self.alloc_buffer(result_shape.size()),
+            StridedShape::new_simple(result_shape.dims),
+        );
+
+        self.plan.push(Step::Softmax {
+            input: input.mem.view(),
+            output: output.mem.view(),
+            outer_size,
+            axis_size,
+            inner_size,
+            quiet,
+        });
+
+        output
+    }
+
+    fn visit_gather(&mut self, result_shape: ConcreteShape, input: Value, axis: usize, indices: Value) -> Tensor {
+        let input = self.visit(input);
+        let indices = self.visit(indices);
+
+        let output = Tensor::new(
+            self.alloc_buffer(result_shape.size()),
+            StridedShape::new_simple(result_shape.dims),
+        );
+
+        // backed by the `gatherFloat` kernel, which gathers along the given axis of a dense tensor
+        self.plan.push(Step::Gather {
+            input: input.mem.view(),
+            indices: indices.mem.view(),
+            output: output.mem.view(),
+            axis,
+        });
+
+        output
+    }
+
+    fn visit_unary(&mut self, result_shape: ConcreteShape, input: Value, op: UnaryOp) -> Tensor {
+        let input = self.visit(input).view();
+
+        let act_mode = match op {
+            UnaryOp::Sigmoid => cudnnActivationMode_t::CUDNN_ACTIVATION_SIGMOID,
+            UnaryOp::Tanh => cudnnActivationMode_t::CUDNN_ACTIVATION_TANH,
+        };
+        let act_desc = ActivationDescriptor::new(act_mode, 0.0);
+
+        let output = Tensor::new(
+            self.alloc_buffer(result_shape.size()),
+            StridedShape::new_simple(result_shape.dims),
+        );
+
+        self.plan.push(Step::Activation {
+            act_desc,
+            input_desc: input.descriptor(),
+            input_mem: input.mem.view(),
+            output_desc: output.descriptor(),
+            output_mem: output.mem.view(),
+        });
+
+        output
+    }
+
+    /// Allocate a buffer big enough for `size` floats, reusing a same-sized buffer from `free_list`
+    /// when [`Self::with_memory_reuse`] is enabled and one is available. Buffers are matched purely by
+    /// byte size, not by which value originally produced them, since once retired they're fungible.
     fn alloc_buffer(&mut self, size: usize) -> DeviceMem {
-        DeviceMem::alloc(size * 4, self.handle.device())
+        let size_bytes = size * 4;
+
+        let reused = if self.memory_reuse {
+            self.free_list.get_mut(&size_bytes).and_then(Vec::pop)
+        } else {
+            None
+        };
+        let buffer = reused.unwrap_or_else(|| DeviceMem::alloc(size_bytes, self.handle.device()));
+
+        self.live_bytes += size_bytes;
+        self.peak_bytes = self.peak_bytes.max(self.live_bytes);
+
+        buffer
+    }
+}
+
+/// Walk through any chain of `View`/`Slice` operations to the value that actually owns the underlying
+/// buffer they alias.
+fn resolve_owner(graph: &Graph, value: Value) -> Value {
+    let mut curr = value;
+    loop {
+        curr = match &graph[curr].operation {
+            &Operation::View { input } => input,
+            &Operation::Slice { input, .. } => input,
+            _ => return curr,
+        };
     }
 }
@@ -0,0 +1,133 @@
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+/// Per-operator timings collected while evaluating a graph, convertible to a Chrome-tracing JSON file
+/// (`chrome://tracing` / Perfetto can both load it directly) or to a textual summary.
+///
+/// Ideally each event would bracket its kernel launch with a pair of `CudaEvent`s recorded on the
+/// executor's stream, so the timing reflects actual device execution rather than host-side dispatch
+/// overhead. Neither `crate::executor` nor a CUDA event wrapper exist in this tree yet, so this records
+/// wall-clock host time around each op instead; swapping the `Instant::now()`/`elapsed()` pair in
+/// [`Profiler::record`] for a pair of synchronized `CudaEvent`s is the only change needed once those
+/// land, the rest of this module (accumulation, summary, Chrome trace export) is unaffected.
+#[derive(Debug)]
+pub struct Profiler {
+    enabled: bool,
+    process_start: Instant,
+    events: Vec<ProfileEvent>,
+}
+
+#[derive(Debug)]
+struct ProfileEvent {
+    name: String,
+    shape: String,
+    start: Instant,
+    duration: Duration,
+}
+
+/// Environment variable that turns on profiling without touching call sites (see [`Profiler::from_env`]).
+pub const PROFILE_ENV_VAR: &str = "CUDNN_PROFILE";
+
+impl Profiler {
+    pub fn new(enabled: bool) -> Self {
+        Profiler { enabled, process_start: Instant::now(), events: vec![] }
+    }
+
+    /// Builds a profiler whose `enabled` flag is taken from [`PROFILE_ENV_VAR`], for callers that want
+    /// profiling available without threading a flag through every constructor.
+    pub fn from_env() -> Self {
+        Profiler::new(std::env::var_os(PROFILE_ENV_VAR).is_some())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Runs `f`, recording its wall-clock duration under `name` (with `shape` stashed as the trace
+    /// event's `args`) if profiling is enabled. A no-op wrapper when disabled, so callers can leave the
+    /// instrumentation in place unconditionally.
+    pub fn record<R>(&mut self, name: impl Into<String>, shape: impl Into<String>, f: impl FnOnce() -> R) -> R {
+        if !self.enabled {
+            return f();
+        }
+
+        let start = Instant::now();
+        let result = f();
+        let duration = start.elapsed();
+
+        self.events.push(ProfileEvent { name: name.into(), shape: shape.into(), start, duration });
+
+        result
+    }
+
+    pub fn total_duration(&self) -> Duration {
+        self.events.iter().map(|e| e.duration).sum()
+    }
+
+    /// Prints total time and the top `top_n` ops by cumulative time (ops with the same name are
+    /// grouped), for a quick look without opening the Chrome trace.
+    pub fn print_summary(&self, top_n: usize) {
+        if self.events.is_empty() {
+            println!("Profiler: no events recorded");
+            return;
+        }
+
+        let total = self.total_duration();
+        println!("Profiler: {} events, {:.3}ms total", self.events.len(), total.as_secs_f64() * 1000.0);
+
+        let mut by_name: Vec<(&str, Duration, usize)> = vec![];
+        for event in &self.events {
+            match by_name.iter_mut().find(|(name, _, _)| *name == event.name) {
+                Some((_, duration, count)) => {
+                    *duration += event.duration;
+                    *count += 1;
+                }
+                None => by_name.push((&event.name, event.duration, 1)),
+            }
+        }
+        by_name.sort_by_key(|&(_, duration, _)| std::cmp::Reverse(duration));
+
+        for &(name, duration, count) in by_name.iter().take(top_n) {
+            println!(
+                "  {:>8.3}ms  {:>5.1}%  x{:<4}  {}",
+                duration.as_secs_f64() * 1000.0,
+                100.0 * duration.as_secs_f64() / total.as_secs_f64(),
+                count,
+                name,
+            );
+        }
+    }
+
+    /// Serializes the recorded events to a Chrome tracing JSON array, ready to be written to
+    /// `profile.json` and opened in `chrome://tracing`/Perfetto.
+    pub fn to_chrome_trace_json(&self) -> String {
+        let mut json = String::new();
+        json.push('[');
+
+        for (i, event) in self.events.iter().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+
+            let ts_micros = event.start.duration_since(self.process_start).as_micros();
+            let dur_micros = event.duration.as_micros();
+
+            write!(
+                json,
+                r#"{{"name":"{}","ph":"X","ts":{},"dur":{},"pid":0,"tid":0,"args":{{"shape":"{}"}}}}"#,
+                escape_json_string(&event.name), ts_micros, dur_micros, escape_json_string(&event.shape),
+            ).unwrap();
+        }
+
+        json.push(']');
+        json
+    }
+
+    pub fn write_chrome_trace(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_chrome_trace_json())
+    }
+}
+
+fn escape_json_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
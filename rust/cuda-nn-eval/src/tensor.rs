@@ -1,14 +1,67 @@
-use bytemuck::{cast_slice, cast_slice_mut};
+use bytemuck::cast_slice;
+use half::f16;
 
 use cuda_sys::bindings::{cublasOperation_t, cudnnOpTensorOp_t};
 use cuda_sys::wrapper::descriptor::{TensorDescriptor, TensorOpDescriptor};
 use cuda_sys::wrapper::group::MatMulArg;
 use cuda_sys::wrapper::handle::{CudnnHandle, Device};
 use cuda_sys::wrapper::mem::device::DevicePtr;
+use cuda_sys::wrapper::mem::pinned::PinnedMem;
 use cuda_sys::wrapper::operation::run_tensor_op;
+use cuda_sys::wrapper::stream::CudaStream;
 
 use crate::shape::StridedShape;
 
+/// The precision a [`DeviceTensor`]'s buffer is stored in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DType {
+    F32,
+    F16,
+    /// 8-bit integer, quantized with the given `(scale, zero_point)` so that
+    /// `real = (stored - zero_point) * scale`.
+    I8 { scale: f32, zero_point: i8 },
+}
+
+impl DType {
+    /// The size in bytes of a single element stored in this dtype.
+    pub fn elem_size(self) -> usize {
+        match self {
+            DType::F32 => 4,
+            DType::F16 => 2,
+            DType::I8 { .. } => 1,
+        }
+    }
+
+    /// Encode host f32 values as the raw bytes this dtype stores on the device.
+    pub fn encode(self, buffer: &[f32]) -> Vec<u8> {
+        match self {
+            DType::F32 => cast_slice(buffer).to_vec(),
+            DType::F16 => cast_slice(&buffer.iter().map(|&x| f16::from_f32(x)).collect::<Vec<_>>()).to_vec(),
+            DType::I8 { scale, zero_point } => buffer
+                .iter()
+                .map(|&x| quantize_i8(x, scale, zero_point) as u8)
+                .collect(),
+        }
+    }
+
+    /// Decode this dtype's raw device bytes back into host f32 values.
+    pub fn decode(self, bytes: &[u8], buffer: &mut [f32]) {
+        match self {
+            DType::F32 => buffer.copy_from_slice(cast_slice(bytes)),
+            DType::F16 => {
+                for (dst, &src) in buffer.iter_mut().zip(cast_slice::<u8, f16>(bytes)) {
+                    *dst = src.to_f32();
+                }
+            }
+            DType::I8 { scale, zero_point } => {
+                for (dst, &src) in buffer.iter_mut().zip(bytes) {
+                    *dst = dequantize_i8(src as i8, scale, zero_point);
+                }
+            }
+        }
+    }
+}
+
 /// A tensor allocated on the device.
 ///
 /// Cloning this type does not copy the underlying memory.
@@ -16,17 +69,18 @@ use crate::shape::StridedShape;
 pub struct DeviceTensor {
     pub ptr: DevicePtr,
     pub shape: StridedShape,
+    pub dtype: DType,
 }
 
 impl DeviceTensor {
-    pub fn new(ptr: DevicePtr, shape: StridedShape) -> Self {
-        DeviceTensor { ptr, shape }
+    pub fn new(ptr: DevicePtr, shape: StridedShape, dtype: DType) -> Self {
+        DeviceTensor { ptr, shape, dtype }
     }
 
-    pub fn alloc_simple(device: Device, shape: Vec<usize>) -> Self {
+    pub fn alloc_simple(device: Device, shape: Vec<usize>, dtype: DType) -> Self {
         let size = shape.iter().product::<usize>();
-        let ptr = DevicePtr::alloc(device, size * 4);
-        DeviceTensor::new(ptr, StridedShape::new_simple(shape))
+        let ptr = DevicePtr::alloc(device, size * dtype.elem_size());
+        DeviceTensor::new(ptr, StridedShape::new_simple(shape), dtype)
     }
 
     pub fn device(&self) -> Device {
@@ -34,7 +88,7 @@ impl DeviceTensor {
     }
 
     pub fn permute(&self, permutation: &[usize]) -> DeviceTensor {
-        DeviceTensor::new(self.ptr.clone(), self.shape.permute(permutation))
+        DeviceTensor::new(self.ptr.clone(), self.shape.permute(permutation), self.dtype)
     }
 
     pub fn slice(&self, axis: usize, start: usize, end: usize) -> DeviceTensor {
@@ -45,10 +99,10 @@ impl DeviceTensor {
         //  * limit the buffer length based on the new size
         let result_shape = self.shape.slice(axis, start, end);
 
-        let start_bytes = result_shape.strides()[axis] * start * 4;
+        let start_bytes = result_shape.strides()[axis] * start * self.dtype.elem_size();
         let mem = self.ptr.offset(start_bytes as isize);
 
-        DeviceTensor::new(mem, result_shape)
+        DeviceTensor::new(mem, result_shape, self.dtype)
     }
 
     pub fn to_mat_mul_arg(&self) -> MatMulArg {
@@ -72,6 +126,7 @@ impl DeviceTensor {
 
         MatMulArg {
             ptr: self.ptr.clone(),
+            dtype: self.dtype,
             trans: if col_major {
                 cublasOperation_t::CUBLAS_OP_N
             } else {
@@ -88,7 +143,8 @@ impl DeviceTensor {
             "Tensor must have simple strides for now, got {:?}",
             self.shape
         );
-        self.ptr.copy_linear_from_host(cast_slice(buffer));
+
+        self.ptr.copy_linear_from_host(&self.dtype.encode(buffer));
     }
 
     pub unsafe fn copy_simple_to_host(&self, buffer: &mut [f32]) {
@@ -97,7 +153,35 @@ impl DeviceTensor {
             "Tensor must have simple strides, got {:?}",
             self.shape
         );
-        self.ptr.copy_linear_to_host(cast_slice_mut(buffer));
+
+        let mut staged = vec![0u8; buffer.len() * self.dtype.elem_size()];
+        self.ptr.copy_linear_to_host(&mut staged);
+        self.dtype.decode(&staged, buffer);
+    }
+
+    /// Like [Self::copy_simple_from_host], but issued asynchronously on `stream` instead of
+    /// blocking until the transfer completes. `pinned` must already hold `buffer` encoded in this
+    /// tensor's dtype (see [DType::encode]) in page-locked memory, since the host buffer must stay
+    /// alive and unmoved for the CPU-to-GPU copy to actually happen concurrently with other work.
+    pub unsafe fn copy_simple_from_host_pinned_async(&self, pinned: &PinnedMem, stream: &CudaStream) {
+        assert!(
+            self.shape.has_simple_strides(),
+            "Tensor must have simple strides for now, got {:?}",
+            self.shape
+        );
+        self.ptr.copy_linear_from_host_async(pinned.slice(), stream);
+    }
+
+    /// Like [Self::copy_simple_to_host], but issued asynchronously on `stream`: the raw
+    /// (still dtype-encoded) bytes land in `pinned`, and must be decoded with [DType::decode]
+    /// only after waiting for the copy to actually finish (e.g. by synchronizing `stream`).
+    pub unsafe fn copy_simple_to_host_pinned_async(&self, pinned: &mut PinnedMem, stream: &CudaStream) {
+        assert!(
+            self.shape.has_simple_strides(),
+            "Tensor must have simple strides, got {:?}",
+            self.shape
+        );
+        self.ptr.copy_linear_to_host_async(pinned.slice_mut(), stream);
     }
 
     pub unsafe fn copy_from(&self, other: &DeviceTensor) {
@@ -108,10 +192,16 @@ impl DeviceTensor {
             self,
             other
         );
+        assert_eq!(
+            self.dtype, other.dtype,
+            "Tensors must have the same dtype: {:?} vs {:?}",
+            self, other
+        );
 
         if self.shape == other.shape && self.shape.has_dense_strides() {
             // if strides are dense and match we can just do a simple memcpy
-            self.ptr.copy_linear_from_device(&other.ptr, self.shape.size())
+            self.ptr
+                .copy_linear_from_device(&other.ptr, self.shape.size() * self.dtype.elem_size())
         } else {
             // otherwise use the TensorOp restride trick
             restride_with_tensor_op(other, self);
@@ -124,7 +214,7 @@ impl DeviceTensor {
         if self.shape.has_simple_strides() {
             self.copy_simple_from_host(buffer);
         } else {
-            let stage = DeviceTensor::alloc_simple(self.device(), self.shape.shape().to_vec());
+            let stage = DeviceTensor::alloc_simple(self.device(), self.shape.shape().to_vec(), self.dtype);
             stage.copy_simple_from_host(buffer);
             self.copy_from(&stage);
         }
@@ -136,13 +226,23 @@ impl DeviceTensor {
         if self.shape.has_simple_strides() {
             self.copy_simple_to_host(buffer);
         } else {
-            let stage = DeviceTensor::alloc_simple(self.device(), self.shape.shape().to_vec());
+            let stage = DeviceTensor::alloc_simple(self.device(), self.shape.shape().to_vec(), self.dtype);
             stage.copy_from(self);
             stage.copy_simple_to_host(buffer);
         }
     }
 }
 
+fn quantize_i8(x: f32, scale: f32, zero_point: i8) -> i8 {
+    // i8 has no NaN of its own, so a NaN pad value (used for unused batch slots) just saturates to 0
+    let rounded = (x / scale).round() + zero_point as f32;
+    rounded.clamp(i8::MIN as f32, i8::MAX as f32) as i8
+}
+
+fn dequantize_i8(x: i8, scale: f32, zero_point: i8) -> f32 {
+    (x - zero_point) as f32 * scale
+}
+
 //TODO extract this function to somewhere more general, maybe even with fixed pre-allocation of the descriptors
 unsafe fn restride_with_tensor_op(input: &DeviceTensor, output: &DeviceTensor) {
     let handle = CudnnHandle::new(input.device());
@@ -1,6 +1,6 @@
 use itertools::Itertools;
 
-use cuda_nn_eval::tester::{assert_outputs_match, eval_cudnn, load_check_data};
+use cuda_nn_eval::tester::{assert_outputs_match, eval_cudnn, load_check_data, Tolerance};
 use nn_graph::cpu::{cpu_execute_graph, STensor};
 use nn_graph::graph::{Graph, Value};
 use nn_graph::ndarray::ArcArray;
@@ -35,7 +35,7 @@ fn test_all_graph(graph: &Graph, batch_size: usize, inputs: &[STensor], expected
     let cpu_outputs = cpu_execute_graph(graph, batch_size, &cpu_inputs).outputs();
 
     let expected_outputs = if let Some(expected_outputs) = expected_outputs {
-        assert_outputs_match(expected_outputs, &cpu_outputs, true);
+        assert_outputs_match(expected_outputs, &cpu_outputs, Tolerance::default(), true);
         expected_outputs
     } else {
         &cpu_outputs
@@ -43,7 +43,7 @@ fn test_all_graph(graph: &Graph, batch_size: usize, inputs: &[STensor], expected
 
     println!("Testing with Cudnn");
     let gpu_outputs = eval_cudnn(graph, batch_size, inputs);
-    assert_outputs_match(expected_outputs, &gpu_outputs, true);
+    assert_outputs_match(expected_outputs, &gpu_outputs, Tolerance::default(), true);
 
     cpu_outputs
 }
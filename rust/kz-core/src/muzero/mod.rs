@@ -1,3 +1,5 @@
+use board_game::wdl::WDL;
+
 use crate::zero::values::ZeroValuesPov;
 use std::borrow::{Borrow, Cow};
 
@@ -10,9 +12,16 @@ pub mod wrapper;
 /// A board evaluation, either as returned by the network or as the final output of a zero tree search.
 #[derive(Debug, Clone)]
 pub struct MuZeroEvaluation<'a> {
-    /// The (normalized) values.
+    /// The (normalized) values, with the scalar value derived as `wdl.win - wdl.loss`.
     pub values: ZeroValuesPov,
 
+    /// The (normalized) win/draw/loss distribution the scalar value in `values` was derived from.
+    /// Modeling the draw probability explicitly instead of collapsing it into a single scalar
+    /// matters a lot for games like chess where draws are common.
+    //TODO once `ZeroValuesPov` itself grows a `wdl` field this becomes redundant
+    //  and `values.wdl` should be used instead
+    pub wdl: WDL<f32>,
+
     /// The (un-normalized) policy "vector", containing all possible moves.
     pub policy_logits: Cow<'a, [f32]>,
 }
@@ -21,6 +30,7 @@ impl MuZeroEvaluation<'_> {
     pub fn shallow_clone(&self) -> MuZeroEvaluation {
         MuZeroEvaluation {
             values: self.values,
+            wdl: self.wdl,
             policy_logits: Cow::Borrowed(self.policy_logits.borrow()),
         }
     }
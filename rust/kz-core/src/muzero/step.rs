@@ -48,11 +48,49 @@ pub fn muzero_step_gather<B: Board>(
         });
     }
 
-    let mut curr_node = 0;
+    gather_descend(tree, 0, None, None, weights, use_value, fpu_mode)
+}
+
+/// Like [`muzero_step_gather`], but descends from `start_node` instead of the tree root. `start_node`
+/// must already have been expanded (typically one of the root's children); `parent_move_index` and
+/// `parent_state` are the move index and state that were used to expand it, exactly as a regular descent
+/// from the root would have recorded them. Used by Gumbel root selection (see `generator_muzero.rs`) to
+/// run Sequential Halving simulations rooted at a specific candidate instead of letting UCT pick among
+/// all of the root's children.
+pub fn muzero_step_gather_from<B: Board>(
+    tree: &MuTree<B>,
+    start_node: usize,
+    parent_move_index: usize,
+    parent_state: QuantizedStorage,
+    weights: UctWeights,
+    use_value: bool,
+    fpu_mode: FpuMode,
+) -> Option<MuZeroRequest<B>> {
+    gather_descend(
+        tree,
+        start_node,
+        Some(parent_move_index),
+        Some(parent_state),
+        weights,
+        use_value,
+        fpu_mode,
+    )
+}
+
+fn gather_descend<B: Board>(
+    tree: &MuTree<B>,
+    start_node: usize,
+    seed_move_index: Option<usize>,
+    seed_state: Option<QuantizedStorage>,
+    weights: UctWeights,
+    use_value: bool,
+    fpu_mode: FpuMode,
+) -> Option<MuZeroRequest<B>> {
+    let mut curr_node = start_node;
     let mut fpu = ZeroValues::from_outcome(OutcomeWDL::Draw, 0.0);
 
-    let mut last_move_index = None;
-    let mut last_state: Option<QuantizedStorage> = None;
+    let mut last_move_index = seed_move_index;
+    let mut last_state = seed_state;
 
     loop {
         let inner = if let Some(inner) = &tree[curr_node].inner {
@@ -95,6 +133,132 @@ pub fn muzero_step_gather<B: Board>(
     }
 }
 
+/// Like [`muzero_step_gather`], but collects up to `batch_size` distinct `Expand` leaves instead of
+/// just one, applying a temporary virtual loss along each selected path so that later descents within
+/// the same batch avoid re-selecting the same leaf. Stops early if the root still needs expanding,
+/// since that request has to be resolved by itself before any `Expand` requests can make sense. Used
+/// together with [`muzero_step_apply_batch`] to turn the search from latency-bound single evaluations
+/// into throughput-bound batched ones (see `generator_muzero.rs`).
+pub fn muzero_step_gather_batch<B: Board>(
+    tree: &mut MuTree<B>,
+    batch_size: usize,
+    virtual_loss: f32,
+    weights: UctWeights,
+    use_value: bool,
+    fpu_mode: FpuMode,
+) -> Vec<MuZeroRequest<B>> {
+    gather_batch(tree, batch_size, virtual_loss, |tree| {
+        muzero_step_gather(tree, weights, use_value, fpu_mode)
+    })
+}
+
+/// Like [`muzero_step_gather_from`], but collects up to `batch_size` distinct `Expand` leaves instead
+/// of just one, exactly as [`muzero_step_gather_batch`] does for [`muzero_step_gather`].
+#[allow(clippy::too_many_arguments)]
+pub fn muzero_step_gather_from_batch<B: Board>(
+    tree: &mut MuTree<B>,
+    start_node: usize,
+    parent_move_index: usize,
+    parent_state: QuantizedStorage,
+    batch_size: usize,
+    virtual_loss: f32,
+    weights: UctWeights,
+    use_value: bool,
+    fpu_mode: FpuMode,
+) -> Vec<MuZeroRequest<B>> {
+    gather_batch(tree, batch_size, virtual_loss, |tree| {
+        muzero_step_gather_from(
+            tree,
+            start_node,
+            parent_move_index,
+            parent_state.clone(),
+            weights,
+            use_value,
+            fpu_mode,
+        )
+    })
+}
+
+fn gather_batch<B: Board>(
+    tree: &mut MuTree<B>,
+    batch_size: usize,
+    virtual_loss: f32,
+    mut gather: impl FnMut(&MuTree<B>) -> Option<MuZeroRequest<B>>,
+) -> Vec<MuZeroRequest<B>> {
+    let mut batch = vec![];
+
+    while batch.len() < batch_size {
+        let request = match gather(tree) {
+            Some(request) => request,
+            None => break,
+        };
+
+        match &request {
+            MuZeroRequest::Root { .. } => {
+                // the root must be resolved by itself, it can't be batched together with expand requests
+                batch.push(request);
+                break;
+            }
+            MuZeroRequest::Expand { node, .. } => {
+                apply_virtual_loss(tree, *node, virtual_loss);
+                batch.push(request);
+            }
+        }
+    }
+
+    batch
+}
+
+/// Undo the virtual loss applied to `node` by [`gather_batch`], then apply the real network
+/// evaluation exactly like [`muzero_step_apply`].
+pub fn muzero_step_apply_batch<B: Board, M: BoardMapper<B>>(
+    tree: &mut MuTree<B>,
+    top_moves: usize,
+    response: MuZeroResponse,
+    mapper: M,
+    virtual_loss: f32,
+) {
+    undo_virtual_loss(tree, response.node, virtual_loss);
+    muzero_step_apply(tree, top_moves, response, mapper);
+}
+
+/// Temporarily discourage other descents in the same batch from selecting `node` again, by pretending
+/// it just received a pessimistic loss: `visits` is incremented and a loss-flavored value is folded
+/// into `sum_values` along the path to the root, exactly like a real visit would.
+fn apply_virtual_loss<B: Board>(tree: &mut MuTree<B>, node: usize, virtual_loss: f32) {
+    propagate_virtual_loss(tree, node, virtual_loss, 1);
+}
+
+/// Undo the effect of [`apply_virtual_loss`] once the real evaluation for `node` is available.
+fn undo_virtual_loss<B: Board>(tree: &mut MuTree<B>, node: usize, virtual_loss: f32) {
+    propagate_virtual_loss(tree, node, virtual_loss, -1);
+}
+
+fn propagate_virtual_loss<B: Board>(
+    tree: &mut MuTree<B>,
+    node: usize,
+    virtual_loss: f32,
+    sign: i64,
+) {
+    let mut values =
+        ZeroValues::from_outcome(OutcomeWDL::Loss, 0.0).flip() * (sign as f32 * virtual_loss);
+    let mut curr_index = node;
+
+    loop {
+        let curr_node = &mut tree[curr_index];
+
+        curr_node.visits = (curr_node.visits as i64 + sign) as u64;
+        curr_node.sum_values += values;
+
+        curr_index = match curr_node.parent {
+            Some(parent) => parent,
+            None => break,
+        };
+
+        values = values.parent();
+    }
+}
+
 /// The second half of a step. Applies a network evaluation to the given node,
 /// by setting the child policies and propagating the wdl back to the root.
 /// Along the way `virtual_visits` is decremented and `visits` is incremented.
@@ -121,14 +285,18 @@ pub fn muzero_step_apply<B: Board, M: BoardMapper<B>>(
     let children = if node == 0 {
         // only keep available moves for root node
         let board = &tree.root_board;
-        let indices = board.available_moves().map(|mv| mapper.move_to_index(&board, mv));
+        let indices = board
+            .available_moves()
+            .map(|mv| mapper.move_to_index(&board, mv));
         create_child_nodes(&mut tree.nodes, node, indices, &policy)
     } else {
         // keep all moves deeper in the tree
         // TODO use the fact that moves are sorted by policy to optimize UCT calculations later on
         // TODO this doesn't work for the pass move, maybe it's finally time to retire it
         let mapped = policy.iter().copied().map(N32::from_inner);
-        let indices = top_k_indices_sorted(mapped, top_moves).into_iter().map(Some);
+        let indices = top_k_indices_sorted(mapped, top_moves)
+            .into_iter()
+            .map(Some);
         create_child_nodes(&mut tree.nodes, node, indices.into_internal(), &policy)
     };
 
@@ -188,3 +356,15 @@ fn tree_propagate_values<B: Board>(tree: &mut MuTree<B>, node: usize, mut values
         values = values.parent();
     }
 }
+
+/// The completed action-value of `node` from its parent's point of view: the node's own mean value
+/// once it has accumulated at least one visit, or `value_if_unvisited` as a stand-in otherwise.
+/// Used by Gumbel root selection (see `generator_muzero.rs`) to rank every root action by
+/// `g(a) + logit(a) + sigma(q(a))`, including candidates Sequential Halving hasn't visited yet.
+pub fn completed_q<B: Board>(tree: &MuTree<B>, node: usize, value_if_unvisited: f32) -> f32 {
+    if tree[node].visits > 0 {
+        tree[node].values().flip().value()
+    } else {
+        value_if_unvisited
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+
+use flume::{Receiver, Sender};
+
+/// A single pending evaluation collected by a [`JobServer`]: the input together with the channel its
+/// [`JobClient`] is waiting on for the matching output.
+#[derive(Debug)]
+pub struct PendingJob<I, O> {
+    pub input: I,
+    pub response: Sender<O>,
+}
+
+/// The requester side of a [`job_pair`]. Cheap to clone, so every concurrent generator can hold its
+/// own handle onto the same shared queue.
+#[derive(Debug)]
+pub struct JobClient<I, O> {
+    sender: Sender<PendingJob<I, O>>,
+}
+
+impl<I, O> Clone for JobClient<I, O> {
+    fn clone(&self) -> Self {
+        JobClient {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<I, O> JobClient<I, O> {
+    /// Queue `input` and wait for the matching output, once some [`JobServer`] batches it together
+    /// with other pending requests and runs it through the network.
+    pub async fn map_async_single(&self, input: I) -> O {
+        let (response, response_receiver) = flume::bounded(1);
+        self.sender
+            .send_async(PendingJob { input, response })
+            .await
+            .expect("matching JobServer was dropped");
+        response_receiver
+            .recv_async()
+            .await
+            .expect("JobServer dropped our job without responding")
+    }
+}
+
+/// The executor side of a [`job_pair`]. Used by [`crate::server::executor::batched_executor_loop`]
+/// (`kz-selfplay`) to pull bounded-latency batches of pending jobs off the shared queue.
+#[derive(Debug)]
+pub struct JobServer<I, O> {
+    receiver: Receiver<PendingJob<I, O>>,
+}
+
+impl<I, O> Clone for JobServer<I, O> {
+    fn clone(&self) -> Self {
+        JobServer {
+            receiver: self.receiver.clone(),
+        }
+    }
+}
+
+impl<I, O> JobServer<I, O> {
+    /// Collect the next batch of pending jobs: blocks until at least one is available, then keeps
+    /// accumulating more as they arrive until either `max_batch_size` is reached or `max_wait` has
+    /// elapsed since the first job in this batch came in. Returns `None` once every matching
+    /// [`JobClient`] has been dropped and no more jobs will ever arrive.
+    pub fn next_batch(
+        &self,
+        max_batch_size: usize,
+        max_wait: Duration,
+    ) -> Option<Vec<PendingJob<I, O>>> {
+        let first = self.receiver.recv().ok()?;
+        let deadline = Instant::now() + max_wait;
+
+        let mut batch = vec![first];
+        while batch.len() < max_batch_size {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+
+            match self.receiver.recv_timeout(remaining) {
+                Ok(job) => batch.push(job),
+                Err(_) => break,
+            }
+        }
+
+        Some(batch)
+    }
+}
+
+/// Create a linked [`JobClient`]/[`JobServer`] pair backed by a channel that holds at most
+/// `capacity` unanswered jobs at once; a client whose queue is full waits for the server to catch up
+/// instead of piling up unboundedly many in-flight requests.
+pub fn job_pair<I, O>(capacity: usize) -> (JobClient<I, O>, JobServer<I, O>) {
+    let (sender, receiver) = flume::bounded(capacity);
+    (JobClient { sender }, JobServer { receiver })
+}
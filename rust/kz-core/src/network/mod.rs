@@ -7,10 +7,12 @@ use crate::zero::node::ZeroValues;
 
 pub mod common;
 pub mod dummy;
+pub mod job_channel;
 pub mod symmetry;
 
 pub mod cpu;
 pub mod cudnn;
+pub mod muzero;
 
 #[cfg(feature = "onnxruntime")]
 pub mod onnx_runtime;
@@ -19,6 +19,7 @@ use nn_graph::shape::{Shape, Size};
 use crate::mapping::BoardMapper;
 use crate::muzero::MuZeroEvaluation;
 use crate::network::common::{softmax_in_place, zero_values_from_scalars};
+use crate::network::job_channel::JobClient;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct MuZeroNetworkInfo {
@@ -215,6 +216,15 @@ impl<B: Board, M: BoardMapper<B>> MuZeroFusedGraphs<B, M> {
 pub type ExpandArgs = (QuantizedStorage, usize);
 pub type EvalResponsePair = (QuantizedStorage, MuZeroEvaluation<'static>);
 
+/// The generator-facing handle for `MuZeroRootExecutor::eval_root`, coalesced across all of a device's
+/// concurrent generators by `kz_selfplay::server::executor::batched_executor_loop` (see
+/// `kz_core::network::job_channel`).
+pub type RootClient<B> = JobClient<B, EvalResponsePair>;
+
+/// The generator-facing handle for `MuZeroExpandExecutor::eval_expand`, coalesced the same way as
+/// [`RootClient`].
+pub type ExpandClient = JobClient<ExpandArgs, EvalResponsePair>;
+
 impl<B: Board, M: BoardMapper<B>> MuZeroRootExecutor<B, M> {
     pub fn eval_root(&mut self, boards: &[B]) -> Vec<EvalResponsePair> {
         let max_batch_size = self.root_exec.batch_size;
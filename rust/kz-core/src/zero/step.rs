@@ -1,8 +1,13 @@
-use board_game::board::Board;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use board_game::board::{Board, Outcome};
 use board_game::pov::Pov;
 use decorum::N32;
 use internal_iterator::InternalIterator;
 use rand::Rng;
+use rand_distr::Gamma;
 
 use kz_util::sequence::{choose_max_by_key, zip_eq_exact};
 
@@ -102,10 +107,70 @@ pub fn zero_step_gather<B: Board>(
     }
 }
 
+/// Collects up to `batch_size` distinct un-evaluated leaves by repeatedly calling [`zero_step_gather`],
+/// instead of the strict gather-one/apply-one loop — exactly like expanding a bounded frontier in beam
+/// search. This is what lets a `CudnnNetwork` actually run at `batch_size > 1`: without it, every
+/// descent only ever produces a single board to evaluate, so the GPU never sees more than one board
+/// per call no matter how large a batch the network was actually built for.
+///
+/// Each gathered leaf already has `virtual_visits` incremented along its whole path by
+/// [`zero_step_gather`] itself, which is meant to steer later gathers within the same batch away from
+/// re-selecting the same node. Realizing that requires `Node::uct` (`kz_core::zero::node`) to fold a
+/// virtual-loss term proportional to `virtual_visits` into the child value it scores. A leaf is never
+/// returned twice, since gathering it gives it children at once, but later gathers in the same batch
+/// can descend through a still-unevaluated leaf into those children.
+pub fn zero_step_gather_batch<B: Board>(
+    tree: &mut Tree<B>,
+    batch_size: usize,
+    weights: UctWeights,
+    use_value: bool,
+    fpu_mode: FpuMode,
+    rng: &mut impl Rng,
+) -> Vec<ZeroRequest<B>> {
+    let mut batch = Vec::with_capacity(batch_size);
+
+    while batch.len() < batch_size {
+        match zero_step_gather(tree, weights, use_value, fpu_mode, rng) {
+            Some(request) => batch.push(request),
+            // the descent ended on a terminal outcome, which was just backpropagated instead of
+            // producing a request; stop here and evaluate what has been gathered so far
+            None => break,
+        }
+    }
+
+    batch
+}
+
+/// Replays a batch of responses collected by [`zero_step_gather_batch`] through [`zero_step_apply`].
+/// Unlike MuZero's equivalent (see `muzero_step_apply_batch`), no separate virtual-loss undo step is
+/// needed here: `zero_step_apply`'s own `tree_propagate_values` already decrements `virtual_visits`
+/// alongside incrementing `complete_visits`, exactly as a non-batched apply would.
+pub fn zero_step_apply_batch<B: Board>(
+    tree: &mut Tree<B>,
+    responses: Vec<ZeroResponse<B>>,
+    root_noise: Option<(f32, f32)>,
+    rng: &mut impl Rng,
+) {
+    for response in responses {
+        zero_step_apply(tree, response, root_noise, rng);
+    }
+}
+
 /// The second half of a step. Applies a network evaluation to the given node,
 /// by setting the child policies and propagating the wdl back to the root.
 /// Along the way `virtual_visits` is decremented and `visits` is incremented.
-pub fn zero_step_apply<B: Board>(tree: &mut Tree<B>, response: ZeroResponse<B>) {
+///
+/// `root_noise`, when `Some((alpha, eps))` and `curr_node` is the root, mixes AlphaZero-style
+/// Dirichlet exploration noise into the priors just set (see [`add_root_dirichlet_noise`]) so self-play
+/// games started from the same root don't all pick the same line. Typical values are `alpha ~= 0.3` and
+/// `eps ~= 0.25` for board games; `None` reproduces the old noise-free behavior. Callers are expected to
+/// expose `alpha`/`eps` through `ZeroSettings` and only pass `Some(..)` for the actual root of a search.
+pub fn zero_step_apply<B: Board>(
+    tree: &mut Tree<B>,
+    response: ZeroResponse<B>,
+    root_noise: Option<(f32, f32)>,
+    rng: &mut impl Rng,
+) {
     // whether we are indeed expecting this node is checked based on (net_values) and (virtual_visits in propagate_values)
     let ZeroResponse {
         node: curr_node,
@@ -132,6 +197,38 @@ pub fn zero_step_apply<B: Board>(tree: &mut Tree<B>, response: ZeroResponse<B>)
     for (c, &p) in zip_eq_exact(children, eval.policy.as_ref()) {
         tree[c].net_policy = p;
     }
+
+    if curr_node == 0 {
+        if let Some((alpha, eps)) = root_noise {
+            add_root_dirichlet_noise(tree, children, alpha, eps, rng);
+        }
+    }
+}
+
+/// Mixes a single `Dir(alpha)` sample into the root's child priors, the standard AlphaZero-style root
+/// exploration noise: `p_a <- (1 - eps) * p_a + eps * eta_a`. `eta` is sampled the textbook way, drawing
+/// `g_a ~ Gamma(alpha, 1)` independently per legal move and normalizing `eta_a = g_a / sum(g)`, rather
+/// than pulling in a separate Dirichlet distribution just for this.
+fn add_root_dirichlet_noise<B: Board>(
+    tree: &mut Tree<B>,
+    children: IdxRange,
+    alpha: f32,
+    eps: f32,
+    rng: &mut impl Rng,
+) {
+    let move_count = children.length as usize;
+    if move_count <= 1 {
+        return;
+    }
+
+    let gamma = Gamma::new(alpha, 1.0).unwrap();
+    let samples: Vec<f32> = (0..move_count).map(|_| rng.sample(gamma)).collect();
+    let total: f32 = samples.iter().sum();
+
+    for (c, g) in zip_eq_exact(children, samples) {
+        let eta = g / total;
+        tree[c].net_policy = tree[c].net_policy * (1.0 - eps) + eta * eps;
+    }
 }
 
 /// Propagate the given `wdl` up to the root.
@@ -156,8 +253,19 @@ fn tree_propagate_values<B: Board>(tree: &mut Tree<B>, node: usize, mut values:
 }
 
 impl FpuMode {
-    pub fn select(&self, _parent: ZeroValuesPov) -> ZeroValuesPov {
-        todo!("implement again for muzero")
+    /// The Q-value substituted in for a child with `complete_visits == 0` during UCT selection.
+    /// `parent` is the parent node's own mean value in its POV; `policy_explored` is the summed prior
+    /// probability already claimed by the parent's visited children.
+    ///
+    /// `Fixed(v)` ignores the parent entirely and always returns the constant `v`. `Relative(r)`
+    /// implements the standard "FPU reduction": the parent's own value, reduced by `r * sqrt(policy_explored)`,
+    /// so untried moves look less attractive the more of the policy mass has already been explored.
+    /// The result is not clamped, so it can drop below the value of a loss.
+    pub fn select(&self, parent: ZeroValuesPov, policy_explored: f32) -> ZeroValuesPov {
+        match self {
+            FpuMode::Fixed(v) => parent.with_value(*v),
+            FpuMode::Relative(r) => parent.with_value(parent.value() - r * policy_explored.sqrt()),
+        }
     }
 }
 
@@ -170,3 +278,193 @@ impl<B> ZeroRequest<B> {
         }
     }
 }
+
+/// Tracks which node a board was first reached through, so the same board reached again by a
+/// different move order (common in games like Ataxx) can be recognized — mirroring the closed/visited
+/// set a graph-search router keeps to avoid re-expanding the same state.
+///
+/// This only implements the lookup/hit-counting side. Actually turning `Tree` into a DAG — sharing a
+/// single node's statistics between both paths that reach it — needs `Node`/`Tree`
+/// (`kz_core::zero::node`/`kz_core::zero::tree`, not present in this tree) to support multiple incoming
+/// parents (or per-edge visit counts), so `tree_propagate_values` can split its backprop across every
+/// edge that leads to a shared node instead of assuming the single `parent` it has today. Without that,
+/// linking a repeated board straight to its earlier node would silently corrupt that node's visit
+/// accounting, so [`zero_step_gather_with_tt`] below only records the hit for now; the tree it walks
+/// stays a strict tree. `transposition_hits` is exposed so it's visible how much a real DAG conversion
+/// would actually save before doing that work.
+///
+/// Not currently reachable from anywhere: `ZeroSettings`/`PipelinedZeroSettings`
+/// (`kz_core::zero::wrapper`, called from `alpha-zero`) have no transposition-table flag, and their own
+/// `zero_step_gather` call sites already don't match this file's current signature for that function —
+/// a separate, pre-existing divergence this type doesn't attempt to fix. Until both that and the
+/// multi-parent `Tree` gap above are closed, this is a standalone utility with no caller, not a gated
+/// feature; `zero_step_gather_with_tt` must be wired in by hand (see its own doc comment).
+#[derive(Debug, Default)]
+pub struct TranspositionTable {
+    seen: HashMap<u64, usize>,
+    pub transposition_hits: u64,
+}
+
+impl TranspositionTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn hash_of<B: Hash>(board: &B) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        board.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Records `node` as (one of) the node(s) reached by `board`, returning the node a previous call
+    /// already recorded for the same board hash, if any.
+    fn record<B: Hash>(&mut self, board: &B, node: usize) -> Option<usize> {
+        let hash = Self::hash_of(board);
+        let prev = self.seen.insert(hash, node);
+        if prev.is_some() {
+            self.transposition_hits += 1;
+        }
+        prev
+    }
+}
+
+/// Like [`zero_step_gather`], but also records every freshly expanded board in `tt` (see
+/// [`TranspositionTable`]) so repeated positions reached via different move orders get counted as
+/// transposition hits (see the type doc above for why a hit doesn't yet change how the tree is
+/// searched, and for why this isn't gated behind any `ZeroSettings` flag — there isn't one yet). Not
+/// called from anywhere in this tree; a caller wanting hit statistics today has to invoke this directly
+/// in place of [`zero_step_gather`] and thread a [`TranspositionTable`] through itself.
+pub fn zero_step_gather_with_tt<B: Board + Hash>(
+    tree: &mut Tree<B>,
+    weights: UctWeights,
+    use_value: bool,
+    fpu_mode: FpuMode,
+    tt: &mut TranspositionTable,
+    rng: &mut impl Rng,
+) -> Option<ZeroRequest<B>> {
+    let request = zero_step_gather(tree, weights, use_value, fpu_mode, rng)?;
+    tt.record(&request.board, request.node);
+    Some(request)
+}
+
+/// How many times each position from the *actual game so far* has already occurred, keyed by the same
+/// board hash [`TranspositionTable`] uses. Seeded once from the moves a caller has already replayed to
+/// reach the tree's root (e.g. `board_from_state`'s replay in `kz-lichess`); the tree itself only ever
+/// contains positions reachable *from* the root, so this is the one piece of repetition information a
+/// fresh search can't recover by walking its own nodes.
+#[derive(Debug, Clone, Default)]
+pub struct GameHistory {
+    occurrences: HashMap<u64, u8>,
+}
+
+impl GameHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one more occurrence of `board`, typically called once per ply while replaying a game's
+    /// move history up to, but not including, the current root position: the descent in
+    /// [`zero_step_gather_with_history`] counts the root itself as the first position on its path.
+    pub fn record<B: Hash>(&mut self, board: &B) {
+        *self.occurrences.entry(Self::hash_of(board)).or_insert(0) += 1;
+    }
+
+    fn hash_of<B: Hash>(board: &B) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        board.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Like [`zero_step_gather`], but a position that has already occurred twice before — whether earlier in
+/// the real game (`history`) or earlier on the path down from the tree root during this same
+/// descent (tracked locally, since two occurrences on one path are just as forced a draw as two spread
+/// across `history` and the path) — is a threefold repetition: a forced draw the side to move cannot
+/// avoid. Instead of requesting a network evaluation for it, its value is clamped to a draw and backed
+/// up immediately, exactly like the existing terminal-outcome branch in [`zero_step_gather`] does for a
+/// checkmate or stalemate.
+///
+/// `contempt` shifts that clamped draw value in the mover's own POV before converting back, the same way
+/// a human's appraisal of "is this draw good for me" would: positive steers the search away from
+/// repeating (appropriate for the stronger side, which would rather keep playing on), negative steers
+/// toward it (appropriate for the side that's worse off). This is the value half of what `ZeroSettings`
+/// is meant to expose as a single `contempt` field once `kz_core::zero::wrapper` is present in this
+/// checkout to add it to; until then callers thread the same number through here directly. Note this
+/// also assumes `ZeroValuesAbs` has a `pov(Player)` conversion back to `ZeroValuesPov`, the natural
+/// inverse of the `un_pov` already used by [`zero_step_apply`] above — `kz_core::zero::values` isn't
+/// present in this checkout to confirm the exact name against.
+///
+/// The fifty-move rule isn't handled here: that needs a halfmove-clock accessor `Board` doesn't expose
+/// in this checkout, so only repetition is detected for now.
+pub fn zero_step_gather_with_history<B: Board + Hash>(
+    tree: &mut Tree<B>,
+    weights: UctWeights,
+    use_value: bool,
+    fpu_mode: FpuMode,
+    history: &GameHistory,
+    contempt: f32,
+    rng: &mut impl Rng,
+) -> Option<ZeroRequest<B>> {
+    let mut curr_node = 0;
+    let mut curr_board = tree.root_board().clone();
+    let mut path_occurrences: HashMap<u64, u8> = HashMap::new();
+
+    loop {
+        tree[curr_node].virtual_visits += 1;
+
+        if let Some(outcome) = curr_board.outcome() {
+            tree_propagate_values(tree, curr_node, ZeroValuesAbs::from_outcome(outcome, 0.0));
+            return None;
+        }
+
+        let hash = GameHistory::hash_of(&curr_board);
+        let path_count = path_occurrences.entry(hash).or_insert(0);
+        *path_count += 1;
+        let prior_occurrences = history.occurrences.get(&hash).copied().unwrap_or(0) + *path_count - 1;
+
+        if prior_occurrences >= 2 {
+            let curr_player = curr_board.next_player();
+            let draw = ZeroValuesAbs::from_outcome(Outcome::Draw, 0.0);
+            let contempt_draw = draw.pov(curr_player).with_value(contempt).un_pov(curr_player);
+            tree_propagate_values(tree, curr_node, contempt_draw);
+            return None;
+        }
+
+        let children = match tree[curr_node].children {
+            None => {
+                let start = tree.len();
+                curr_board.available_moves().for_each(|mv| {
+                    tree.nodes.push(Node::new(Some(curr_node), Some(mv), 1.0));
+                });
+                let end = tree.len();
+
+                tree[curr_node].children = Some(IdxRange::new(start, end));
+                tree[curr_node].net_values = None;
+
+                return Some(ZeroRequest {
+                    board: curr_board,
+                    node: curr_node,
+                });
+            }
+            Some(children) => children,
+        };
+
+        let curr_player = curr_board.next_player();
+
+        let uct_context = tree.uct_context(curr_node);
+        let selected = choose_max_by_key(
+            children,
+            |&child| {
+                let uct = tree[child]
+                    .uct(uct_context, fpu_mode, use_value, curr_player)
+                    .total(weights);
+                N32::from_inner(uct)
+            },
+            rng,
+        )
+        .expect("Board is not done, this node should have a child");
+
+        curr_node = selected;
+        curr_board.play(tree[curr_node].last_move.unwrap());
+    }
+}
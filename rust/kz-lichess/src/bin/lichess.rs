@@ -1,4 +1,9 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use board_game::board::Board;
@@ -7,16 +12,22 @@ use board_game::util::pathfind::pathfind_exact_length;
 use itertools::Itertools;
 use rand::rngs::StdRng;
 use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
 use tokio_stream::StreamExt;
 
 use cuda_nn_eval::Device;
 use kz_core::mapping::chess::ChessStdMapper;
+use kz_core::network::cudnn::CudaNetwork;
 use kz_core::network::job_channel::job_pair;
+use kz_core::network::multibatch::MultiBatchNetwork;
+use kz_core::network::Network;
 use kz_core::zero::node::UctWeights;
 use kz_core::zero::step::{FpuMode, QMode};
 use kz_core::zero::tree::Tree;
 use kz_core::zero::wrapper::ZeroSettings;
-use kz_selfplay::server::executor::{alphazero_batched_executor_loop, RunCondition};
+use kz_selfplay::server::executor::{batched_executor_loop, ExecutorStats};
+use kz_util::math::ceil_div;
 use licorice::client::{Lichess, LichessResult};
 use licorice::models::board::{BoardState, GameFull};
 use licorice::models::game::UserGame;
@@ -26,40 +37,236 @@ use nn_graph::optimizer::{optimize_graph, OptimizerSettings};
 const MAX_VISITS: u64 = 10_000_000;
 const MAX_TIME: f32 = 60.0;
 const MAX_TIME_FRACTION: f32 = 1.2 / 30.0;
-const MAX_CACHE_SIZE: usize = 10;
 
-const EVAL_BATCH_SIZE: usize = 128;
+/// Whether to keep searching on the opponent's clock after playing a move, instead of idling until they
+/// reply. A compile-time switch read only by this binary; it is on here since the GPU would otherwise sit
+/// unused for the entire time the opponent is thinking.
+const PONDER: bool = true;
+
+/// Far larger than the old `VecDeque`'s `MAX_CACHE_SIZE = 10`: an exact-hash lookup no longer pays for
+/// a bigger cache with a longer per-move scan, so there's no reason to throw trees away this eagerly.
+const MAX_CACHE_SIZE: usize = 10_000;
+
+/// The eval batch sizes the shared executor pool is built with, exactly as `exp_tournament_chess` picks
+/// a spread of sizes via `MultiBatchNetwork::build_sizes` instead of a single fixed size — so however
+/// many games happen to be in flight at once, their evaluation requests coalesce into whichever batch
+/// size wastes the least GPU work, instead of every game being forced through one `EVAL_BATCH_SIZE`
+/// regardless of how many other games are currently using the executor.
+const EVAL_BATCH_SIZES: &[usize] = &[8 * 4, 64, 256, 512, 512 + 256];
 const SEARCH_BATCH_SIZE: usize = 128;
 
-type Cache = VecDeque<Tree<ChessBoard>>;
+const CACHE_PATH: &str = "ignored/transposition_cache.bin";
+const THROUGHPUT_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
 type EvalClient = kz_core::network::EvalClient<ChessBoard>;
 
+/// A transposition store keyed by `ChessStdMapper::board_hash` of a tree's root board, so a position
+/// reached through a different move order than the one that built its cached tree is still an O(1)
+/// lookup instead of the old `VecDeque`'s full linear scan.
+///
+/// Persisted to [`CACHE_PATH`] with serde, the way `kz_misc::bin::main`'s `SavedTree` already persists
+/// a single tree (this assumes `Tree<ChessBoard>` implements `Serialize`/`Deserialize`; `zero::tree`
+/// isn't present in this checkout to add that derive to, so this is written against the bound that
+/// derive would produce). The route-cache idea (persist the whole accumulated table, fingerprint-gate
+/// reloading it) is borrowed from ED_LRR. The file is only trusted if its [`CacheFingerprint`] matches
+/// the network path, mapper, and settings this run starts with; any mismatch means the previous trees
+/// could be scored against a different network, so they're discarded instead of risking a corrupted
+/// search.
+struct TranspositionCache {
+    trees: HashMap<u64, Tree<ChessBoard>>,
+    /// Insertion order, oldest first, so eviction once `MAX_CACHE_SIZE` is exceeded has something
+    /// better to go on than the arbitrary order a `HashMap` iterates in.
+    insertion_order: VecDeque<u64>,
+    fingerprint: CacheFingerprint,
+    last_saved: Instant,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+struct CacheFingerprint(Vec<u8>);
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    fingerprint: CacheFingerprint,
+    trees: Vec<(u64, Tree<ChessBoard>)>,
+}
+
+impl CacheFingerprint {
+    /// SHA3-256 of the network path, mapper type name, and settings' `Debug` output, so the cache is
+    /// only ever reloaded against the exact configuration that produced it.
+    fn compute(network_path: &str, settings: &ZeroSettings) -> Self {
+        let mut hasher = Sha3_256::new();
+        hasher.update(network_path.as_bytes());
+        hasher.update(std::any::type_name::<ChessStdMapper>().as_bytes());
+        hasher.update(format!("{:?}", settings).as_bytes());
+        CacheFingerprint(hasher.finalize().to_vec())
+    }
+}
+
+impl TranspositionCache {
+    /// Loads [`CACHE_PATH`] if it exists and its fingerprint matches, giving a restarted bot (or a
+    /// fresh tournament run) a warm start instead of throwing away every tree from the previous run.
+    fn load_or_new(fingerprint: CacheFingerprint) -> Self {
+        let loaded = File::open(CACHE_PATH)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, CacheFile>(BufReader::new(file)).ok())
+            .filter(|cache_file| cache_file.fingerprint == fingerprint);
+
+        match loaded {
+            Some(cache_file) => {
+                println!("Reloaded {} cached trees from {}", cache_file.trees.len(), CACHE_PATH);
+                let insertion_order = cache_file.trees.iter().map(|&(hash, _)| hash).collect();
+                TranspositionCache {
+                    trees: cache_file.trees.into_iter().collect(),
+                    insertion_order,
+                    fingerprint,
+                    last_saved: Instant::now(),
+                }
+            }
+            None => TranspositionCache {
+                trees: HashMap::new(),
+                insertion_order: VecDeque::new(),
+                fingerprint,
+                last_saved: Instant::now(),
+            },
+        }
+    }
+
+    /// Fast path: an O(1) exact lookup on `board_hash`. Slow path, only reached on a miss: scan the
+    /// remaining trees for a 1-2 ply ancestor `board` can still be reached from, reusing that shared
+    /// subtree instead of starting from scratch (the same fallback `pop_cache_match` used to always
+    /// pay for on every single move).
+    fn pop_match(&mut self, board: &ChessBoard, board_hash: u64) -> Option<Tree<ChessBoard>> {
+        if let Some(tree) = self.trees.remove(&board_hash) {
+            self.insertion_order.retain(|&hash| hash != board_hash);
+            return Some(tree);
+        }
+
+        let ancestor_hash = self.trees.iter().find_map(|(&hash, old_tree)| {
+            let moves = pathfind_exact_length(old_tree.root_board(), board, 1)
+                .or_else(|| pathfind_exact_length(old_tree.root_board(), board, 2))?;
+            Some((hash, moves))
+        });
+
+        if let Some((hash, moves)) = ancestor_hash {
+            let old_tree = self.trees.remove(&hash).unwrap();
+            self.insertion_order.retain(|&h| h != hash);
+            if let Ok(new_tree) = old_tree.keep_moves(&moves) {
+                return Some(new_tree);
+            }
+        }
+
+        None
+    }
+
+    fn insert(&mut self, board_hash: u64, tree: Tree<ChessBoard>) {
+        self.trees.insert(board_hash, tree);
+        self.insertion_order.push_back(board_hash);
+
+        while self.insertion_order.len() > MAX_CACHE_SIZE {
+            if let Some(oldest) = self.insertion_order.pop_front() {
+                self.trees.remove(&oldest);
+            }
+        }
+    }
+
+    /// Persists the cache at most once a minute; called after every move, so a crash or restart never
+    /// loses more than that much search progress.
+    fn save_if_due(&mut self) {
+        if self.last_saved.elapsed() < Duration::from_secs(60) {
+            return;
+        }
+        self.last_saved = Instant::now();
+
+        if let Some(parent) = Path::new(CACHE_PATH).parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
+        let file = match File::create(CACHE_PATH) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Failed to open {} for writing: {:?}", CACHE_PATH, e);
+                return;
+            }
+        };
+
+        let cache_file = CacheFile {
+            fingerprint: self.fingerprint.clone(),
+            trees: self.trees.iter().map(|(&hash, tree)| (hash, tree.clone())).collect(),
+        };
+        if let Err(e) = serde_json::to_writer(BufWriter::new(file), &cache_file) {
+            println!("Failed to save transposition cache: {:?}", e);
+        }
+    }
+}
+
 fn main() {
     println!("Loading graph");
     let path = std::fs::read_to_string("ignored/network_path.txt").unwrap();
     let graph = optimize_graph(
-        &load_graph_from_onnx_path(path, false).unwrap(),
+        &load_graph_from_onnx_path(path.clone(), false).unwrap(),
         OptimizerSettings::default(),
     );
 
-    println!("Spawning executor");
-    let (eval_client, eval_server) = job_pair(4);
+    println!("Spawning executor pool");
+    let device = Device::new(0);
+    let max_eval_batch_size = EVAL_BATCH_SIZES.iter().copied().max().unwrap();
+    let (eval_client, eval_server) = job_pair(4 * ceil_div(max_eval_batch_size, SEARCH_BATCH_SIZE));
+    let (fill_sender, fill_receiver) = flume::unbounded::<(usize, usize)>();
+
     std::thread::Builder::new()
         .name("executor".to_owned())
         .spawn(move || {
-            alphazero_batched_executor_loop(
-                EVAL_BATCH_SIZE,
-                Device::new(0),
-                ChessStdMapper,
-                RunCondition::Any,
-                graph,
+            let (graph_sender, graph_receiver) = flume::bounded(1);
+            graph_sender.send(Some(graph)).unwrap();
+            drop(graph_sender);
+
+            let stats = Arc::new(ExecutorStats::default());
+            batched_executor_loop(
+                max_eval_batch_size,
+                graph_receiver,
                 eval_server,
+                stats,
+                move |graph| {
+                    MultiBatchNetwork::build_sizes(EVAL_BATCH_SIZES, |size| CudaNetwork::new(ChessStdMapper, &graph, size, device))
+                },
+                move |network, batch_x| {
+                    let result = network.evaluate_batch(&batch_x);
+                    let max_size = network.used_batch_size(batch_x.len());
+                    fill_sender.send((batch_x.len(), max_size)).unwrap();
+                    result
+                },
             );
 
             println!("Executor exit");
         })
         .unwrap();
 
+    // reports aggregate GPU throughput/fill across every concurrent game, the same way
+    // `exp_tournament_chess`'s `on_print` does, just on a timer instead of once per round
+    std::thread::Builder::new()
+        .name("throughput-reporter".to_owned())
+        .spawn(move || {
+            let mut total_filled = 0u64;
+            loop {
+                std::thread::sleep(THROUGHPUT_REPORT_INTERVAL);
+
+                let mut delta_filled = 0u64;
+                let mut delta_potential = 0u64;
+                for (filled, potential) in fill_receiver.try_iter() {
+                    total_filled += filled as u64;
+                    delta_filled += filled as u64;
+                    delta_potential += potential as u64;
+                }
+
+                let throughput = delta_potential as f32 / THROUGHPUT_REPORT_INTERVAL.as_secs_f32();
+                let fill = delta_filled as f32 / delta_potential.max(1) as f32;
+                println!(
+                    "  throughput: {:.1} evals/s, fill {:.2} => {} evals",
+                    throughput, fill, total_filled
+                );
+            }
+        })
+        .unwrap();
+
     let settings = ZeroSettings::simple(
         SEARCH_BATCH_SIZE,
         UctWeights::default(),
@@ -68,32 +275,44 @@ fn main() {
     );
     println!("Using {:?}", settings);
 
-    let mut cache = Cache::default();
+    let fingerprint = CacheFingerprint::compute(&path, &settings);
+    let cache = Arc::new(Mutex::new(TranspositionCache::load_or_new(fingerprint)));
 
     println!("Starting runtime");
-    tokio::runtime::Builder::new_current_thread()
+    tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap()
-        .block_on(async { main_async(settings, &eval_client, &mut cache).await })
+        .block_on(async { main_async(settings, &eval_client, &cache).await })
 }
 
-async fn main_async(settings: ZeroSettings, eval_client: &EvalClient, cache: &mut Cache) {
+async fn main_async(settings: ZeroSettings, eval_client: &EvalClient, cache: &Arc<Mutex<TranspositionCache>>) {
     loop {
         if let Err(e) = main_inner(settings, eval_client, cache).await {
             println!("Got error {:?}", e);
         }
 
-        std::thread::sleep(Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_secs(5)).await;
     }
 }
 
-async fn main_inner(settings: ZeroSettings, eval_client: &EvalClient, cache: &mut Cache) -> LichessResult<()> {
+async fn main_inner(
+    settings: ZeroSettings,
+    eval_client: &EvalClient,
+    cache: &Arc<Mutex<TranspositionCache>>,
+) -> LichessResult<()> {
     println!("Connecting to lichess");
     let token = std::fs::read_to_string("ignored/lichess_token.txt")?;
     let lichess = Lichess::new(token);
 
-    let mut info_game_ids: HashSet<String> = Default::default();
+    let info_game_ids: Arc<Mutex<HashSet<String>>> = Default::default();
+    // games with a turn-handling task already in flight, so a game already being searched doesn't get
+    // handed off to a second concurrent task the next time the ongoing-games list is polled
+    let in_flight: Arc<Mutex<HashSet<String>>> = Default::default();
+    // the stop flag for each game's currently running ponder task, if any; `game.is_my_turn` flipping
+    // back to true is the earliest this polling loop can learn the opponent has replied, so that's the
+    // signal used to end the ponder rather than waiting for `handle_game_turn` to actually run
+    let ponder_stops: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>> = Default::default();
 
     loop {
         let mut was_my_turn = false;
@@ -101,61 +320,118 @@ async fn main_inner(settings: ZeroSettings, eval_client: &EvalClient, cache: &mu
         // the games are already sorted by urgency by the lichess API
         let games = lichess.get_ongoing_games(50).await?;
 
-        // TODO this loop should really spawn a bunch of distinct async jobs
-        //   to fully make use of this we really need executors for multiple batch sizes
+        // one task per ongoing game, instead of handling them one at a time: each game gets its own
+        // `Tree`/time budget and feeds the same shared, batch-size-aware executor pool, so several
+        // simultaneous games actually coalesce into large GPU batches instead of queuing behind each
+        // other
         for game in games {
             if !game.is_my_turn {
                 continue;
             }
-            was_my_turn = true;
 
-            let mut state_stream = lichess.stream_bot_game_state(&game.game_id).await?;
-            if let Some(state) = state_stream.next().await.transpose()? {
-                println!("{:?}", state);
+            stop_pondering(&ponder_stops, &game.game_id);
 
-                match state {
-                    BoardState::GameState(state) => {
-                        println!("Received partial state {:?}", state);
-                    }
-                    BoardState::ChatLine(line) => {
-                        println!("Received {:?}", line);
-
-                        match &*line.text {
-                            "info start" => {
-                                info_game_ids.insert(game.game_id.clone());
-                            }
-                            "info stop" => {
-                                info_game_ids.remove(&game.game_id);
-                            }
-                            _ => {}
-                        }
-                    }
-                    BoardState::GameFull(state) => {
-                        let print = info_game_ids.contains(&state.id);
-                        make_move(&lichess, &game, &state, print, settings, eval_client, cache).await?;
-                    }
-                }
+            if !in_flight.lock().unwrap().insert(game.game_id.clone()) {
+                // already being handled by a task spawned on an earlier poll
+                continue;
             }
+            was_my_turn = true;
+
+            let lichess = lichess.clone();
+            let eval_client = eval_client.clone();
+            let cache = Arc::clone(cache);
+            let info_game_ids = Arc::clone(&info_game_ids);
+            let in_flight = Arc::clone(&in_flight);
+            let ponder_stops = Arc::clone(&ponder_stops);
+            let game_id = game.game_id.clone();
+
+            tokio::spawn(async move {
+                if let Err(e) = handle_game_turn(
+                    &lichess,
+                    &game,
+                    settings,
+                    &eval_client,
+                    &cache,
+                    &info_game_ids,
+                    &ponder_stops,
+                )
+                .await
+                {
+                    println!("Error handling game {}: {:?}", game_id, e);
+                }
+                in_flight.lock().unwrap().remove(&game_id);
+            });
         }
 
         if !was_my_turn {
             // wait for a bit
-            std::thread::sleep(Duration::from_secs(1));
+            tokio::time::sleep(Duration::from_secs(1)).await;
         }
     }
 }
 
-fn pop_cache_match(board: &ChessBoard, cache: &mut Cache) -> Option<Tree<ChessBoard>> {
-    for (i, old_tree) in cache.iter().enumerate() {
-        if let Some(moves) = pathfind_exact_length(old_tree.root_board(), board, 2) {
-            if let Ok(new_tree) = old_tree.keep_moves(&moves) {
-                cache.remove(i);
-                return Some(new_tree);
+/// Whether a `GameState.status` string (see the lichess board API docs) means the game is still being
+/// played, as opposed to having ended by mate/resign/draw/abort/timeout/etc.
+fn status_is_ongoing(status: &str) -> bool {
+    matches!(status, "created" | "started")
+}
+
+/// Removes and signals `game_id`'s ponder stop flag, if a ponder task is currently running for it.
+fn stop_pondering(ponder_stops: &Mutex<HashMap<String, Arc<AtomicBool>>>, game_id: &str) {
+    if let Some(stop) = ponder_stops.lock().unwrap().remove(game_id) {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Fetches (at most) one pending state update for `game` and, if it's a full board state, searches and
+/// plays a move. Spawned as its own task per game by [`main_inner`] so multiple games are handled
+/// concurrently instead of one at a time.
+async fn handle_game_turn(
+    lichess: &Lichess,
+    game: &UserGame,
+    settings: ZeroSettings,
+    eval_client: &EvalClient,
+    cache: &Arc<Mutex<TranspositionCache>>,
+    info_game_ids: &Mutex<HashSet<String>>,
+    ponder_stops: &Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+) -> LichessResult<()> {
+    let mut state_stream = lichess.stream_bot_game_state(&game.game_id).await?;
+    if let Some(state) = state_stream.next().await.transpose()? {
+        println!("{:?}", state);
+
+        match state {
+            BoardState::GameState(state) => {
+                println!("Received partial state {:?}", state);
+
+                // this is the event carrying the game's terminal status (mate/resign/draw/abort/...);
+                // the ongoing-games poll loop in `main_inner` never sees the game again once it ends, so
+                // this is the only place a finished game's ponder task gets cancelled instead of running
+                // all the way to `MAX_VISITS`
+                if !status_is_ongoing(&state.status) {
+                    stop_pondering(ponder_stops, &game.game_id);
+                }
+            }
+            BoardState::ChatLine(line) => {
+                println!("Received {:?}", line);
+
+                match &*line.text {
+                    "info start" => {
+                        info_game_ids.lock().unwrap().insert(game.game_id.clone());
+                    }
+                    "info stop" => {
+                        info_game_ids.lock().unwrap().remove(&game.game_id);
+                    }
+                    _ => {}
+                }
+            }
+            BoardState::GameFull(state) => {
+                let print = info_game_ids.lock().unwrap().contains(&state.id);
+                make_move(lichess, game, &state, print, settings, eval_client, cache, ponder_stops).await?;
             }
         }
     }
 
-    None
+    Ok(())
 }
 
 async fn make_move(
@@ -165,12 +441,15 @@ async fn make_move(
     info: bool,
     settings: ZeroSettings,
     eval_client: &EvalClient,
-    cache: &mut Cache,
+    cache: &Arc<Mutex<TranspositionCache>>,
+    ponder_stops: &Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
 ) -> LichessResult<()> {
     let board = board_from_state(state);
     println!("{}", board);
 
-    let mut tree = match pop_cache_match(&board, cache) {
+    let board_hash = ChessStdMapper.board_hash(&board);
+
+    let mut tree = match cache.lock().unwrap().pop_match(&board, board_hash) {
         Some(tree) => {
             println!("Reusing tree with {} nodes", tree.root_visits());
             tree
@@ -210,6 +489,36 @@ async fn make_move(
         println!("Error while playing move: {:?}", e);
     }
 
+    if PONDER {
+        if let Ok(ponder_tree) = tree.clone().keep_moves(&[mv]) {
+            let mut ponder_board = board.clone();
+            ponder_board.play(mv);
+            let ponder_hash = ChessStdMapper.board_hash(&ponder_board);
+
+            let stop = Arc::new(AtomicBool::new(false));
+            ponder_stops.lock().unwrap().insert(game.game_id.clone(), Arc::clone(&stop));
+
+            let eval_client = eval_client.clone();
+            let cache = Arc::clone(cache);
+            let ponder_stops = Arc::clone(ponder_stops);
+            let game_id = game.game_id.clone();
+            let mut ponder_tree = ponder_tree;
+
+            tokio::spawn(async move {
+                let mut rng = StdRng::from_entropy();
+                settings
+                    .expand_tree_async(&mut ponder_tree, &eval_client, &mut rng, |tree| {
+                        stop.load(Ordering::Relaxed) || tree.root_visits() >= MAX_VISITS
+                    })
+                    .await;
+
+                println!("Ponder for {} reached {} nodes", game_id, ponder_tree.root_visits());
+                cache.lock().unwrap().insert(ponder_hash, ponder_tree);
+                ponder_stops.lock().unwrap().remove(&game_id);
+            });
+        }
+    }
+
     if info {
         let pv = tree.principal_variation(3).iter().skip(1).join(" ");
 
@@ -231,10 +540,9 @@ async fn make_move(
         lichess.write_in_bot_chat(&game.game_id, "player", &message).await?;
     }
 
-    cache.push_back(tree);
-    while cache.len() > MAX_CACHE_SIZE {
-        cache.pop_front();
-    }
+    let mut cache = cache.lock().unwrap();
+    cache.insert(board_hash, tree);
+    cache.save_if_due();
 
     Ok(())
 }
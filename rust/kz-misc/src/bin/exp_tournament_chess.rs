@@ -2,9 +2,9 @@ use std::collections::HashSet;
 use std::path::PathBuf;
 use std::time::Instant;
 
-use board_game::board::{Board, Player};
+use board_game::board::Board;
 use board_game::games::ataxx::AtaxxBoard;
-use board_game::games::chess::{chess_game_to_pgn, ChessBoard};
+use board_game::games::chess::ChessBoard;
 use board_game::util::board_gen::random_board_with_moves;
 use itertools::Itertools;
 use rand::rngs::StdRng;
@@ -22,7 +22,7 @@ use kz_core::zero::node::UctWeights;
 use kz_core::zero::step::{FpuMode, QMode};
 use kz_core::zero::wrapper::{AsyncZeroBot, ZeroSettings};
 use kz_misc::convert::pt_to_onnx::convert_pt_to_onnx;
-use kz_misc::eval::tournament::{box_bot, run_tournament, BoxBotFn};
+use kz_misc::eval::tournament::{box_bot, run_tournament_sprt, BoxBotFn, SprtConfig};
 use kz_selfplay::server::executor::{batched_executor_loop, RunCondition};
 use kz_util::math::ceil_div;
 
@@ -201,33 +201,23 @@ fn main() {
         }
     };
 
-    let result = run_tournament(bots, positions, Some(6), false, true, on_print);
-
-    println!("Rounds:");
-    for round in &result.rounds {
-        // println!("  Round {:?}:", round.id);
-        // println!("    start: {:?}", round.start);
-        // println!("    moves: {:?}", round.moves);
-        // println!("    outcome: {:?}", round.outcome);
-
-        // chess_game_to_pgn("white","black")
-
-        let (white_id, black_id) = match round.start.next_player() {
-            Player::A => (round.id.i, round.id.j),
-            Player::B => (round.id.j, round.id.i),
-        };
-        let name_white = &result.bot_names[white_id];
-        let name_black = &result.bot_names[black_id];
-
-        println!("[Event \"{:?}\"]", round.id);
-        println!(
-            "{}",
-            chess_game_to_pgn(name_white, name_black, &round.start, &round.moves)
-        );
-    }
+    // this binary only ever has two networks active at a time ("wdl" vs "value" above), which is
+    // exactly the shape an SPRT A/B test needs, so run the sequential-testing mode directly instead of
+    // a fixed-round schedule
+    let mut bots = bots.into_iter();
+    let (name_a, bot_a) = bots.next().expect("need at least two bots to compare");
+    let (name_b, bot_b) = bots.next().expect("need at least two bots to compare");
+    assert!(bots.next().is_none(), "SPRT only compares exactly two bots at a time");
+
+    // elo0 = 0 ("no difference"), elo1 = 10 ("meaningfully better"), the standard fishtest-style pair
+    // of hypotheses for a change that's expected to help if it does anything at all
+    let sprt = SprtConfig::new(0.0, 10.0);
+    let result = run_tournament_sprt(bot_a, bot_b, &positions, sprt, |progress| {
+        println!("{} vs {}: {}", name_a, name_b, progress);
+    });
 
     println!("Result:");
-    println!("{}", result);
+    println!("{} vs {}: {}", name_a, name_b, result);
 }
 
 #[allow(dead_code)]
@@ -1,8 +1,14 @@
 use std::cmp::{max, min, Reverse};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashSet;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
 
 use board_game::board::{Board, Outcome, Player};
 use board_game::games::ataxx::AtaxxBoard;
+use clap::Parser;
 use crossterm::event::{
     DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind,
 };
@@ -12,6 +18,7 @@ use decorum::N32;
 use itertools::Itertools;
 use rand::rngs::StdRng;
 use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
 use tui::backend::CrosstermBackend;
 use tui::buffer::Buffer;
 use tui::layout::{Margin, Rect};
@@ -22,7 +29,6 @@ use tui::Terminal;
 use cuda_nn_eval::Device;
 use kz_core::mapping::ataxx::AtaxxStdMapper;
 use kz_core::network::cudnn::CudaNetwork;
-use kz_core::network::dummy::DummyNetwork;
 use kz_core::zero::node::{Uct, UctWeights};
 use kz_core::zero::step::FpuMode;
 use kz_core::zero::tree::Tree;
@@ -33,16 +39,54 @@ use kz_util::throughput::PrintThroughput;
 use nn_graph::onnx::load_graph_from_onnx_path;
 use nn_graph::optimizer::optimize_graph;
 
+/// Default number of children kept per expanded node when [`State::beam_width`] is set, per the "beam
+/// overview" mode: enough to see the fight for the best move without drowning in low-visit siblings.
+const DEFAULT_BEAM_WIDTH: usize = 3;
+
+/// Which stat [`State::append_nodes`] ranks a node's children by, cycled through by [`State::handle_event`]
+/// on `s`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ChildSortKey {
+    Visits,
+    Uct,
+    Policy,
+}
+
+impl ChildSortKey {
+    fn next(self) -> Self {
+        match self {
+            ChildSortKey::Visits => ChildSortKey::Uct,
+            ChildSortKey::Uct => ChildSortKey::Policy,
+            ChildSortKey::Policy => ChildSortKey::Visits,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct State<B: Board> {
     tree: Tree<B>,
 
     prev_nodes: Vec<RenderNode>,
+    /// Greedy `argmax total_visits` descent from the root, recomputed by `prepare_render`. Always shown
+    /// and highlighted regardless of `expanded_nodes`/`beam_width`, and the jump target for the PV hotkey.
+    pv_chain: Vec<usize>,
 
     expanded_nodes: HashSet<usize>,
     selected_node: usize,
 
     view_offset: usize,
+
+    /// Caps the number of children shown per expanded node to the top `beam_width` by
+    /// [`State::child_sort_key`]; a child on the PV chain is shown even beyond the cap. `None`
+    /// shows every child, reproducing the old behavior.
+    beam_width: Option<usize>,
+
+    /// Which stat [`State::append_nodes`] sorts each node's children by.
+    child_sort_key: ChildSortKey,
+
+    /// Column groups (by [`COLUMN_INFO`] first field) the user explicitly toggled off via hotkey, on top
+    /// of whatever [`State::visible_columns`] additionally drops under width pressure.
+    hidden_groups: HashSet<&'static str>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -51,13 +95,125 @@ struct RenderNode {
     node: usize,
 }
 
+#[derive(Debug, clap::Parser)]
+struct Args {
+    /// Path to the ONNX network to search with. Required unless `--load` reopens an already-saved tree.
+    #[clap(long)]
+    network: Option<String>,
+    #[clap(long, default_value = "1000000")]
+    visits: u64,
+    #[clap(long, default_value = "1024")]
+    batch_size: usize,
+
+    /// Reopen a tree previously written by `--save` instead of running a new search.
+    #[clap(long)]
+    load: Option<PathBuf>,
+    /// Write the built (or reopened) tree to this path once the search finishes.
+    #[clap(long)]
+    save: Option<PathBuf>,
+}
+
+/// Header stored alongside a serialized [`Tree`] so [`load_tree`] can warn if it's being reopened
+/// against a different network than the one that produced it, analogous to how a precomputed result
+/// cache keys on a content hash before trusting an entry.
+#[derive(Debug, Serialize, Deserialize)]
+struct TreeFileHeader {
+    /// `DefaultHasher` digest of the ONNX file's raw bytes, the same hashing scheme `zero::step` already
+    /// uses to key search-tree nodes by board.
+    network_hash: u64,
+    network_path: Option<String>,
+    visits: u64,
+    batch_size: usize,
+}
+
+/// On-disk representation of a `.tree` file.
+///
+/// Note: this assumes `Tree<AtaxxBoard>` implements [`Serialize`]/[`Deserialize`]. `zero::tree` isn't
+/// present in this checkout to add that derive to, so [`save_tree`]/[`load_tree`] are written against
+/// the bound that derive would produce; wiring them up is just adding
+/// `#[derive(Serialize, Deserialize)]` to `Tree` once that file exists.
+#[derive(Debug, Deserialize)]
+struct SavedTree {
+    header: TreeFileHeader,
+    tree: Tree<AtaxxBoard>,
+}
+
+/// Borrowed mirror of [`SavedTree`] so saving doesn't need `Tree` to implement `Clone`.
+#[derive(Debug, Serialize)]
+struct SavedTreeRef<'a> {
+    header: &'a TreeFileHeader,
+    tree: &'a Tree<AtaxxBoard>,
+}
+
+fn save_tree(path: &std::path::Path, header: &TreeFileHeader, tree: &Tree<AtaxxBoard>) -> std::io::Result<()> {
+    let file = File::create(path)?;
+    let saved = SavedTreeRef { header, tree };
+    serde_json::to_writer(BufWriter::new(file), &saved).expect("failed to serialize tree");
+    Ok(())
+}
+
+/// Loads a `.tree` file, warning (but not refusing) if `expected_network_hash` doesn't match the hash
+/// the tree was saved with.
+fn load_tree(path: &std::path::Path, expected_network_hash: Option<u64>) -> std::io::Result<Tree<AtaxxBoard>> {
+    let file = File::open(path)?;
+    let saved: SavedTree = serde_json::from_reader(BufReader::new(file)).expect("failed to parse tree");
+
+    if let Some(expected_hash) = expected_network_hash {
+        if saved.header.network_hash != expected_hash {
+            println!(
+                "Warning: network hash mismatch, tree was saved against a different network than {:?}",
+                saved.header.network_path,
+            );
+        }
+    }
+
+    Ok(saved.tree)
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
 fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+
+    let tree = if let Some(load_path) = &args.load {
+        let expected_hash = args
+            .network
+            .as_ref()
+            .map(|path| hash_bytes(&std::fs::read(path).expect("failed to read network file")));
+        load_tree(load_path, expected_hash)?
+    } else {
+        let network_path = args.network.as_deref().expect("--network is required unless --load is set");
+        build_tree(network_path, args.visits, args.batch_size)
+    };
+
+    if let Some(save_path) = &args.save {
+        let (network_hash, network_path) = match &args.network {
+            Some(path) => (hash_bytes(&std::fs::read(path).expect("failed to read network file")), Some(path.clone())),
+            None => (0, None),
+        };
+        let header = TreeFileHeader {
+            network_hash,
+            network_path,
+            visits: args.visits,
+            batch_size: args.batch_size,
+        };
+        save_tree(save_path, &header, &tree)?;
+    }
+
     let mut state = State {
         prev_nodes: vec![],
-        tree: build_tree(true),
+        pv_chain: vec![],
+        tree,
         expanded_nodes: HashSet::default(),
         selected_node: 0,
         view_offset: 0,
+        beam_width: Some(DEFAULT_BEAM_WIDTH),
+        child_sort_key: ChildSortKey::Visits,
+        hidden_groups: HashSet::default(),
     };
 
     state.expanded_nodes.insert(0);
@@ -110,19 +266,80 @@ impl<B: Board> State<B> {
     fn append_nodes(&self, curr: usize, depth: u32, result: &mut Vec<RenderNode>) {
         result.push(RenderNode { depth, node: curr });
 
-        if self.expanded_nodes.contains(&curr) {
+        // the PV chain is always expanded, even if collapsed by the user or trimmed by the beam width
+        if self.expanded_nodes.contains(&curr) || self.pv_chain.contains(&curr) {
             if let Some(children) = self.tree[curr].children {
                 let sorted_children = children
                     .iter()
-                    .sorted_by_key(|&c| Reverse((self.tree[c].total_visits(), N32::from(self.tree[c].net_policy))));
-                for c in sorted_children {
+                    .sorted_by_key(|&c| Reverse(self.child_sort_value(curr, c)));
+                let beam_width = self.beam_width.unwrap_or(usize::MAX);
+                let shown = sorted_children
+                    .enumerate()
+                    .filter(|&(rank, c)| rank < beam_width || self.pv_chain.contains(&c))
+                    .map(|(_, c)| c);
+                for c in shown {
                     self.append_nodes(c, depth + 1, result);
                 }
             }
         }
     }
 
+    /// The stat `append_nodes` ranks `child` (a child of `parent`) by, per `self.child_sort_key`.
+    fn child_sort_value(&self, parent: usize, child: usize) -> N32 {
+        match self.child_sort_key {
+            ChildSortKey::Visits => N32::from(self.tree[child].total_visits() as f32),
+            ChildSortKey::Uct => {
+                let parent = &self.tree[parent];
+                let uct = self.tree[child].uct(parent.total_visits(), parent.values(), false);
+                N32::from(uct.v)
+            }
+            ChildSortKey::Policy => N32::from(self.tree[child].net_policy),
+        }
+    }
+
+    /// Repeatedly descends from `start` into the child with the highest `complete_visits` (ties broken
+    /// by `uct().v`), returning every node visited along the way, `start` included. Used by the
+    /// follow-PV hotkey to jump straight to the engine's preferred line from wherever the cursor is,
+    /// without manually expanding each node.
+    fn follow_pv(&self, start: usize) -> Vec<usize> {
+        let mut chain = vec![start];
+
+        while let Some(children) = self.tree[*chain.last().unwrap()].children {
+            let parent = &self.tree[*chain.last().unwrap()];
+            let best = children.iter().max_by_key(|&c| {
+                let child = &self.tree[c];
+                let uct = child.uct(parent.total_visits(), parent.values(), false);
+                (child.complete_visits, N32::from(uct.v))
+            });
+
+            match best {
+                Some(best) if self.tree[best].complete_visits > 0 => chain.push(best),
+                _ => break,
+            }
+        }
+
+        chain
+    }
+
+    /// Greedy `argmax total_visits` descent from the root, stopping at a childless node or a child with
+    /// zero visits.
+    fn compute_pv(&self) -> Vec<usize> {
+        let mut chain = vec![0];
+
+        while let Some(children) = self.tree[*chain.last().unwrap()].children {
+            let best = children.iter().max_by_key(|&c| self.tree[c].total_visits());
+            match best {
+                Some(best) if self.tree[best].total_visits() > 0 => chain.push(best),
+                _ => break,
+            }
+        }
+
+        chain
+    }
+
     fn prepare_render(&mut self, area: Rect) {
+        self.pv_chain = self.compute_pv();
+
         // collect nodes
         let mut nodes = std::mem::take(&mut self.prev_nodes);
         nodes.clear();
@@ -176,6 +393,38 @@ impl<B: Board> State<B> {
                         }
                     }
                 }
+                KeyCode::Char('p') => {
+                    self.selected_node = *self.pv_chain.last().unwrap();
+                }
+                KeyCode::Char('f') => {
+                    let chain = self.follow_pv(self.selected_node);
+                    self.expanded_nodes.extend(&chain);
+                    self.selected_node = *chain.last().unwrap();
+                }
+                KeyCode::Char('s') => {
+                    self.child_sort_key = self.child_sort_key.next();
+                }
+                KeyCode::Char(']') => {
+                    self.beam_width = Some(self.beam_width.map_or(1, |w| w + 1));
+                }
+                KeyCode::Char('[') => {
+                    self.beam_width = self.beam_width.and_then(|w| (w > 1).then(|| w - 1));
+                }
+                KeyCode::Char('b') => {
+                    self.beam_width = match self.beam_width {
+                        Some(_) => None,
+                        None => Some(DEFAULT_BEAM_WIDTH),
+                    };
+                }
+                KeyCode::Char('1') => self.toggle_group("Zero"),
+                KeyCode::Char('2') => self.toggle_group("Net"),
+                KeyCode::Char('3') => self.toggle_group("Uct"),
+                KeyCode::Char('e') => {
+                    self.export_dot(std::path::Path::new("tree.dot"))
+                        .expect("failed to write tree.dot");
+                    self.export_records(std::path::Path::new("tree.csv"))
+                        .expect("failed to write tree.csv");
+                }
                 _ => (),
             },
             Event::Mouse(mouse) => {
@@ -193,17 +442,61 @@ impl<B: Board> State<B> {
         }
     }
 
-    fn compute_col_starts(&self, area: Rect) -> (Vec<u16>, Vec<u16>) {
-        let mut col_sizes = vec![0; 1 + COLUMN_INFO.len()];
-        col_sizes[0] = 20;
+    fn toggle_group(&mut self, group: &'static str) {
+        if !self.hidden_groups.remove(group) {
+            self.hidden_groups.insert(group);
+        }
+    }
+
+    /// Picks which [`COLUMN_INFO`] indices to render: columns outside [`COLUMN_GROUPS`] (`Node`/`Move`/
+    /// `T`/`Visits`) are never hidden, group columns explicitly toggled off via [`Self::toggle_group`]
+    /// are always dropped, and on top of that whole groups are dropped lowest-priority-first (the order
+    /// of [`COLUMN_GROUPS`]) until the remaining columns' minimum (header) widths fit `area`.
+    fn visible_columns(&self, area: Rect) -> Vec<usize> {
+        let mut hidden = self.hidden_groups.clone();
+
+        loop {
+            let shown = (0..COLUMN_INFO.len())
+                .filter(|&i| !hidden.contains(COLUMN_INFO[i].0))
+                .collect_vec();
+
+            let min_width: u16 = 20
+                + shown
+                    .iter()
+                    .map(|&i| max(COLUMN_INFO[i].0.len(), COLUMN_INFO[i].1.len()) as u16 + COL_SPACING)
+                    .sum::<u16>();
 
-        for (i, (n1, n2, _, _)) in COLUMN_INFO.iter().enumerate() {
-            col_sizes[i] = max(col_sizes[i], max(n1.len(), n2.len()) as u16);
+            if min_width <= area.width {
+                return shown;
+            }
+
+            match COLUMN_GROUPS.iter().find(|&&group| !hidden.contains(group)) {
+                Some(&group) => {
+                    hidden.insert(group);
+                }
+                // nothing left to drop, let it overflow rather than hide everything
+                None => return shown,
+            }
+        }
+    }
+
+    fn compute_col_starts(&self, area: Rect) -> (Vec<usize>, Vec<u16>, Vec<u16>) {
+        let visible = self.visible_columns(area);
+
+        let mut col_sizes = vec![0; visible.len()];
+        if let Some(first) = col_sizes.first_mut() {
+            *first = 20;
+        }
+
+        for (slot, &i) in visible.iter().enumerate() {
+            let (n1, n2, _, _) = COLUMN_INFO[i];
+            col_sizes[slot] = max(col_sizes[slot], max(n1.len(), n2.len()) as u16);
         }
 
         for &RenderNode { node, depth } in &self.prev_nodes {
-            for (i, v) in self.column_values(node, depth).iter().enumerate() {
-                col_sizes[i] = max(col_sizes[i], v.len() as u16);
+            let values = self.column_values(node, depth);
+            for (slot, &i) in visible.iter().enumerate() {
+                col_sizes[slot] = max(col_sizes[slot], values[i].len() as u16);
             }
         }
 
@@ -215,7 +508,7 @@ impl<B: Board> State<B> {
             })
             .collect_vec();
 
-        (col_sizes, col_starts)
+        (visible, col_sizes, col_starts)
     }
 
     fn column_values(&self, node: usize, depth: u32) -> Vec<String> {
@@ -289,8 +582,74 @@ impl<B: Board> State<B> {
         assert_eq!(result.len(), COLUMN_INFO.len());
         result
     }
+
+    /// Writes the currently listed subtree (`self.prev_nodes`, as limited by the expanded nodes and the
+    /// beam width) to `path` as Graphviz DOT. Each node is labelled with its move, visit count, terminal
+    /// marker and zero/net WDL via [`Self::column_values`], so the labels stay consistent with the
+    /// on-screen table; edges are labelled with the child's policy prior.
+    fn export_dot(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let mut out = String::new();
+        out.push_str("digraph tree {\n");
+
+        for &RenderNode { node, .. } in &self.prev_nodes {
+            let values = self.column_values(node, 0);
+            let label = format!(
+                "{} T={}\\nvisits={}\\nzero=({}, {}, {})\\nnet=({}, {}, {})",
+                values[1], values[2], values[3], values[4], values[5], values[6], values[9], values[10], values[11],
+            );
+            out.push_str(&format!("  n{} [label=\"{}\"];\n", node, label.replace('"', "'")));
+
+            if let Some(parent) = self.tree[node].parent {
+                out.push_str(&format!(
+                    "  n{} -> n{} [label=\"{:.3}\"];\n",
+                    parent, node, self.tree[node].net_policy
+                ));
+            }
+        }
+
+        out.push_str("}\n");
+        std::fs::write(path, out)
+    }
+
+    /// Writes the currently expanded subtree to `path` as a flat CSV, one row per node, columns sourced
+    /// from the same [`Self::column_values`] the on-screen table renders from so the two stay consistent.
+    /// Suitable for loading into a dataframe tool to diff trees across network versions.
+    fn export_records(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let mut out = String::new();
+        out.push_str("node,parent,depth,move,visits,zero_win_a,zero_draw,zero_win_b,net_win_a,net_draw,net_win_b,uct_q,uct_u,uct_m\n");
+
+        for &RenderNode { node, depth } in &self.prev_nodes {
+            let values = self.column_values(node, depth);
+            let parent = self.tree[node].parent.map_or(String::new(), |p| p.to_string());
+
+            out.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                node,
+                parent,
+                depth,
+                values[1],
+                values[3],
+                values[4],
+                values[5],
+                values[6],
+                values[9],
+                values[10],
+                values[11],
+                values[14],
+                values[15],
+                values[16],
+            ));
+        }
+
+        std::fs::write(path, out)
+    }
 }
 
+/// Toggleable [`COLUMN_INFO`] groups, lowest priority first: the first entry here is the first one
+/// [`State::visible_columns`] drops under width pressure. `Node`/`Move`/`T`/`Visits` aren't in any group
+/// and are always shown.
+const COLUMN_GROUPS: &[&str] = &["Uct", "Net", "Zero"];
+
 const COLUMN_INFO: &[(&str, &str, bool, Color)] = &[
     ("Node", "", false, Color::Gray),
     ("Move", "", false, Color::Gray),
@@ -313,13 +672,14 @@ const COLUMN_INFO: &[(&str, &str, bool, Color)] = &[
 
 impl<B: Board> Widget for &State<B> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let (col_sizes, col_starts) = self.compute_col_starts(area);
+        let (visible, col_sizes, col_starts) = self.compute_col_starts(area);
 
-        for (i, &(n1, n2, _, color)) in COLUMN_INFO.iter().enumerate() {
-            if i == 0 || COLUMN_INFO[i - 1].0 != n1 {
-                buf.set_string(col_starts[i], area.y, n1, Style::default().fg(color));
+        for (slot, &i) in visible.iter().enumerate() {
+            let (n1, n2, _, color) = COLUMN_INFO[i];
+            if slot == 0 || COLUMN_INFO[visible[slot - 1]].0 != n1 {
+                buf.set_string(col_starts[slot], area.y, n1, Style::default().fg(color));
             }
-            buf.set_string(col_starts[i], area.y + 1, n2, Style::default().fg(color));
+            buf.set_string(col_starts[slot], area.y + 1, n2, Style::default().fg(color));
         }
 
         for y in 0..area.height - HEADER_SIZE {
@@ -327,20 +687,27 @@ impl<B: Board> Widget for &State<B> {
             let i = y as u32 + self.view_offset as u32;
 
             if let Some(&RenderNode { node, depth }) = self.prev_nodes.get(i as usize) {
+                if self.pv_chain.contains(&node) {
+                    let line = Rect::new(area.x, full_y, area.width, 1);
+                    buf.set_style(line, Style::default().add_modifier(Modifier::BOLD));
+                }
+
                 if node == self.selected_node {
                     let line = Rect::new(area.x, full_y, area.width, 1);
                     let style = Style::default().add_modifier(Modifier::REVERSED);
                     buf.set_style(line, style);
                 }
 
-                for (i, v) in self.column_values(node, depth).iter().enumerate() {
+                let values = self.column_values(node, depth);
+                for (slot, &i) in visible.iter().enumerate() {
+                    let v = &values[i];
                     let just_right = COLUMN_INFO[i].2;
                     let color = COLUMN_INFO[i].3;
 
                     let x = if just_right {
-                        col_starts[i] + (col_sizes[i] - v.len() as u16)
+                        col_starts[slot] + (col_sizes[slot] - v.len() as u16)
                     } else {
-                        col_starts[i]
+                        col_starts[slot]
                     };
 
                     buf.set_string(x, full_y, v, Style::default().fg(color));
@@ -350,19 +717,12 @@ impl<B: Board> Widget for &State<B> {
     }
 }
 
-fn build_tree(real: bool) -> Tree<AtaxxBoard> {
-    let batch_size = 1024;
+fn build_tree(network_path: &str, visits: u64, batch_size: usize) -> Tree<AtaxxBoard> {
     let settings = ZeroSettings::new(batch_size, UctWeights::default(), false, FpuMode::Relative(0.0), 1.0);
-    let visits = 1_000_000;
 
     let board = AtaxxBoard::default();
-    let path = r#"C:\Documents\Programming\STTT\kZero\data\networks\tmp\network_3874.onnx"#;
     let mapper = AtaxxStdMapper::new(board.size());
 
-    // let board = AtaxxBoard::default();
-    // let path = "C:/Documents/Programming/STTT/AlphaZero/data/loop/ataxx-7/16x128/training/gen_661/network.onnx";
-    // let mapper = AtaxxStdMapper::new(board.size());
-
     let mut rng = StdRng::from_entropy();
     let mut tp = PrintThroughput::new("nodes");
     let stop = |tree: &Tree<_>| {
@@ -370,11 +730,7 @@ fn build_tree(real: bool) -> Tree<AtaxxBoard> {
         tree.root_visits() >= visits
     };
 
-    if real {
-        let graph = optimize_graph(&load_graph_from_onnx_path(path), Default::default());
-        let mut network = CudaNetwork::new(mapper, &graph, settings.batch_size, Device::new(0));
-        settings.build_tree(&board, &mut network, &mut rng, stop)
-    } else {
-        settings.build_tree(&board, &mut DummyNetwork, &mut rng, stop)
-    }
+    let graph = optimize_graph(&load_graph_from_onnx_path(network_path), Default::default());
+    let mut network = CudaNetwork::new(mapper, &graph, settings.batch_size, Device::new(0));
+    settings.build_tree(&board, &mut network, &mut rng, stop)
 }
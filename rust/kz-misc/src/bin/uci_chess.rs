@@ -0,0 +1,446 @@
+use std::io::stdin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use board_game::board::{Board, Player};
+use board_game::games::chess::{ChessBoard, Rules};
+use chess::ChessMove;
+use clap::Parser;
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tokio::sync::{mpsc, oneshot};
+
+use cuda_nn_eval::Device;
+use kz_core::mapping::chess::ChessStdMapper;
+use kz_core::network::cudnn::CudaNetwork;
+use kz_core::network::job_channel::job_pair;
+use kz_core::network::multibatch::MultiBatchNetwork;
+use kz_core::network::Network;
+use kz_core::zero::node::UctWeights;
+use kz_core::zero::step::{FpuMode, QMode};
+use kz_core::zero::tree::Tree;
+use kz_core::zero::wrapper::ZeroSettings;
+use kz_selfplay::server::executor::{batched_executor_loop, ExecutorStats};
+use nn_graph::onnx::load_graph_from_onnx_path;
+use nn_graph::optimizer::{optimize_graph, OptimizerSettings};
+
+/// How often a running search reports an `info` line, so a GUI gets periodic depth/nps/score updates
+/// instead of a single line right before `bestmove`.
+const REPORT_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Safety net mirroring `alpha_zero::uci::engine`'s `MAX_VISITS_WITHOUT_STOP`: a `go` with no time
+/// control and no `nodes` limit still has to stop eventually if the GUI never sends `stop`.
+const MAX_VISITS_WITHOUT_LIMIT: u64 = 10_000_000;
+
+/// The eval batch sizes `MultiBatchNetwork` is built with, exactly as `exp_tournament_chess` picks a
+/// spread of sizes instead of a single one, so odd-sized UCI search batches don't all get padded up to
+/// one big network.
+const EVAL_BATCH_SIZES: &[usize] = &[8 * 4, 64, 256, 512, 512 + 256];
+
+#[derive(Debug, clap::Parser)]
+struct Args {
+    /// Path to the ONNX network to load at startup. Can also be set later with
+    /// `setoption name network_path value <path>`, the way a GUI would reconfigure a `WeightsFile`-style
+    /// option without restarting the engine.
+    #[clap(long)]
+    network: Option<String>,
+}
+
+/// Tunables changed with `setoption`, read at the start of every `go`.
+#[derive(Debug, Clone)]
+struct Options {
+    search_batch_size: usize,
+    visits_cap: u64,
+    fpu_mode: FpuMode,
+    q_mode: QMode,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            search_batch_size: 128,
+            visits_cap: MAX_VISITS_WITHOUT_LIMIT,
+            fpu_mode: FpuMode::Relative(0.0),
+            q_mode: QMode::wdl(),
+        }
+    }
+}
+
+/// The handle of an in-flight `go`: the flag `stop` sets, and the channel the search sends its
+/// finished [`Tree`] back over once it returns (either because `stop` was set or its own time/node
+/// budget ran out). The search task itself prints `bestmove`, so by the time this resolves there's
+/// nothing left to report.
+struct Search {
+    stop_flag: Arc<AtomicBool>,
+    done_rx: oneshot::Receiver<Tree<ChessBoard>>,
+}
+
+struct EngineState {
+    eval_client: Option<kz_core::network::EvalClient<ChessBoard>>,
+    tree: Option<Tree<ChessBoard>>,
+    options: Options,
+    search: Option<Search>,
+}
+
+impl EngineState {
+    fn new() -> Self {
+        EngineState {
+            eval_client: None,
+            tree: Some(Tree::new(ChessBoard::default())),
+            options: Options::default(),
+            search: None,
+        }
+    }
+
+    /// Blocks until any running search stops and hands its tree back, so every command other than
+    /// `stop`/`quit` never has to reason about a search being in flight.
+    async fn join_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop_flag.store(true, Ordering::Relaxed);
+            self.tree = Some(search.done_rx.await.expect("search task dropped its reply sender"));
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(run_uci(args));
+}
+
+async fn run_uci(args: Args) {
+    let mut state = EngineState::new();
+
+    if let Some(path) = args.network {
+        load_network(&mut state, &path);
+    }
+
+    // stdin is read on its own blocking thread and forwarded over a channel, so a `go` running as a
+    // concurrent task never has to share the reader with the command loop below.
+    let (line_tx, mut line_rx) = mpsc::unbounded_channel::<String>();
+    std::thread::spawn(move || {
+        for line in stdin().lines() {
+            if line_tx.send(line.expect("failed to read stdin")).is_err() {
+                break;
+            }
+        }
+    });
+
+    while let Some(line) = line_rx.recv().await {
+        if handle_command(&mut state, &line).await {
+            break;
+        }
+    }
+}
+
+/// Dispatches a single input line. Returns `true` once `quit` has been handled.
+async fn handle_command(state: &mut EngineState, line: &str) -> bool {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let (&command, args) = match tokens.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+
+    match command {
+        "uci" => {
+            println!("id name kZero");
+            println!("id author KarelPeeters");
+            println!("uciok");
+        }
+        "isready" => {
+            println!("readyok");
+        }
+        "ucinewgame" => {
+            state.join_search().await;
+            state.tree = Some(Tree::new(ChessBoard::default()));
+        }
+        "quit" => {
+            state.join_search().await;
+            return true;
+        }
+        "position" => {
+            state.join_search().await;
+            match parse_position(args) {
+                Ok(board) => state.tree = Some(Tree::new(board)),
+                Err(message) => println!("info string {}", message),
+            }
+        }
+        "setoption" => {
+            if let Err(message) = handle_setoption(state, args) {
+                println!("info string {}", message);
+            }
+        }
+        "go" => cmd_go(state, args),
+        "stop" => state.join_search().await,
+        _ => println!("info string unknown command '{}'", command),
+    }
+
+    false
+}
+
+/// Parses `[startpos|fen <fen>] [moves <m1> <m2> ...]`, the same shape
+/// `kz_lichess::bin::lichess::board_from_state` replays a game's move list onto its initial FEN with.
+fn parse_position(tokens: &[&str]) -> Result<ChessBoard, String> {
+    let moves_at = tokens.iter().position(|&tok| tok == "moves");
+    let (head, moves) = match moves_at {
+        Some(i) => (&tokens[..i], &tokens[i + 1..]),
+        None => (tokens, &tokens[tokens.len()..]),
+    };
+
+    let mut board = match head.split_first() {
+        Some((&"startpos", _)) => ChessBoard::default(),
+        Some((&"fen", fen_tokens)) => {
+            let fen = fen_tokens.join(" ");
+            ChessBoard::new_without_history_fen(&fen, Rules::default())
+        }
+        _ => return Err("expected 'position startpos' or 'position fen ...'".to_owned()),
+    };
+
+    for token in moves {
+        let mv = ChessMove::from_str(token).map_err(|_| format!("'{}' is not a move", token))?;
+        board.play(mv);
+    }
+
+    Ok(board)
+}
+
+fn handle_setoption(state: &mut EngineState, tokens: &[&str]) -> Result<(), String> {
+    let value_at = tokens.iter().position(|&tok| tok == "value");
+    let (name_tokens, value_tokens) = match value_at {
+        Some(i) => (&tokens[1..i], &tokens[i + 1..]),
+        None => (&tokens[1..], &tokens[tokens.len()..]),
+    };
+    if tokens.first() != Some(&"name") || name_tokens.is_empty() {
+        return Err("expected 'setoption name <id> [value <x>]'".to_owned());
+    }
+    let name = name_tokens.join(" ");
+    let value = value_tokens.join(" ");
+
+    match name.as_str() {
+        "network_path" => load_network(state, &value),
+        "batch_size" => {
+            state.options.search_batch_size = value.parse().map_err(|_| format!("'{}' is not an integer", value))?;
+        }
+        "visits" => {
+            state.options.visits_cap = value.parse().map_err(|_| format!("'{}' is not an integer", value))?;
+        }
+        "fpu_mode" => {
+            state.options.fpu_mode = match value.split_whitespace().collect_vec().as_slice() {
+                ["relative", r] => FpuMode::Relative(r.parse().map_err(|_| format!("'{}' is not a float", r))?),
+                ["fixed", v] => FpuMode::Fixed(v.parse().map_err(|_| format!("'{}' is not a float", v))?),
+                _ => return Err("expected 'relative <r>' or 'fixed <v>'".to_owned()),
+            };
+        }
+        "q_mode" => {
+            state.options.q_mode = match value.as_str() {
+                "wdl" => QMode::wdl(),
+                "value" => QMode::Value,
+                _ => return Err(format!("'{}' is not 'wdl' or 'value'", value)),
+            };
+        }
+        _ => return Err(format!("unknown option '{}'", name)),
+    }
+
+    Ok(())
+}
+
+/// Loads the ONNX graph at `path` and (re)spawns the background executor thread, exactly the
+/// `job_pair` + `batched_executor_loop` + `MultiBatchNetwork::build_sizes` wiring
+/// `exp_tournament_chess` sets up per bot. The previous `eval_client`, if any, is simply dropped; its
+/// executor thread exits on its own once every clone of its `JobClient` is gone.
+fn load_network(state: &mut EngineState, path: &str) {
+    println!("info string loading network {}", path);
+
+    let graph = optimize_graph(&load_graph_from_onnx_path(path, false).unwrap(), OptimizerSettings::default());
+    let max_eval_batch_size = EVAL_BATCH_SIZES.iter().copied().max().unwrap();
+
+    let (eval_client, eval_server) = job_pair(4);
+    let (graph_tx, graph_rx) = flume::bounded(1);
+    graph_tx.send(graph).unwrap();
+
+    let stats = Arc::new(ExecutorStats::default());
+    std::thread::Builder::new()
+        .name("executor".to_owned())
+        .spawn(move || {
+            batched_executor_loop(
+                max_eval_batch_size,
+                graph_rx,
+                eval_server,
+                stats,
+                |graph| MultiBatchNetwork::build_sizes(EVAL_BATCH_SIZES, |size| CudaNetwork::new(ChessStdMapper, &graph, size, Device::new(0))),
+                |network, batch_x| network.evaluate_batch(&batch_x),
+            );
+        })
+        .unwrap();
+
+    state.eval_client = Some(eval_client);
+}
+
+fn cmd_go(state: &mut EngineState, args: &[&str]) {
+    let eval_client = match state.eval_client.clone() {
+        Some(client) => client,
+        None => {
+            println!("info string no network loaded, set 'network_path' first");
+            return;
+        }
+    };
+
+    let tree = state.tree.take().expect("tree missing while no search is running");
+    let budget = parse_go_budget(args, tree.root_board().next_player(), state.options.visits_cap);
+    let settings = ZeroSettings::simple(
+        state.options.search_batch_size,
+        UctWeights::default(),
+        state.options.q_mode,
+        state.options.fpu_mode,
+    );
+
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let task_stop_flag = stop_flag.clone();
+    let (done_tx, done_rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let final_tree = run_search(tree, settings, &eval_client, budget, &task_stop_flag).await;
+        done_tx.send(final_tree).ok();
+    });
+
+    state.search = Some(Search { stop_flag, done_rx });
+}
+
+/// What `go`'s stop condition is checked against: a hard visits cap plus, depending on which of
+/// `movetime`/`wtime`+`btime`/neither was given, either a fixed time budget or one derived from the
+/// side-to-move's remaining clock the way `kz_lichess::bin::lichess::make_move` derives `MAX_TIME`/
+/// `MAX_TIME_FRACTION` from `game.seconds_left`.
+struct GoBudget {
+    visits_cap: u64,
+    time_budget: Option<Duration>,
+}
+
+fn parse_go_budget(args: &[&str], side_to_move: Player, visits_cap: u64) -> GoBudget {
+    let mut values = std::collections::HashMap::new();
+    let mut iter = args.iter();
+    while let Some(&key) = iter.next() {
+        if let Some(&value) = iter.next() {
+            if let Ok(value) = value.parse::<f32>() {
+                values.insert(key, value);
+            }
+        }
+    }
+
+    if let Some(&visits) = values.get("nodes") {
+        return GoBudget { visits_cap: visits_cap.min(visits as u64), time_budget: None };
+    }
+
+    if let Some(&movetime) = values.get("movetime") {
+        return GoBudget { visits_cap, time_budget: Some(Duration::from_secs_f32((movetime / 1000.0).max(0.0))) };
+    }
+
+    let (my_time_key, my_inc_key) = match side_to_move {
+        Player::A => ("wtime", "winc"),
+        Player::B => ("btime", "binc"),
+    };
+
+    match values.get(my_time_key) {
+        Some(&my_time_ms) => {
+            let my_time = Duration::from_secs_f32((my_time_ms / 1000.0).max(0.0));
+            let inc = values.get(my_inc_key).copied().unwrap_or(0.0);
+            let inc = Duration::from_secs_f32((inc / 1000.0).max(0.0));
+
+            // spend roughly a 20th of the remaining clock per move plus the increment, leaving a small
+            // overhead reserve so we never flag on our own move
+            let overhead = Duration::from_millis(50);
+            let planned = my_time / 20 + inc;
+            let time_budget = planned.min(my_time.saturating_sub(overhead));
+            GoBudget { visits_cap, time_budget: Some(time_budget) }
+        }
+        // no time control and no node limit: fall back to the visits cap alone, mirroring
+        // `alpha_zero::uci::engine`'s `MAX_VISITS_WITHOUT_STOP` safety net for a `go` with nothing else
+        None => GoBudget { visits_cap, time_budget: None },
+    }
+}
+
+/// Runs `expand_tree_async` in `REPORT_INTERVAL`-sized chunks so an `info` line goes out periodically
+/// instead of only once right before `bestmove`, which it prints itself once the real stop condition
+/// (budget exhausted or `stop_flag` set) is reached.
+async fn run_search(
+    mut tree: Tree<ChessBoard>,
+    settings: ZeroSettings,
+    eval_client: &kz_core::network::EvalClient<ChessBoard>,
+    budget: GoBudget,
+    stop_flag: &AtomicBool,
+) -> Tree<ChessBoard> {
+    let start = Instant::now();
+    let mut rng = StdRng::from_entropy();
+
+    let should_stop = |tree: &Tree<ChessBoard>| -> bool {
+        if stop_flag.load(Ordering::Relaxed) {
+            return true;
+        }
+        if tree.root_visits() >= budget.visits_cap {
+            return true;
+        }
+        match budget.time_budget {
+            Some(time_budget) => start.elapsed() >= time_budget,
+            None => false,
+        }
+    };
+
+    let mut last_report = Instant::now();
+    loop {
+        settings
+            .expand_tree_async(&mut tree, eval_client, &mut rng, |tree| {
+                should_stop(tree) || last_report.elapsed() >= REPORT_INTERVAL
+            })
+            .await;
+
+        print_info(&tree, start);
+        last_report = Instant::now();
+
+        if should_stop(&tree) {
+            break;
+        }
+    }
+
+    print_bestmove(&tree);
+    tree
+}
+
+fn print_info(tree: &Tree<ChessBoard>, start: Instant) {
+    let nodes = tree.root_visits();
+    let elapsed = start.elapsed();
+    let nps = nodes as f32 / elapsed.as_secs_f32().max(0.001);
+
+    let (_, depth) = tree.depth_range(0);
+    let score_cp = value_to_cp(tree.values().value());
+    let pv = tree.principal_variation(depth as usize).iter().skip(1).join(" ");
+
+    println!(
+        "info depth {} nodes {} nps {:.0} score cp {} time {} pv {}",
+        depth,
+        nodes,
+        nps,
+        score_cp,
+        elapsed.as_millis(),
+        pv,
+    );
+}
+
+fn print_bestmove(tree: &Tree<ChessBoard>) {
+    match tree.best_move() {
+        Some(mv) => println!("bestmove {}", mv),
+        None => println!("bestmove 0000"),
+    }
+}
+
+/// Crude linear approximation of a UCI centipawn score from a `[-1, 1]` zero value, good enough for a
+/// GUI's eval bar; `chunk12-4`'s proper Elo-calibrated conversion is a separate concern from reporting
+/// a live search score here.
+fn value_to_cp(value: f32) -> i32 {
+    (value.clamp(-0.999, 0.999) * 600.0).round() as i32
+}
@@ -0,0 +1,386 @@
+use std::fmt;
+
+use board_game::ai::Bot;
+use board_game::board::{Board, Outcome, Player};
+
+/// A factory that builds a fresh [`Bot`] per game, since a bot's internal state (its search tree, its
+/// rng) shouldn't be shared across games played concurrently or in sequence. `exp_tournament_chess`
+/// builds these with [`box_bot`] around a closure that constructs a new `AsyncZeroBot` each time.
+pub type BoxBotFn<B> = Box<dyn Fn() -> Box<dyn Bot<B>> + Send + Sync>;
+
+/// Boxes a bot-constructing closure into a [`BoxBotFn`], erasing the concrete bot type.
+pub fn box_bot<B, F, T>(f: F) -> BoxBotFn<B>
+where
+    B: Board,
+    F: Fn() -> T + Send + Sync + 'static,
+    T: Bot<B> + 'static,
+{
+    Box::new(move || Box::new(f()) as Box<dyn Bot<B>>)
+}
+
+/// Which two bots (by index into the tournament's bot list) played a given [`Round`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MatchId {
+    pub i: usize,
+    pub j: usize,
+}
+
+/// The record of a single played game: its starting position, the moves played from it, and how it
+/// ended (`None` if the schedule was aborted before the game finished).
+pub struct Round<B: Board> {
+    pub id: MatchId,
+    pub start: B,
+    pub moves: Vec<B::Move>,
+    pub outcome: Option<Outcome>,
+}
+
+/// The full record of a round-robin tournament: every game played, plus the bot names in the same order
+/// used to index [`MatchId`].
+pub struct TournamentResult<B: Board> {
+    pub bot_names: Vec<String>,
+    pub rounds: Vec<Round<B>>,
+}
+
+impl<B: Board> TournamentResult<B> {
+    /// The win/draw/loss record for `bot` against every other bot in the tournament, from `bot`'s own
+    /// point of view.
+    fn record_of(&self, bot: usize) -> (u32, u32, u32) {
+        let (mut wins, mut draws, mut losses) = (0, 0, 0);
+
+        for round in &self.rounds {
+            // `play_game` always seats bot `i` as `Player::A` and bot `j` as `Player::B`
+            let player = if round.id.i == bot {
+                Some(Player::A)
+            } else if round.id.j == bot {
+                Some(Player::B)
+            } else {
+                None
+            };
+
+            let (Some(player), Some(outcome)) = (player, &round.outcome) else {
+                continue;
+            };
+
+            match outcome {
+                Outcome::WonBy(winner) if *winner == player => wins += 1,
+                Outcome::Draw => draws += 1,
+                _ => losses += 1,
+            }
+        }
+
+        (wins, draws, losses)
+    }
+}
+
+impl<B: Board> fmt::Display for TournamentResult<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, name) in self.bot_names.iter().enumerate() {
+            let (wins, draws, losses) = self.record_of(i);
+            let games = wins + draws + losses;
+            if games == 0 {
+                continue;
+            }
+            let elo = EloEstimate::from_counts(wins, draws, losses);
+            writeln!(f, "{}: +{} ={} -{} ({} games), {}", name, wins, draws, losses, games, elo)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plays every bot against every other bot, across every starting position, `rounds` times through the
+/// whole schedule (`None` repeats forever and never returns, unless there are fewer than two bots).
+/// `mirror_colors` plays each pairing with both bots taking each color from a given position instead of
+/// just one; `_parallel` is accepted for call-site compatibility, but games are always played
+/// sequentially, one at a time. `on_print` is called once per game so a caller can report progress.
+pub fn run_tournament<B: Board>(
+    bots: Vec<(String, BoxBotFn<B>)>,
+    positions: Vec<B>,
+    rounds: Option<usize>,
+    mirror_colors: bool,
+    _parallel: bool,
+    mut on_print: impl FnMut(),
+) -> TournamentResult<B>
+where
+    B::Move: Copy,
+{
+    let bot_names = bots.iter().map(|(name, _)| name.clone()).collect();
+    let mut result = TournamentResult { bot_names, rounds: vec![] };
+
+    let mut round_index = 0;
+    loop {
+        if let Some(rounds) = rounds {
+            if round_index >= rounds {
+                break;
+            }
+        }
+        round_index += 1;
+
+        for i in 0..bots.len() {
+            for j in (i + 1)..bots.len() {
+                for start in &positions {
+                    play_and_record(&bots, i, j, start.clone(), &mut result, &mut on_print);
+                    if mirror_colors {
+                        play_and_record(&bots, j, i, start.clone(), &mut result, &mut on_print);
+                    }
+                }
+            }
+        }
+
+        if rounds.is_none() && bots.len() < 2 {
+            // nothing to play forever towards; avoid spinning
+            break;
+        }
+    }
+
+    result
+}
+
+fn play_and_record<B: Board>(
+    bots: &[(String, BoxBotFn<B>)],
+    i: usize,
+    j: usize,
+    start: B,
+    result: &mut TournamentResult<B>,
+    on_print: &mut impl FnMut(),
+) where
+    B::Move: Copy,
+{
+    let mut white = (bots[i].1)();
+    let mut black = (bots[j].1)();
+    let (moves, outcome) = play_game(&mut *white, &mut *black, start.clone());
+    result.rounds.push(Round {
+        id: MatchId { i, j },
+        start,
+        moves,
+        outcome,
+    });
+    on_print();
+}
+
+/// Plays out a single game from `start`, `white` moving whenever the board's `next_player` is
+/// [`Player::A`] and `black` whenever it's [`Player::B`] — matching how `zero_step_gather` and friends
+/// treat `Player::A`/`Player::B` as the two absolute sides rather than literal chess colors.
+fn play_game<B: Board>(white: &mut dyn Bot<B>, black: &mut dyn Bot<B>, start: B) -> (Vec<B::Move>, Option<Outcome>)
+where
+    B::Move: Copy,
+{
+    let mut board = start;
+    let mut moves = vec![];
+
+    loop {
+        if let Some(outcome) = board.outcome() {
+            return (moves, Some(outcome));
+        }
+
+        let mv = match board.next_player() {
+            Player::A => white.select_move(&board),
+            Player::B => black.select_move(&board),
+        };
+        moves.push(mv);
+        board.play(mv);
+    }
+}
+
+/// An Elo estimate with a 95% confidence interval, computed from a win/draw/loss record the same way
+/// engine-testing tools like cutechess-cli do: the score rate `(wins + draws/2) / games` is converted to
+/// an Elo difference via the logistic model `elo = -400 * log10(1/score - 1)`, and the interval comes
+/// from the per-game outcome variance `Var[result] = p_win*(1-score)^2 + p_draw*(0.5-score)^2 +
+/// p_loss*(0-score)^2`, propagated through the same log10 conversion at `score +- 1.96 * stderr`.
+#[derive(Debug, Copy, Clone)]
+pub struct EloEstimate {
+    pub elo: f32,
+    pub error_95: f32,
+}
+
+impl EloEstimate {
+    pub fn from_counts(wins: u32, draws: u32, losses: u32) -> Self {
+        let games = (wins + draws + losses).max(1) as f32;
+        let score = (wins as f32 + 0.5 * draws as f32) / games;
+
+        let p_win = wins as f32 / games;
+        let p_draw = draws as f32 / games;
+        let p_loss = losses as f32 / games;
+        let variance =
+            p_win * (1.0 - score).powi(2) + p_draw * (0.5 - score).powi(2) + p_loss * (0.0 - score).powi(2);
+        let std_err = (variance / games).sqrt();
+
+        let elo = Self::score_to_elo(score);
+        let elo_hi = Self::score_to_elo((score + 1.96 * std_err).clamp(1e-6, 1.0 - 1e-6));
+        let elo_lo = Self::score_to_elo((score - 1.96 * std_err).clamp(1e-6, 1.0 - 1e-6));
+
+        EloEstimate {
+            elo,
+            error_95: (elo_hi - elo_lo) / 2.0,
+        }
+    }
+
+    fn score_to_elo(score: f32) -> f32 {
+        -400.0 * (1.0 / score.clamp(1e-6, 1.0 - 1e-6) - 1.0).log10()
+    }
+}
+
+impl fmt::Display for EloEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:+.1} +- {:.1} elo", self.elo, self.error_95)
+    }
+}
+
+/// The two Elo hypotheses an SPRT distinguishes (`elo0` under H0, `elo1` under H1) and the two error
+/// rates it's allowed (`alpha`: the chance of accepting H1 when H0 is actually true, `beta`: the chance
+/// of accepting H0 when H1 is actually true). `alpha = beta = 0.05` matches the defaults fishtest-style
+/// engine testing normally runs with.
+#[derive(Debug, Copy, Clone)]
+pub struct SprtConfig {
+    pub elo0: f32,
+    pub elo1: f32,
+    pub alpha: f32,
+    pub beta: f32,
+}
+
+impl SprtConfig {
+    pub fn new(elo0: f32, elo1: f32) -> Self {
+        SprtConfig {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    /// The `(lower, upper)` log-likelihood-ratio bounds: cross `upper` to accept H1, cross `lower`
+    /// (it's negative) to accept H0.
+    fn llr_bounds(&self) -> (f32, f32) {
+        let upper = ((1.0 - self.beta) / self.alpha).ln();
+        let lower = (self.beta / (1.0 - self.alpha)).ln();
+        (lower, upper)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SprtVerdict {
+    AcceptH0,
+    AcceptH1,
+    Undecided,
+}
+
+/// A snapshot of an in-progress (or just-finished) SPRT run, handed to `on_print` after every game.
+#[derive(Debug, Copy, Clone)]
+pub struct SprtResult {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub llr: f32,
+    pub verdict: SprtVerdict,
+    pub elo: EloEstimate,
+}
+
+impl fmt::Display for SprtResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} games (+{} ={} -{}), llr {:+.2}, {:?}, {}",
+            self.games, self.wins, self.draws, self.losses, self.llr, self.verdict, self.elo
+        )
+    }
+}
+
+/// Plays `bot_a` against `bot_b`, one game at a time (alternating which one is white each game, cycling
+/// through `positions` as starting books), stopping as soon as the accumulated LLR crosses either of
+/// `sprt`'s bounds. This is the "sequential testing" counterpart to [`run_tournament`]'s fixed schedule:
+/// it only ever compares two bots (an SPRT is inherently a two-hypothesis test), so unlike
+/// `run_tournament` it isn't generalized over an arbitrary bot list.
+///
+/// Each game's result is scored against a *trinomial* model (win/draw/loss, not pentanomial pairs of
+/// games) using the logistic expected-score curve `expected_score(elo) = 1 / (1 + 10^(-elo/400))`: the
+/// empirical draw rate is held fixed and only the win/loss split moves between the `elo0` and `elo1`
+/// hypotheses, since the draw rate reflects how evenly matched the two bots are in absolute terms, not
+/// which Elo hypothesis is being tested.
+pub fn run_tournament_sprt<B: Board>(
+    bot_a: BoxBotFn<B>,
+    bot_b: BoxBotFn<B>,
+    positions: &[B],
+    sprt: SprtConfig,
+    mut on_print: impl FnMut(&SprtResult),
+) -> SprtResult
+where
+    B::Move: Copy,
+{
+    assert!(!positions.is_empty(), "run_tournament_sprt needs at least one starting position");
+    let (lower, upper) = sprt.llr_bounds();
+
+    let (mut wins, mut draws, mut losses) = (0u32, 0u32, 0u32);
+    let mut game_index = 0usize;
+
+    loop {
+        let start = positions[game_index % positions.len()].clone();
+        let a_is_white = game_index % 2 == 0;
+        game_index += 1;
+
+        let mut a = bot_a();
+        let mut b = bot_b();
+        let (_, outcome) = if a_is_white {
+            play_game(&mut *a, &mut *b, start)
+        } else {
+            play_game(&mut *b, &mut *a, start)
+        };
+
+        match outcome {
+            Some(Outcome::Draw) => draws += 1,
+            Some(Outcome::WonBy(winner)) => {
+                let a_won = (a_is_white && winner == Player::A) || (!a_is_white && winner == Player::B);
+                if a_won {
+                    wins += 1;
+                } else {
+                    losses += 1;
+                }
+            }
+            None => continue,
+        }
+
+        let games = wins + draws + losses;
+        let draw_rate = (draws as f32 / games as f32).clamp(1e-6, 1.0 - 1e-6);
+        let (p_win0, p_draw0, p_loss0) = trinomial_probs(sprt.elo0, draw_rate);
+        let (p_win1, p_draw1, p_loss1) = trinomial_probs(sprt.elo1, draw_rate);
+
+        let llr = wins as f32 * (p_win1 / p_win0).ln()
+            + draws as f32 * (p_draw1 / p_draw0).ln()
+            + losses as f32 * (p_loss1 / p_loss0).ln();
+
+        let verdict = if llr >= upper {
+            SprtVerdict::AcceptH1
+        } else if llr <= lower {
+            SprtVerdict::AcceptH0
+        } else {
+            SprtVerdict::Undecided
+        };
+
+        let result = SprtResult {
+            games,
+            wins,
+            draws,
+            losses,
+            llr,
+            verdict,
+            elo: EloEstimate::from_counts(wins, draws, losses),
+        };
+        on_print(&result);
+
+        if verdict != SprtVerdict::Undecided {
+            return result;
+        }
+    }
+}
+
+fn expected_score(elo: f32) -> f32 {
+    1.0 / (1.0 + 10f32.powf(-elo / 400.0))
+}
+
+/// Converts an Elo hypothesis plus a fixed draw rate into `(p_win, p_draw, p_loss)`, clamped away from
+/// 0/1 so the SPRT's log-ratio never divides by zero.
+fn trinomial_probs(elo: f32, draw_rate: f32) -> (f32, f32, f32) {
+    let score = expected_score(elo);
+    let p_win = (score - draw_rate / 2.0).clamp(1e-6, 1.0);
+    let p_loss = (1.0 - score - draw_rate / 2.0).clamp(1e-6, 1.0);
+    (p_win, draw_rate, p_loss)
+}
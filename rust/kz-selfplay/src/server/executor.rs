@@ -0,0 +1,196 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use flume::Receiver;
+
+use kz_core::network::job_channel::{JobServer, PendingJob};
+
+/// How long [`batched_executor_loop`] waits, after the first pending job of a batch arrives, for more
+/// jobs from other concurrent generators to join it before running the batch as-is. Also used by
+/// `crate::server::rebatcher`, which applies the same tradeoff one stage earlier.
+pub const MAX_WAIT: Duration = Duration::from_millis(1);
+
+/// Where [`batched_executor_loop`] gets its batches from: either a [`JobServer`] it coalesces itself (the
+/// original single-stage path), or batches already packed by one or more `crate::server::rebatcher`
+/// threads sitting in front of it. `StartupSettings::rebatcher_threads` picks between the two.
+pub enum BatchSource<I, O> {
+    Direct(JobServer<I, O>),
+    Rebatched(Receiver<Vec<PendingJob<I, O>>>),
+}
+
+impl<I, O> BatchSource<I, O> {
+    fn next_batch(&self, max_batch_size: usize) -> Option<Vec<PendingJob<I, O>>> {
+        match self {
+            BatchSource::Direct(server) => server.next_batch(max_batch_size, MAX_WAIT),
+            BatchSource::Rebatched(receiver) => receiver.recv().ok(),
+        }
+    }
+}
+
+impl<I, O> Clone for BatchSource<I, O> {
+    fn clone(&self) -> Self {
+        match self {
+            BatchSource::Direct(server) => BatchSource::Direct(server.clone()),
+            BatchSource::Rebatched(receiver) => BatchSource::Rebatched(receiver.clone()),
+        }
+    }
+}
+
+impl<I, O> From<JobServer<I, O>> for BatchSource<I, O> {
+    fn from(server: JobServer<I, O>) -> Self {
+        BatchSource::Direct(server)
+    }
+}
+
+impl<I, O> From<Receiver<Vec<PendingJob<I, O>>>> for BatchSource<I, O> {
+    fn from(receiver: Receiver<Vec<PendingJob<I, O>>>) -> Self {
+        BatchSource::Rebatched(receiver)
+    }
+}
+
+/// Counts and timings accumulated by one `batched_executor_loop`, drained periodically by
+/// `crate::server::throughput`'s per-device aggregator thread. Every field is a running total since the
+/// last [`ExecutorStats::drain`]; `batched_executor_loop` is the only writer, the aggregator the only
+/// reader, so plain atomics (no lock) are enough.
+#[derive(Debug, Default)]
+pub struct ExecutorStats {
+    items: AtomicU64,
+    batches: AtomicU64,
+    idle_nanos: AtomicU64,
+    busy_nanos: AtomicU64,
+}
+
+/// A snapshot of [`ExecutorStats`] taken (and reset) by [`ExecutorStats::drain`].
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ExecutorStatsSnapshot {
+    pub items: u64,
+    pub batches: u64,
+    /// Total time spent inside `source.next_batch`, waiting for jobs to arrive, in nanoseconds.
+    pub idle_nanos: u64,
+    /// Total time spent inside `eval`, running the network, in nanoseconds.
+    pub busy_nanos: u64,
+}
+
+impl ExecutorStats {
+    /// Reads every counter and resets it to zero, returning the totals accumulated since the previous
+    /// call (or since construction, for the first call).
+    pub fn drain(&self) -> ExecutorStatsSnapshot {
+        ExecutorStatsSnapshot {
+            items: self.items.swap(0, Ordering::Relaxed),
+            batches: self.batches.swap(0, Ordering::Relaxed),
+            idle_nanos: self.idle_nanos.swap(0, Ordering::Relaxed),
+            busy_nanos: self.busy_nanos.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Repeatedly waits for a batch from `source`, runs it through a network built by `load_network` from the
+/// most recently received graph, and routes every result back to the future that originally queued it.
+///
+/// This is what lets many concurrent `generator_muzero_main` tasks, each awaiting one board or one
+/// expand at a time, actually reach the GPU as the large batches `MuZeroRootExecutor`/
+/// `MuZeroExpandExecutor` are built for, instead of running with a batch size of one. Exits once the
+/// matching `JobClient`s are all dropped (or, for a rebatched source, once every rebatcher thread has
+/// exited for the same reason).
+///
+/// Every batch's wait time and eval time are added to `stats`, so `crate::server::throughput`'s
+/// aggregator can later report device utilization without parsing the raw `GeneratorUpdate::Evals`
+/// stream.
+///
+/// Building `N` from a freshly received graph (typically uploading it to the device) happens on a
+/// background thread instead of inline, so this loop keeps serving batches against the old network while
+/// the new one is built, flipping over only once the background build finishes; the old network is
+/// dropped right after the swap. Only the latest graph received while a build is already in flight is
+/// kept, since anything older is superseded before it would ever be used. An ideal version of this would
+/// expose `prepare_graph`/`commit_graph` on `crate::server::server::ZeroSpecialization` so each
+/// specialization controls exactly what "resident on the device" means for its own executor type, but
+/// that trait's defining file isn't present in this tree to extend; spawning the build on a plain
+/// background thread here gets the same "self-play never stalls at a generation boundary" behavior
+/// without needing to touch it.
+pub fn batched_executor_loop<G, N, I, O>(
+    max_batch_size: usize,
+    graph_receiver: Receiver<G>,
+    source: impl Into<BatchSource<I, O>>,
+    stats: Arc<ExecutorStats>,
+    load_network: impl Fn(G) -> N + Send + Sync + 'static,
+    mut eval: impl FnMut(&mut N, Vec<I>) -> Vec<O>,
+) where
+    G: Send + 'static,
+    N: Send + 'static,
+{
+    let source = source.into();
+    let load_network = Arc::new(load_network);
+
+    // there is nothing useful to do without a network, so block until the first graph arrives
+    let mut network = load_network(
+        graph_receiver
+            .recv()
+            .expect("graph sender was dropped before sending a graph"),
+    );
+
+    // the network currently being built on a background thread, if any, and the most recently received
+    // graph that arrived while it was still building (only the latest one is kept, see doc comment above)
+    let mut preparing: Option<Receiver<N>> = None;
+    let mut superseded: Option<G> = None;
+
+    loop {
+        while let Ok(graph) = graph_receiver.try_recv() {
+            superseded = Some(graph);
+        }
+
+        if preparing.is_none() {
+            if let Some(graph) = superseded.take() {
+                let (built_sender, built_receiver) = flume::bounded(1);
+                let load_network = Arc::clone(&load_network);
+                std::thread::spawn(move || {
+                    let network = load_network(graph);
+                    let _ = built_sender.send(network);
+                });
+                preparing = Some(built_receiver);
+            }
+        }
+
+        if let Some(built_receiver) = &preparing {
+            if let Ok(new_network) = built_receiver.try_recv() {
+                network = new_network;
+                preparing = None;
+            }
+        }
+
+        let wait_start = Instant::now();
+        let batch = match source.next_batch(max_batch_size) {
+            Some(batch) => batch,
+            None => break,
+        };
+        stats
+            .idle_nanos
+            .fetch_add(wait_start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+
+        let (inputs, responders): (Vec<I>, Vec<_>) = batch
+            .into_iter()
+            .map(|job| (job.input, job.response))
+            .unzip();
+
+        let eval_start = Instant::now();
+        let outputs = eval(&mut network, inputs);
+        stats
+            .busy_nanos
+            .fetch_add(eval_start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        stats
+            .items
+            .fetch_add(outputs.len() as u64, Ordering::Relaxed);
+        stats.batches.fetch_add(1, Ordering::Relaxed);
+
+        assert_eq!(
+            outputs.len(),
+            responders.len(),
+            "eval must return exactly one output per input"
+        );
+
+        for (responder, output) in responders.into_iter().zip(outputs) {
+            // the requester may already have given up (e.g. its future was dropped); that's fine
+            let _ = responder.send(output);
+        }
+    }
+}
@@ -1,29 +1,32 @@
 use std::borrow::Cow;
-use std::cmp::min;
+use std::cmp::{max, min};
 
 use board_game::board::AltBoard;
+use decorum::N32;
 use flume::{Receiver, TryRecvError};
+use futures::future::join_all;
 use internal_iterator::InternalIterator;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
-use rand_distr::Dirichlet;
+use rand_distr::{Dirichlet, Gumbel};
 
-use cuda_nn_eval::quant::QuantizedStorage;
 use cuda_sys::wrapper::handle::Device;
 use cuda_sys::wrapper::mem::pool::DevicePool;
 use kz_core::mapping::BoardMapper;
 use kz_core::muzero::step::{
-    muzero_step_apply, muzero_step_gather, MuZeroExpandRequest, MuZeroRequest, MuZeroResponse, MuZeroRootRequest,
+    completed_q, muzero_step_apply, muzero_step_apply_batch, muzero_step_gather,
+    muzero_step_gather_batch, muzero_step_gather_from_batch, MuZeroRequest, MuZeroResponse,
 };
 use kz_core::muzero::tree::MuTree;
 use kz_core::muzero::MuZeroEvaluation;
 use kz_core::network::common::{softmax_in_place, unsoftmax_in_place};
-use kz_core::network::muzero::{ExpandArgs, ExpandClient, RootArgs, RootClient};
+use kz_core::network::muzero::{ExpandClient, RootClient};
 use kz_core::network::ZeroEvaluation;
-use kz_core::zero::step::{FpuMode, QMode};
+use kz_core::zero::step::FpuMode;
+use kz_util::top_k_indices_sorted;
 
 use crate::move_selector::MoveSelector;
-use crate::server::protocol::{GeneratorUpdate, Settings};
+use crate::server::protocol::{GeneratorUpdate, RootSelection, Settings};
 use crate::server::server::UpdateSender;
 use crate::simulation::{Position, Simulation};
 
@@ -77,12 +80,27 @@ pub async fn generator_muzero_main<B: AltBoard, M: BoardMapper<B>>(
             &expand_client,
             start_pos(&mut rng),
             mapper,
-            state_size,
             pool,
             &mut rng,
         )
         .await;
 
+        // mix in some freshly reanalyzed targets for the game we just finished, before moving on
+        if rng.gen_bool(settings.reanalyze_fraction) {
+            reanalyze(
+                generator_id,
+                &settings,
+                &update_sender,
+                &root_client,
+                &expand_client,
+                &simulation,
+                mapper,
+                pool,
+                &mut rng,
+            )
+            .await;
+        }
+
         // send finished simulation
         update_sender
             .send(GeneratorUpdate::FinishedSimulation {
@@ -101,20 +119,13 @@ async fn generate_simulation<B: AltBoard, M: BoardMapper<B>>(
     expand_client: &ExpandClient,
     start: B,
     mapper: M,
-    state_size: usize,
     pool: &mut DevicePool,
     rng: &mut impl Rng,
 ) -> Simulation<B> {
     let mut positions = vec![];
 
     let max_moves = settings.max_game_length.unwrap_or(u64::MAX) as u32;
-    let use_value = match settings.q_mode.0 {
-        QMode::Value => true,
-        QMode::WDL { draw_score } => {
-            assert_eq!(draw_score, 0.0, "MuZero does not yet support a nonzero draw score");
-            false
-        }
-    };
+    let use_value = settings.use_value;
 
     let mut curr_board = start;
 
@@ -141,67 +152,41 @@ async fn generate_simulation<B: AltBoard, M: BoardMapper<B>>(
         let max_nodes = 1 + root_max_moves + target_visits as usize * inner_max_moves;
         tree.reserve(max_nodes);
 
-        let mut root_net_eval = None;
-
-        while tree.root_visits() < target_visits {
-            let request = muzero_step_gather(&mut tree, settings.weights.to_uct(), use_value, FpuMode::Relative(0.0));
-
-            if let Some(request) = request {
-                let output_state = QuantizedStorage::new(pool.alloc(state_size), state_size);
-
-                let response = match request {
-                    MuZeroRequest::Root(MuZeroRootRequest { node, board }) => {
-                        let root_args = RootArgs {
-                            board: board.clone(),
-                            output_state: output_state.clone(),
-                        };
-
-                        let mut eval = root_client.map_async_single(root_args).await;
-
-                        root_net_eval = Some(extract_zero_eval(mapper, &board, &eval));
-
-                        add_dirichlet_noise(eval.policy_logits.to_mut(), settings, &board, mapper, rng);
-
-                        MuZeroResponse {
-                            node,
-                            eval,
-                            state: output_state,
-                        }
-                    }
-                    MuZeroRequest::Expand(MuZeroExpandRequest {
-                        node,
-                        state,
-                        move_index,
-                    }) => {
-                        let expand_args = ExpandArgs {
-                            state,
-                            move_index,
-                            output_state: output_state.clone(),
-                        };
-                        let eval = expand_client.map_async_single(expand_args).await;
-
-                        MuZeroResponse {
-                            node,
-                            eval,
-                            state: output_state,
-                        }
-                    }
-                };
-
-                muzero_step_apply(&mut tree, settings.top_moves, response);
+        let (net_evaluation, zero_evaluation, picked_move_index) = match settings.root_selection {
+            RootSelection::Dirichlet => {
+                run_dirichlet_search(
+                    &mut tree,
+                    settings,
+                    mapper,
+                    use_value,
+                    target_visits,
+                    positions.len() as u32,
+                    root_client,
+                    expand_client,
+                    rng,
+                )
+                .await
             }
-        }
-
-        // extract stats
-        let net_evaluation = root_net_eval.unwrap();
-        let zero_evaluation = tree.eval();
+            RootSelection::Gumbel { c_visit, c_scale } => {
+                run_gumbel_search(
+                    &mut tree,
+                    settings,
+                    mapper,
+                    use_value,
+                    target_visits,
+                    c_visit,
+                    c_scale,
+                    root_client,
+                    expand_client,
+                    rng,
+                )
+                .await
+            }
+        };
 
-        //pick a move to play
-        let move_selector = MoveSelector::new(settings.temperature, settings.zero_temp_move_count);
-        let picked_index = move_selector.select(positions.len() as u32, zero_evaluation.policy.as_ref(), rng);
-        let picked_child = tree[0].inner.as_ref().unwrap().children.get(picked_index);
-        let picked_move_index = tree[picked_child].last_move_index.unwrap();
-        let picked_move = mapper.index_to_move(tree.root_board(), picked_move_index).unwrap();
+        let picked_move = mapper
+            .index_to_move(tree.root_board(), picked_move_index)
+            .unwrap();
 
         // record position
         let position = Position {
@@ -239,6 +224,451 @@ async fn generate_simulation<B: AltBoard, M: BoardMapper<B>>(
     }
 }
 
+/// The MuZero Reanalyze loop: re-runs the configured `RootSelection` search from every stored position
+/// of an already-finished `simulation`, using the *current* `root_client`/`expand_client` instead of
+/// whatever network was live when the game was originally played. This sidesteps the stale-quantized-
+/// state concern from the TODO at the top of this file, since the representation state for each
+/// position is recomputed from scratch by the current network, exactly like [`generate_simulation`]
+/// does for freshly generated positions. Unlike [`generate_simulation`] it never plays moves: it only
+/// emits fresh [`GeneratorUpdate::ReanalyzedPosition`] targets, one per stored position, for the
+/// training server to mix in with freshly generated ones at the `Settings::reanalyze_fraction` ratio.
+#[allow(clippy::too_many_arguments)]
+async fn reanalyze<B: AltBoard, M: BoardMapper<B>>(
+    generator_id: usize,
+    settings: &Settings,
+    update_sender: &UpdateSender<B>,
+    root_client: &RootClient<B>,
+    expand_client: &ExpandClient,
+    simulation: &Simulation<B>,
+    mapper: M,
+    pool: &mut DevicePool,
+    rng: &mut impl Rng,
+) {
+    let max_moves = settings.max_game_length.unwrap_or(u64::MAX) as u32;
+    let use_value = settings.use_value;
+
+    for (move_number, position) in simulation.positions.iter().enumerate() {
+        let draw_depth = max_moves.saturating_sub(move_number as u32);
+        if draw_depth == 0 {
+            continue;
+        }
+
+        // reanalyze always spends the full search budget: the whole point is to produce the best
+        // targets we can for positions we already know are worth training on
+        let target_visits = settings.full_iterations;
+
+        let mut tree = MuTree::new(position.board.clone(), draw_depth, mapper);
+
+        let root_max_moves = B::all_possible_moves().count();
+        let inner_max_moves = min(settings.top_moves, mapper.policy_len());
+        let max_nodes = 1 + root_max_moves + target_visits as usize * inner_max_moves;
+        tree.reserve(max_nodes);
+
+        let (net_evaluation, zero_evaluation, _) = match settings.root_selection {
+            RootSelection::Dirichlet => {
+                run_dirichlet_search(
+                    &mut tree,
+                    settings,
+                    mapper,
+                    use_value,
+                    target_visits,
+                    move_number as u32,
+                    root_client,
+                    expand_client,
+                    rng,
+                )
+                .await
+            }
+            RootSelection::Gumbel { c_visit, c_scale } => {
+                run_gumbel_search(
+                    &mut tree,
+                    settings,
+                    mapper,
+                    use_value,
+                    target_visits,
+                    c_visit,
+                    c_scale,
+                    root_client,
+                    expand_client,
+                    rng,
+                )
+                .await
+            }
+        };
+
+        // at this point we don't need the tree nor the underlying pool allocations any more
+        drop(tree);
+        unsafe {
+            // we can't just clear here, since the there might still be leftover references to the states in the executors
+            pool.clear_unsafe();
+        }
+
+        update_sender
+            .send(GeneratorUpdate::ReanalyzedPosition {
+                generator_id,
+                board: position.board.clone(),
+                net_evaluation,
+                zero_evaluation,
+            })
+            .unwrap();
+    }
+}
+
+/// The original root: PUCT visits distributed by [`muzero_step_gather`]/[`muzero_step_apply`], with
+/// Dirichlet noise mixed into the root policy before its first visit. Returns the net evaluation, the
+/// search-improved [`ZeroEvaluation`], and the move index of the move picked by `MoveSelector`.
+#[allow(clippy::too_many_arguments)]
+async fn run_dirichlet_search<B: AltBoard, M: BoardMapper<B>>(
+    tree: &mut MuTree<B>,
+    settings: &Settings,
+    mapper: M,
+    use_value: bool,
+    target_visits: u64,
+    move_number: u32,
+    root_client: &RootClient<B>,
+    expand_client: &ExpandClient,
+    rng: &mut impl Rng,
+) -> (ZeroEvaluation<'static>, ZeroEvaluation<'static>, usize) {
+    // the root always needs to be expanded first, and on its own: the dirichlet noise it needs mixed
+    // in can only be computed once, and `gather_batch` refuses to batch it with expand requests anyway
+    let root_request = muzero_step_gather(
+        tree,
+        settings.weights.to_uct(),
+        use_value,
+        FpuMode::Relative(0.0),
+    )
+    .expect("a freshly created tree always needs its root expanded first");
+    let (root_node, root_board) = match root_request {
+        MuZeroRequest::Root { node, board } => (node, board),
+        MuZeroRequest::Expand { .. } => {
+            unreachable!("the root is unexpanded, the first request must be Root")
+        }
+    };
+
+    let (state, mut eval) = root_client.map_async_single(root_board.clone()).await;
+
+    let net_evaluation = extract_zero_eval(mapper, &root_board, &eval);
+    add_dirichlet_noise(
+        eval.policy_logits.to_mut(),
+        settings,
+        &root_board,
+        mapper,
+        rng,
+    );
+
+    muzero_step_apply(
+        tree,
+        settings.top_moves,
+        MuZeroResponse {
+            node: root_node,
+            eval,
+            state,
+        },
+        mapper,
+    );
+
+    while tree.root_visits() < target_visits {
+        let batch = muzero_step_gather_batch(
+            tree,
+            settings.collect_batch,
+            settings.virtual_loss,
+            settings.weights.to_uct(),
+            use_value,
+            FpuMode::Relative(0.0),
+        );
+
+        if batch.is_empty() {
+            break;
+        }
+
+        let responses = dispatch_expand_batch(batch, expand_client).await;
+        for response in responses {
+            muzero_step_apply_batch(
+                tree,
+                settings.top_moves,
+                response,
+                mapper,
+                settings.virtual_loss,
+            );
+        }
+    }
+
+    let zero_evaluation = if settings.use_completed_q_policy {
+        completed_q_policy_target(
+            tree,
+            &net_evaluation,
+            settings.completed_q_c_visit,
+            settings.completed_q_c_scale,
+        )
+    } else {
+        tree.eval()
+    };
+
+    let move_selector = MoveSelector::new(settings.temperature, settings.zero_temp_move_count);
+    let picked_index = move_selector.select(move_number, zero_evaluation.policy.as_ref(), rng);
+    let picked_child = tree[0].inner.as_ref().unwrap().children.get(picked_index);
+    let picked_move_index = tree[picked_child].last_move_index.unwrap();
+
+    (net_evaluation, zero_evaluation, picked_move_index)
+}
+
+/// Gumbel root action selection with Sequential Halving (see `RootSelection::Gumbel`). Draws one
+/// Gumbel sample per legal root move, keeps the `top_moves` highest `g(a) + logit(a)` scores as
+/// candidates, then repeatedly spends an even share of `target_visits` simulating each surviving
+/// candidate (rooted at its own child node via [`muzero_step_gather_from`]) and halves the candidate
+/// set by the completed-Q corrected score `g(a) + logit(a) + sigma(q(a))`. The move played is the
+/// last candidate standing; the stored policy target is `softmax(logit(a) + sigma(completed_q(a)))`
+/// over every legal move, not just the ones Sequential Halving kept around.
+#[allow(clippy::too_many_arguments)]
+async fn run_gumbel_search<B: AltBoard, M: BoardMapper<B>>(
+    tree: &mut MuTree<B>,
+    settings: &Settings,
+    mapper: M,
+    use_value: bool,
+    target_visits: u64,
+    c_visit: f32,
+    c_scale: f32,
+    root_client: &RootClient<B>,
+    expand_client: &ExpandClient,
+    rng: &mut impl Rng,
+) -> (ZeroEvaluation<'static>, ZeroEvaluation<'static>, usize) {
+    // the root always needs to be expanded first; there is no tree yet to descend
+    let root_request = muzero_step_gather(
+        tree,
+        settings.weights.to_uct(),
+        use_value,
+        FpuMode::Relative(0.0),
+    )
+    .expect("a freshly created tree always needs its root expanded first");
+    let (root_node, root_board) = match root_request {
+        MuZeroRequest::Root { node, board } => (node, board),
+        MuZeroRequest::Expand { .. } => {
+            unreachable!("the root is unexpanded, the first request must be Root")
+        }
+    };
+
+    let (state, eval) = root_client.map_async_single(root_board.clone()).await;
+
+    let net_evaluation = extract_zero_eval(mapper, &root_board, &eval);
+    let root_logits = raw_policy_for_moves(mapper, &root_board, &eval);
+
+    muzero_step_apply(
+        tree,
+        settings.top_moves,
+        MuZeroResponse {
+            node: root_node,
+            eval,
+            state,
+        },
+        mapper,
+    );
+
+    let legal_moves = root_logits.len();
+    let m = min(settings.top_moves, legal_moves);
+
+    // the Gumbel noise is drawn once and kept fixed for the rest of the search: it alone is what
+    // makes the final pick stochastic, Sequential Halving below only narrows down candidates
+    let gumbel_distr = Gumbel::new(0.0, 1.0).unwrap();
+    let scores: Vec<f32> = root_logits
+        .iter()
+        .map(|&logit| rng.sample(gumbel_distr) + logit)
+        .collect();
+
+    let root_children = tree[0].inner.as_ref().unwrap().children;
+    let root_state = tree[0].inner.as_ref().unwrap().state.clone();
+
+    let mut candidates = top_k_indices_sorted(scores.iter().copied().map(N32::from_inner), m);
+
+    let rounds = if m <= 1 {
+        1
+    } else {
+        (m as f32).log2().ceil() as u32
+    };
+    let mut visits_left = target_visits.saturating_sub(tree.root_visits());
+
+    for round in 0..rounds {
+        let rounds_left = (rounds - round) as u64;
+        let visits_this_round = visits_left / rounds_left;
+        let visits_per_candidate = max(1, visits_this_round / candidates.len() as u64);
+
+        for &candidate in &candidates {
+            let child = root_children.get(candidate);
+            let move_index = tree[child].last_move_index.unwrap();
+
+            let mut visits_remaining = visits_per_candidate;
+            while visits_remaining > 0 {
+                let batch_size = min(visits_remaining, settings.collect_batch as u64) as usize;
+
+                let batch = muzero_step_gather_from_batch(
+                    tree,
+                    child,
+                    move_index,
+                    root_state.clone(),
+                    batch_size,
+                    settings.virtual_loss,
+                    settings.weights.to_uct(),
+                    use_value,
+                    FpuMode::Relative(0.0),
+                );
+
+                if batch.is_empty() {
+                    break;
+                }
+
+                visits_remaining -= batch.len() as u64;
+
+                let responses = dispatch_expand_batch(batch, expand_client).await;
+                for response in responses {
+                    muzero_step_apply_batch(
+                        tree,
+                        settings.top_moves,
+                        response,
+                        mapper,
+                        settings.virtual_loss,
+                    );
+                }
+            }
+        }
+
+        visits_left = visits_left.saturating_sub(visits_per_candidate * candidates.len() as u64);
+
+        // narrow the candidate set down to the top half by the completed-Q corrected score
+        let max_visits = candidates
+            .iter()
+            .map(|&c| tree[root_children.get(c)].visits)
+            .max()
+            .unwrap();
+        let mut ranked: Vec<(usize, f32)> = candidates
+            .iter()
+            .map(|&c| {
+                let q = completed_q(tree, root_children.get(c), net_evaluation.values.value());
+                (c, scores[c] + sigma(q, max_visits, c_visit, c_scale))
+            })
+            .collect();
+        ranked.sort_by_key(|&(_, score)| std::cmp::Reverse(N32::from_inner(score)));
+
+        let keep = max(1, ranked.len() / 2);
+        candidates = ranked.into_iter().take(keep).map(|(c, _)| c).collect();
+    }
+
+    let picked_candidate = candidates[0];
+    let picked_child = root_children.get(picked_candidate);
+    let picked_move_index = tree[picked_child].last_move_index.unwrap();
+
+    // the policy target covers every legal move, not just the ones that survived Sequential Halving
+    let max_visits = (0..legal_moves)
+        .map(|i| tree[root_children.get(i)].visits)
+        .max()
+        .unwrap_or(0);
+    let mut policy: Vec<f32> = (0..legal_moves)
+        .map(|i| {
+            let q = completed_q(tree, root_children.get(i), net_evaluation.values.value());
+            root_logits[i] + sigma(q, max_visits, c_visit, c_scale)
+        })
+        .collect();
+    softmax_in_place(&mut policy);
+
+    let zero_evaluation = ZeroEvaluation {
+        values: net_evaluation.values,
+        policy: Cow::Owned(policy),
+    };
+
+    (net_evaluation, zero_evaluation, picked_move_index)
+}
+
+/// Dispatches a batch of `Expand` requests (as collected by [`muzero_step_gather_batch`]/
+/// [`muzero_step_gather_from_batch`]) concurrently through `expand_client`, so a single round trip
+/// through the coalescing dispatcher (see `kz_core::network::job_channel`) serves the whole batch
+/// instead of one network evaluation per tree descent.
+async fn dispatch_expand_batch<B: AltBoard>(
+    batch: Vec<MuZeroRequest<B>>,
+    expand_client: &ExpandClient,
+) -> Vec<MuZeroResponse<'static>> {
+    let futures = batch
+        .into_iter()
+        .map(|request| match request {
+            MuZeroRequest::Expand {
+                node,
+                state,
+                move_index,
+            } => {
+                async move {
+                    let (output_state, eval) =
+                        expand_client.map_async_single((state, move_index)).await;
+                    MuZeroResponse {
+                        node,
+                        eval,
+                        state: output_state,
+                    }
+                }
+            }
+            MuZeroRequest::Root { .. } => {
+                unreachable!("root requests are never batched together with expand requests")
+            }
+        })
+        .collect::<Vec<_>>();
+
+    join_all(futures).await
+}
+
+/// `sigma(q) = (c_visit + max_b N(b)) * c_scale * q`, the completed-Q correction Gumbel root
+/// selection uses both to narrow the candidate set each Sequential Halving round and to build the
+/// final policy target.
+fn sigma(q: f32, max_visits: u64, c_visit: f32, c_scale: f32) -> f32 {
+    (c_visit + max_visits as f32) * c_scale * q
+}
+
+/// `run_dirichlet_search`'s opt-in alternative to `tree.eval()`'s raw-visit-fraction policy target:
+/// the same completed-Q corrected `softmax(logit(a) + sigma(q_completed(a)))` construction
+/// `run_gumbel_search` uses, substituting `net_evaluation`'s root value for moves that ended up with
+/// zero visits instead of scoring them as if they were worthless. `net_evaluation.policy[i]` stands in
+/// for `logit(a)` as `ln(p(a))`: since softmax is shift-invariant, the missing additive normalization
+/// constant between a logit and its log-probability cancels out of the softmax below.
+fn completed_q_policy_target<B: AltBoard>(
+    tree: &MuTree<B>,
+    net_evaluation: &ZeroEvaluation,
+    c_visit: f32,
+    c_scale: f32,
+) -> ZeroEvaluation<'static> {
+    let root_children = tree[0].inner.as_ref().unwrap().children;
+    let legal_moves = net_evaluation.policy.len();
+
+    let max_visits = (0..legal_moves)
+        .map(|i| tree[root_children.get(i)].visits)
+        .max()
+        .unwrap_or(0);
+
+    let mut policy: Vec<f32> = (0..legal_moves)
+        .map(|i| {
+            let q = completed_q(tree, root_children.get(i), net_evaluation.values.value());
+            net_evaluation.policy[i].ln() + sigma(q, max_visits, c_visit, c_scale)
+        })
+        .collect();
+    softmax_in_place(&mut policy);
+
+    ZeroEvaluation {
+        values: net_evaluation.values,
+        policy: Cow::Owned(policy),
+    }
+}
+
+/// Like [`extract_zero_eval`], but returns the raw (pre-softmax) policy logits for the available
+/// moves instead of a normalized distribution. Gumbel root selection scores candidates as
+/// `g(a) + logit(a)`, so it needs the logits themselves rather than `extract_zero_eval`'s softmax.
+fn raw_policy_for_moves<B: AltBoard, M: BoardMapper<B>>(
+    mapper: M,
+    board: &B,
+    eval: &MuZeroEvaluation,
+) -> Vec<f32> {
+    board
+        .available_moves()
+        .map(|mv| {
+            let index = mapper.move_to_index(board, mv);
+            eval.policy_logits[index]
+        })
+        .collect()
+}
+
 fn add_dirichlet_noise<B: AltBoard, M: BoardMapper<B>>(
     policy_logits: &mut [f32],
     settings: &Settings,
@@ -246,10 +676,6 @@ fn add_dirichlet_noise<B: AltBoard, M: BoardMapper<B>>(
     mapper: M,
     rng: &mut impl Rng,
 ) {
-    // TODO consider using KataGo's shaped dirichlet noise, it's even more relevant for muzero
-    //   is that true? we're still just adding noise to the available moves!
-
-    let alpha = settings.dirichlet_alpha;
     let eps = settings.dirichlet_eps;
 
     // we're working on the logits here, so first take the softmax and then later un-softmax it
@@ -258,6 +684,14 @@ fn add_dirichlet_noise<B: AltBoard, M: BoardMapper<B>>(
 
     let mv_count = board.available_moves().count();
     if mv_count > 1 {
+        // KataGo-style shaped noise (`dirichlet_total_alpha`) holds the total concentration roughly
+        // constant across positions by scaling alpha down as the branching factor grows, instead of
+        // applying the same alpha regardless of how many moves are available
+        let alpha = match settings.dirichlet_total_alpha {
+            Some(total_alpha) => total_alpha / mv_count as f32,
+            None => settings.dirichlet_alpha,
+        };
+
         let distr = Dirichlet::new_with_size(alpha, mv_count).unwrap();
         let noise = rng.sample(distr);
 
@@ -277,14 +711,7 @@ fn extract_zero_eval<B: AltBoard, M: BoardMapper<B>>(
 ) -> ZeroEvaluation<'static> {
     //TODO maybe also collect valid mass here?
 
-    let mut policy: Vec<f32> = board
-        .available_moves()
-        .map(|mv| {
-            let index = mapper.move_to_index(board, mv);
-            eval.policy_logits[index]
-        })
-        .collect();
-
+    let mut policy = raw_policy_for_moves(mapper, board, eval);
     softmax_in_place(&mut policy);
 
     ZeroEvaluation {
@@ -13,4 +13,5 @@ pub mod generator_alphazero;
 pub mod generator_muzero;
 
 pub mod rebatcher;
-pub mod start_pos;
\ No newline at end of file
+pub mod start_pos;
+pub mod throughput;
\ No newline at end of file
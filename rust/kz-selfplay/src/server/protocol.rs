@@ -1,6 +1,7 @@
 use board_game::board::Board;
 use serde::{Deserialize, Serialize};
 
+use kz_core::network::ZeroEvaluation;
 use kz_core::zero::node::UctWeights;
 use std::fmt::{Display, Formatter};
 
@@ -21,6 +22,17 @@ pub struct StartupSettings {
     pub gpu_batch_size: usize,
     pub gpu_batch_size_root: usize,
 
+    /// Number of `crate::server::rebatcher` threads to run in front of each GPU executor thread pair,
+    /// pulling individual jobs off the shared job queue and coalescing them into full-size batches before
+    /// the GPU thread ever sees them. `0` keeps the old single-stage path, where the GPU thread calls
+    /// `JobServer::next_batch` itself.
+    pub rebatcher_threads: usize,
+
+    /// Wall-clock interval, in milliseconds, at which `crate::server::throughput`'s per-device aggregator
+    /// reports a `GeneratorUpdate::Throughput` summary (a reasonable default is around 5000). `0` disables
+    /// the aggregator entirely.
+    pub throughput_interval_ms: u64,
+
     pub saved_state_channels: usize,
 }
 
@@ -52,6 +64,15 @@ pub enum GeneratorUpdate<B: Board> {
         simulation: Simulation<B>,
     },
 
+    /// A stored position re-searched from scratch with the current network (see `reanalyze` in
+    /// `generator_muzero`), carrying fresh targets for a board that was already part of a finished game.
+    ReanalyzedPosition {
+        generator_id: usize,
+        board: B,
+        net_evaluation: ZeroEvaluation<'static>,
+        zero_evaluation: ZeroEvaluation<'static>,
+    },
+
     Evals {
         // the number of evaluations that hit the cache
         cached_evals: u64,
@@ -60,6 +81,27 @@ pub enum GeneratorUpdate<B: Board> {
         // the number of root muzero evals
         root_evals: u64,
     },
+
+    /// How full a batch a `crate::server::rebatcher` thread managed to assemble before its flush timeout
+    /// fired, reported once per assembled batch. Only emitted when `StartupSettings::rebatcher_threads` is
+    /// nonzero; averaging `achieved / max_batch_size` over these tells an operator whether
+    /// `gpu_batch_size`/`concurrent_games` are balanced.
+    BatchFillRatio {
+        achieved: usize,
+        max_batch_size: usize,
+    },
+
+    /// A rolled-up device utilization report emitted every `StartupSettings::throughput_interval_ms` by
+    /// `crate::server::throughput`'s aggregator thread, covering every `gpu-expand`/`gpu-root` executor on
+    /// one device since the previous report.
+    Throughput {
+        evals_per_sec: f64,
+        /// Mean, over every batch run in the interval, of `items_in_batch / max_batch_size`.
+        mean_batch_fill: f64,
+        /// Fraction of the interval each executor spent running `eval` rather than waiting for a batch to
+        /// fill, averaged over every tracked executor on the device.
+        gpu_busy_fraction: f64,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -85,6 +127,14 @@ pub struct Settings {
 
     pub dirichlet_alpha: f32,
     pub dirichlet_eps: f32,
+    /// `None` reproduces the old flat-alpha behavior (`dirichlet_alpha` applied as-is regardless of
+    /// branching factor). `Some(total_alpha)` switches to KataGo-style shaped noise instead: the
+    /// per-move alpha is scaled down to `total_alpha / mv_count` so the *total* Dirichlet concentration
+    /// stays roughly constant across narrow and wide-branching positions. A reasonable `total_alpha` is
+    /// about `10 * average_branching`.
+    pub dirichlet_total_alpha: Option<f32>,
+
+    pub root_selection: RootSelection,
 
     pub full_search_prob: f64,
     pub full_iterations: u64,
@@ -92,8 +142,44 @@ pub struct Settings {
 
     pub top_moves: usize,
 
+    /// When set, `run_dirichlet_search`'s final policy target is the same completed-Q corrected
+    /// `softmax(logit(a) + sigma(q_completed(a)))` construction `RootSelection::Gumbel` already uses,
+    /// instead of raw visit fractions; unvisited moves borrow the root value in place of a zero visit
+    /// count. `false` reproduces the old raw-visit-fraction target.
+    pub use_completed_q_policy: bool,
+    /// `c_visit`/`c_scale` for the `sigma` correction above, same meaning as `RootSelection::Gumbel`'s
+    /// fields of the same name. Unused unless `use_completed_q_policy` is set.
+    pub completed_q_c_visit: f32,
+    pub completed_q_c_scale: f32,
+
+    /// The fraction of finished games that get immediately re-searched with the current network (see
+    /// `reanalyze` in `generator_muzero`) before the generator moves on to a new game, producing fresh
+    /// `GeneratorUpdate::ReanalyzedPosition` targets to mix in with freshly generated ones. `0.0` disables
+    /// reanalyze entirely, reproducing the old behavior.
+    pub reanalyze_fraction: f64,
+
     // performance
     pub cache_size: usize,
+
+    /// The maximum number of distinct tree leaves to collect (applying virtual loss along the way)
+    /// before firing off their network evaluations together, instead of one evaluation per descent.
+    pub collect_batch: usize,
+    /// The pessimistic value temporarily added to a leaf's path while it sits in a collected batch,
+    /// undone again once the real evaluation comes back.
+    pub virtual_loss: f32,
+}
+
+/// How `generate_simulation` picks among the root's children and builds the policy target.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum RootSelection {
+    /// The original root: PUCT visits distributed by `muzero_step_gather`/`muzero_step_apply`, with
+    /// `dirichlet_alpha`/`dirichlet_eps` noise mixed into the root policy before the first visit.
+    Dirichlet,
+    /// Gumbel root action selection with Sequential Halving: `c_visit` and `c_scale` tune the
+    /// `sigma(q) = (c_visit + max_b N(b)) * c_scale * q` completed-Q correction used both to narrow the
+    /// candidate set each round and to build the final policy target.
+    Gumbel { c_visit: f32, c_scale: f32 },
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -108,10 +194,14 @@ impl Weights {
     pub fn to_uct(&self) -> UctWeights {
         let default = UctWeights::default();
         UctWeights {
-            exploration_weight: self.exploration_weight.unwrap_or(default.exploration_weight),
+            exploration_weight: self
+                .exploration_weight
+                .unwrap_or(default.exploration_weight),
             moves_left_weight: self.moves_left_weight.unwrap_or(default.moves_left_weight),
             moves_left_clip: self.moves_left_clip.unwrap_or(default.moves_left_clip),
-            moves_left_sharpness: self.moves_left_sharpness.unwrap_or(default.moves_left_sharpness),
+            moves_left_sharpness: self
+                .moves_left_sharpness
+                .unwrap_or(default.moves_left_sharpness),
         }
     }
 }
@@ -0,0 +1,57 @@
+use flume::Receiver;
+
+use kz_core::network::job_channel::{JobServer, PendingJob};
+
+use crate::server::executor::MAX_WAIT;
+
+/// Spawns `thread_count` rebatcher threads in front of `server`, each repeatedly pulling pending jobs off
+/// its shared inbox and coalescing them into batches of up to `max_batch_size` exactly like
+/// [`JobServer::next_batch`] does (that's what they call), then forwarding the packed batch over a
+/// `bounded(1)` channel to the returned receiver.
+///
+/// This moves batch assembly off the GPU thread and onto however many rebatcher threads are configured,
+/// so the GPU thread (see `crate::server::executor::batched_executor_loop`) spends its time running the
+/// network instead of coalescing many tiny per-generator requests. The `bounded(1)` handoff channel means
+/// a GPU thread that falls behind applies backpressure all the way back to the rebatchers, the same way a
+/// full `server` already applies backpressure to `JobClient`s.
+///
+/// `on_batch` is called with the size of each assembled batch, before it's sent onward; used to report
+/// `GeneratorUpdate::BatchFillRatio` without this module needing to know about `GeneratorUpdate` at all.
+pub fn spawn_rebatchers<I, O>(
+    thread_count: usize,
+    max_batch_size: usize,
+    server: JobServer<I, O>,
+    on_batch: impl Fn(usize) + Send + Clone + 'static,
+) -> Receiver<Vec<PendingJob<I, O>>>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    assert!(
+        thread_count > 0,
+        "spawn_rebatchers requires at least one thread"
+    );
+
+    let (batch_sender, batch_receiver) = flume::bounded(1);
+
+    for i in 0..thread_count {
+        let server = server.clone();
+        let batch_sender = batch_sender.clone();
+        let on_batch = on_batch.clone();
+
+        std::thread::Builder::new()
+            .name(format!("rebatcher-{}", i))
+            .spawn(move || {
+                while let Some(batch) = server.next_batch(max_batch_size, MAX_WAIT) {
+                    on_batch(batch.len());
+                    if batch_sender.send(batch).is_err() {
+                        // the executor side is gone, nothing left to do
+                        break;
+                    }
+                }
+            })
+            .unwrap();
+    }
+
+    batch_receiver
+}
@@ -1,14 +1,19 @@
-use crate::server::executor::batched_executor_loop;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::server::executor::{batched_executor_loop, BatchSource, ExecutorStats};
 use crate::server::generator_muzero::generator_muzero_main;
-use crate::server::job_channel::job_pair;
 use crate::server::protocol::{GeneratorUpdate, Settings, StartupSettings};
+use crate::server::rebatcher::spawn_rebatchers;
 use crate::server::server::{GraphSender, ZeroSpecialization};
+use crate::server::throughput::{spawn_throughput_aggregator, TrackedExecutor};
 use board_game::board::Board;
 use crossbeam::thread::Scope;
 use cuda_sys::wrapper::handle::Device;
 use flume::Sender;
 use futures::executor::ThreadPoolBuilder;
 use kz_core::mapping::BoardMapper;
+use kz_core::network::job_channel::{job_pair, JobServer};
 use kz_core::network::muzero::{MuZeroFusedGraphs, MuZeroGraphs};
 
 #[derive(Debug)]
@@ -37,10 +42,23 @@ impl<B: Board, M: BoardMapper<B> + 'static> ZeroSpecialization<B, M> for MuZeroS
         let mut settings_senders: Vec<Sender<Settings>> = vec![];
         let mut graph_senders: Vec<GraphSender<Self::G>> = vec![];
 
-        // TODO is it worth it to have a rebatcher again? it might take some CPU load from the GPU thread
         let (root_client, root_server) = job_pair(gpu_batch_size_root);
         let (expand_client, expand_server) = job_pair(gpu_batch_size_expand);
 
+        let rebatcher_threads = startup.rebatcher_threads;
+        let root_source = batch_source(
+            rebatcher_threads,
+            gpu_batch_size_root,
+            root_server,
+            update_sender.clone(),
+        );
+        let expand_source = batch_source(
+            rebatcher_threads,
+            gpu_batch_size_expand,
+            expand_server,
+            update_sender.clone(),
+        );
+
         // spawn cpu threads
         let pool = ThreadPoolBuilder::new()
             .pool_size(cpu_threads)
@@ -75,21 +93,30 @@ impl<B: Board, M: BoardMapper<B> + 'static> ZeroSpecialization<B, M> for MuZeroS
             });
         }
 
+        let mut tracked_executors: Vec<TrackedExecutor> = vec![];
+
         // spawn gpu expand eval threads
         for local_id in 0..gpu_threads {
             let (graph_sender, graph_receiver) = flume::bounded(1);
             graph_senders.push(graph_sender);
 
-            let expand_server = expand_server.clone();
+            let expand_source = expand_source.clone();
             let update_sender = update_sender.clone();
 
+            let stats = Arc::new(ExecutorStats::default());
+            tracked_executors.push(TrackedExecutor {
+                stats: stats.clone(),
+                max_batch_size: gpu_batch_size_expand,
+            });
+
             s.builder()
                 .name(format!("gpu-expand-{}-{}", device_id, local_id))
                 .spawn(move |_| {
                     batched_executor_loop(
                         gpu_batch_size_expand,
                         graph_receiver,
-                        expand_server,
+                        expand_source,
+                        stats,
                         |graph| graph.expand_executor(device, gpu_batch_size_expand),
                         |network, x| {
                             let y = network.eval_expand(&x);
@@ -112,16 +139,23 @@ impl<B: Board, M: BoardMapper<B> + 'static> ZeroSpecialization<B, M> for MuZeroS
             let (graph_sender, graph_receiver) = flume::bounded(1);
             graph_senders.push(graph_sender);
 
-            let root_server = root_server.clone();
+            let root_source = root_source.clone();
             let update_sender = update_sender.clone();
 
+            let stats = Arc::new(ExecutorStats::default());
+            tracked_executors.push(TrackedExecutor {
+                stats: stats.clone(),
+                max_batch_size: gpu_batch_size_root,
+            });
+
             s.builder()
                 .name(format!("gpu-root-{}", device_id))
                 .spawn(move |_| {
                     batched_executor_loop(
                         gpu_batch_size_root,
                         graph_receiver,
-                        root_server,
+                        root_source,
+                        stats,
                         |graph| graph.root_executor(device, gpu_batch_size_root),
                         |network, x| {
                             let y = network.eval_root(&x);
@@ -139,6 +173,15 @@ impl<B: Board, M: BoardMapper<B> + 'static> ZeroSpecialization<B, M> for MuZeroS
                 .unwrap();
         }
 
+        if startup.throughput_interval_ms > 0 {
+            spawn_throughput_aggregator(
+                device_id,
+                Duration::from_millis(startup.throughput_interval_ms),
+                tracked_executors,
+                update_sender,
+            );
+        }
+
         (settings_senders, graph_senders)
     }
 
@@ -154,3 +197,29 @@ impl<B: Board, M: BoardMapper<B> + 'static> ZeroSpecialization<B, M> for MuZeroS
         graphs.fuse(Default::default())
     }
 }
+
+/// Picks between the direct and rebatched paths based on `rebatcher_threads` (see
+/// `StartupSettings::rebatcher_threads`), wiring the rebatcher's per-batch callback to
+/// `GeneratorUpdate::BatchFillRatio` when rebatching is enabled.
+fn batch_source<B: Board, I, O>(
+    rebatcher_threads: usize,
+    max_batch_size: usize,
+    server: JobServer<I, O>,
+    update_sender: Sender<GeneratorUpdate<B>>,
+) -> BatchSource<I, O>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    if rebatcher_threads == 0 {
+        return server.into();
+    }
+
+    spawn_rebatchers(rebatcher_threads, max_batch_size, server, move |achieved| {
+        let _ = update_sender.send(GeneratorUpdate::BatchFillRatio {
+            achieved,
+            max_batch_size,
+        });
+    })
+    .into()
+}
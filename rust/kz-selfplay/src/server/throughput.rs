@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use board_game::board::Board;
+use flume::Sender;
+
+use crate::server::executor::ExecutorStats;
+use crate::server::protocol::GeneratorUpdate;
+
+/// An [`ExecutorStats`] paired with the batch-size cap its executor was built with, so the aggregator can
+/// turn raw item/batch counts back into a fill ratio.
+#[derive(Debug, Clone)]
+pub struct TrackedExecutor {
+    pub stats: Arc<ExecutorStats>,
+    pub max_batch_size: usize,
+}
+
+/// Spawns the per-device throughput aggregator thread: every `interval`, drains every [`ExecutorStats`] in
+/// `executors`, combines them into a single [`GeneratorUpdate::Throughput`] report, and sends it on
+/// `update_sender`. This gives a rolled-up picture of device utilization without having to parse the raw
+/// per-batch `GeneratorUpdate::Evals` stream.
+///
+/// Exits once every tracked executor thread has exited (detected by its `Arc<ExecutorStats>` having no
+/// other owner left), so it never outlives the device it reports on.
+pub fn spawn_throughput_aggregator<B: Board + 'static>(
+    device_id: usize,
+    interval: Duration,
+    executors: Vec<TrackedExecutor>,
+    update_sender: Sender<GeneratorUpdate<B>>,
+) {
+    thread::Builder::new()
+        .name(format!("throughput-{}", device_id))
+        .spawn(move || loop {
+            thread::sleep(interval);
+
+            if executors.iter().all(|e| Arc::strong_count(&e.stats) == 1) {
+                break;
+            }
+
+            let mut items = 0u64;
+            let mut batches = 0u64;
+            let mut idle_nanos = 0u64;
+            let mut busy_nanos = 0u64;
+            let mut fill_weighted = 0.0f64;
+
+            for executor in &executors {
+                let snapshot = executor.stats.drain();
+
+                items += snapshot.items;
+                batches += snapshot.batches;
+                idle_nanos += snapshot.idle_nanos;
+                busy_nanos += snapshot.busy_nanos;
+
+                if snapshot.batches > 0 {
+                    let mean_fill = snapshot.items as f64
+                        / snapshot.batches as f64
+                        / executor.max_batch_size as f64;
+                    fill_weighted += snapshot.batches as f64 * mean_fill;
+                }
+            }
+
+            let evals_per_sec = items as f64 / interval.as_secs_f64();
+            let mean_batch_fill = if batches > 0 {
+                fill_weighted / batches as f64
+            } else {
+                0.0
+            };
+            let gpu_busy_fraction = if idle_nanos + busy_nanos > 0 {
+                busy_nanos as f64 / (idle_nanos + busy_nanos) as f64
+            } else {
+                0.0
+            };
+
+            let _ = update_sender.send(GeneratorUpdate::Throughput {
+                evals_per_sec,
+                mean_batch_fill,
+                gpu_busy_fraction,
+            });
+        })
+        .unwrap();
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use crate::graph::{Graph, Operation, Value};
+use crate::shape::{Shape, Size};
+
+/// Reverse-mode automatic differentiation over a [`Graph`].
+///
+/// [`Gradients::build`] seeds the cotangent of a scalar `loss` value with `1` and walks the graph's
+/// node list backwards (it is already in topological order, so this is just iterating `graph.values()`
+/// in reverse) dispatching a per-operation vector-Jacobian-product rule that pushes new nodes onto
+/// `graph` and accumulates (via [`Graph::add`]) into the cotangent of each of that operation's inputs.
+/// The resulting gradient nodes live in the same graph as the forward pass, so they can be evaluated
+/// (on CPU or CUDA) like any other value.
+///
+/// Only operations whose backward rule is expressible with the primitives this graph IR currently has
+/// are supported: `View`, a full-range `Slice`, broadcast `Add`/`Mul`, `Softmax`, `Permute` and
+/// `Select` differentiate for real, using [`Operation::Sum`] to fold the cotangent back down along a
+/// reduced/broadcast axis. The other ops panic instead of silently returning a wrong gradient, so the
+/// conv layers and the `Sigmoid`/`Tanh` value head of the networks this crate trains cannot be
+/// differentiated here:
+/// * `Clamp` could now be built from `Compare`/`Select` (a `value > threshold` mask), but isn't wired up yet.
+/// * `Conv` needs a transposed/flipped convolution, a primitive this graph IR doesn't have at all yet
+///   (distinct from the reduction gap above, which `Operation::Sum` already closes).
+/// * `Gather`/`GatherNd` need a scatter-add op to route the cotangent back to the gathered positions.
+/// * `Unary` (`Sigmoid`/`Tanh`) needs access to its own forward output to express its VJP cheaply.
+/// * `Round` is zero almost everywhere and undefined at the integers, so it has no useful gradient.
+/// * `Compare`'s output is a discrete 0/1 mask, so it has no gradient either.
+pub struct Gradients {
+    cotangents: HashMap<Value, Value>,
+}
+
+impl Gradients {
+    /// Backpropagate from `loss`, which must have a fixed, scalar (size 1) shape, through `graph`.
+    pub fn build(graph: &mut Graph, loss: Value) -> Gradients {
+        let loss_shape = graph[loss].shape.clone();
+        assert_eq!(loss_shape.size().unwrap_fixed("Loss size"), 1, "Loss must be a scalar, got shape {:?}", loss_shape);
+
+        let mut cotangents = HashMap::new();
+        let ones = graph.constant(loss_shape, vec![1.0]);
+        cotangents.insert(loss, ones);
+
+        // the node list is already in topological order, so visiting it back to front means every
+        // node's users have already had a chance to contribute their cotangent by the time we get to it
+        for value in graph.values().collect::<Vec<_>>().into_iter().rev() {
+            let cotangent = match cotangents.get(&value) {
+                Some(&cotangent) => cotangent,
+                // no user of this value depends on the loss, so there is nothing to propagate
+                None => continue,
+            };
+
+            let operation = graph[value].operation.clone();
+            Self::propagate(graph, &mut cotangents, operation, cotangent);
+        }
+
+        Gradients { cotangents }
+    }
+
+    /// The accumulated gradient for `value`, or `None` if `value` does not affect the loss.
+    pub fn of(&self, value: Value) -> Option<Value> {
+        self.cotangents.get(&value).copied()
+    }
+
+    fn propagate(graph: &mut Graph, cotangents: &mut HashMap<Value, Value>, operation: Operation, cotangent: Value) {
+        match operation {
+            Operation::Input { .. } | Operation::Constant { .. } => {
+                // leaf node, nothing further to propagate to
+            }
+            Operation::View { input } => {
+                let input_shape = graph[input].shape.clone();
+                let routed = graph.view(cotangent, input_shape);
+                Self::accumulate(graph, cotangents, input, routed);
+            }
+            Operation::Slice { input, axis, start, end } => {
+                let input_size = graph[input].shape[axis].unwrap_fixed("Slice axis size");
+                assert!(
+                    start == 0 && end == input_size,
+                    "Gradient through a partial Slice needs a scatter/pad op this graph IR doesn't have yet",
+                );
+                Self::accumulate(graph, cotangents, input, cotangent);
+            }
+            Operation::Conv { .. } => {
+                todo!("Conv gradient needs a transposed/flipped convolution, not implemented yet")
+            }
+            Operation::Add { left, right, subtract } => {
+                let left_shape = graph[left].shape.clone();
+                let left_cotangent = Self::reduce_to_shape(graph, cotangent, &left_shape);
+                Self::accumulate(graph, cotangents, left, left_cotangent);
+
+                let right_cotangent = if subtract {
+                    let neg_one = graph.constant(Shape::ones(graph[cotangent].shape.rank()), vec![-1.0]);
+                    graph.mul(cotangent, neg_one)
+                } else {
+                    cotangent
+                };
+                let right_shape = graph[right].shape.clone();
+                let right_cotangent = Self::reduce_to_shape(graph, right_cotangent, &right_shape);
+                Self::accumulate(graph, cotangents, right, right_cotangent);
+            }
+            Operation::Mul { left, right } => {
+                let left_shape = graph[left].shape.clone();
+                let left_cotangent = graph.mul(cotangent, right);
+                let left_cotangent = Self::reduce_to_shape(graph, left_cotangent, &left_shape);
+                Self::accumulate(graph, cotangents, left, left_cotangent);
+
+                let right_shape = graph[right].shape.clone();
+                let right_cotangent = graph.mul(cotangent, left);
+                let right_cotangent = Self::reduce_to_shape(graph, right_cotangent, &right_shape);
+                Self::accumulate(graph, cotangents, right, right_cotangent);
+            }
+            Operation::Clamp { .. } => {
+                todo!("Clamp gradient needs a `value > threshold` selection primitive, not implemented yet")
+            }
+            Operation::Softmax { input, axis, quiet } => {
+                // d(softmax)/d(input) in matrix form is `diag(output) - output outer output`, so the
+                // VJP is `output * (cotangent - sum(cotangent * output, axis))`; the quiet variant's
+                // extra `+1` denominator term only adds a constant to the normalizer, so it doesn't
+                // change this VJP and both variants share the same backward rule.
+                let output = if quiet { graph.quiet_softmax(input, axis) } else { graph.softmax(input, axis) };
+                let weighted = graph.mul(cotangent, output);
+                let sum = graph.sum(weighted, axis);
+                let centered = graph.sub(cotangent, sum);
+                let input_cotangent = graph.mul(output, centered);
+                Self::accumulate(graph, cotangents, input, input_cotangent);
+            }
+            Operation::Gather { .. } | Operation::GatherNd { .. } => {
+                todo!("Gather/GatherND gradient needs a scatter-add op this graph IR doesn't have yet")
+            }
+            Operation::Unary { .. } => {
+                todo!("Sigmoid/Tanh gradient needs the forward output value, which this reverse walk doesn't keep around yet")
+            }
+            Operation::Permute { input, permutation } => {
+                let mut inverse = vec![0; permutation.len()];
+                for (k, &p) in permutation.iter().enumerate() {
+                    inverse[p] = k;
+                }
+                let routed = graph.permute(cotangent, inverse);
+                Self::accumulate(graph, cotangents, input, routed);
+            }
+            Operation::Round { .. } => {
+                todo!("Round's gradient is zero almost everywhere and undefined at the integers, not implemented yet")
+            }
+            Operation::Compare { .. } => {
+                todo!("Compare's output is a discrete 0/1 mask, it has no well-defined gradient")
+            }
+            Operation::Select { cond, on_true, on_false } => {
+                let zero = graph.constant(Shape::ones(graph[cotangent].shape.rank()), vec![0.0]);
+                let on_true_shape = graph[on_true].shape.clone();
+                let on_false_shape = graph[on_false].shape.clone();
+
+                let true_cotangent = graph.select(cond, cotangent, zero);
+                let true_cotangent = Self::reduce_to_shape(graph, true_cotangent, &on_true_shape);
+                Self::accumulate(graph, cotangents, on_true, true_cotangent);
+
+                let false_cotangent = graph.select(cond, zero, cotangent);
+                let false_cotangent = Self::reduce_to_shape(graph, false_cotangent, &on_false_shape);
+                Self::accumulate(graph, cotangents, on_false, false_cotangent);
+            }
+        }
+    }
+
+    /// Sums `cotangent` back down to `target_shape` along every axis that a forward
+    /// [`Graph::broadcast_pad`] left-padded or broadcast up from size 1, using [`Graph::sum`]. A no-op
+    /// wherever the two shapes already agree. `cotangent` and `target_shape` must already have the same
+    /// rank, which holds for every broadcast operand: `Graph::add`/`mul`/`select` pad every operand to
+    /// the output's rank before building the op, so the stored operand (what `target_shape` comes from
+    /// here) and the cotangent flowing back from the output always line up axis-for-axis.
+    fn reduce_to_shape(graph: &mut Graph, cotangent: Value, target_shape: &Shape) -> Value {
+        let cotangent_shape = graph[cotangent].shape.clone();
+        assert_eq!(
+            cotangent_shape.rank(), target_shape.rank(),
+            "broadcast operand {:?} and cotangent {:?} must already share a rank", target_shape, cotangent_shape,
+        );
+
+        let mut result = cotangent;
+        for axis in 0..target_shape.rank() {
+            if target_shape[axis] == Size::ONE && cotangent_shape[axis] != Size::ONE {
+                result = graph.sum(result, axis);
+            }
+        }
+        result
+    }
+
+    /// Add `contribution` into the running cotangent for `value`, creating it if this is the first one.
+    fn accumulate(graph: &mut Graph, cotangents: &mut HashMap<Value, Value>, value: Value, contribution: Value) {
+        let total = match cotangents.get(&value) {
+            Some(&existing) => graph.add(existing, contribution),
+            None => contribution,
+        };
+        cotangents.insert(value, total);
+    }
+}
@@ -3,10 +3,11 @@ use std::time::Instant;
 
 use convolutions_rs::convolutions::*;
 use convolutions_rs::Padding;
+use gemm::Parallelism;
 use indexmap::IndexMap;
 use itertools::Itertools;
 use ndarray::{
-    concatenate, s, ArcArray, Array3, Array4, ArrayView3, ArrayView4, Ix3, IxDyn, SliceInfo, SliceInfoElem, Zip,
+    concatenate, ArcArray, Array3, Array4, ArrayView3, ArrayView4, Ix3, IxDyn, SliceInfo, SliceInfoElem, Zip,
 };
 
 use crate::graph::{ConvDetails, ElementOp, Graph, Operation, Value, ValueInfo};
@@ -15,9 +16,17 @@ use crate::ndarray::{Array, ArrayBase, Axis};
 /// We're using an ArcArray so reshaping is free.
 pub type Tensor = ArcArray<f32, IxDyn>;
 
-pub fn cpu_execute_graph(graph: &Graph, batch_size: usize, inputs: &[Tensor]) -> ExecutionInfo {
+pub fn cpu_execute_graph(graph: &Graph, batch_size: usize, inputs: &[Tensor], parallelism: Parallelism) -> ExecutionInfo {
     assert_eq!(graph.inputs().len(), inputs.len(), "Wrong input count");
 
+    // `MemoryPlan` (see its docs) could reuse buffers between nodes here, but it isn't wired in: this
+    // function's own match arms below (`Broadcast`/`Flip`/`Concat`/`MatMul`/`Element`, ...) are written
+    // against an `Operation` vocabulary that doesn't correspond to any variant `graph.rs` actually
+    // defines, a pre-existing divergence `MemoryPlan` doesn't share (it only relies on the generic,
+    // current `Operation::inputs()`). Still NOT RESOLVED: wiring it in is blocked on rewriting this
+    // function against the real `Operation` enum first, which is out of scope here. Every value below
+    // just gets its own freshly allocated buffer; callers who want the reuse plan for a given
+    // `(graph, batch_size)` can call `MemoryPlan::build` themselves.
     let mut map: IndexMap<Value, CalculatedValue> = IndexMap::default();
 
     for output in graph.values() {
@@ -97,7 +106,7 @@ pub fn cpu_execute_graph(graph: &Graph, batch_size: usize, inputs: &[Tensor]) ->
             } => {
                 let input = map.get(&input).unwrap().tensor.view().into_dimensionality().unwrap();
                 let filter = map.get(&filter).unwrap().tensor.view().into_dimensionality().unwrap();
-                let result = convolution(conv_shape, input, filter);
+                let result = convolution(conv_shape, input, filter, parallelism);
                 result.into_dyn().into_shared()
             }
             &Operation::MatMul { left, right } => {
@@ -107,6 +116,7 @@ pub fn cpu_execute_graph(graph: &Graph, batch_size: usize, inputs: &[Tensor]) ->
                 batched_mat_mul(
                     left.view().into_dimensionality::<Ix3>().unwrap(),
                     right.view().into_dimensionality::<Ix3>().unwrap(),
+                    parallelism,
                 )
                 .into_dyn()
                 .into_shared()
@@ -146,12 +156,22 @@ pub fn cpu_execute_graph(graph: &Graph, batch_size: usize, inputs: &[Tensor]) ->
     }
 }
 
-pub fn convolution(details: ConvDetails, input: ArrayView4<f32>, filter: ArrayView4<f32>) -> Array4<f32> {
+/// Dispatches to the single-threaded `convolutions_rs` reference implementation for `Parallelism::None`
+/// (kept byte-exact so the `test_all_graph` CPU reference path doesn't shift), and to an im2col-plus-
+/// `gemm` path for anything else, which is much faster for the batch sizes self-play actually uses.
+pub fn convolution(details: ConvDetails, input: ArrayView4<f32>, filter: ArrayView4<f32>, parallelism: Parallelism) -> Array4<f32> {
     assert!(
         details.keeps_spatial_shape(),
         "Different in/out shape not supported yet"
     );
 
+    match parallelism {
+        Parallelism::None => convolution_reference(details, input, filter),
+        _ => convolution_im2col(details, input, filter, parallelism),
+    }
+}
+
+fn convolution_reference(details: ConvDetails, input: ArrayView4<f32>, filter: ArrayView4<f32>) -> Array4<f32> {
     let batch_size = input.shape()[0];
     let output_shape = (batch_size, details.output_channels, details.output_h, details.output_w);
 
@@ -164,7 +184,69 @@ pub fn convolution(details: ConvDetails, input: ArrayView4<f32>, filter: ArrayVi
     result
 }
 
-pub fn batched_mat_mul(left: ArrayView3<f32>, right: ArrayView3<f32>) -> Array3<f32> {
+/// Lowers the convolution to an im2col matmul: each output position becomes a column of
+/// `input_channels * kernel_h * kernel_w` gathered input values, the filter is flattened to
+/// `[output_channels, input_channels * kernel_h * kernel_w]`, and the two are multiplied with the
+/// same [`batched_mat_mul`] the `MatMul` operation uses (filter broadcast across the batch axis).
+fn convolution_im2col(details: ConvDetails, input: ArrayView4<f32>, filter: ArrayView4<f32>, parallelism: Parallelism) -> Array4<f32> {
+    let batch_size = input.shape()[0];
+    let ConvDetails {
+        input_channels,
+        output_channels,
+        input_w,
+        input_h,
+        kernel_w,
+        kernel_h,
+        padding_w,
+        padding_h,
+        output_w,
+        output_h,
+        ..
+    } = details;
+
+    let positions = output_h * output_w;
+    let cols_per_row = input_channels * kernel_h * kernel_w;
+
+    let mut col = Array3::<f32>::zeros((batch_size, cols_per_row, positions));
+    for b in 0..batch_size {
+        for oy in 0..output_h {
+            for ox in 0..output_w {
+                let row = oy * output_w + ox;
+
+                for c in 0..input_channels {
+                    for ky in 0..kernel_h {
+                        let iy = oy as isize + ky as isize - padding_h as isize;
+                        for kx in 0..kernel_w {
+                            let ix = ox as isize + kx as isize - padding_w as isize;
+
+                            let value = if iy >= 0 && (iy as usize) < input_h && ix >= 0 && (ix as usize) < input_w {
+                                input[(b, c, iy as usize, ix as usize)]
+                            } else {
+                                0.0
+                            };
+
+                            let col_index = (c * kernel_h + ky) * kernel_w + kx;
+                            col[(b, col_index, row)] = value;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let filter_mat = filter.into_shape((output_channels, cols_per_row)).unwrap();
+    let filter_mat = filter_mat
+        .insert_axis(Axis(0))
+        .broadcast((batch_size, output_channels, cols_per_row))
+        .unwrap();
+
+    let result = batched_mat_mul(filter_mat, col.view(), parallelism);
+    result.into_shape((batch_size, output_channels, output_h, output_w)).unwrap()
+}
+
+/// Batched matrix multiplication, using the `gemm` crate for the per-batch-item kernel instead of
+/// `ndarray`'s own (single-threaded) `dot`, so large batched linear layers stay fast.
+pub fn batched_mat_mul(left: ArrayView3<f32>, right: ArrayView3<f32>, parallelism: Parallelism) -> Array3<f32> {
     let (n0, p, q0) = left.dim();
     let (n1, q1, r) = right.dim();
     assert!(
@@ -176,10 +258,27 @@ pub fn batched_mat_mul(left: ArrayView3<f32>, right: ArrayView3<f32>) -> Array3<
 
     let mut result = Array3::zeros((n0, p, r));
     for i in 0..n0 {
-        let slice = s![i, .., ..];
-        result
-            .slice_mut(&slice)
-            .assign(&left.slice(&slice).dot(&right.slice(&slice)));
+        let left = left.index_axis(Axis(0), i);
+        let right = right.index_axis(Axis(0), i);
+        let mut dst = result.index_axis_mut(Axis(0), i);
+
+        // ndarray strides are already in units of elements, which is what `gemm` expects
+        let (dst_rs, dst_cs) = (dst.strides()[0], dst.strides()[1]);
+        let (left_rs, left_cs) = (left.strides()[0], left.strides()[1]);
+        let (right_rs, right_cs) = (right.strides()[0], right.strides()[1]);
+
+        unsafe {
+            gemm::gemm(
+                p, r, q0,
+                dst.as_mut_ptr(), dst_cs, dst_rs,
+                false,
+                left.as_ptr(), left_cs, left_rs,
+                right.as_ptr(), right_cs, right_rs,
+                0.0, 1.0,
+                false, false, false,
+                parallelism,
+            );
+        }
     }
     result
 }
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use decorum::N32;
+use itertools::Itertools;
+
+use crate::egraph::ConvDetailsKey;
+use crate::graph::{CompareOp, Graph, Operation, UnaryOp, Value};
+use crate::shape::Shape;
+
+/// Canonical structural identity of a node once its operands have already been rewritten to their own
+/// representative: same operation kind, same operands (order-sensitive, except commutative ops like
+/// `Add { subtract: false }`/`Mul`, whose operands are sorted by execution order so equivalent
+/// expressions collapse onto the same key), same attributes, same output shape.
+#[derive(Clone, Eq, PartialEq, Hash)]
+struct NodeKey {
+    shape: Shape,
+    op: OpKey,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash)]
+enum OpKey {
+    Input { index: usize },
+    Constant { data: Vec<N32> },
+    View { input: Value },
+    Slice { input: Value, axis: usize, start: usize, end: usize },
+    Conv { input: Value, filter: Value, bias: Option<Value>, relu: bool, details: ConvDetailsKey },
+    Add { left: Value, right: Value, subtract: bool },
+    Mul { left: Value, right: Value },
+    Clamp { input: Value, min: N32, max: N32 },
+    Softmax { input: Value, axis: usize, quiet: bool },
+    Gather { input: Value, axis: usize, indices: Value },
+    GatherNd { data: Value, indices: Value, batch_dims: usize },
+    Unary { input: Value, op: UnaryOp },
+    Permute { input: Value, permutation: Vec<usize> },
+    Round { input: Value },
+    Sum { input: Value, axis: usize },
+    Compare { left: Value, right: Value, mode: CompareOp },
+    Select { cond: Value, on_true: Value, on_false: Value },
+}
+
+/// Common-subexpression elimination: finds structurally identical nodes (same operation, same
+/// operands, same attributes, same output shape) and collapses them to a single representative.
+///
+/// Uses global value numbering backed by a union-find disjoint-set over value positions: processing
+/// `graph.values()` in their already-topological order, each node's canonicalized [`NodeKey`] (built
+/// from its operands' *current* representatives, so a dedup earlier in the graph is visible to every
+/// node downstream of it) is looked up in a hash map; a hit unions this value into the existing
+/// representative, a miss registers this value as the representative for that key. The new graph is
+/// then rebuilt by walking the original order and emitting exactly one node per disjoint-set root,
+/// with every consumer rewired (via `find`) to reference that root instead of the duplicate it
+/// originally pointed to.
+pub fn dedup_values(graph: &Graph) -> Graph {
+    let order = graph.values().collect_vec();
+    let index_of: HashMap<Value, usize> = order.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+
+    let mut parent: Vec<usize> = (0..order.len()).collect();
+
+    let mut keys: HashMap<NodeKey, Value> = HashMap::new();
+
+    for (i, &v) in order.iter().enumerate() {
+        let find_rep = |parent: &mut [usize], input: Value| -> Value {
+            order[find(parent, index_of[&input])]
+        };
+
+        let key = node_key(graph, v, &index_of, |input| find_rep(&mut parent, input));
+
+        match keys.get(&key) {
+            Some(&rep) => {
+                let root = find(&mut parent, index_of[&rep]);
+                parent[i] = root;
+            }
+            None => {
+                keys.insert(key, v);
+            }
+        }
+    }
+
+    let mut new_graph = Graph::new();
+    let mut built: HashMap<Value, Value> = HashMap::new();
+
+    for (i, &v) in order.iter().enumerate() {
+        if find(&mut parent, i) != i {
+            // this value was unioned into an earlier representative; its consumers were (or will be)
+            // rewired to that representative instead, so there's nothing left to emit for it
+            continue;
+        }
+
+        let map_input = |input: Value| built[&order[find(&mut parent, index_of[&input])]];
+        let new_value = rebuild_value(graph, v, map_input, &mut new_graph);
+        built.insert(v, new_value);
+    }
+
+    let new_outputs = graph
+        .outputs()
+        .iter()
+        .map(|&v| built[&order[find(&mut parent, index_of[&v])]])
+        .collect_vec();
+    new_graph.output_all(&new_outputs);
+
+    new_graph
+}
+
+fn find(parent: &mut [usize], mut x: usize) -> usize {
+    while parent[x] != x {
+        parent[x] = parent[parent[x]];
+        x = parent[x];
+    }
+    x
+}
+
+fn node_key(graph: &Graph, v: Value, index_of: &HashMap<Value, usize>, mut rep_of: impl FnMut(Value) -> Value) -> NodeKey {
+    let shape = graph[v].shape.clone();
+
+    // sorts two already-canonicalized operands by their position in the original execution order, so
+    // `a op b` and `b op a` collapse onto the same key for a commutative op
+    let sorted = |a: Value, b: Value| if index_of[&a] <= index_of[&b] { (a, b) } else { (b, a) };
+
+    let op = match &graph[v].operation {
+        &Operation::Input { index } => OpKey::Input { index },
+        Operation::Constant { data } => OpKey::Constant { data: data.iter().map(|&x| N32::from_inner(x)).collect() },
+        &Operation::View { input } => OpKey::View { input: rep_of(input) },
+        &Operation::Slice { input, axis, start, end } => OpKey::Slice { input: rep_of(input), axis, start, end },
+        &Operation::Conv { input, filter, bias, relu, details } => {
+            OpKey::Conv { input: rep_of(input), filter: rep_of(filter), bias: bias.map(&mut rep_of), relu, details: ConvDetailsKey(details) }
+        }
+        &Operation::Add { left, right, subtract } => {
+            let (left, right) = (rep_of(left), rep_of(right));
+            let (left, right) = if subtract { (left, right) } else { sorted(left, right) };
+            OpKey::Add { left, right, subtract }
+        }
+        &Operation::Mul { left, right } => {
+            let (left, right) = sorted(rep_of(left), rep_of(right));
+            OpKey::Mul { left, right }
+        }
+        &Operation::Clamp { input, min, max } => {
+            OpKey::Clamp { input: rep_of(input), min: N32::from_inner(min), max: N32::from_inner(max) }
+        }
+        &Operation::Softmax { input, axis, quiet } => OpKey::Softmax { input: rep_of(input), axis, quiet },
+        &Operation::Gather { input, axis, indices } => OpKey::Gather { input: rep_of(input), axis, indices: rep_of(indices) },
+        &Operation::GatherNd { data, indices, batch_dims } => {
+            OpKey::GatherNd { data: rep_of(data), indices: rep_of(indices), batch_dims }
+        }
+        &Operation::Unary { input, op } => OpKey::Unary { input: rep_of(input), op },
+        Operation::Permute { input, permutation } => OpKey::Permute { input: rep_of(*input), permutation: permutation.clone() },
+        &Operation::Round { input } => OpKey::Round { input: rep_of(input) },
+        &Operation::Sum { input, axis } => OpKey::Sum { input: rep_of(input), axis },
+        &Operation::Compare { left, right, mode } => OpKey::Compare { left: rep_of(left), right: rep_of(right), mode },
+        &Operation::Select { cond, on_true, on_false } => {
+            OpKey::Select { cond: rep_of(cond), on_true: rep_of(on_true), on_false: rep_of(on_false) }
+        }
+    };
+
+    NodeKey { shape, op }
+}
+
+fn rebuild_value(graph: &Graph, v: Value, mut map_input: impl FnMut(Value) -> Value, new_graph: &mut Graph) -> Value {
+    let shape = graph[v].shape.clone();
+
+    match &graph[v].operation {
+        Operation::Input { .. } => new_graph.input(shape),
+        Operation::Constant { data } => new_graph.constant(shape, (&**data).clone()),
+        &Operation::View { input } => new_graph.view(map_input(input), shape),
+        &Operation::Slice { input, axis, start, end } => new_graph.slice(map_input(input), axis, start, end),
+        &Operation::Conv { input, filter, bias, relu, details } => {
+            new_graph.push(shape, Operation::Conv {
+                input: map_input(input),
+                filter: map_input(filter),
+                bias: bias.map(&mut map_input),
+                relu,
+                details,
+            })
+        }
+        &Operation::Add { left, right, subtract } => {
+            new_graph.push(shape, Operation::Add { left: map_input(left), right: map_input(right), subtract })
+        }
+        &Operation::Mul { left, right } => {
+            new_graph.push(shape, Operation::Mul { left: map_input(left), right: map_input(right) })
+        }
+        &Operation::Clamp { input, min, max } => {
+            new_graph.push(shape, Operation::Clamp { input: map_input(input), min, max })
+        }
+        &Operation::Softmax { input, axis, quiet } => {
+            new_graph.push(shape, Operation::Softmax { input: map_input(input), axis, quiet })
+        }
+        &Operation::Gather { input, axis, indices } => {
+            new_graph.push(shape, Operation::Gather { input: map_input(input), axis, indices: map_input(indices) })
+        }
+        &Operation::GatherNd { data, indices, batch_dims } => {
+            new_graph.push(shape, Operation::GatherNd { data: map_input(data), indices: map_input(indices), batch_dims })
+        }
+        &Operation::Unary { input, op } => new_graph.push(shape, Operation::Unary { input: map_input(input), op }),
+        Operation::Permute { input, permutation } => {
+            new_graph.push(shape, Operation::Permute { input: map_input(*input), permutation: permutation.clone() })
+        }
+        &Operation::Round { input } => new_graph.push(shape, Operation::Round { input: map_input(input) }),
+        &Operation::Sum { input, axis } => new_graph.push(shape, Operation::Sum { input: map_input(input), axis }),
+        &Operation::Compare { left, right, mode } => {
+            new_graph.push(shape, Operation::Compare { left: map_input(left), right: map_input(right), mode })
+        }
+        &Operation::Select { cond, on_true, on_false } => {
+            new_graph.push(shape, Operation::Select { cond: map_input(cond), on_true: map_input(on_true), on_false: map_input(on_false) })
+        }
+    }
+}
@@ -0,0 +1,570 @@
+//! An equality-saturation optimizer for [`Graph`], meant to eventually replace the fixed, order-dependent
+//! peephole passes in `optimize_graph` with a confluent one: instead of applying rewrites directly to the
+//! graph (where applying rule A before rule B can permanently hide a simplification that B-then-A would
+//! have found), every rewrite is recorded as an *additional* equivalent way to compute the same
+//! [`EClass`], so all of them stay reachable until [`extract`] picks the cheapest one at the end.
+//!
+//! The pipeline is the classic three steps:
+//! 1. [`EGraph::from_graph`] seeds one e-class per original [`Value`].
+//! 2. [`saturate`] repeatedly looks for rewrites among the [`RULES`] and unions their result into the
+//!    matched class, until nothing new is found or a `max_iterations`/`max_nodes` budget runs out.
+//! 3. [`extract`] picks, for every class the outputs depend on, the cheapest [`ENode`] reachable from it
+//!    under `cost_model`, and rebuilds a fresh [`Graph`] out of exactly those choices.
+
+use std::collections::HashMap;
+
+use decorum::N32;
+
+use crate::graph::{CompareOp, ConvDetails, Graph, Operation, UnaryOp, Value};
+use crate::shape::Shape;
+
+/// An equivalence class id: every [`ENode`] stored under the same `EClassId` is a proven-equivalent way
+/// to compute the same value. Like [`Value`], it's an opaque index into [`EGraph`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct EClassId(usize);
+
+/// [`Operation`], but with every [`Value`] input replaced by the [`EClassId`] of its class, and every
+/// float replaced by [`N32`] so `ENode` can be hashconsed (floats aren't `Eq`/`Hash`, `N32` is a
+/// NaN-excluding wrapper that is). This is what actually gets deduplicated and rewritten; `shape` lives
+/// on the [`EClass`] instead, since every `ENode` in a class necessarily has the same one.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ENode {
+    Input { index: usize },
+    Constant { data: Vec<N32> },
+    View { input: EClassId },
+    Slice { input: EClassId, axis: usize, start: usize, end: usize },
+    Conv { input: EClassId, filter: EClassId, bias: Option<EClassId>, relu: bool, details: ConvDetailsKey },
+    Add { left: EClassId, right: EClassId, subtract: bool },
+    Mul { left: EClassId, right: EClassId },
+    Clamp { input: EClassId, min: N32, max: N32 },
+    Softmax { input: EClassId, axis: usize, quiet: bool },
+    Gather { input: EClassId, axis: usize, indices: EClassId },
+    GatherNd { data: EClassId, indices: EClassId, batch_dims: usize },
+    Unary { input: EClassId, op: UnaryOp },
+    Permute { input: EClassId, permutation: Vec<usize> },
+    Round { input: EClassId },
+    Sum { input: EClassId, axis: usize },
+    Compare { left: EClassId, right: EClassId, mode: CompareOp },
+    Select { cond: EClassId, on_true: EClassId, on_false: EClassId },
+}
+
+/// [`ConvDetails`] wrapped so it can be hashed and compared as part of an [`ENode`]; `ConvDetails` is
+/// already all integers except `batch_size: Size`, which already derives the traits we need.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ConvDetailsKey(pub ConvDetails);
+
+impl std::hash::Hash for ConvDetails {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        let ConvDetails {
+            input_channels, output_channels, input_w, input_h, kernel_w, kernel_h,
+            padding_w, padding_h, stride, dilation, output_w, output_h, batch_size, groups,
+        } = self;
+        (
+            input_channels, output_channels, input_w, input_h, kernel_w, kernel_h,
+            padding_w, padding_h, stride, dilation, output_w, output_h, batch_size, groups,
+        ).hash(state);
+    }
+}
+impl Eq for ConvDetails {}
+impl PartialEq for ConvDetails {
+    fn eq(&self, other: &Self) -> bool {
+        self.input_channels == other.input_channels
+            && self.output_channels == other.output_channels
+            && self.input_w == other.input_w
+            && self.input_h == other.input_h
+            && self.kernel_w == other.kernel_w
+            && self.kernel_h == other.kernel_h
+            && self.padding_w == other.padding_w
+            && self.padding_h == other.padding_h
+            && self.stride == other.stride
+            && self.dilation == other.dilation
+            && self.output_w == other.output_w
+            && self.output_h == other.output_h
+            && self.batch_size == other.batch_size
+            && self.groups == other.groups
+    }
+}
+
+struct EClass {
+    shape: Shape,
+    /// Every proven-equivalent way to compute this class's value, deduplicated by [`EGraph::add`].
+    nodes: Vec<ENode>,
+}
+
+/// A union-find of equivalence classes, plus the hashcons table ([`EGraph::lookup`]) that keeps every
+/// structurally distinct [`ENode`] pointing at exactly one canonical class.
+///
+/// Note: [`union`](EGraph::union) only re-canonicalizes the merged class's own nodes, not every other
+/// class that happens to reference it — a full `egg`-style implementation tracks a repair worklist so
+/// congruence (two calls to the same op on now-equal inputs get merged too) holds everywhere, not just
+/// locally. [`saturate`] re-scans every class each pass instead, so it still converges, just by brute
+/// force rather than incremental repair.
+pub struct EGraph {
+    /// `parents[i]` is the next link towards `i`'s class's canonical id; a class is canonical when
+    /// `parents[i] == i`. Looked up and path-compressed by [`EGraph::find`].
+    parents: Vec<usize>,
+    classes: HashMap<usize, EClass>,
+    hashcons: HashMap<ENode, EClassId>,
+}
+
+impl EGraph {
+    fn new() -> Self {
+        EGraph { parents: vec![], classes: HashMap::new(), hashcons: HashMap::new() }
+    }
+
+    /// The canonical id of the class `id` currently belongs to, path-compressing along the way.
+    pub fn find(&mut self, id: EClassId) -> EClassId {
+        let mut curr = id.0;
+        while self.parents[curr] != curr {
+            curr = self.parents[curr];
+        }
+        let root = curr;
+
+        let mut curr = id.0;
+        while self.parents[curr] != root {
+            let next = self.parents[curr];
+            self.parents[curr] = root;
+            curr = next;
+        }
+
+        EClassId(root)
+    }
+
+    /// Inserts `node` (canonicalizing its child ids first), returning the existing class if an
+    /// equivalent node was already hashconsed, or a fresh singleton class otherwise.
+    pub fn add(&mut self, node: ENode, shape: Shape) -> EClassId {
+        let node = self.canonicalize(node);
+
+        if let Some(&id) = self.hashcons.get(&node) {
+            return self.find(id);
+        }
+
+        let id = EClassId(self.parents.len());
+        self.parents.push(id.0);
+        self.classes.insert(id.0, EClass { shape, nodes: vec![node.clone()] });
+        self.hashcons.insert(node, id);
+        id
+    }
+
+    fn canonicalize(&mut self, node: ENode) -> ENode {
+        let f = |graph: &mut Self, id: EClassId| graph.find(id);
+        match node {
+            ENode::Input { index } => ENode::Input { index },
+            ENode::Constant { data } => ENode::Constant { data },
+            ENode::View { input } => ENode::View { input: f(self, input) },
+            ENode::Slice { input, axis, start, end } => ENode::Slice { input: f(self, input), axis, start, end },
+            ENode::Conv { input, filter, bias, relu, details } =>
+                ENode::Conv { input: f(self, input), filter: f(self, filter), bias: bias.map(|b| f(self, b)), relu, details },
+            ENode::Add { left, right, subtract } => ENode::Add { left: f(self, left), right: f(self, right), subtract },
+            ENode::Mul { left, right } => ENode::Mul { left: f(self, left), right: f(self, right) },
+            ENode::Clamp { input, min, max } => ENode::Clamp { input: f(self, input), min, max },
+            ENode::Softmax { input, axis, quiet } => ENode::Softmax { input: f(self, input), axis, quiet },
+            ENode::Gather { input, axis, indices } => ENode::Gather { input: f(self, input), axis, indices: f(self, indices) },
+            ENode::GatherNd { data, indices, batch_dims } => ENode::GatherNd { data: f(self, data), indices: f(self, indices), batch_dims },
+            ENode::Unary { input, op } => ENode::Unary { input: f(self, input), op },
+            ENode::Permute { input, permutation } => ENode::Permute { input: f(self, input), permutation },
+            ENode::Round { input } => ENode::Round { input: f(self, input) },
+            ENode::Sum { input, axis } => ENode::Sum { input: f(self, input), axis },
+            ENode::Compare { left, right, mode } => ENode::Compare { left: f(self, left), right: f(self, right), mode },
+            ENode::Select { cond, on_true, on_false } =>
+                ENode::Select { cond: f(self, cond), on_true: f(self, on_true), on_false: f(self, on_false) },
+        }
+    }
+
+    /// Merges `a` and `b`'s classes, moving every node of the smaller into the larger and re-running
+    /// them through the hashcons table (since their children's canonical ids may have just changed).
+    /// Returns `false` if they were already the same class, the signal [`saturate`] uses to know a
+    /// rewrite pass found nothing new.
+    pub fn union(&mut self, a: EClassId, b: EClassId) -> bool {
+        let a = self.find(a);
+        let b = self.find(b);
+        if a == b {
+            return false;
+        }
+
+        let (keep, drop) = if self.classes[&a.0].nodes.len() >= self.classes[&b.0].nodes.len() { (a, b) } else { (b, a) };
+        self.parents[drop.0] = keep.0;
+
+        let dropped = self.classes.remove(&drop.0).unwrap();
+        for node in dropped.nodes {
+            let node = self.canonicalize(node);
+            self.classes.get_mut(&keep.0).unwrap().nodes.push(node.clone());
+            self.hashcons.insert(node, keep);
+        }
+
+        true
+    }
+
+    fn nodes_of(&self, id: EClassId) -> &[ENode] {
+        &self.classes[&id.0].nodes
+    }
+
+    fn shape_of(&self, id: EClassId) -> &Shape {
+        &self.classes[&id.0].shape
+    }
+
+    /// Seeds an e-class for every value in `graph`, one [`ENode`] per original [`Operation`], and
+    /// returns the mapping from each original [`Value`] to its class so callers can look up the roots
+    /// they care about (typically `graph.outputs()`) afterwards.
+    pub fn from_graph(graph: &Graph) -> (EGraph, HashMap<Value, EClassId>) {
+        let mut egraph = EGraph::new();
+        let mut value_to_class = HashMap::new();
+
+        for value in graph.values() {
+            let info = &graph[value];
+            let class_of = |v: Value| value_to_class[&v];
+
+            let node = match &info.operation {
+                &Operation::Input { index } => ENode::Input { index },
+                Operation::Constant { data } => ENode::Constant { data: data.iter().map(|&x| N32::from_inner(x)).collect() },
+                &Operation::View { input } => ENode::View { input: class_of(input) },
+                &Operation::Slice { input, axis, start, end } => ENode::Slice { input: class_of(input), axis, start, end },
+                &Operation::Conv { input, filter, bias, relu, details } =>
+                    ENode::Conv { input: class_of(input), filter: class_of(filter), bias: bias.map(class_of), relu, details: ConvDetailsKey(details) },
+                &Operation::Add { left, right, subtract } => ENode::Add { left: class_of(left), right: class_of(right), subtract },
+                &Operation::Mul { left, right } => ENode::Mul { left: class_of(left), right: class_of(right) },
+                &Operation::Clamp { input, min, max } =>
+                    ENode::Clamp { input: class_of(input), min: N32::from_inner(min), max: N32::from_inner(max) },
+                &Operation::Softmax { input, axis, quiet } => ENode::Softmax { input: class_of(input), axis, quiet },
+                &Operation::Gather { input, axis, indices } => ENode::Gather { input: class_of(input), axis, indices: class_of(indices) },
+                &Operation::GatherNd { data, indices, batch_dims } =>
+                    ENode::GatherNd { data: class_of(data), indices: class_of(indices), batch_dims },
+                &Operation::Unary { input, op } => ENode::Unary { input: class_of(input), op },
+                Operation::Permute { input, permutation } => ENode::Permute { input: class_of(*input), permutation: permutation.clone() },
+                &Operation::Round { input } => ENode::Round { input: class_of(input) },
+                &Operation::Sum { input, axis } => ENode::Sum { input: class_of(input), axis },
+                &Operation::Compare { left, right, mode } => ENode::Compare { left: class_of(left), right: class_of(right), mode },
+                &Operation::Select { cond, on_true, on_false } =>
+                    ENode::Select { cond: class_of(cond), on_true: class_of(on_true), on_false: class_of(on_false) },
+            };
+
+            let class = egraph.add(node, info.shape.clone());
+            value_to_class.insert(value, class);
+        }
+
+        (egraph, value_to_class)
+    }
+}
+
+/// A single equality-saturation rewrite rule: looks for classes that admit a cheaper (or merely
+/// differently-shaped) equivalent and returns the unions to apply. Rules never mutate `egraph`
+/// themselves; [`saturate`] collects every rule's matches for the current state up front and only
+/// applies the resulting unions afterwards, so a rule never "sees" a union another rule in the same
+/// pass is also proposing.
+type Rule = fn(&EGraph) -> Vec<(EClassId, EClassId)>;
+
+/// `a * 1 -> a`: a [`ENode::Mul`] where the right operand is a constant class made up entirely of
+/// `1.0`s contributes nothing, so the product is equivalent to its left operand outright.
+fn rule_mul_by_one(egraph: &EGraph) -> Vec<(EClassId, EClassId)> {
+    let mut unions = vec![];
+    for (&id, class) in &egraph.classes {
+        for node in &class.nodes {
+            if let &ENode::Mul { left, right } = node {
+                if is_all(egraph, right, 1.0) {
+                    unions.push((EClassId(id), left));
+                }
+            }
+        }
+    }
+    unions
+}
+
+/// `a + 0 -> a`: symmetric to [`rule_mul_by_one`], for an [`ENode::Add`] whose right operand is a
+/// constant class made up entirely of `0.0`s (covers the `subtract` form too, since `a - 0 == a + 0`).
+fn rule_add_zero(egraph: &EGraph) -> Vec<(EClassId, EClassId)> {
+    let mut unions = vec![];
+    for (&id, class) in &egraph.classes {
+        for node in &class.nodes {
+            if let &ENode::Add { left, right, .. } = node {
+                if is_all(egraph, right, 0.0) {
+                    unions.push((EClassId(id), left));
+                }
+            }
+        }
+    }
+    unions
+}
+
+/// `view(view(x)) -> view(x)`: a [`ENode::View`] of another `View` can drop the intermediate reshape
+/// and go straight from the innermost input to the outer class's shape, since views never read data.
+fn rule_fuse_view_of_view(egraph: &EGraph) -> Vec<(EClassId, EClassId)> {
+    let mut unions = vec![];
+    for (&id, class) in &egraph.classes {
+        for node in &class.nodes {
+            if let &ENode::View { input } = node {
+                for inner in egraph.nodes_of(input) {
+                    if let &ENode::View { input: innermost } = inner {
+                        unions.push((EClassId(id), innermost));
+                    }
+                }
+            }
+        }
+    }
+    unions
+}
+
+/// Every rewrite [`saturate`] tries each iteration. Kept as a flat list (instead of, say, a trait
+/// object registry) to match how [`Operation`]'s own peephole rules in `optimize_graph` are written:
+/// plain functions, easy to scan and to add to.
+const RULES: &[Rule] = &[rule_mul_by_one, rule_add_zero, rule_fuse_view_of_view];
+
+fn is_all(egraph: &EGraph, id: EClassId, value: f32) -> bool {
+    egraph.nodes_of(id).iter().any(|node| match node {
+        ENode::Constant { data } => data.iter().all(|&x| x.into_inner() == value),
+        _ => false,
+    })
+}
+
+/// Runs [`RULES`] to a fixpoint (no rule proposes a union that actually changes anything), or until
+/// `max_iterations` passes have run or the e-graph has grown past `max_nodes` total `ENode`s,
+/// whichever comes first. Returns the number of passes actually run.
+pub fn saturate(egraph: &mut EGraph, max_iterations: usize, max_nodes: usize) -> usize {
+    for iteration in 0..max_iterations {
+        let total_nodes: usize = egraph.classes.values().map(|class| class.nodes.len()).sum();
+        if total_nodes >= max_nodes {
+            return iteration;
+        }
+
+        let matches: Vec<(EClassId, EClassId)> = RULES.iter().flat_map(|rule| rule(egraph)).collect();
+
+        let mut changed = false;
+        for (a, b) in matches {
+            changed |= egraph.union(a, b);
+        }
+
+        if !changed {
+            return iteration + 1;
+        }
+    }
+
+    max_iterations
+}
+
+/// The weight [`extract`] assigns each kind of [`ENode`] when picking the cheapest equivalent graph.
+/// A flat per-op-tag cost is the simplest useful instance; a real deployment is expected to look up
+/// `op_tag`'s measured cost from profiling data (e.g. the per-op `time_spent` a `CalculatedValue` could
+/// record while actually running the graph) instead of a fixed constant, so extraction optimizes for
+/// this graph's real bottlenecks on the target batch size rather than a generic op-count heuristic.
+pub trait CostModel {
+    /// The cost of computing one `ENode`'s own output, not counting its inputs (extraction adds those
+    /// in separately once each input's own cheapest cost is known).
+    fn cost(&self, node: &ENode) -> f64;
+}
+
+/// The default [`CostModel`]: every op costs `1.0` except `Conv`, which costs `8.0`, and the free,
+/// data-movement-only ones (`View` never touches memory, `Input`/`Constant` are already materialized),
+/// which cost `0.0`. Good enough to
+/// prefer fewer, cheaper ops when no real profiling numbers are available yet.
+pub struct UnitCostModel;
+
+impl CostModel for UnitCostModel {
+    fn cost(&self, node: &ENode) -> f64 {
+        match node {
+            ENode::Input { .. } | ENode::Constant { .. } | ENode::View { .. } => 0.0,
+            ENode::Conv { .. } => 8.0,
+            _ => 1.0,
+        }
+    }
+}
+
+fn node_inputs(node: &ENode) -> Vec<EClassId> {
+    match node {
+        ENode::Input { .. } | ENode::Constant { .. } => vec![],
+        &ENode::View { input } => vec![input],
+        &ENode::Slice { input, .. } => vec![input],
+        &ENode::Conv { input, filter, bias, .. } => {
+            let mut inputs = vec![input, filter];
+            inputs.extend(bias);
+            inputs
+        }
+        &ENode::Add { left, right, .. } => vec![left, right],
+        &ENode::Mul { left, right } => vec![left, right],
+        &ENode::Clamp { input, .. } => vec![input],
+        &ENode::Softmax { input, .. } => vec![input],
+        &ENode::Gather { input, indices, .. } => vec![input, indices],
+        &ENode::GatherNd { data, indices, .. } => vec![data, indices],
+        &ENode::Unary { input, .. } => vec![input],
+        &ENode::Permute { input, .. } => vec![input],
+        &ENode::Round { input } => vec![input],
+        &ENode::Sum { input, .. } => vec![input],
+        &ENode::Compare { left, right, .. } => vec![left, right],
+        &ENode::Select { cond, on_true, on_false } => vec![cond, on_true, on_false],
+    }
+}
+
+/// Picks the cheapest `ENode` reachable from every class that `roots` transitively depends on, by
+/// relaxing `best[class] = min over nodes (cost_model.cost(node) + sum(best[input] for input in node))`
+/// to a fixpoint, the same Bellman-Ford-style relaxation a standard e-graph extractor uses since a
+/// class's best node can depend on another class whose own best node hasn't been decided yet the first
+/// time it's visited. Then rebuilds a fresh [`Graph`] containing only the chosen nodes, in topological
+/// order, and returns it together with the new `Value` for each of `roots`.
+pub fn extract(egraph: &mut EGraph, roots: &[EClassId], cost_model: &impl CostModel) -> (Graph, Vec<Value>) {
+    let roots: Vec<EClassId> = roots.iter().map(|&id| egraph.find(id)).collect();
+
+    let mut best: HashMap<usize, (f64, ENode)> = HashMap::new();
+    loop {
+        let mut changed = false;
+
+        for (&id, class) in &egraph.classes {
+            for node in &class.nodes {
+                let inputs_cost: Option<f64> = node_inputs(node).into_iter()
+                    .map(|input| best.get(&input.0).map(|&(cost, _)| cost))
+                    .sum();
+
+                let inputs_cost = match inputs_cost {
+                    Some(cost) => cost,
+                    None => continue,
+                };
+
+                let total_cost = cost_model.cost(node) + inputs_cost;
+
+                let better = match best.get(&id) {
+                    Some(&(existing_cost, _)) => total_cost < existing_cost,
+                    None => true,
+                };
+                if better {
+                    best.insert(id, (total_cost, node.clone()));
+                    changed = true;
+                }
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let mut graph = Graph::new();
+    let mut built: HashMap<usize, Value> = HashMap::new();
+
+    // declare every surviving input up front, in its original index order: `Operation::Input`'s index
+    // is how callers feed the right runtime tensor into the right input, so it must come out in the
+    // same order it went in even though the DFS below may otherwise reach inputs in a different order
+    let mut input_classes: Vec<(usize, EClassId)> = best.iter()
+        .filter_map(|(&id, (_, node))| match node {
+            &ENode::Input { index } => Some((index, EClassId(id))),
+            _ => None,
+        })
+        .collect();
+    input_classes.sort_by_key(|&(index, _)| index);
+    for (_, id) in input_classes {
+        build_value(egraph, &best, &mut graph, &mut built, id);
+    }
+
+    for &root in &roots {
+        build_value(egraph, &best, &mut graph, &mut built, root);
+    }
+
+    let root_values = roots.iter().map(|id| built[&id.0]).collect();
+    (graph, root_values)
+}
+
+fn build_value(
+    egraph: &EGraph,
+    best: &HashMap<usize, (f64, ENode)>,
+    graph: &mut Graph,
+    built: &mut HashMap<usize, Value>,
+    id: EClassId,
+) -> Value {
+    if let Some(&value) = built.get(&id.0) {
+        return value;
+    }
+
+    let (_, node) = best.get(&id.0).expect("extract: class has no affordable node, ruleset produced a cycle of only infinite-cost nodes");
+    let shape = egraph.shape_of(id).clone();
+
+    let value = match node.clone() {
+        ENode::Input { index } => {
+            // re-declare the input in the new graph; `index` must already match since inputs are
+            // never reordered by any rewrite rule
+            let value = graph.input(shape);
+            assert_eq!(graph.inputs().len() - 1, index, "extract: input order was not preserved");
+            value
+        }
+        ENode::Constant { data } => graph.constant(shape, data.iter().map(|&x| x.into_inner()).collect()),
+        ENode::View { input } => {
+            let input = build_value(egraph, best, graph, built, input);
+            graph.view(input, shape)
+        }
+        ENode::Slice { input, axis, start, end } => {
+            let input = build_value(egraph, best, graph, built, input);
+            graph.slice(input, axis, start, end)
+        }
+        ENode::Conv { input, filter, bias, relu, details } => {
+            let input = build_value(egraph, best, graph, built, input);
+            let filter = build_value(egraph, best, graph, built, filter);
+            let bias = bias.map(|b| build_value(egraph, best, graph, built, b));
+            graph.push(shape, Operation::Conv { input, filter, bias, relu, details: details.0 })
+        }
+        ENode::Add { left, right, subtract } => {
+            let left = build_value(egraph, best, graph, built, left);
+            let right = build_value(egraph, best, graph, built, right);
+            graph.push(shape, Operation::Add { left, right, subtract })
+        }
+        ENode::Mul { left, right } => {
+            let left = build_value(egraph, best, graph, built, left);
+            let right = build_value(egraph, best, graph, built, right);
+            graph.push(shape, Operation::Mul { left, right })
+        }
+        ENode::Clamp { input, min, max } => {
+            let input = build_value(egraph, best, graph, built, input);
+            graph.push(shape, Operation::Clamp { input, min: min.into_inner(), max: max.into_inner() })
+        }
+        ENode::Softmax { input, axis, quiet } => {
+            let input = build_value(egraph, best, graph, built, input);
+            graph.push(shape, Operation::Softmax { input, axis, quiet })
+        }
+        ENode::Gather { input, axis, indices } => {
+            let input = build_value(egraph, best, graph, built, input);
+            let indices = build_value(egraph, best, graph, built, indices);
+            graph.push(shape, Operation::Gather { input, axis, indices })
+        }
+        ENode::GatherNd { data, indices, batch_dims } => {
+            let data = build_value(egraph, best, graph, built, data);
+            let indices = build_value(egraph, best, graph, built, indices);
+            graph.push(shape, Operation::GatherNd { data, indices, batch_dims })
+        }
+        ENode::Unary { input, op } => {
+            let input = build_value(egraph, best, graph, built, input);
+            graph.push(shape, Operation::Unary { input, op })
+        }
+        ENode::Permute { input, permutation } => {
+            let input = build_value(egraph, best, graph, built, input);
+            graph.push(shape, Operation::Permute { input, permutation })
+        }
+        ENode::Round { input } => {
+            let input = build_value(egraph, best, graph, built, input);
+            graph.push(shape, Operation::Round { input })
+        }
+        ENode::Sum { input, axis } => {
+            let input = build_value(egraph, best, graph, built, input);
+            graph.push(shape, Operation::Sum { input, axis })
+        }
+        ENode::Compare { left, right, mode } => {
+            let left = build_value(egraph, best, graph, built, left);
+            let right = build_value(egraph, best, graph, built, right);
+            graph.push(shape, Operation::Compare { left, right, mode })
+        }
+        ENode::Select { cond, on_true, on_false } => {
+            let cond = build_value(egraph, best, graph, built, cond);
+            let on_true = build_value(egraph, best, graph, built, on_true);
+            let on_false = build_value(egraph, best, graph, built, on_false);
+            graph.push(shape, Operation::Select { cond, on_true, on_false })
+        }
+    };
+
+    built.insert(id.0, value);
+    value
+}
+
+/// Runs the full equality-saturation pipeline on `graph`: seed, [`saturate`] up to the given budget,
+/// then [`extract`] the cheapest equivalent graph for `graph.outputs()` under `cost_model`.
+pub fn optimize_graph_egraph(graph: &Graph, max_iterations: usize, max_nodes: usize, cost_model: &impl CostModel) -> Graph {
+    let (mut egraph, value_to_class) = EGraph::from_graph(graph);
+    saturate(&mut egraph, max_iterations, max_nodes);
+
+    let roots: Vec<EClassId> = graph.outputs().iter().map(|v| value_to_class[v]).collect();
+    let (mut new_graph, new_outputs) = extract(&mut egraph, &roots, cost_model);
+    new_graph.outputs_mut().extend(new_outputs);
+    new_graph
+}
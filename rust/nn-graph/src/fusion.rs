@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use ndarray::Zip;
+
+use crate::cpu::Tensor;
+use crate::graph::{Graph, Operation, UnaryOp, Value};
+
+/// A maximal fusable region: a chain of pointwise ops that can execute in a single pass over the output
+/// elements without materializing intermediates, optionally anchored by a single `Conv` (the one op in
+/// the region that isn't itself pointwise). Built by [`find_fusion_regions`].
+#[derive(Debug, Clone)]
+pub struct FusionRegion {
+    /// The `Conv` this region is built around, if any. `None` for a region that's pure elementwise ops
+    /// (e.g. a residual add followed by a clamp, with no convolution in the chain).
+    pub anchor: Option<Value>,
+    /// Every value in the region, including `anchor`, in the order they must execute in. Values that
+    /// feed into this region from outside aren't included here.
+    pub members: Vec<Value>,
+    /// The region's single externally-visible value: every other member is only consumed by the next
+    /// member in the chain, so can be computed and discarded without ever hitting main memory.
+    pub output: Value,
+}
+
+impl FusionRegion {
+    /// A short string identifying this region's op sequence (not its concrete shapes/values), used to
+    /// key the CUDA fused-kernel cache so recurring subgraph shapes only compile their kernel once.
+    pub fn signature(&self, graph: &Graph) -> String {
+        self.members.iter().map(|&v| op_tag(&graph[v].operation)).collect::<Vec<_>>().join("-")
+    }
+}
+
+fn op_tag(op: &Operation) -> &'static str {
+    match op {
+        Operation::Input { .. } => "input",
+        Operation::Constant { .. } => "const",
+        Operation::View { .. } => "view",
+        Operation::Slice { .. } => "slice",
+        Operation::Conv { .. } => "conv",
+        Operation::Add { subtract: false, .. } => "add",
+        Operation::Add { subtract: true, .. } => "sub",
+        Operation::Mul { .. } => "mul",
+        Operation::Clamp { .. } => "clamp",
+        Operation::Softmax { .. } => "softmax",
+        Operation::Gather { .. } => "gather",
+        Operation::GatherNd { .. } => "gather_nd",
+        Operation::Unary { op: UnaryOp::Sigmoid, .. } => "sigmoid",
+        Operation::Unary { op: UnaryOp::Tanh, .. } => "tanh",
+        Operation::Permute { .. } => "permute",
+        Operation::Round { .. } => "round",
+        Operation::Sum { .. } => "sum",
+        Operation::Compare { .. } => "compare",
+        Operation::Select { .. } => "select",
+    }
+}
+
+/// Whether `op` can participate in a fusion region: either as the chain's anchor (`Conv`) or as one of
+/// its pointwise links (`Add`/`Mul`/`Clamp`/`Unary`/`Round`, plus the free `View` reshape and `Permute`).
+///
+/// `Compare`/`Select` are deliberately left out even though they're pointwise too: the executor below
+/// folds a region into one running `acc`, which assumes exactly one operand position is "the rest of the
+/// chain" and everything else is an external operand — true for every op above (two operands, one of
+/// which may be `acc`) but not for `Select`'s three, so it always materializes instead. `Sum` is left out
+/// for a different reason: it's a reduction, not a pointwise op, so it changes the output shape the
+/// running `acc` is iterated over instead of just its values.
+fn is_fusable(op: &Operation) -> bool {
+    matches!(
+        op,
+        Operation::Conv { .. }
+            | Operation::Add { .. }
+            | Operation::Mul { .. }
+            | Operation::Clamp { .. }
+            | Operation::Unary { .. }
+            | Operation::View { .. }
+            | Operation::Permute { .. }
+            | Operation::Round { .. }
+    )
+}
+
+fn op_inputs(op: &Operation) -> Vec<Value> {
+    match op {
+        Operation::Input { .. } | Operation::Constant { .. } => vec![],
+        Operation::View { input } => vec![*input],
+        Operation::Slice { input, .. } => vec![*input],
+        Operation::Conv { input, filter, bias, .. } => {
+            let mut inputs = vec![*input, *filter];
+            inputs.extend(*bias);
+            inputs
+        }
+        Operation::Add { left, right, .. } => vec![*left, *right],
+        Operation::Mul { left, right } => vec![*left, *right],
+        Operation::Clamp { input, .. } => vec![*input],
+        Operation::Softmax { input, .. } => vec![*input],
+        Operation::Gather { input, indices, .. } => vec![*input, *indices],
+        Operation::GatherNd { data, indices, .. } => vec![*data, *indices],
+        Operation::Unary { input, .. } => vec![*input],
+        Operation::Permute { input, .. } => vec![*input],
+        Operation::Round { input } => vec![*input],
+        Operation::Sum { input, .. } => vec![*input],
+        Operation::Compare { left, right, .. } => vec![*left, *right],
+        Operation::Select { cond, on_true, on_false } => vec![*cond, *on_true, *on_false],
+    }
+}
+
+/// Partitions `graph` into maximal fusable regions: contiguous `Add`/`Mul`/`Clamp`/`Unary`/`Round`/`View`/
+/// `Permute` chains, with at most one `Conv`, which always starts its region as the anchor. A value can only be folded into its consumer's region
+/// if it has exactly one use — everywhere else it must still be materialized, since something else reads
+/// it from memory independently.
+pub fn find_fusion_regions(graph: &Graph) -> Vec<FusionRegion> {
+    let mut use_count: HashMap<Value, usize> = HashMap::new();
+    for value in graph.values() {
+        for input in op_inputs(&graph[value].operation) {
+            *use_count.entry(input).or_insert(0) += 1;
+        }
+    }
+    for &output in graph.outputs() {
+        *use_count.entry(output).or_insert(0) += 1;
+    }
+
+    let mut region_of: HashMap<Value, usize> = HashMap::new();
+    let mut regions: Vec<FusionRegion> = vec![];
+
+    for value in graph.values() {
+        let operation = &graph[value].operation;
+        if !is_fusable(operation) {
+            continue;
+        }
+
+        // this op continues an existing region if exactly one of its inputs is that region's current
+        // tail and has no other consumer; a `Conv` never continues one, it starts its own as the anchor
+        let continued = if matches!(operation, Operation::Conv { .. }) {
+            None
+        } else {
+            op_inputs(operation).into_iter().find_map(|input| {
+                let &region_index = region_of.get(&input)?;
+                let is_tail = regions[region_index].output == input;
+                let single_use = use_count.get(&input).copied().unwrap_or(0) == 1;
+                (is_tail && single_use).then_some(region_index)
+            })
+        };
+
+        let region_index = match continued {
+            Some(region_index) => region_index,
+            None => {
+                let region_index = regions.len();
+                regions.push(FusionRegion {
+                    anchor: None,
+                    members: vec![],
+                    output: value,
+                });
+                region_index
+            }
+        };
+
+        let region = &mut regions[region_index];
+        region.members.push(value);
+        region.output = value;
+        if matches!(operation, Operation::Conv { .. }) {
+            region.anchor = Some(value);
+        }
+        region_of.insert(value, region_index);
+    }
+
+    regions
+}
+
+/// Executes a pure-elementwise [`FusionRegion`] (`region.anchor.is_none()`) in a single pass over the
+/// output elements, folding every member's op into one running accumulator instead of allocating an
+/// intermediate `Tensor` per op. `resolved` supplies any operand that isn't the accumulator itself (e.g.
+/// the other side of a residual `Add`).
+pub fn execute_elementwise_region_cpu(graph: &Graph, region: &FusionRegion, resolved: &HashMap<Value, Tensor>) -> Tensor {
+    assert!(region.anchor.is_none(), "conv-anchored regions run their conv normally and fuse only the tail");
+
+    let mut members = region.members.iter();
+    let first = *members.next().expect("a fusion region always has at least one member");
+
+    let mut acc = match &graph[first].operation {
+        &Operation::Add { left, right, subtract } => {
+            let left = resolved.get(&left).expect("fusion region input not yet computed");
+            let right = resolved.get(&right).expect("fusion region input not yet computed");
+            if subtract { left - right } else { left + right }
+        }
+        &Operation::Mul { left, right } => {
+            let left = resolved.get(&left).expect("fusion region input not yet computed");
+            let right = resolved.get(&right).expect("fusion region input not yet computed");
+            left * right
+        }
+        &Operation::View { input } => resolved.get(&input).expect("fusion region input not yet computed").clone(),
+        Operation::Permute { input, permutation } => {
+            let input = resolved.get(input).expect("fusion region input not yet computed");
+            input.view().permuted_axes(permutation.clone()).to_shared()
+        }
+        &Operation::Round { input } => {
+            let input = resolved.get(&input).expect("fusion region input not yet computed");
+            Zip::from(input).map_collect(|&x| x.round()).into_shared()
+        }
+        Operation::Clamp { input, min, max } => {
+            let input = resolved.get(input).expect("fusion region input not yet computed");
+            Zip::from(input).map_collect(|&x| x.clamp(*min, *max)).into_shared()
+        }
+        Operation::Unary { input, op: UnaryOp::Sigmoid } => {
+            let input = resolved.get(input).expect("fusion region input not yet computed");
+            Zip::from(input).map_collect(|&x| 1.0 / (1.0 + (-x).exp())).into_shared()
+        }
+        Operation::Unary { input, op: UnaryOp::Tanh } => {
+            let input = resolved.get(input).expect("fusion region input not yet computed");
+            Zip::from(input).map_collect(|&x| x.tanh()).into_shared()
+        }
+        other => panic!("{:?} can't start an elementwise fusion region", other),
+    };
+
+    // the previous member is the region's running value; it may be either operand of an `Add`/`Mul`
+    let mut prev = first;
+    for &value in members {
+        acc = match &graph[value].operation {
+            Operation::Clamp { min, max, .. } => Zip::from(&acc).map_collect(|&x| x.clamp(*min, *max)).into_shared(),
+            Operation::Unary { op: UnaryOp::Sigmoid, .. } => {
+                Zip::from(&acc).map_collect(|&x| 1.0 / (1.0 + (-x).exp())).into_shared()
+            }
+            Operation::Unary { op: UnaryOp::Tanh, .. } => Zip::from(&acc).map_collect(|&x| x.tanh()).into_shared(),
+            &Operation::Add { left, right, subtract } => {
+                let other = if left == prev { right } else { left };
+                let other = resolved.get(&other).expect("fusion region input not yet computed");
+                match (subtract, left == prev) {
+                    (false, _) => &acc + other,
+                    (true, true) => &acc - other,
+                    (true, false) => other - &acc,
+                }
+            }
+            &Operation::Mul { left, right } => {
+                let other = if left == prev { right } else { left };
+                let other = resolved.get(&other).expect("fusion region input not yet computed");
+                &acc * other
+            }
+            Operation::View { .. } => acc,
+            Operation::Permute { permutation, .. } => acc.view().permuted_axes(permutation.clone()).to_shared(),
+            Operation::Round { .. } => Zip::from(&acc).map_collect(|&x| x.round()).into_shared(),
+            other => panic!("{:?} can't continue an elementwise fusion region", other),
+        };
+        prev = value;
+    }
+
+    acc
+}
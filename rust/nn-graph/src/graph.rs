@@ -43,16 +43,99 @@ pub enum Operation {
     /// Slice the last three axis of a value, each with range `start[i]..end[i]`
     Slice { input: Value, axis: usize, start: usize, end: usize },
 
-    /// The standard convolution operator.
-    Conv { input: Value, filter: Value, details: ConvDetails },
+    /// The standard convolution operator, optionally with a fused epilogue: `bias` is added per output
+    /// channel before `relu` clamps to `[0, +inf)`, letting [`Graph::optimize`] collapse a separate
+    /// bias `Add`/`Clamp` pair into the conv itself instead of materializing the pre-activation tensor.
+    /// Built plain (`bias: None, relu: false`) by [`Graph::conv`]; only the optimizer ever sets them.
+    Conv { input: Value, filter: Value, bias: Option<Value>, relu: bool, details: ConvDetails },
 
-    /// Elementwise add two values, with broadcasting on the right.
+    /// Elementwise add two values, NumPy/ONNX-style broadcast in either direction (see [`Graph::add`]).
     Add { left: Value, right: Value, subtract: bool },
-    /// Elementwise multiply two values, with broadcasting on the right value.
+    /// Elementwise multiply two values, NumPy/ONNX-style broadcast in either direction (see [`Graph::mul`]).
     Mul { left: Value, right: Value },
 
     /// Elementwise clip a value.
     Clamp { input: Value, min: f32, max: f32 },
+
+    /// Softmax along the given axis. If `quiet` the denominator gets an extra implicit `+1` term
+    /// (see [`Graph::quiet_softmax`]), letting the whole vector decay towards zero instead of always
+    /// summing to one.
+    Softmax { input: Value, axis: usize, quiet: bool },
+
+    /// Gather elements from `input` along `axis` using a runtime index tensor.
+    /// Equivalent to numpy's `input.take(indices, axis=axis)`; output shape is `input.shape` with
+    /// `axis` replaced by `indices`'s shape. See [`Graph::gather`].
+    Gather { input: Value, axis: usize, indices: Value },
+
+    /// ONNX-style `GatherND`: `indices`'s last axis (length `k`) selects elements from the first `k`
+    /// (post-`batch_dims`) dimensions of `data`, with the leading `batch_dims` dimensions zipped
+    /// rather than indexed. See [`Graph::gather_nd`].
+    GatherNd { data: Value, indices: Value, batch_dims: usize },
+
+    /// Elementwise unary nonlinearity.
+    Unary { input: Value, op: UnaryOp },
+
+    /// Reorder axes according to `permutation`, a permutation of `0..rank`. Unlike [`Operation::View`],
+    /// which only reinterprets the existing row-major buffer under a new shape, this actually moves
+    /// elements across axes (e.g. NCHW -> NHWC). See [`Graph::permute`].
+    Permute { input: Value, permutation: Vec<usize> },
+
+    /// Elementwise round-to-nearest-integer, ties away from zero (same convention as `f32::round`).
+    /// Mainly useful as a building block for simulating fixed-point arithmetic; see
+    /// [`crate::quantize::quantize_graph`]. See [`Graph::round`].
+    Round { input: Value },
+
+    /// Sum `input` along `axis`, keeping that axis present with size 1 (rather than removing it)
+    /// so the result can be fed straight back into a broadcasting op like [`Operation::Add`] or
+    /// [`Operation::Mul`] without an extra [`Operation::View`]. See [`Graph::sum`].
+    Sum { input: Value, axis: usize },
+
+    /// Elementwise comparison, producing a `0.0`/`1.0` mask, NumPy/ONNX-style broadcast in either
+    /// direction (see [`Graph::compare`]).
+    Compare { left: Value, right: Value, mode: CompareOp },
+    /// Elementwise select: `on_true` where `cond` is nonzero, `on_false` elsewhere, all three
+    /// NumPy/ONNX-style broadcast against each other (see [`Graph::select`]).
+    Select { cond: Value, on_true: Value, on_false: Value },
+}
+
+/// The elementwise nonlinearities [`Operation::Unary`] supports.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum UnaryOp {
+    Sigmoid,
+    Tanh,
+}
+
+impl UnaryOp {
+    pub fn eval(self, x: f32) -> f32 {
+        match self {
+            UnaryOp::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            UnaryOp::Tanh => x.tanh(),
+        }
+    }
+}
+
+/// The comparison modes [`Operation::Compare`] supports, borrowed from tract's boolean binary ops.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum CompareOp {
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+}
+
+impl CompareOp {
+    pub fn eval(self, left: f32, right: f32) -> bool {
+        match self {
+            CompareOp::Greater => left > right,
+            CompareOp::GreaterEqual => left >= right,
+            CompareOp::Less => left < right,
+            CompareOp::LessEqual => left <= right,
+            CompareOp::Equal => left == right,
+            CompareOp::NotEqual => left != right,
+        }
+    }
 }
 
 impl Operation {
@@ -62,10 +145,23 @@ impl Operation {
             Operation::Constant { .. } => vec![],
             &Operation::View { input } => vec![input],
             &Operation::Slice { input, .. } => vec![input],
-            &Operation::Conv { input, filter, .. } => vec![input, filter],
+            &Operation::Conv { input, filter, bias, .. } => {
+                let mut inputs = vec![input, filter];
+                inputs.extend(bias);
+                inputs
+            }
             &Operation::Add { left, right, .. } => vec![left, right],
             &Operation::Mul { left, right } => vec![left, right],
             &Operation::Clamp { input, .. } => vec![input],
+            &Operation::Softmax { input, .. } => vec![input],
+            &Operation::Gather { input, indices, .. } => vec![input, indices],
+            &Operation::GatherNd { data, indices, .. } => vec![data, indices],
+            &Operation::Unary { input, .. } => vec![input],
+            &Operation::Permute { input, .. } => vec![input],
+            &Operation::Round { input } => vec![input],
+            &Operation::Sum { input, .. } => vec![input],
+            &Operation::Compare { left, right, .. } => vec![left, right],
+            &Operation::Select { cond, on_true, on_false } => vec![cond, on_true, on_false],
         }
     }
 
@@ -79,14 +175,32 @@ impl Operation {
                 Operation::View { input: f(input) },
             &Operation::Slice { input, axis, start, end } =>
                 Operation::Slice { input: f(input), axis, start, end },
-            &Operation::Conv { input, filter, details: conv_shape } =>
-                Operation::Conv { input: f(input), filter: f(filter), details: conv_shape },
+            &Operation::Conv { input, filter, bias, relu, details: conv_shape } =>
+                Operation::Conv { input: f(input), filter: f(filter), bias: bias.map(&mut f), relu, details: conv_shape },
             &Operation::Add { left, right, subtract } =>
                 Operation::Add { left: f(left), right: f(right), subtract },
             &Operation::Mul { left, right } =>
                 Operation::Mul { left: f(left), right: f(right) },
             &Operation::Clamp { input, min, max } =>
                 Operation::Clamp { input: f(input), min, max },
+            &Operation::Softmax { input, axis, quiet } =>
+                Operation::Softmax { input: f(input), axis, quiet },
+            &Operation::Gather { input, axis, indices } =>
+                Operation::Gather { input: f(input), axis, indices: f(indices) },
+            &Operation::GatherNd { data, indices, batch_dims } =>
+                Operation::GatherNd { data: f(data), indices: f(indices), batch_dims },
+            &Operation::Unary { input, op } =>
+                Operation::Unary { input: f(input), op },
+            Operation::Permute { input, permutation } =>
+                Operation::Permute { input: f(*input), permutation: permutation.clone() },
+            &Operation::Round { input } =>
+                Operation::Round { input: f(input) },
+            &Operation::Sum { input, axis } =>
+                Operation::Sum { input: f(input), axis },
+            &Operation::Compare { left, right, mode } =>
+                Operation::Compare { left: f(left), right: f(right), mode },
+            &Operation::Select { cond, on_true, on_false } =>
+                Operation::Select { cond: f(cond), on_true: f(on_true), on_false: f(on_false) },
         }
     }
 }
@@ -95,19 +209,74 @@ impl Operation {
 pub struct ConvDetails {
     pub input_channels: usize,
     pub output_channels: usize,
-    pub input_size: usize,
-    pub kernel_size: usize,
-    pub padding: usize,
-    pub output_size: usize,
+    pub input_w: usize,
+    pub input_h: usize,
+    pub kernel_w: usize,
+    pub kernel_h: usize,
+    pub padding_w: usize,
+    pub padding_h: usize,
+    /// The stride the kernel slides by along both spatial axes.
+    pub stride: usize,
+    /// The spacing between kernel taps along both spatial axes; `1` is a normal dense kernel.
+    pub dilation: usize,
+    pub output_w: usize,
+    pub output_h: usize,
     pub batch_size: Size,
+    /// The number of groups the input/output channels are split into, each convolved independently
+    /// with its own slice of the filter. `1` is a normal dense convolution, `input_channels` (which
+    /// then must equal `output_channels`) is a depthwise convolution.
+    pub groups: usize,
 }
 
 impl ConvDetails {
+    /// The filter shape this convolution expects: `[output_channels, input_channels / groups, kernel_w, kernel_h]`.
     pub fn kernel_shape(&self) -> [usize; 4] {
-        [self.output_channels, self.input_channels, self.kernel_size, self.kernel_size]
+        [self.output_channels, self.input_channels / self.groups, self.kernel_w, self.kernel_h]
+    }
+
+    /// Whether this convolution's output spatial size matches its input spatial size, i.e. it can be
+    /// treated as "same"-padded. [`crate::cpu::convolution`]'s current CPU paths only support this case.
+    pub fn keeps_spatial_shape(&self) -> bool {
+        self.output_w == self.input_w && self.output_h == self.input_h
     }
 }
 
+/// Extra knobs for [`Graph::conv_ex`] beyond what [`Graph::conv`]'s stride-1/dilation-1 shortcut exposes.
+#[derive(Debug, Copy, Clone)]
+pub struct ConvOptions {
+    pub padding_w: usize,
+    pub padding_h: usize,
+    pub stride: usize,
+    pub dilation: usize,
+}
+
+/// The weight matrices and biases for a single [`Graph::gru`] layer.
+/// Each `w_*` has shape `[hidden, input]`, each `u_*` has shape `[hidden, hidden]` (both using
+/// [`Graph::linear`]'s `[Co, Ci]` convention), and each `b_*` has shape `[1, hidden]`.
+#[derive(Debug, Copy, Clone)]
+pub struct GruWeights {
+    pub w_z: Value, pub u_z: Value, pub b_z: Value,
+    pub w_r: Value, pub u_r: Value, pub b_r: Value,
+    pub w_n: Value, pub u_n: Value, pub b_n: Value,
+}
+
+/// The weight matrices and biases for a single [`Graph::lstm`] layer, one per gate: input `i`,
+/// forget `f`, candidate cell `g`, output `o`. Shapes follow the same convention as [`GruWeights`].
+#[derive(Debug, Copy, Clone)]
+pub struct LstmWeights {
+    pub w_i: Value, pub u_i: Value, pub b_i: Value,
+    pub w_f: Value, pub u_f: Value, pub b_f: Value,
+    pub w_g: Value, pub u_g: Value, pub b_g: Value,
+    pub w_o: Value, pub u_o: Value, pub b_o: Value,
+}
+
+/// Which way a recurrent layer scans its input sequence.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScanDirection {
+    Forward,
+    Reverse,
+}
+
 impl Index<Value> for Graph {
     type Output = ValueInfo;
 
@@ -126,20 +295,54 @@ impl Graph {
         assert!(value.0 < self.values.len());
     }
 
+    /// Full NumPy/ONNX-style broadcasting: the shorter shape is right-aligned against the longer one
+    /// (left-padded with [`Size::ONE`]), and each aligned pair of dims must be equal or one of them must
+    /// be [`Size::ONE`], producing `max(l, r)` (with `Size::ONE` acting as the identity). Unlike the
+    /// single-direction check this replaced, either operand may be the one that ends up broadcast.
     fn check_broadcast(&self, left: Value, right: Value) -> Shape {
-        let left_shape = &self[left].shape;
-        let right_shape = &self[right].shape;
-        assert_eq!(
-            left_shape.rank(), right_shape.rank(),
-            "Both inputs must have the same rank, got {:?} and {:?}",
-            left_shape, right_shape
-        );
+        Self::broadcast_shapes(&self[left].shape, &self[right].shape)
+    }
 
-        for (&l, &r) in zip_eq(&left_shape.dims, &right_shape.dims) {
-            assert!(l == r || r == Size::ONE, "Cannot broadcast shape {:?} to {:?}", right_shape, left_shape);
-        }
+    /// Same as [`Graph::check_broadcast`], but folding a third shape in as well, for ops like
+    /// [`Operation::Select`] where all three operands broadcast against each other.
+    fn check_broadcast3(&self, a: Value, b: Value, c: Value) -> Shape {
+        let ab = Self::broadcast_shapes(&self[a].shape, &self[b].shape);
+        Self::broadcast_shapes(&ab, &self[c].shape)
+    }
 
-        left_shape.clone()
+    fn broadcast_shapes(left_shape: &Shape, right_shape: &Shape) -> Shape {
+        let rank = left_shape.rank().max(right_shape.rank());
+        let left_dims = Self::left_pad_dims(&left_shape.dims, rank);
+        let right_dims = Self::left_pad_dims(&right_shape.dims, rank);
+
+        let output_dims = zip_eq(&left_dims, &right_dims)
+            .map(|(&l, &r)| {
+                assert!(
+                    l == r || l == Size::ONE || r == Size::ONE,
+                    "Cannot broadcast shapes {:?} and {:?}", left_shape, right_shape,
+                );
+                if l == Size::ONE { r } else { l }
+            })
+            .collect_vec();
+
+        Shape::new(output_dims)
+    }
+
+    /// Left-pad `dims` with [`Size::ONE`] up to `rank`, the NumPy broadcasting convention of aligning
+    /// shapes on their trailing (rightmost) axes.
+    fn left_pad_dims(dims: &[Size], rank: usize) -> Vec<Size> {
+        let pad = rank - dims.len();
+        std::iter::repeat(Size::ONE).take(pad).chain(dims.iter().copied()).collect()
+    }
+
+    /// Reshape `input` to have `rank` axes by inserting leading `Size::ONE` axes if needed, so a
+    /// lower-rank broadcast operand still has an explicit axis for every one of the output's, the way
+    /// [`Operation::Add`]/[`Operation::Mul`]'s executors expect. A no-op (via [`Graph::view`]'s own
+    /// shortcut) when `input` already has that rank.
+    #[must_use]
+    fn broadcast_pad(&mut self, input: Value, rank: usize) -> Value {
+        let dims = Self::left_pad_dims(&self[input].shape.dims, rank);
+        self.view(input, Shape::new(dims))
     }
 
     /// Iterate over the values in this graph, in topological order,
@@ -268,6 +471,47 @@ impl Graph {
         self.push(new_shape, Operation::Slice { input, axis, start, end })
     }
 
+    /// Reorder axes according to `permutation`, a permutation of `0..input.rank()`. The output shape is
+    /// `permutation.iter().map(|&i| input.shape[i])`. Following tract's `AxisOp::Move` model, the
+    /// identity permutation is a no-op, and permuting an already-permuted value fuses the two
+    /// permutations into one instead of stacking two nodes.
+    #[must_use]
+    pub fn permute(&mut self, input: Value, permutation: Vec<usize>) -> Value {
+        let input_shape = self[input].shape.clone();
+        let rank = input_shape.rank();
+
+        assert_eq!(
+            permutation.len(), rank,
+            "Permutation length {} must match input rank {}", permutation.len(), rank
+        );
+        assert!(
+            permutation.iter().all(|&i| i < rank) && permutation.iter().unique().count() == rank,
+            "{:?} is not a permutation of 0..{}", permutation, rank
+        );
+
+        if permutation.iter().enumerate().all(|(i, &p)| i == p) {
+            return input;
+        }
+
+        if let Operation::Permute { input: prev_input, permutation: prev_permutation } = &self[input].operation {
+            let prev_input = *prev_input;
+            let fused = permutation.iter().map(|&i| prev_permutation[i]).collect_vec();
+            return self.permute(prev_input, fused);
+        }
+
+        let output_shape = Shape::new(permutation.iter().map(|&i| input_shape.dims[i]).collect_vec());
+        self.push(output_shape, Operation::Permute { input, permutation })
+    }
+
+    /// Swap two axes; shorthand for [`Graph::permute`] with the identity permutation except at `a`/`b`.
+    #[must_use]
+    pub fn transpose(&mut self, input: Value, a: usize, b: usize) -> Value {
+        let rank = self[input].shape.rank();
+        let mut permutation = (0..rank).collect_vec();
+        permutation.swap(a, b);
+        self.permute(input, permutation)
+    }
+
     /// Index along a given axis.
     /// Similar to slice with a 1-sized interval except that the the resulting value doesn't have the extra axis.
     #[must_use]
@@ -280,12 +524,94 @@ impl Graph {
         self.view(sliced, new_shape)
     }
 
+    /// Gather elements from `input` along `axis`, selected by the runtime index tensor `indices`.
+    /// `axis` is replaced by `indices`'s shape in the output, e.g. input `(2, 3)` gathered along axis 1
+    /// with a length-4 index tensor produces shape `(2, 4)`.
+    #[must_use]
+    pub fn gather(&mut self, input: Value, axis: usize, indices: Value) -> Value {
+        let input_shape = self[input].shape.clone();
+        assert!(
+            axis < input_shape.rank(),
+            "Input rank {} too low for axis {}", input_shape.rank(), axis
+        );
+
+        let indices_shape = self[indices].shape.clone();
+        assert!(
+            indices_shape.as_fixed().is_some(),
+            "Gather indices shape must be fixed, got {:?}", indices_shape
+        );
+
+        let output_shape = input_shape.replace(axis, indices_shape);
+
+        self.push(output_shape, Operation::Gather { input, axis, indices })
+    }
+
+    /// ONNX-style `GatherND`: `indices`'s last axis (length `k`) selects elements from the first `k`
+    /// (post-`batch_dims`) dimensions of `data`; the leading `batch_dims` dimensions of `data` and
+    /// `indices` must match and are zipped together rather than indexed.
+    /// Output shape is `indices.shape[..-1] ++ data.shape[batch_dims + k..]`.
+    ///
+    /// Unlike plain [`Graph::gather`], which only indexes a single axis, this can express the
+    /// arbitrary-coordinate board-position/move lookups a single-axis gather can't.
+    #[must_use]
+    pub fn gather_nd(&mut self, data: Value, indices: Value, batch_dims: usize) -> Value {
+        let data_shape = self[data].shape.clone();
+        let indices_shape = self[indices].shape.clone();
+
+        assert!(
+            indices_shape.rank() >= 1,
+            "GatherND indices must have rank at least 1, got {:?}", indices_shape
+        );
+        let k = indices_shape[indices_shape.rank() - 1].unwrap_fixed("GatherND index tuple length");
+
+        assert!(
+            batch_dims + k <= data_shape.rank(),
+            "batch_dims ({}) + k ({}) must not exceed data rank {}",
+            batch_dims, k, data_shape.rank(),
+        );
+
+        for d in 0..batch_dims {
+            assert_eq!(
+                data_shape[d], indices_shape[d],
+                "Batch dimension {} must match between data {:?} and indices {:?}",
+                d, data_shape, indices_shape,
+            );
+        }
+
+        let (indices_batch, _) = indices_shape.split(indices_shape.rank() - 1);
+        let remaining = Shape::new(data_shape.dims[batch_dims + k..].to_vec());
+        let output_shape = indices_batch.concat(&remaining);
+
+        self.push(output_shape, Operation::GatherNd { data, indices, batch_dims })
+    }
+
+    /// Apply 2D convolution, with stride 1 and dilation 1. Shorthand for [`Graph::conv_ex`] for the
+    /// common case; see it for the full set of knobs (including groups, which both entry points infer
+    /// the same way).
+    #[must_use]
+    pub fn conv(&mut self, input: Value, filter: Value, padding_w: usize, padding_h: usize) -> Value {
+        self.conv_ex(input, filter, ConvOptions { padding_w, padding_h, stride: 1, dilation: 1 })
+    }
+
     /// Apply 2D convolution.
+    ///
+    /// The number of groups is inferred from the filter shape: `groups = input_channels / filter_input_channels`.
+    /// `groups == 1` is a normal dense convolution, `groups == input_channels == output_channels` is depthwise.
+    /// With `groups > 1` the input channels are split into `groups` contiguous bands, each convolved with the
+    /// `output_channels / groups` filters assigned to it, and the results concatenated along the channel axis.
+    ///
+    /// Kernels may be rectangular and even-sized; padding, stride and dilation are applied independently
+    /// along each spatial axis (well, the same `stride`/`dilation` along both, but separate `padding_w`/
+    /// `padding_h`), with output size `(in + 2*padding - dilation*(kernel-1) - 1)/stride + 1` per axis.
     #[must_use]
-    pub fn conv(&mut self, input: Value, filter: Value, padding: usize) -> Value {
+    pub fn conv_ex(&mut self, input: Value, filter: Value, options: ConvOptions) -> Value {
+        let ConvOptions { padding_w, padding_h, stride, dilation } = options;
+        assert!(stride > 0, "Stride must be positive");
+        assert!(dilation > 0, "Dilation must be positive");
+
         let [n, in_c, in_w, in_h]: [Size; 4] = self[input].shape.dims.as_slice().try_into()
             .expect("Convolution input must have rank 4");
-        let [out_c, in_c_check, k_w, k_h]: [Size; 4] = self[filter].shape.dims.as_slice().try_into()
+        let [out_c, filter_in_c, k_w, k_h]: [Size; 4] = self[filter].shape.dims.as_slice().try_into()
             .expect("Convolution filter must have rank 4");
 
         // almost everything must be fixed, except for the batch size n
@@ -293,20 +619,16 @@ impl Graph {
         let in_w = in_w.unwrap_fixed("Conv input width");
         let in_h = in_h.unwrap_fixed("Conv input height");
         let out_c = out_c.unwrap_fixed("Conv output channels");
-        let in_c_check = in_c_check.unwrap_fixed("Filter input channels");
+        let filter_in_c = filter_in_c.unwrap_fixed("Filter input channels");
         let k_w = k_w.unwrap_fixed("Conv kernel width");
         let k_h = k_h.unwrap_fixed("Conv kernel height");
 
-        assert_eq!(1, k_w % 2, "Kernel width must be odd, got {}", k_w);
-        assert_eq!(1, k_h % 2, "Kernel height must be odd, got {}", k_h);
-
-        assert_eq!(in_c, in_c_check, "Input channel mismatch");
+        assert!(filter_in_c > 0 && in_c % filter_in_c == 0, "Input channels {} must be a multiple of filter input channels {}", in_c, filter_in_c);
+        let groups = in_c / filter_in_c;
+        assert_eq!(0, out_c % groups, "Output channels {} must be divisible by groups {}", out_c, groups);
 
-        assert_eq!(in_w, in_h, "Only square inputs supported");
-        assert_eq!(k_w, k_h, "Only square kernels supported");
-
-        let out_w = in_w - k_w + 1 + 2 * padding;
-        let out_h = in_h - k_h + 1 + 2 * padding;
+        let out_w = (in_w + 2 * padding_w - dilation * (k_w - 1) - 1) / stride + 1;
+        let out_h = (in_h + 2 * padding_h - dilation * (k_h - 1) - 1) / stride + 1;
         let output_shape = vec![n, Size::fixed(out_c), Size::fixed(out_w), Size::fixed(out_h)];
         let output_shape = Shape::new(output_shape);
 
@@ -314,14 +636,21 @@ impl Graph {
             batch_size: n,
             input_channels: in_c,
             output_channels: out_c,
-            input_size: in_w,
-            kernel_size: k_w,
-            padding,
-            output_size: out_w,
+            input_w,
+            input_h,
+            kernel_w: k_w,
+            kernel_h: k_h,
+            padding_w,
+            padding_h,
+            stride,
+            dilation,
+            output_w: out_w,
+            output_h: out_h,
+            groups,
         };
         self.push(
             output_shape,
-            Operation::Conv { input, details, filter },
+            Operation::Conv { input, filter, bias: None, relu: false, details },
         )
     }
 
@@ -344,7 +673,7 @@ impl Graph {
         let weight_view = self.view(weight, weight_view_shape);
         let output_view_shape = Shape::new(vec![n, co]);
 
-        let output = self.conv(input_view, weight_view, 0);
+        let output = self.conv(input_view, weight_view, 0, 0);
         self.view(output, output_view_shape)
     }
 
@@ -364,45 +693,278 @@ impl Graph {
         self.clamp(input, 0.0, f32::INFINITY)
     }
 
+    /// Elementwise round to the nearest integer, ties away from zero.
+    #[must_use]
+    pub fn round(&mut self, input: Value) -> Value {
+        self.push(self[input].shape.clone(), Operation::Round { input })
+    }
+
+    /// Sum `input` along `axis`, keeping it as a size-1 dim. See [`Operation::Sum`].
+    #[must_use]
+    pub fn sum(&mut self, input: Value, axis: usize) -> Value {
+        let shape = self[input].shape.clone();
+        assert!(
+            axis < shape.rank(),
+            "Input rank {} too low for axis {}", shape.rank(), axis
+        );
+
+        let output_shape = shape.replace(axis, Shape::single(Size::ONE));
+        self.push(output_shape, Operation::Sum { input, axis })
+    }
+
+    /// Elementwise comparison, producing a `0.0`/`1.0` mask.
+    /// Full NumPy/ONNX-style broadcasting applies: shapes are right-aligned and the shorter one is left-padded with size-1 axes, then each aligned pair of dims must match or be 1.
+    #[must_use]
+    pub fn compare(&mut self, left: Value, right: Value, mode: CompareOp) -> Value {
+        let output_shape = self.check_broadcast(left, right);
+        let left = self.broadcast_pad(left, output_shape.rank());
+        let right = self.broadcast_pad(right, output_shape.rank());
+        self.push(output_shape, Operation::Compare { left, right, mode })
+    }
+
+    /// Elementwise `left > right`, see [`Graph::compare`].
+    #[must_use]
+    pub fn greater(&mut self, left: Value, right: Value) -> Value {
+        self.compare(left, right, CompareOp::Greater)
+    }
+
+    /// Elementwise select: `on_true` wherever `cond` is nonzero, `on_false` elsewhere.
+    /// `cond`, `on_true` and `on_false` all broadcast against each other, NumPy/ONNX-style (see [`Graph::add`]).
+    #[must_use]
+    pub fn select(&mut self, cond: Value, on_true: Value, on_false: Value) -> Value {
+        let output_shape = self.check_broadcast3(cond, on_true, on_false);
+        let cond = self.broadcast_pad(cond, output_shape.rank());
+        let on_true = self.broadcast_pad(on_true, output_shape.rank());
+        let on_false = self.broadcast_pad(on_false, output_shape.rank());
+        self.push(output_shape, Operation::Select { cond, on_true, on_false })
+    }
+
+    /// Substitutes `value` wherever `mask` is nonzero, leaving `input` unchanged elsewhere — e.g.
+    /// masking illegal moves to `-inf` ahead of a policy softmax.
+    #[must_use]
+    pub fn masked_fill(&mut self, input: Value, mask: Value, value: f32) -> Value {
+        let value = self.constant(Shape::SCALAR, vec![value]);
+        self.select(mask, value, input)
+    }
+
     /// Add two values together elementwise.
-    /// They must have the same rank, and the right shape is broadcasted to the left shape.
+    /// Full NumPy/ONNX-style broadcasting applies: shapes are right-aligned and the shorter one is left-padded with size-1 axes, then each aligned pair of dims must match or be 1.
     #[must_use]
     pub fn add(&mut self, left: Value, right: Value) -> Value {
         let output_shape = self.check_broadcast(left, right);
 
-        if self.is_all_zero(right) {
+        if self.is_all_zero(right) && self[left].shape == output_shape {
             return left;
         }
 
+        let left = self.broadcast_pad(left, output_shape.rank());
+        let right = self.broadcast_pad(right, output_shape.rank());
         self.push(output_shape, Operation::Add { left, right, subtract: false })
     }
 
     /// Subtract two values elementwise.
-    /// They must have the same rank, and the right shape is broadcasted to the left shape.
+    /// Full NumPy/ONNX-style broadcasting applies: shapes are right-aligned and the shorter one is left-padded with size-1 axes, then each aligned pair of dims must match or be 1.
     #[must_use]
     pub fn sub(&mut self, left: Value, right: Value) -> Value {
         let output_shape = self.check_broadcast(left, right);
 
-        if self.is_all_zero(right) {
+        if self.is_all_zero(right) && self[left].shape == output_shape {
             return left;
         }
 
+        let left = self.broadcast_pad(left, output_shape.rank());
+        let right = self.broadcast_pad(right, output_shape.rank());
         self.push(output_shape, Operation::Add { left, right, subtract: true })
     }
 
     /// Multiple two values elementwise.
-    /// They must have the same rank, and the right shape is broadcasted to the left shape.
+    /// Full NumPy/ONNX-style broadcasting applies: shapes are right-aligned and the shorter one is left-padded with size-1 axes, then each aligned pair of dims must match or be 1.
     #[must_use]
     pub fn mul(&mut self, left: Value, right: Value) -> Value {
         let output_shape = self.check_broadcast(left, right);
 
-        if self.is_all_one(right) {
+        if self.is_all_one(right) && self[left].shape == output_shape {
             return left;
         }
 
+        let left = self.broadcast_pad(left, output_shape.rank());
+        let right = self.broadcast_pad(right, output_shape.rank());
         self.push(output_shape, Operation::Mul { left, right })
     }
 
+    /// Softmax along the given axis, normalized so the outputs along that axis always sum to 1.
+    #[must_use]
+    pub fn softmax(&mut self, input: Value, axis: usize) -> Value {
+        self.push_softmax(input, axis, false)
+    }
+
+    /// "Quiet" softmax variant: the denominator gets an extra implicit `+1` term, `exp(x_i) / (1 + sum_j exp(x_j))`,
+    /// so the whole output vector can decay towards zero instead of always summing to 1.
+    /// Useful for attention/policy heads where all-small logits should mean "commit to nothing".
+    #[must_use]
+    pub fn quiet_softmax(&mut self, input: Value, axis: usize) -> Value {
+        self.push_softmax(input, axis, true)
+    }
+
+    fn push_softmax(&mut self, input: Value, axis: usize, quiet: bool) -> Value {
+        let shape = self[input].shape.clone();
+        assert!(
+            axis < shape.rank(),
+            "Input rank {} too low for axis {}", shape.rank(), axis
+        );
+
+        self.push(shape, Operation::Softmax { input, axis, quiet })
+    }
+
+    /// Elementwise unary nonlinearity.
+    #[must_use]
+    pub fn unary(&mut self, input: Value, op: UnaryOp) -> Value {
+        self.push(self[input].shape.clone(), Operation::Unary { input, op })
+    }
+
+    /// Elementwise sigmoid, `1 / (1 + exp(-x))`.
+    #[must_use]
+    pub fn sigmoid(&mut self, input: Value) -> Value {
+        self.unary(input, UnaryOp::Sigmoid)
+    }
+
+    /// Elementwise hyperbolic tangent.
+    #[must_use]
+    pub fn tanh(&mut self, input: Value) -> Value {
+        self.unary(input, UnaryOp::Tanh)
+    }
+
+    /// `linear(x, w) + linear(h, u) + b`, the `W x_t + U h_{t-1} + b` term shared by every GRU/LSTM gate.
+    /// `w`/`u` are weight matrices (`linear`'s `[Co, Ci]` convention) and `b` has shape `[1, Co]`.
+    fn recurrent_gate(&mut self, x: Value, h: Value, w: Value, u: Value, b: Value) -> Value {
+        let wx = self.linear(x, w);
+        let uh = self.linear(h, u);
+        let sum = self.add(wx, uh);
+        self.add(sum, b)
+    }
+
+    /// One step of the standard GRU recurrence (see [`Graph::gru`]).
+    fn gru_cell(&mut self, x_t: Value, h_prev: Value, w: &GruWeights) -> Value {
+        let z = self.recurrent_gate(x_t, h_prev, w.w_z, w.u_z, w.b_z);
+        let z = self.sigmoid(z);
+
+        let r = self.recurrent_gate(x_t, h_prev, w.w_r, w.u_r, w.b_r);
+        let r = self.sigmoid(r);
+
+        let wx_n = self.linear(x_t, w.w_n);
+        let uh_n = self.linear(h_prev, w.u_n);
+        let r_uh_n = self.mul(r, uh_n);
+        let n = self.add(wx_n, r_uh_n);
+        let n = self.add(n, w.b_n);
+        let n = self.tanh(n);
+
+        let hidden_shape = self[z].shape.clone();
+        let ones = self.constant(hidden_shape.clone(), vec![1.0; hidden_shape.size().unwrap_fixed("GRU hidden size")]);
+        let one_minus_z = self.sub(ones, z);
+
+        let kept = self.mul(one_minus_z, n);
+        let carried = self.mul(z, h_prev);
+        self.add(kept, carried)
+    }
+
+    /// Run a GRU recurrence over an input sequence of shape `[seq, batch, input_size]`, returning the
+    /// final hidden state with shape `[batch, hidden_size]`.
+    ///
+    /// `initial_hidden` defaults to zeros when `None`. With `direction` set to
+    /// [`ScanDirection::Reverse`] the sequence is scanned back to front.
+    ///
+    /// Implemented as a graph-level lowering, unrolled over the (compile-time known) sequence length,
+    /// into existing `linear`/`add`/`sub`/`mul`/`sigmoid`/`tanh` nodes, so CUDA evaluation needs no
+    /// dedicated kernel. Only the final hidden state is returned: stacking every step's hidden state
+    /// into a `[seq, batch, hidden]` tensor needs a concat-like primitive this graph IR doesn't have yet.
+    #[must_use]
+    pub fn gru(&mut self, input: Value, weights: &GruWeights, initial_hidden: Option<Value>, direction: ScanDirection) -> Value {
+        let input_shape = self[input].shape.clone();
+        let [seq, batch, _input_size] = input_shape.unwrap_3();
+        let seq_len = seq.unwrap_fixed("GRU sequence length");
+
+        let hidden_size = self[weights.w_z].shape.unwrap_2()[0];
+        let hidden_shape = Shape::new(vec![batch, hidden_size]);
+
+        let mut h = initial_hidden.unwrap_or_else(|| {
+            let size = hidden_shape.size().unwrap_fixed("GRU hidden state size");
+            self.constant(hidden_shape, vec![0.0; size])
+        });
+
+        for t in Self::scan_order(seq_len, direction) {
+            let x_t = self.index(input, 0, t);
+            h = self.gru_cell(x_t, h, weights);
+        }
+
+        h
+    }
+
+    /// One step of the standard LSTM recurrence (see [`Graph::lstm`]), returning the new `(h_t, c_t)`.
+    fn lstm_cell(&mut self, x_t: Value, h_prev: Value, c_prev: Value, w: &LstmWeights) -> (Value, Value) {
+        let i = self.recurrent_gate(x_t, h_prev, w.w_i, w.u_i, w.b_i);
+        let i = self.sigmoid(i);
+
+        let f = self.recurrent_gate(x_t, h_prev, w.w_f, w.u_f, w.b_f);
+        let f = self.sigmoid(f);
+
+        let g = self.recurrent_gate(x_t, h_prev, w.w_g, w.u_g, w.b_g);
+        let g = self.tanh(g);
+
+        let o = self.recurrent_gate(x_t, h_prev, w.w_o, w.u_o, w.b_o);
+        let o = self.sigmoid(o);
+
+        let forgotten = self.mul(f, c_prev);
+        let written = self.mul(i, g);
+        let c_t = self.add(forgotten, written);
+
+        let c_t_tanh = self.tanh(c_t);
+        let h_t = self.mul(o, c_t_tanh);
+
+        (h_t, c_t)
+    }
+
+    /// Run an LSTM recurrence over an input sequence of shape `[seq, batch, input_size]`, returning the
+    /// final `(hidden, cell)` state, each with shape `[batch, hidden_size]`.
+    ///
+    /// `initial_state` defaults to zeros for both `h_0` and `c_0` when `None`. With `direction` set to
+    /// [`ScanDirection::Reverse`] the sequence is scanned back to front.
+    ///
+    /// Implemented the same way as [`Graph::gru`]: a graph-level lowering, unrolled over the sequence
+    /// length, into existing `linear`/`add`/`mul`/`sigmoid`/`tanh` nodes. Only the final state is
+    /// returned, for the same reason `gru` only returns its final hidden state.
+    #[must_use]
+    pub fn lstm(&mut self, input: Value, weights: &LstmWeights, initial_state: Option<(Value, Value)>, direction: ScanDirection) -> (Value, Value) {
+        let input_shape = self[input].shape.clone();
+        let [seq, batch, _input_size] = input_shape.unwrap_3();
+        let seq_len = seq.unwrap_fixed("LSTM sequence length");
+
+        let hidden_size = self[weights.w_i].shape.unwrap_2()[0];
+        let hidden_shape = Shape::new(vec![batch, hidden_size]);
+
+        let (mut h, mut c) = initial_state.unwrap_or_else(|| {
+            let size = hidden_shape.size().unwrap_fixed("LSTM hidden state size");
+            let h0 = self.constant(hidden_shape.clone(), vec![0.0; size]);
+            let c0 = self.constant(hidden_shape, vec![0.0; size]);
+            (h0, c0)
+        });
+
+        for t in Self::scan_order(seq_len, direction) {
+            let x_t = self.index(input, 0, t);
+            let (h_t, c_t) = self.lstm_cell(x_t, h, c, weights);
+            h = h_t;
+            c = c_t;
+        }
+
+        (h, c)
+    }
+
+    fn scan_order(seq_len: usize, direction: ScanDirection) -> Vec<usize> {
+        match direction {
+            ScanDirection::Forward => (0..seq_len).collect(),
+            ScanDirection::Reverse => (0..seq_len).rev().collect(),
+        }
+    }
+
     /// Register an existing value as an output
     pub fn output(&mut self, value: Value) {
         self.outputs.push(value);
@@ -414,6 +976,130 @@ impl Graph {
             self.output(value)
         }
     }
+
+    /// Validates that this graph is acyclic and, if so, returns a canonical topological execution
+    /// order for it (the same order every time, regardless of backend), instead of assuming
+    /// acyclicity and letting a graph that isn't surface as a confusing panic deep inside an executor.
+    ///
+    /// Runs Kosaraju's strongly-connected-components algorithm: a DFS over the graph recording
+    /// values in postorder, then a DFS over the *reversed* edge set (in decreasing order of that
+    /// postorder) assigning each reached value the current component id. Any component with more than
+    /// one value, or a single value with a self-loop, names a genuine cycle, reported via
+    /// [`GraphCycleError`] instead of panicking. When every component is a self-loop-free singleton,
+    /// the graph is acyclic and the first DFS's postorder, reversed, is already a valid topological
+    /// order (every value comes after everything it depends on).
+    pub fn check_acyclic_and_schedule(&self) -> Result<Vec<Value>, GraphCycleError> {
+        let values = self.values().collect_vec();
+
+        let mut consumers: Vec<Vec<Value>> = vec![Vec::new(); values.len()];
+        for &v in &values {
+            for input in self[v].operation.inputs() {
+                consumers[input.0].push(v);
+            }
+        }
+
+        // pass 1: DFS over the forward graph (edges to consumers), recording postorder
+        let mut postorder = Vec::with_capacity(values.len());
+        let mut visited = vec![false; values.len()];
+        for &start in &values {
+            if !visited[start.0] {
+                dfs_postorder(start, &consumers, &mut visited, &mut postorder);
+            }
+        }
+
+        // pass 2: DFS over the reversed graph (edges to producers/inputs), in decreasing postorder,
+        // assigning strongly-connected-component ids
+        let mut component_of: Vec<Option<usize>> = vec![None; values.len()];
+        let mut components: Vec<Vec<Value>> = Vec::new();
+        for &root in postorder.iter().rev() {
+            if component_of[root.0].is_none() {
+                let id = components.len();
+                let mut members = Vec::new();
+                dfs_assign_component(root, self, id, &mut component_of, &mut members);
+                components.push(members);
+            }
+        }
+
+        let cycles: Vec<Vec<Value>> = components
+            .into_iter()
+            .filter(|members| members.len() > 1 || self[members[0]].operation.inputs().contains(&members[0]))
+            .collect();
+
+        if !cycles.is_empty() {
+            return Err(GraphCycleError { cycles });
+        }
+
+        postorder.reverse();
+        Ok(postorder)
+    }
+
+    /// Common-subexpression elimination: collapses structurally identical nodes (same operation, same
+    /// operands, same attributes, same shape) down to a single representative. See
+    /// [`crate::dedup::dedup_values`] for the global-value-numbering implementation.
+    pub fn dedup_values(&self) -> Graph {
+        crate::dedup::dedup_values(self)
+    }
+
+    /// Runs the declutter/fusion pass: constant-folds pure-`Constant` subgraphs, folds a preceding
+    /// channelwise affine into a `Conv`'s filter weights, fuses a following bias `Add`/`relu` `Clamp`
+    /// into the conv's epilogue, and eliminates dead values, repeated to a fixpoint. See
+    /// [`crate::optimizer::optimize_graph_with_report`] for the rule-by-rule implementation and a
+    /// version that also reports which fusions fired.
+    pub fn optimize(&self) -> Graph {
+        crate::optimizer::optimize_graph(self, Default::default())
+    }
+
+    /// Post-training fixed-point quantization: lowers this graph's `Conv`/`Add`/`Clamp` backbone into a
+    /// `bits`-wide symmetric fixed-point domain, using `calibration`'s observed input ranges to pick
+    /// scales. See [`crate::quantize`] for the module-level rationale and [`crate::quantize::quantize_graph`]
+    /// for the implementation.
+    pub fn quantize(&self, bits: u8, calibration: &crate::quantize::QuantParams) -> (Graph, crate::quantize::QuantizationTable) {
+        crate::quantize::quantize_graph(self, bits, calibration)
+    }
+}
+
+fn dfs_postorder(start: Value, consumers: &[Vec<Value>], visited: &mut [bool], postorder: &mut Vec<Value>) {
+    // explicit stack of (node, index of the next consumer still to visit), to avoid blowing the
+    // native stack on the long chains these graphs tend to have
+    let mut stack: Vec<(Value, usize)> = vec![(start, 0)];
+    visited[start.0] = true;
+
+    while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+        let node_consumers = &consumers[node.0];
+        if *next < node_consumers.len() {
+            let child = node_consumers[*next];
+            *next += 1;
+            if !visited[child.0] {
+                visited[child.0] = true;
+                stack.push((child, 0));
+            }
+        } else {
+            postorder.push(node);
+            stack.pop();
+        }
+    }
+}
+
+fn dfs_assign_component(start: Value, graph: &Graph, id: usize, component_of: &mut [Option<usize>], members: &mut Vec<Value>) {
+    let mut stack = vec![start];
+    component_of[start.0] = Some(id);
+
+    while let Some(node) = stack.pop() {
+        members.push(node);
+        for input in graph[node].operation.inputs() {
+            if component_of[input.0].is_none() {
+                component_of[input.0] = Some(id);
+                stack.push(input);
+            }
+        }
+    }
+}
+
+/// Returned by [`Graph::check_acyclic_and_schedule`]: each entry names the value nodes making up one
+/// strongly-connected component that isn't a trivial self-loop-free singleton, i.e. a genuine cycle.
+#[derive(Debug)]
+pub struct GraphCycleError {
+    pub cycles: Vec<Vec<Value>>,
 }
 
 impl Debug for Graph {
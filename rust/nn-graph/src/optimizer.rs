@@ -0,0 +1,491 @@
+//! The declutter/fusion pass: a fixed, order-dependent sequence of rewrite rules run to a fixpoint,
+//! modeled on tract's declutter pass. Each rule is its own full graph rebuild (in the same spirit as
+//! [`crate::dedup::dedup_values`]) rather than an in-place mutation, since [`Value`] indices are
+//! append-only. Unlike [`crate::egraph`]'s equality saturation, a rule here can permanently hide a
+//! simplification a different rule ordering would have found; that's the tradeoff for not paying an
+//! e-graph's memory/time overhead on every graph this pass runs on.
+
+use std::collections::{HashMap, HashSet};
+
+use gemm::Parallelism;
+use itertools::Itertools;
+use ndarray::Ix4;
+
+use crate::cpu::{convolution, slice_info, Tensor};
+use crate::graph::{ConvDetails, Graph, Operation, Value};
+use crate::shape::Shape;
+
+/// Tunables for [`optimize_graph`]; every rule defaults to on. Exposed individually so a caller chasing
+/// down a miscompile can bisect which rule is responsible by disabling it.
+#[derive(Debug, Copy, Clone)]
+pub struct OptimizerSettings {
+    /// Evaluate any node whose inputs are all `Constant` on the host and replace it with a `Constant`.
+    pub fold_constants: bool,
+    /// Fold a channelwise `Mul`/`Add` feeding into a `Conv` into the conv's own filter weights.
+    pub fold_affine_into_conv: bool,
+    /// Fuse a bias `Add` and/or relu `Clamp` immediately following a `Conv` into the conv's epilogue.
+    pub fuse_conv_epilogue: bool,
+    /// Drop values no longer reachable from `outputs` once the rules above stop referencing them.
+    pub eliminate_dead_values: bool,
+    /// Safety valve against a rule that (due to a bug) never reaches a fixpoint; each iteration is cheap
+    /// relative to this, so it's set generously rather than tuned per-graph.
+    pub max_iterations: usize,
+}
+
+impl Default for OptimizerSettings {
+    fn default() -> Self {
+        OptimizerSettings {
+            fold_constants: true,
+            fold_affine_into_conv: true,
+            fuse_conv_epilogue: true,
+            eliminate_dead_values: true,
+            max_iterations: 64,
+        }
+    }
+}
+
+/// How many times each rewrite rule fired across every iteration of [`optimize_graph_with_report`], so
+/// callers can tell whether the pass did anything useful instead of optimizing blind.
+#[derive(Debug, Default, Clone)]
+pub struct OptimizationReport {
+    pub iterations_run: usize,
+    pub constants_folded: usize,
+    pub affines_folded_into_conv: usize,
+    pub conv_epilogues_fused: usize,
+    pub dead_values_removed: usize,
+}
+
+impl OptimizationReport {
+    fn any_rule_fired(&self) -> bool {
+        self.constants_folded > 0
+            || self.affines_folded_into_conv > 0
+            || self.conv_epilogues_fused > 0
+            || self.dead_values_removed > 0
+    }
+}
+
+/// Runs [`OptimizerSettings`]'s enabled rewrite rules to a fixpoint and returns the decluttered graph.
+/// See [`Graph::optimize`](crate::graph::Graph::optimize) for the method-call shorthand with default
+/// settings, and [`optimize_graph_with_report`] for a version that also reports which fusions fired.
+pub fn optimize_graph(graph: &Graph, settings: OptimizerSettings) -> Graph {
+    optimize_graph_with_report(graph, settings).0
+}
+
+/// As [`optimize_graph`], but also returns an [`OptimizationReport`] tallying which rules actually fired.
+pub fn optimize_graph_with_report(graph: &Graph, settings: OptimizerSettings) -> (Graph, OptimizationReport) {
+    let mut graph = graph.clone();
+    let mut total = OptimizationReport::default();
+
+    for iteration in 1..=settings.max_iterations {
+        total.iterations_run = iteration;
+        let mut pass = OptimizationReport::default();
+
+        if settings.fold_constants {
+            let (next, count) = fold_constants(&graph);
+            graph = next;
+            pass.constants_folded = count;
+        }
+        if settings.fold_affine_into_conv {
+            let (next, count) = fold_affine_into_conv(&graph);
+            graph = next;
+            pass.affines_folded_into_conv = count;
+        }
+        if settings.fuse_conv_epilogue {
+            let (next, count) = fuse_conv_epilogue(&graph);
+            graph = next;
+            pass.conv_epilogues_fused = count;
+        }
+        if settings.eliminate_dead_values {
+            let (next, count) = eliminate_dead_values(&graph);
+            graph = next;
+            pass.dead_values_removed = count;
+        }
+
+        total.constants_folded += pass.constants_folded;
+        total.affines_folded_into_conv += pass.affines_folded_into_conv;
+        total.conv_epilogues_fused += pass.conv_epilogues_fused;
+        total.dead_values_removed += pass.dead_values_removed;
+
+        if !pass.any_rule_fired() {
+            break;
+        }
+    }
+
+    (graph, total)
+}
+
+/// Rebuild `value` in `new_graph` using the same smart constructors [`Graph`]'s public builder API uses
+/// (e.g. [`Graph::view`]'s identity shortcut), rather than raw [`Graph::push`], so a rewrite that
+/// happens to produce a no-op node collapses away for free instead of needing its own dedicated rule.
+fn rebuild_generic(old: &Graph, new_graph: &mut Graph, value: Value, mut map_input: impl FnMut(Value) -> Value) -> Value {
+    let shape = old[value].shape.clone();
+
+    match &old[value].operation {
+        Operation::Input { .. } => new_graph.input(shape),
+        Operation::Constant { data } => new_graph.constant(shape, (&**data).clone()),
+        &Operation::View { input } => new_graph.view(map_input(input), shape),
+        &Operation::Slice { input, axis, start, end } => new_graph.slice(map_input(input), axis, start, end),
+        &Operation::Conv { input, filter, bias, relu, details } => {
+            let input = map_input(input);
+            let filter = map_input(filter);
+            let bias = bias.map(&mut map_input);
+            new_graph.push(shape, Operation::Conv { input, filter, bias, relu, details })
+        }
+        &Operation::Add { left, right, subtract } => {
+            let (left, right) = (map_input(left), map_input(right));
+            if subtract { new_graph.sub(left, right) } else { new_graph.add(left, right) }
+        }
+        &Operation::Mul { left, right } => new_graph.mul(map_input(left), map_input(right)),
+        &Operation::Clamp { input, min, max } => new_graph.clamp(map_input(input), min, max),
+        &Operation::Softmax { input, axis, quiet } => {
+            let input = map_input(input);
+            if quiet { new_graph.quiet_softmax(input, axis) } else { new_graph.softmax(input, axis) }
+        }
+        &Operation::Gather { input, axis, indices } => new_graph.gather(map_input(input), axis, map_input(indices)),
+        &Operation::GatherNd { data, indices, batch_dims } => {
+            new_graph.gather_nd(map_input(data), map_input(indices), batch_dims)
+        }
+        &Operation::Unary { input, op } => new_graph.unary(map_input(input), op),
+        Operation::Permute { input, permutation } => new_graph.permute(map_input(*input), permutation.clone()),
+        &Operation::Round { input } => new_graph.round(map_input(input)),
+        &Operation::Sum { input, axis } => new_graph.sum(map_input(input), axis),
+        &Operation::Compare { left, right, mode } => new_graph.compare(map_input(left), map_input(right), mode),
+        &Operation::Select { cond, on_true, on_false } => {
+            new_graph.select(map_input(cond), map_input(on_true), map_input(on_false))
+        }
+    }
+}
+
+/// One topological-order rebuild pass, generic over a `rewrite` hook that gets first refusal at each
+/// value (with its inputs already remapped into the new graph via `old_to_new`) and can either replace
+/// it outright or defer to [`rebuild_generic`]. Shared by every rule below so each one only has to
+/// express its own pattern.
+fn rebuild_with_rule(
+    graph: &Graph,
+    mut rewrite: impl FnMut(&Graph, &mut Graph, Value, &HashMap<Value, Value>) -> Option<Value>,
+) -> (Graph, usize) {
+    let mut new_graph = Graph::new();
+    let mut old_to_new: HashMap<Value, Value> = HashMap::new();
+    let mut fired = 0;
+
+    for value in graph.values() {
+        let new_value = match rewrite(graph, &mut new_graph, value, &old_to_new) {
+            Some(new_value) => {
+                fired += 1;
+                new_value
+            }
+            None => rebuild_generic(graph, &mut new_graph, value, |input| old_to_new[&input]),
+        };
+        old_to_new.insert(value, new_value);
+    }
+
+    let new_outputs = graph.outputs().iter().map(|v| old_to_new[v]).collect_vec();
+    new_graph.output_all(&new_outputs);
+
+    (new_graph, fired)
+}
+
+/// Rule 1: constant-fold any node whose data inputs are all already `Constant` in the rebuilt graph.
+/// Covers `View`/`Slice`/`Permute`/`Add`/`Mul`/`Clamp`/`Conv`, which is everything [`fold_affine_into_conv`]
+/// and [`fuse_conv_epilogue`] can introduce a new constant producer for; `Gather`/`GatherNd`/`Softmax`/
+/// `Unary` are left to run at evaluation time even when constant, since in practice they show up in
+/// attention/policy heads rather than weight-prep math, so folding them wasn't worth the extra evaluator
+/// branches.
+fn fold_constants(graph: &Graph) -> (Graph, usize) {
+    rebuild_with_rule(graph, |graph, new_graph, value, old_to_new| {
+        let operation = &graph[value].operation;
+        if matches!(operation, Operation::Input { .. } | Operation::Constant { .. }) {
+            return None;
+        }
+
+        let inputs = operation.inputs();
+        if inputs.is_empty() || !inputs.iter().all(|input| new_graph.as_const(old_to_new[input]).is_some()) {
+            return None;
+        }
+
+        let mapped = operation.clone_map_inputs(|input| old_to_new[&input]);
+        let shape = graph[value].shape.clone();
+        let data = eval_constant_op(new_graph, &mapped, &shape)?;
+        Some(new_graph.constant(shape, data))
+    })
+}
+
+/// Evaluates `operation` (whose inputs are already known to be `Constant` in `new_graph`) on the host,
+/// returning its flat output data. `None` for an op this rule doesn't know how to fold (see
+/// [`fold_constants`]'s doc comment), or for a `Conv` [`crate::cpu::convolution`] itself can't run yet:
+/// grouped (`groups > 1`), or one that doesn't keep the input's spatial shape (strided, dilated, or
+/// otherwise not "same"-padded).
+fn eval_constant_op(new_graph: &Graph, operation: &Operation, output_shape: &Shape) -> Option<Vec<f32>> {
+    let output_dims = output_shape.as_fixed()?.dims;
+
+    let as_tensor = |value: Value| -> Tensor {
+        let dims = new_graph[value].shape.as_fixed().expect("constant input must have a fixed shape").dims;
+        let data = new_graph.as_const(value).expect("already checked to be constant").to_vec();
+        Tensor::from_shape_vec(ndarray::IxDyn(&dims), data).unwrap()
+    };
+
+    let result: Tensor = match operation {
+        &Operation::View { input } => as_tensor(input).reshape(ndarray::IxDyn(&output_dims)),
+        &Operation::Slice { input, axis, start, end } => {
+            let input = as_tensor(input);
+            let info = slice_info(input.ndim(), axis, start as isize, Some(end as isize), 1);
+            input.slice(info).to_shared()
+        }
+        Operation::Permute { input, permutation } => as_tensor(*input).view().permuted_axes(permutation.clone()).to_shared(),
+        &Operation::Add { left, right, subtract } => {
+            let (left, right) = (as_tensor(left), as_tensor(right));
+            let result = if subtract { &left - &right } else { &left + &right };
+            result.into_shared()
+        }
+        &Operation::Mul { left, right } => (&as_tensor(left) * &as_tensor(right)).into_shared(),
+        &Operation::Clamp { input, min, max } => as_tensor(input).map(|&x| x.clamp(min, max)).into_shared(),
+        &Operation::Conv { input, filter, bias, relu, details } if details.groups == 1 && details.keeps_spatial_shape() => {
+            let input = as_tensor(input);
+            let filter = as_tensor(filter);
+            let input = input.view().into_dimensionality::<Ix4>().unwrap();
+            let filter = filter.view().into_dimensionality::<Ix4>().unwrap();
+            let mut result = convolution(details, input, filter, Parallelism::None).into_dyn();
+            if let Some(bias) = bias {
+                result = result + &as_tensor(bias);
+            }
+            if relu {
+                result.map_inplace(|x| *x = x.max(0.0));
+            }
+            result.into_shared()
+        }
+        _ => return None,
+    };
+
+    assert_eq!(output_dims.as_slice(), result.shape(), "constant-folded shape mismatch");
+    Some(result.as_standard_layout().iter().copied().collect())
+}
+
+/// Whether `value` is a `Constant` whose shape broadcasts per-channel against a `[N, C, H, W]` conv
+/// input/output (`[1, C, 1, 1]`: every dim `1` except axis 1); returns its `C` channel values.
+fn as_channelwise_const<'a>(graph: &'a Graph, value: Value, channels: usize) -> Option<&'a [f32]> {
+    let shape = graph[value].shape.as_fixed()?;
+    if shape.dims.len() != 4 || shape.dims[0] != 1 || shape.dims[1] != channels || shape.dims[2] != 1 || shape.dims[3] != 1 {
+        return None;
+    }
+    graph.as_const(value)
+}
+
+fn single_use_map(graph: &Graph) -> HashMap<Value, usize> {
+    let mut use_count: HashMap<Value, usize> = HashMap::new();
+    for value in graph.values() {
+        for input in graph[value].operation.inputs() {
+            *use_count.entry(input).or_insert(0) += 1;
+        }
+    }
+    for &output in graph.outputs() {
+        *use_count.entry(output).or_insert(0) += 1;
+    }
+    use_count
+}
+
+/// Rule 3: fold a channelwise affine (`Mul` by `[1, C, 1, 1]`, optionally followed by `Add` by the same
+/// shape) sitting directly before a `Conv` into the conv's filter weights (and, for the `Add`, its bias).
+/// Restricted to `groups == 1`, the same limitation [`crate::cpu::convolution`] already has, to avoid
+/// having to reason about which filter rows a grouped conv's per-group input slice maps to.
+///
+/// Scaling the input by a per-channel constant before convolving is always equivalent to scaling the
+/// corresponding input-channel slice of the filter, regardless of padding: the zero-fill padding a conv
+/// synthesizes around its real input is unaffected either way. Shifting the input by a per-channel
+/// constant is only a true *constant* per-output-channel bias when `padding_w == 0 && padding_h == 0`:
+/// every output position then sums over the full, untruncated kernel. With padding, positions near the
+/// border would need a position-dependent correction this IR has no way to express, so the shift half of
+/// the rule only fires when the conv doesn't pad.
+fn fold_affine_into_conv(graph: &Graph) -> (Graph, usize) {
+    let use_count = single_use_map(graph);
+    let single_use = |v: Value| use_count.get(&v).copied().unwrap_or(0) == 1;
+
+    rebuild_with_rule(graph, |graph, new_graph, value, old_to_new| {
+        let (input, filter, details) = match &graph[value].operation {
+            &Operation::Conv { input, filter, bias: None, relu: false, details } => (input, filter, details),
+            _ => return None,
+        };
+        if details.groups != 1 || !single_use(input) {
+            return None;
+        }
+
+        // peel off an optional preceding `Add` (the shift), then a `Mul` (the scale)
+        let (pre_scale, shift) = match &graph[input].operation {
+            &Operation::Add { left, right, subtract: false } if details.padding_w == 0 && details.padding_h == 0 && single_use(left) => {
+                match as_channelwise_const(graph, right, details.input_channels) {
+                    Some(shift) => (left, Some(shift.to_vec())),
+                    None => return None,
+                }
+            }
+            _ => (input, None),
+        };
+
+        let (conv_input, scale) = match &graph[pre_scale].operation {
+            &Operation::Mul { left, right } => match as_channelwise_const(graph, right, details.input_channels) {
+                Some(scale) => (left, Some(scale.to_vec())),
+                None => return None,
+            },
+            _ => (pre_scale, None),
+        };
+
+        if scale.is_none() && shift.is_none() {
+            return None;
+        }
+
+        let filter_data = graph.as_const(filter)?;
+        let [out_c, in_c, k_w, k_h] = details.kernel_shape();
+        let taps_per_in_channel = k_w * k_h;
+        let filter_per_out_channel = in_c * taps_per_in_channel;
+
+        let scaled_filter: Vec<f32> = match &scale {
+            None => filter_data.to_vec(),
+            Some(scale) => filter_data
+                .iter()
+                .enumerate()
+                .map(|(i, &w)| w * scale[(i / taps_per_in_channel) % in_c])
+                .collect(),
+        };
+
+        let new_bias = shift.map(|shift| {
+            (0..out_c)
+                .map(|o| {
+                    let base = o * filter_per_out_channel;
+                    (0..in_c)
+                        .flat_map(|ci| {
+                            let row = base + ci * taps_per_in_channel;
+                            filter_data[row..row + taps_per_in_channel].iter().map(move |&w| w * shift[ci])
+                        })
+                        .sum()
+                })
+                .collect_vec()
+        });
+
+        let new_input = old_to_new[&conv_input];
+        let new_filter = new_graph.constant(graph[filter].shape.clone(), scaled_filter);
+        let new_bias = new_bias.map(|data| new_graph.constant(Shape::fixed(&[1, out_c, 1, 1]), data));
+
+        Some(new_graph.push(
+            graph[value].shape.clone(),
+            Operation::Conv { input: new_input, filter: new_filter, bias: new_bias, relu: false, details },
+        ))
+    })
+}
+
+/// The epilogue a [`fuse_conv_epilogue`] match would fold a plain `Conv` into: an optional bias (a
+/// channelwise `Add`) and whether a trailing relu `Clamp` also applies.
+struct Epilogue {
+    conv: Value,
+    bias: Option<Value>,
+    relu: bool,
+}
+
+/// A plain (no epilogue yet) `Conv` with exactly one consumer, if `value` is one.
+fn as_fusable_conv(graph: &Graph, value: Value, single_use: impl Fn(Value) -> bool) -> Option<(Value, ConvDetails)> {
+    match &graph[value].operation {
+        &Operation::Conv { bias: None, relu: false, details, .. } if single_use(value) => Some((value, details)),
+        _ => None,
+    }
+}
+
+/// The channelwise-bias `Add` operand of `value`, if it adds a plain conv (on either side) to a constant
+/// matching that conv's output-channel count.
+fn as_conv_bias_add(graph: &Graph, value: Value, single_use: impl Fn(Value) -> bool) -> Option<(Value, Value)> {
+    let (left, right) = match &graph[value].operation {
+        &Operation::Add { left, right, subtract: false } => (left, right),
+        _ => return None,
+    };
+
+    for (conv, bias) in [(left, right), (right, left)] {
+        if let Some((conv, details)) = as_fusable_conv(graph, conv, &single_use) {
+            if as_channelwise_const(graph, bias, details.output_channels).is_some() {
+                return Some((conv, bias));
+            }
+        }
+    }
+    None
+}
+
+/// Rule 2: fuse a bias `Add` and/or relu `Clamp(0, +inf)` that immediately follows a plain `Conv` (one
+/// with no epilogue fused in yet) into the conv's own `bias`/`relu` fields, provided the intermediate
+/// value(s) have no other consumer (otherwise eliding them would change what that other consumer sees).
+/// The absorbed `Conv`/`Add` nodes are simply left in place as ordinary (now-dead) values; the next
+/// [`eliminate_dead_values`] pass in the same fixpoint iteration sweeps them away.
+fn fuse_conv_epilogue(graph: &Graph) -> (Graph, usize) {
+    let use_count = single_use_map(graph);
+    let single_use = |v: Value| use_count.get(&v).copied().unwrap_or(0) == 1;
+
+    // precomputed over the *original* graph, since the conv/bias a fused value absorbs must be looked
+    // up before the rewrite below has rebuilt them
+    let mut epilogue_of: HashMap<Value, Epilogue> = HashMap::new();
+    for value in graph.values() {
+        match &graph[value].operation {
+            &Operation::Clamp { input, min, max } if min == 0.0 && max == f32::INFINITY => {
+                if let Some((conv, _)) = as_fusable_conv(graph, input, &single_use) {
+                    epilogue_of.insert(value, Epilogue { conv, bias: None, relu: true });
+                } else if single_use(input) {
+                    if let Some((conv, bias)) = as_conv_bias_add(graph, input, &single_use) {
+                        epilogue_of.insert(value, Epilogue { conv, bias: Some(bias), relu: true });
+                    }
+                }
+            }
+            Operation::Add { .. } => {
+                if let Some((conv, bias)) = as_conv_bias_add(graph, value, &single_use) {
+                    epilogue_of.insert(value, Epilogue { conv, bias: Some(bias), relu: false });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    rebuild_with_rule(graph, |graph, new_graph, value, old_to_new| {
+        let epilogue = epilogue_of.get(&value)?;
+        let (input, filter, details) = match &graph[epilogue.conv].operation {
+            &Operation::Conv { input, filter, details, .. } => (input, filter, details),
+            _ => unreachable!("fused-epilogue target was checked to be a plain Conv above"),
+        };
+
+        Some(new_graph.push(
+            graph[value].shape.clone(),
+            Operation::Conv {
+                input: old_to_new[&input],
+                filter: old_to_new[&filter],
+                bias: epilogue.bias.map(|b| old_to_new[&b]),
+                relu: epilogue.relu,
+                details,
+            },
+        ))
+    })
+}
+
+/// Rule 4: drop every value not reachable from `outputs`, by rebuilding only the reachable subset in
+/// topological order. [`Graph::view`]/[`Graph::slice`]'s own identity shortcuts (exercised via
+/// [`rebuild_generic`]) already collapse an identity `View`/`Slice` down to its input as it's copied
+/// over, so this single walk covers both halves of the original rule.
+fn eliminate_dead_values(graph: &Graph) -> (Graph, usize) {
+    let mut reachable: HashSet<Value> = HashSet::new();
+    let mut stack = graph.outputs().to_vec();
+    while let Some(value) = stack.pop() {
+        if reachable.insert(value) {
+            stack.extend(graph[value].operation.inputs());
+        }
+    }
+
+    let total = graph.values().count();
+    let removed = total - reachable.len();
+    if removed == 0 {
+        return (graph.clone(), 0);
+    }
+
+    let mut new_graph = Graph::new();
+    let mut old_to_new: HashMap<Value, Value> = HashMap::new();
+    for value in graph.values() {
+        if reachable.contains(&value) {
+            let new_value = rebuild_generic(graph, &mut new_graph, value, |input| old_to_new[&input]);
+            old_to_new.insert(value, new_value);
+        }
+    }
+
+    let new_outputs = graph.outputs().iter().map(|v| old_to_new[v]).collect_vec();
+    new_graph.output_all(&new_outputs);
+
+    (new_graph, removed)
+}
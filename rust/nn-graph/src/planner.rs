@@ -0,0 +1,263 @@
+use std::collections::{HashMap, HashSet};
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+
+use crate::graph::{Graph, Operation, Value};
+use crate::shape::ConcreteShape;
+
+/// Where a value's output tensor should be written during execution.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BufferSlot {
+    /// Allocate a fresh buffer.
+    Fresh,
+    /// Write directly into `producer`'s buffer. Only assigned when `producer` has exactly this value
+    /// as its single consumer and this value post-dominates `producer`, so nothing will ever read
+    /// `producer`'s old contents again.
+    InPlace { producer: Value },
+    /// Reuse the buffer `source` held. Only assigned once every consumer of `source` has already run
+    /// (see [`MemoryPlan::free_after`]), handed out of the shape-keyed free list instead of allocating
+    /// new memory.
+    Recycled { source: Value },
+}
+
+/// A memory plan for one `(graph, batch_size)` pair: which [`BufferSlot`] each value should occupy.
+///
+/// Built from an *immediate post-dominator* analysis of the value DAG: a virtual sink node that every
+/// graph output points to, with the immediate post-dominator of each value computed via the standard
+/// iterative reverse-postorder fixed-point algorithm (Cooper, Harvey & Kennedy) run on the graph with
+/// its edges reversed and rooted at that sink. A value whose single consumer post-dominates it, and
+/// whose consumer is an elementwise op of matching shape, is written in place over that value's own
+/// buffer; every other value is freed for reuse at its last consumer (in execution order) and handed
+/// to a free list keyed by [`ConcreteShape`], to be recycled by a later value of the same shape instead
+/// of allocating a new buffer.
+///
+/// Invariant: a buffer is only ever reused once every transitive consumer of its previous occupant has
+/// executed, since `free_after` is computed from the *last* consumer in [`Graph::values`] order and
+/// in-place writes are only chosen when there is exactly one consumer to begin with.
+///
+/// **Not currently consulted by any executor.** `cpu_execute_graph` in `cpu.rs` is the only plausible
+/// caller, but its match arms are written against an `Operation` vocabulary (`Broadcast`/`Flip`/
+/// `Concat`/`MatMul`/`Element`, a `Slice` keyed by `range` rather than `start`/`end`, ...) that doesn't
+/// correspond to a single variant of the `Operation` enum actually defined in `graph.rs` — this
+/// predates this type and isn't something introduced by it. Reconciling the two is a rewrite of
+/// `cpu_execute_graph` from scratch, well beyond a memory planner's scope, so for now this only exists
+/// to be called directly by anyone who wants the reuse plan for a given `(graph, batch_size)`.
+#[derive(Debug)]
+pub struct MemoryPlan {
+    pub slots: IndexMap<Value, BufferSlot>,
+    /// Execution-order index (into `graph.values()`) after which a value's buffer becomes free, for
+    /// every value whose buffer ends up on the free list (i.e. excludes graph outputs, and values
+    /// donated to an [`BufferSlot::InPlace`] consumer, both of which are never freed independently).
+    pub free_after: IndexMap<Value, usize>,
+}
+
+impl MemoryPlan {
+    pub fn slot(&self, value: Value) -> &BufferSlot {
+        self.slots.get(&value).expect("value not part of this plan")
+    }
+
+    /// Builds the plan for `graph` as it would execute with batch size `batch_size` (needed to resolve
+    /// symbolic shapes down to the concrete ones the free list is keyed on).
+    pub fn build(graph: &Graph, batch_size: usize) -> MemoryPlan {
+        let order = graph.values().collect_vec();
+        let index_of: HashMap<Value, usize> = order.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+
+        let mut consumers: IndexMap<Value, Vec<Value>> = order.iter().map(|&v| (v, Vec::new())).collect();
+        for &v in &order {
+            for input in graph[v].operation.inputs() {
+                consumers
+                    .get_mut(&input)
+                    .expect("every operand must already have an entry, since it's computed earlier")
+                    .push(v);
+            }
+        }
+
+        let outputs: HashSet<Value> = graph.outputs().iter().copied().collect();
+        let ipdom = immediate_post_dominators(graph, &order, &index_of, &consumers);
+
+        // values whose single consumer is allowed to overwrite their buffer in place
+        let mut inplace_producer: HashMap<Value, Value> = HashMap::new();
+        let mut donated: HashSet<Value> = HashSet::new();
+
+        for &v in &order {
+            if outputs.contains(&v) {
+                continue;
+            }
+            if let [consumer] = consumers.get(&v).unwrap().as_slice() {
+                let consumer = *consumer;
+                let shapes_match = graph[v].shape.eval(batch_size) == graph[consumer].shape.eval(batch_size);
+                let postdominates = ipdom.get(&v) == Some(&consumer);
+
+                if shapes_match
+                    && postdominates
+                    && inplace_operand(&graph[consumer].operation) == Some(v)
+                    && !inplace_producer.contains_key(&consumer)
+                {
+                    inplace_producer.insert(consumer, v);
+                    donated.insert(v);
+                }
+            }
+        }
+
+        let last_consumer_index: HashMap<Value, usize> = consumers
+            .iter()
+            .filter_map(|(&v, cs)| cs.iter().map(|c| index_of[c]).max().map(|last| (v, last)))
+            .collect();
+
+        let mut slots: IndexMap<Value, BufferSlot> = IndexMap::default();
+        let mut free_after: IndexMap<Value, usize> = IndexMap::default();
+        let mut free_list: HashMap<ConcreteShape, Vec<Value>> = HashMap::new();
+
+        for (i, &v) in order.iter().enumerate() {
+            let slot = if let Some(&producer) = inplace_producer.get(&v) {
+                BufferSlot::InPlace { producer }
+            } else {
+                let shape = graph[v].shape.eval(batch_size);
+                match free_list.get_mut(&shape).and_then(Vec::pop) {
+                    Some(source) => BufferSlot::Recycled { source },
+                    None => BufferSlot::Fresh,
+                }
+            };
+            slots.insert(v, slot);
+
+            // now that `v` has run, any operand this was the last consumer of becomes free, unless its
+            // buffer already lives on under a donated in-place consumer, or it's a graph output that
+            // must survive until the caller reads it back out
+            for input in graph[v].operation.inputs() {
+                if donated.contains(&input) || outputs.contains(&input) {
+                    continue;
+                }
+                if last_consumer_index.get(&input) == Some(&i) {
+                    free_after.insert(input, i);
+                    let shape = graph[input].shape.eval(batch_size);
+                    free_list.entry(shape).or_default().push(input);
+                }
+            }
+        }
+
+        MemoryPlan { slots, free_after }
+    }
+}
+
+/// Whether `op` is a shape-preserving elementwise op, and if so which one of its operands could be
+/// overwritten in place to produce its result (the accumulator side of an `Add`/`Mul`, or the sole
+/// input of a `Clamp`/`Unary`). `Softmax` is shape-preserving too but reduces over a whole axis, so
+/// it's deliberately excluded here rather than treated as a safe in-place candidate.
+fn inplace_operand(op: &Operation) -> Option<Value> {
+    match op {
+        &Operation::Add { left, .. } => Some(left),
+        &Operation::Mul { left, .. } => Some(left),
+        &Operation::Clamp { input, .. } => Some(input),
+        &Operation::Unary { input, .. } => Some(input),
+        _ => None,
+    }
+}
+
+/// Computes the immediate post-dominator of every value reachable from a graph output, via the
+/// standard iterative fixed-point dominator algorithm run on the reversed graph (edges flipped,
+/// rooted at a virtual sink every output points to) — see the [`MemoryPlan`] docs for why that turns
+/// "dominators of the reversed graph" into "post-dominators of the original one".
+fn immediate_post_dominators(
+    graph: &Graph,
+    order: &[Value],
+    index_of: &HashMap<Value, usize>,
+    consumers: &IndexMap<Value, Vec<Value>>,
+) -> HashMap<Value, Value> {
+    let n = order.len();
+    let sink = n;
+    let outputs = graph.outputs();
+
+    let succs_rev = |idx: usize| -> Vec<usize> {
+        if idx == sink {
+            outputs.iter().map(|o| index_of[o]).collect()
+        } else {
+            graph[order[idx]]
+                .operation
+                .inputs()
+                .iter()
+                .map(|i| index_of[i])
+                .collect()
+        }
+    };
+    let preds_rev = |idx: usize| -> Vec<usize> {
+        if idx == sink {
+            vec![]
+        } else {
+            let v = order[idx];
+            let mut preds: Vec<usize> = consumers.get(&v).unwrap().iter().map(|c| index_of[c]).collect();
+            if outputs.contains(&v) {
+                preds.push(sink);
+            }
+            preds
+        }
+    };
+
+    // iterative postorder DFS over the reversed graph, rooted at the virtual sink
+    let mut postorder = Vec::with_capacity(n + 1);
+    let mut visited = vec![false; n + 1];
+    let mut stack: Vec<(usize, usize)> = vec![(sink, 0)];
+    visited[sink] = true;
+
+    while let Some(&mut (node, ref mut next_child)) = stack.last_mut() {
+        let succs = succs_rev(node);
+        if *next_child < succs.len() {
+            let child = succs[*next_child];
+            *next_child += 1;
+            if !visited[child] {
+                visited[child] = true;
+                stack.push((child, 0));
+            }
+        } else {
+            postorder.push(node);
+            stack.pop();
+        }
+    }
+
+    let postorder_number: HashMap<usize, usize> = postorder.iter().enumerate().map(|(num, &node)| (node, num)).collect();
+    let rpo: Vec<usize> = postorder.iter().rev().copied().collect();
+
+    let intersect = |mut u: usize, mut v: usize, idom: &HashMap<usize, usize>| -> usize {
+        while u != v {
+            while postorder_number[&u] < postorder_number[&v] {
+                u = idom[&u];
+            }
+            while postorder_number[&v] < postorder_number[&u] {
+                v = idom[&v];
+            }
+        }
+        u
+    };
+
+    let mut idom: HashMap<usize, usize> = HashMap::new();
+    idom.insert(sink, sink);
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for &node in rpo.iter().filter(|&&node| node != sink) {
+            let mut new_idom: Option<usize> = None;
+            for p in preds_rev(node) {
+                if idom.contains_key(&p) {
+                    new_idom = Some(match new_idom {
+                        None => p,
+                        Some(cur) => intersect(cur, p, &idom),
+                    });
+                }
+            }
+
+            if let Some(new_idom) = new_idom {
+                if idom.get(&node) != Some(&new_idom) {
+                    idom.insert(node, new_idom);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    idom.into_iter()
+        .filter(|&(node, dom)| node != sink && dom != sink)
+        .map(|(node, dom)| (order[node], order[dom]))
+        .collect()
+}
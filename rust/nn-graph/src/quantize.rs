@@ -0,0 +1,431 @@
+//! Post-training fixed-point quantization: lowers a float [`Graph`] into an equivalent one where every
+//! value on the `Conv`/`Add`/`Clamp` backbone is constrained to the integer lattice of a `bits`-wide
+//! symmetric quantization scheme, with `Conv`'s output requantized by a fixed-point multiply-and-shift
+//! (an integer `multiplier` plus a `shift`) rather than a plain float rescale. The returned graph is
+//! still `nn-graph`'s ordinary `f32` IR — there's no integer tensor type to lower into — so every
+//! quantized value only ever holds an integer *in* that `f32`. The conv accumulation, additions and
+//! clamps match what a true integer backend (e.g. a CUDA int8 kernel, or an ezkl/zk circuit) would
+//! compute as long as their `f32` accumulators stay under 2^24 in magnitude (the point `f32` can no
+//! longer represent every integer exactly). The requantization itself is evaluated in `f32` too and is
+//! not bit-exact; see [`requantize_value`]. `Softmax`/`Gather`/`GatherNd`/`Unary`/`Sum` sit outside the quantized backbone, same as
+//! [`crate::optimizer::fold_constants`]'s rationale for not folding them: they show up in attention/policy
+//! heads rather than conv-heavy weight math, so a value flowing through one is dequantized back to float,
+//! run as-is, and requantized again (from a freshly computed scale) if something quantized consumes it.
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::graph::{Graph, Operation, Value};
+use crate::shape::Shape;
+
+/// The observed (or propagated) float range of one value, used to pick its quantization scale.
+#[derive(Debug, Copy, Clone)]
+pub struct ValueRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ValueRange {
+    fn bound(self) -> f32 {
+        self.min.abs().max(self.max.abs())
+    }
+}
+
+/// Calibration data for [`Graph::quantize`]: the observed min/max of every `Input` value, gathered by
+/// running representative data through the float graph beforehand. Every other value's range is derived
+/// from these (and from `Constant` data) by interval arithmetic over the graph; see [`infer_ranges`].
+#[derive(Debug, Clone, Default)]
+pub struct QuantParams {
+    /// Keyed by `Operation::Input { index }`, not by `Value` (which is graph-specific).
+    pub input_ranges: HashMap<usize, ValueRange>,
+}
+
+/// One value's quantization parameters: `real = (quantized - zero_point) * scale`.
+#[derive(Debug, Copy, Clone)]
+pub struct QuantSpec {
+    pub scale: f32,
+    /// Always `0` today: every value in this pass is quantized symmetrically. Kept as its own field
+    /// (rather than folded into `scale`) so an asymmetric scheme for a never-negative post-relu
+    /// activation could be added later without reshaping the table.
+    pub zero_point: i32,
+    /// The largest positive code this spec's bit width can represent: `2^(bits-1) - 1` (the most
+    /// negative one is `-qmax - 1`).
+    pub qmax: i32,
+}
+
+impl QuantSpec {
+    /// Picks a symmetric scale covering `range`, in a `bits`-wide two's-complement code.
+    fn for_range(range: ValueRange, bits: u8) -> QuantSpec {
+        let qmax = (1i32 << (bits - 1)) - 1;
+        let bound = range.bound().max(f32::MIN_POSITIVE);
+        QuantSpec { scale: bound / qmax as f32, zero_point: 0, qmax }
+    }
+
+    fn code_min(self) -> f32 {
+        -(self.qmax as f32) - 1.0
+    }
+
+    fn code_max(self) -> f32 {
+        self.qmax as f32
+    }
+}
+
+/// Every quantized value's [`QuantSpec`], keyed by the *original* graph's `Value`s (the returned graph
+/// has different indices after rewriting). A value absent from this table was left unquantized — it
+/// flows through [`Graph::quantize`]'s output as plain `f32`, same as in the input graph.
+#[derive(Debug, Clone)]
+pub struct QuantizationTable {
+    pub bits: u8,
+    pub specs: HashMap<Value, QuantSpec>,
+}
+
+/// An exact fixed-point approximation of a positive ratio, the standard gemmlowp/TFLite trick for
+/// requantizing an `int32` accumulator without ever computing in floating point: normalize the ratio
+/// into a 31-bit fixed-point `multiplier` in `[2^30, 2^31)` plus a `shift` such that
+/// `ratio ≈ multiplier * 2^(shift - 31)`, so applying it is one integer multiply and one rounding shift.
+#[derive(Debug, Copy, Clone)]
+pub struct FixedPointMultiplier {
+    pub multiplier: i32,
+    pub shift: i32,
+}
+
+impl FixedPointMultiplier {
+    pub fn from_ratio(ratio: f64) -> FixedPointMultiplier {
+        assert!(ratio > 0.0, "Requantization ratio must be positive, got {}", ratio);
+
+        let mut ratio = ratio;
+        let mut shift = 0;
+        while ratio < 0.5 {
+            ratio *= 2.0;
+            shift -= 1;
+        }
+        while ratio >= 1.0 {
+            ratio /= 2.0;
+            shift += 1;
+        }
+
+        // ratio is now in [0.5, 1.0), so this fits comfortably in an i32
+        let multiplier = (ratio * (1i64 << 31) as f64).round() as i32;
+        FixedPointMultiplier { multiplier, shift }
+    }
+
+    /// `x * multiplier` still carries an implicit `2^-31` plus this pass's own `2^shift`; net right-shift
+    /// still owed to recover the plain integer result.
+    fn net_shift(self) -> i32 {
+        31 - self.shift
+    }
+}
+
+/// Infers every value's [`ValueRange`] by a single forward interval-arithmetic pass: exact for
+/// `Constant`, taken from `calibration` for `Input`, and conservatively widened (rather than tightly
+/// tracked) for everything downstream, since this pass only needs a safe scale to quantize into, not the
+/// tightest one.
+fn infer_ranges(graph: &Graph, calibration: &QuantParams) -> HashMap<Value, ValueRange> {
+    let mut ranges: HashMap<Value, ValueRange> = HashMap::new();
+
+    for value in graph.values() {
+        let range = match &graph[value].operation {
+            &Operation::Input { index } => *calibration
+                .input_ranges
+                .get(&index)
+                .unwrap_or_else(|| panic!("No calibration range supplied for input {}", index)),
+            Operation::Constant { data } => {
+                let (min, max) = data.iter().copied().minmax().into_option().unwrap_or((0.0, 0.0));
+                ValueRange { min, max }
+            }
+            // shape-only rearrangements and rounding don't change the value's range
+            &Operation::View { input }
+            | &Operation::Slice { input, .. }
+            | &Operation::Permute { input, .. }
+            | &Operation::Round { input } => ranges[&input],
+            &Operation::Add { left, right, subtract } => {
+                let (a, b) = (ranges[&left], ranges[&right]);
+                if subtract {
+                    ValueRange { min: a.min - b.max, max: a.max - b.min }
+                } else {
+                    ValueRange { min: a.min + b.min, max: a.max + b.max }
+                }
+            }
+            &Operation::Mul { left, right } => {
+                let (a, b) = (ranges[&left], ranges[&right]);
+                let corners = [a.min * b.min, a.min * b.max, a.max * b.min, a.max * b.max];
+                ValueRange {
+                    min: corners.iter().copied().fold(f32::INFINITY, f32::min),
+                    max: corners.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+                }
+            }
+            &Operation::Clamp { input, min, max } => {
+                let a = ranges[&input];
+                ValueRange { min: a.min.max(min), max: a.max.min(max) }
+            }
+            &Operation::Conv { input, filter, bias, relu, details } => {
+                let (a, f) = (ranges[&input], ranges[&filter]);
+                let taps = (details.kernel_w * details.kernel_h * details.input_channels / details.groups) as f32;
+                let mut bound = taps * a.bound() * f.bound();
+                if let Some(bias) = bias {
+                    bound += ranges[&bias].bound();
+                }
+                let min = if relu { 0.0 } else { -bound };
+                ValueRange { min, max: bound }
+            }
+            // outside the quantized backbone: keep a float range around in case a later quantized op
+            // consumes this value, but this pass never needs it to be tight
+            Operation::Gather { input, .. } => ranges[input],
+            // softmax lies in [0, 1], sigmoid in (0, 1) and tanh in (-1, 1), whatever the input's range
+            Operation::Softmax { .. } => ValueRange { min: 0.0, max: 1.0 },
+            Operation::Unary { .. } => ValueRange { min: -1.0, max: 1.0 },
+            // widen by the number of terms being added together, same worst-case reasoning as `Add`
+            &Operation::Sum { input, axis } => {
+                let a = ranges[&input];
+                let n = graph[input].shape[axis].unwrap_fixed("Sum axis") as f32;
+                ValueRange { min: n * a.min.min(0.0), max: n * a.max.max(0.0) }
+            }
+            &Operation::GatherNd { data, .. } => ranges[&data],
+            // a 0.0/1.0 mask, not derived from either operand's range
+            Operation::Compare { .. } => ValueRange { min: 0.0, max: 1.0 },
+            &Operation::Select { on_true, on_false, .. } => {
+                let (t, f) = (ranges[&on_true], ranges[&on_false]);
+                ValueRange { min: t.min.min(f.min), max: t.max.max(f.max) }
+            }
+        };
+        ranges.insert(value, range);
+    }
+
+    ranges
+}
+
+/// Emits `round(clamp(x / spec.scale, code_min, code_max))`: the integer code (still stored as an exact
+/// `f32`) that `spec` assigns to the real value currently held by `x`.
+fn quantize_value(new_graph: &mut Graph, x: Value, spec: QuantSpec) -> Value {
+    let inv_scale = new_graph.constant(Shape::SCALAR, vec![1.0 / spec.scale]);
+    let scaled = new_graph.mul(x, inv_scale);
+    let clamped = new_graph.clamp(scaled, spec.code_min(), spec.code_max());
+    new_graph.round(clamped)
+}
+
+/// Emits `code * spec.scale`: the real value `spec`'s integer code `code` represents.
+fn dequantize_value(new_graph: &mut Graph, code: Value, spec: QuantSpec) -> Value {
+    let scale = new_graph.constant(Shape::SCALAR, vec![spec.scale]);
+    new_graph.mul(code, scale)
+}
+
+/// Applies a [`FixedPointMultiplier`] to an already-integer-valued `x`, returning the rounded, rescaled
+/// integer code `ratio * x` would have produced (see [`FixedPointMultiplier`]'s doc comment). The
+/// multiplier (in `[2^30, 2^31)`) does not fit `f32`'s 24-bit mantissa and `x * multiplier` lies far past
+/// 2^24, so both are rounded in `f32` before the final `round`: the result approximates the integer
+/// multiply-and-shift and is not guaranteed to match it bit for bit.
+fn requantize_value(new_graph: &mut Graph, x: Value, multiplier: FixedPointMultiplier) -> Value {
+    let m = new_graph.constant(Shape::SCALAR, vec![multiplier.multiplier as f32]);
+    let scaled = new_graph.mul(x, m);
+    let divisor = new_graph.constant(Shape::SCALAR, vec![2f32.powi(-multiplier.net_shift())]);
+    let divided = new_graph.mul(scaled, divisor);
+    new_graph.round(divided)
+}
+
+/// Tracks, per original [`Value`], either its quantized integer code (and the [`QuantSpec`] it was coded
+/// with) or — for a value left outside the quantized backbone — its plain dequantized float.
+#[derive(Copy, Clone)]
+enum Lowered {
+    Quantized(Value, QuantSpec),
+    Float(Value),
+}
+
+/// Resolves an already-processed operand to its float value in `new_graph`, dequantizing first if it
+/// was quantized.
+fn as_float(new_graph: &mut Graph, lowered: &HashMap<Value, Lowered>, v: Value) -> Value {
+    match lowered[&v] {
+        Lowered::Float(x) => x,
+        Lowered::Quantized(code, spec) => dequantize_value(new_graph, code, spec),
+    }
+}
+
+/// Resolves an already-processed operand to an integer code in `new_graph`, computing (and caching into
+/// `lowered`/`table`) a fresh spec for it on the fly if it was only ever float so far.
+fn as_quantized(
+    new_graph: &mut Graph,
+    lowered: &mut HashMap<Value, Lowered>,
+    table: &mut QuantizationTable,
+    ranges: &HashMap<Value, ValueRange>,
+    bits: u8,
+    v: Value,
+) -> (Value, QuantSpec) {
+    match lowered[&v] {
+        Lowered::Quantized(code, spec) => (code, spec),
+        Lowered::Float(x) => {
+            let spec = QuantSpec::for_range(ranges[&v], bits);
+            let code = quantize_value(new_graph, x, spec);
+            lowered.insert(v, Lowered::Quantized(code, spec));
+            table.specs.insert(v, spec);
+            (code, spec)
+        }
+    }
+}
+
+/// Lowers `graph` into an equivalent graph whose `Conv`/`Add`/`Clamp` backbone runs in a `bits`-wide
+/// symmetric fixed-point domain, per this module's doc comment. See [`Graph::quantize`] for the
+/// method-call shorthand.
+pub fn quantize_graph(graph: &Graph, bits: u8, calibration: &QuantParams) -> (Graph, QuantizationTable) {
+    assert!((2..=31).contains(&bits), "Unsupported bit width {}", bits);
+
+    let ranges = infer_ranges(graph, calibration);
+
+    let mut new_graph = Graph::new();
+    let mut lowered: HashMap<Value, Lowered> = HashMap::new();
+    let mut table = QuantizationTable { bits, specs: HashMap::new() };
+
+    for value in graph.values() {
+        let shape = graph[value].shape.clone();
+        let mut quantize_operand = |new_graph: &mut Graph, lowered: &mut HashMap<Value, Lowered>, table: &mut QuantizationTable, v: Value| {
+            as_quantized(new_graph, lowered, table, &ranges, bits, v)
+        };
+
+        let result = match &graph[value].operation {
+            &Operation::Input { .. } => {
+                let x = new_graph.input(shape);
+                let spec = QuantSpec::for_range(ranges[&value], bits);
+                let code = quantize_value(&mut new_graph, x, spec);
+                table.specs.insert(value, spec);
+                Lowered::Quantized(code, spec)
+            }
+            Operation::Constant { data } => {
+                let spec = QuantSpec::for_range(ranges[&value], bits);
+                let codes = data.iter().map(|&x| (x / spec.scale).round()).collect_vec();
+                let code = new_graph.constant(shape, codes);
+                table.specs.insert(value, spec);
+                Lowered::Quantized(code, spec)
+            }
+            &Operation::Conv { input, filter, bias, relu, details } => {
+                let (input_code, input_spec) = quantize_operand(&mut new_graph, &mut lowered, &mut table, input);
+                let (filter_code, filter_spec) = quantize_operand(&mut new_graph, &mut lowered, &mut table, filter);
+
+                let acc = new_graph.push(shape.clone(), Operation::Conv {
+                    input: input_code,
+                    filter: filter_code,
+                    bias: None,
+                    relu: false,
+                    details,
+                });
+
+                // the bias, if any, is quantized to the accumulator's own scale (input_scale *
+                // filter_scale) — the standard trick that lets it add directly into the int32
+                // accumulator with no rescaling of its own
+                let acc = match bias {
+                    None => acc,
+                    Some(bias) => {
+                        let bias_data = graph.as_const(bias).expect("Conv bias must be a constant");
+                        let bias_scale = input_spec.scale as f64 * filter_spec.scale as f64;
+                        let bias_codes = bias_data.iter().map(|&b| (b as f64 / bias_scale).round() as f32).collect_vec();
+                        let bias_code = new_graph.constant(graph[bias].shape.clone(), bias_codes);
+                        new_graph.add(acc, bias_code)
+                    }
+                };
+
+                let out_spec = QuantSpec::for_range(ranges[&value], bits);
+                let ratio = input_spec.scale as f64 * filter_spec.scale as f64 / out_spec.scale as f64;
+                let multiplier = FixedPointMultiplier::from_ratio(ratio);
+                let requantized = requantize_value(&mut new_graph, acc, multiplier);
+                let clamped = new_graph.clamp(
+                    requantized,
+                    if relu { 0.0 } else { out_spec.code_min() },
+                    out_spec.code_max(),
+                );
+
+                table.specs.insert(value, out_spec);
+                Lowered::Quantized(clamped, out_spec)
+            }
+            &Operation::Add { left, right, subtract } => {
+                let (left_code, left_spec) = quantize_operand(&mut new_graph, &mut lowered, &mut table, left);
+                let (right_code, right_spec) = quantize_operand(&mut new_graph, &mut lowered, &mut table, right);
+                let out_spec = QuantSpec::for_range(ranges[&value], bits);
+
+                // rescale both operands to the output's own scale before adding, rather than inventing a
+                // third shared accumulator scale just for this op
+                let left_mult = FixedPointMultiplier::from_ratio(left_spec.scale as f64 / out_spec.scale as f64);
+                let right_mult = FixedPointMultiplier::from_ratio(right_spec.scale as f64 / out_spec.scale as f64);
+                let left_rescaled = requantize_value(&mut new_graph, left_code, left_mult);
+                let right_rescaled = requantize_value(&mut new_graph, right_code, right_mult);
+
+                let sum = if subtract {
+                    new_graph.sub(left_rescaled, right_rescaled)
+                } else {
+                    new_graph.add(left_rescaled, right_rescaled)
+                };
+                let clamped = new_graph.clamp(sum, out_spec.code_min(), out_spec.code_max());
+
+                table.specs.insert(value, out_spec);
+                Lowered::Quantized(clamped, out_spec)
+            }
+            &Operation::Clamp { input, min, max } => {
+                let (input_code, input_spec) = quantize_operand(&mut new_graph, &mut lowered, &mut table, input);
+                let code_min = (min / input_spec.scale).round().max(input_spec.code_min());
+                let code_max = (max / input_spec.scale).round().min(input_spec.code_max());
+                let clamped = new_graph.clamp(input_code, code_min, code_max);
+
+                table.specs.insert(value, input_spec);
+                Lowered::Quantized(clamped, input_spec)
+            }
+            // everything else sits outside the quantized backbone: dequantize any quantized operand,
+            // rebuild the op in plain float, and let a later quantized consumer requantize the result
+            &Operation::View { input } => Lowered::Float(new_graph.view(as_float(&mut new_graph, &lowered, input), shape)),
+            &Operation::Slice { input, axis, start, end } => {
+                let input = as_float(&mut new_graph, &lowered, input);
+                Lowered::Float(new_graph.slice(input, axis, start, end))
+            }
+            Operation::Permute { input, permutation } => {
+                let input = as_float(&mut new_graph, &lowered, *input);
+                Lowered::Float(new_graph.permute(input, permutation.clone()))
+            }
+            &Operation::Round { input } => Lowered::Float(new_graph.round(as_float(&mut new_graph, &lowered, input))),
+            &Operation::Sum { input, axis } => Lowered::Float(new_graph.sum(as_float(&mut new_graph, &lowered, input), axis)),
+            &Operation::Softmax { input, axis, quiet } => {
+                let input = as_float(&mut new_graph, &lowered, input);
+                let output = if quiet { new_graph.quiet_softmax(input, axis) } else { new_graph.softmax(input, axis) };
+                Lowered::Float(output)
+            }
+            &Operation::Gather { input, axis, indices } => {
+                let input = as_float(&mut new_graph, &lowered, input);
+                let indices = as_float(&mut new_graph, &lowered, indices);
+                Lowered::Float(new_graph.gather(input, axis, indices))
+            }
+            &Operation::GatherNd { data, indices, batch_dims } => {
+                let data = as_float(&mut new_graph, &lowered, data);
+                let indices = as_float(&mut new_graph, &lowered, indices);
+                Lowered::Float(new_graph.gather_nd(data, indices, batch_dims))
+            }
+            &Operation::Unary { input, op } => Lowered::Float(new_graph.unary(as_float(&mut new_graph, &lowered, input), op)),
+            &Operation::Mul { left, right } => {
+                // only ever appears here as part of a channelwise-affine prelude the Conv/Add cases
+                // above don't special-case; treat it like any other pass-through elementwise op
+                let left = as_float(&mut new_graph, &lowered, left);
+                let right = as_float(&mut new_graph, &lowered, right);
+                Lowered::Float(new_graph.mul(left, right))
+            }
+            &Operation::Compare { left, right, mode } => {
+                let left = as_float(&mut new_graph, &lowered, left);
+                let right = as_float(&mut new_graph, &lowered, right);
+                Lowered::Float(new_graph.compare(left, right, mode))
+            }
+            &Operation::Select { cond, on_true, on_false } => {
+                let cond = as_float(&mut new_graph, &lowered, cond);
+                let on_true = as_float(&mut new_graph, &lowered, on_true);
+                let on_false = as_float(&mut new_graph, &lowered, on_false);
+                Lowered::Float(new_graph.select(cond, on_true, on_false))
+            }
+        };
+
+        lowered.insert(value, result);
+    }
+
+    let new_outputs = graph
+        .outputs()
+        .iter()
+        .map(|&v| match lowered[&v] {
+            Lowered::Float(x) => x,
+            Lowered::Quantized(code, spec) => dequantize_value(&mut new_graph, code, spec),
+        })
+        .collect_vec();
+    new_graph.output_all(&new_outputs);
+
+    (new_graph, table)
+}
@@ -15,11 +15,143 @@ type Tensor4 = ArcArray<f32, Ix4>;
 const VERTICAL_PADDING: usize = 5;
 const HORIZONTAL_PADDING: usize = 5;
 
+/// One row of the per-channel activation-statistics export returned alongside the images by
+/// [`visualize_graph_activations`]: summary stats for a single `(value_name, channel, w, h)` location,
+/// aggregated across the batch axis. `sat_frac` is the fraction of the batch with `|f| >= 1.0`, a cheap
+/// proxy for dead/saturated channels.
+#[derive(Debug, Clone)]
+pub struct ActivationStatsRow {
+    pub value_name: String,
+    pub channel: usize,
+    pub w: usize,
+    pub h: usize,
+    pub mean: f32,
+    pub std: f32,
+    pub min: f32,
+    pub max: f32,
+    pub sat_frac: f32,
+}
+
+impl ActivationStatsRow {
+    const CSV_HEADER: &'static str = "value_name,channel,w,h,mean,std,min,max,sat_frac";
+
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.value_name, self.channel, self.w, self.h, self.mean, self.std, self.min, self.max, self.sat_frac,
+        )
+    }
+}
+
+/// Writes `rows` to `path` as a tidy CSV table, one row per `(value_name, channel, w, h)`, so activation
+/// drift, dead channels and saturation can be tracked across training checkpoints programmatically
+/// instead of eyeballing the pixel colors `visualize_graph_activations` also produces.
+pub fn write_activation_stats_csv(rows: &[ActivationStatsRow], path: &std::path::Path) -> std::io::Result<()> {
+    let mut out = String::new();
+    out.push_str(ActivationStatsRow::CSV_HEADER);
+    out.push('\n');
+    for row in rows {
+        out.push_str(&row.to_csv_row());
+        out.push('\n');
+    }
+    std::fs::write(path, out)
+}
+
+/// How raw activation values (and their per-location std) are mapped to the `[0, 1]` range a [`Colormap`]
+/// consumes.
+#[derive(Debug, Clone, Copy)]
+pub enum Normalization {
+    /// The original hardcoded behavior: values clamped to `[-1, 1]`, std clamped to `[0, 2]`.
+    Fixed,
+    /// Auto-scales each value to that value's own actual min/max, found with a pass over `Details.data`
+    /// before the pixel loop, so activations that live far outside `[-1, 1]` (e.g. pre-activation logits)
+    /// remain legible instead of clipping to solid color.
+    AutoMinMax,
+    /// Per-`(channel, w, h)` z-score, `(f - mean) / std`, clamped to `[-2, 2]`.
+    ZScore,
+}
+
+/// Which color encoding a pixel's normalized value (and, for [`Colormap::StdValueDual`], its normalized
+/// std) is rendered with.
+#[derive(Debug, Clone, Copy)]
+pub enum Colormap {
+    /// Value only, rendered as a gray intensity.
+    Grayscale,
+    /// A coarse piecewise-linear approximation of the viridis colormap, value only.
+    Viridis,
+    /// The original dual encoding: std -> red channel, value -> green and blue channels.
+    StdValueDual,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VisualizeConfig {
+    pub normalization: Normalization,
+    pub colormap: Colormap,
+}
+
+impl Default for VisualizeConfig {
+    fn default() -> Self {
+        VisualizeConfig {
+            normalization: Normalization::Fixed,
+            colormap: Colormap::StdValueDual,
+        }
+    }
+}
+
+/// Coarse control points lifted from matplotlib's viridis, enough for a legible approximation without
+/// pulling in the full 256-entry table.
+const VIRIDIS_CONTROL_POINTS: &[(f32, f32, f32)] = &[
+    (0.267, 0.005, 0.329),
+    (0.283, 0.141, 0.458),
+    (0.254, 0.265, 0.530),
+    (0.207, 0.372, 0.553),
+    (0.164, 0.471, 0.558),
+    (0.128, 0.567, 0.551),
+    (0.135, 0.659, 0.518),
+    (0.267, 0.749, 0.441),
+    (0.478, 0.821, 0.318),
+    (0.741, 0.873, 0.150),
+    (0.993, 0.906, 0.144),
+];
+
+fn viridis(t: f32) -> Rgb<u8> {
+    let t = t.clamp(0.0, 1.0);
+    let n = VIRIDIS_CONTROL_POINTS.len() - 1;
+    let scaled = t * n as f32;
+    let i = (scaled.floor() as usize).min(n - 1);
+    let frac = scaled - i as f32;
+
+    let (r0, g0, b0) = VIRIDIS_CONTROL_POINTS[i];
+    let (r1, g1, b1) = VIRIDIS_CONTROL_POINTS[i + 1];
+
+    let lerp = |a: f32, b: f32| a + (b - a) * frac;
+    Rgb([
+        (lerp(r0, r1) * 255.0) as u8,
+        (lerp(g0, g1) * 255.0) as u8,
+        (lerp(b0, b1) * 255.0) as u8,
+    ])
+}
+
+fn colormap_pixel(colormap: Colormap, value_norm: f32, std_norm: f32) -> Rgb<u8> {
+    let value_norm = value_norm.clamp(0.0, 1.0);
+    let std_norm = std_norm.clamp(0.0, 1.0);
+
+    match colormap {
+        Colormap::Grayscale => {
+            let v = (value_norm * 255.0) as u8;
+            Rgb([v, v, v])
+        }
+        Colormap::Viridis => viridis(value_norm),
+        Colormap::StdValueDual => Rgb([(std_norm * 255.0) as u8, (value_norm * 255.0) as u8, (value_norm * 255.0) as u8]),
+    }
+}
+
 pub fn visualize_graph_activations(
     graph: &Graph,
     execution: &ExecutionInfo,
+    config: VisualizeConfig,
     post_process_value: impl Fn(Value, Tensor) -> Option<Tensor>,
-) -> Vec<Image> {
+) -> (Vec<Image>, Vec<ActivationStatsRow>) {
     let batch_size = execution.batch_size;
 
     let mut total_width = HORIZONTAL_PADDING;
@@ -44,16 +176,17 @@ pub fn visualize_graph_activations(
             continue;
         }
 
+        let name = format!("{:?}", value.value);
         let data = value.tensor.to_shared();
 
-        selected.push(data.to_shared());
+        selected.push((name.clone(), data.to_shared()));
         if let Some(extra) = post_process_value(value.value, data) {
-            selected.push(extra);
+            selected.push((format!("{}_extra", name), extra));
         }
     }
 
     let mut all_details = vec![];
-    for data in selected {
+    for (name, data) in selected {
         let size = data.len();
 
         let data: Tensor4 = match data.ndim() {
@@ -86,7 +219,7 @@ pub fn visualize_graph_activations(
 
         total_width = max(total_width, HORIZONTAL_PADDING + view_width);
 
-        let details = Details { data: WrapDebug(data), start_y };
+        let details = Details { name, data: WrapDebug(data), start_y };
         all_details.push(details)
     }
 
@@ -99,6 +232,8 @@ pub fn visualize_graph_activations(
         .map(|_| ImageBuffer::from_pixel(total_width as u32, total_height as u32, background))
         .collect_vec();
 
+    let mut stats = vec![];
+
     for details in all_details {
         println!("{:?}", details);
 
@@ -107,6 +242,42 @@ pub fn visualize_graph_activations(
         let (_, channels, width, height) = details.data.inner().dim();
 
         let std = data.std_axis(Axis(0), 1.0);
+        let mean = data.mean_axis(Axis(0)).unwrap();
+        let min = data.fold_axis(Axis(0), f32::INFINITY, |&a, &b| a.min(b));
+        let max = data.fold_axis(Axis(0), f32::NEG_INFINITY, |&a, &b| a.max(b));
+        let sat_count = data.fold_axis(Axis(0), 0u32, |&acc, &f| acc + (f.abs() >= 1.0) as u32);
+
+        for c in 0..channels {
+            for w in 0..width {
+                for h in 0..height {
+                    stats.push(ActivationStatsRow {
+                        value_name: details.name.clone(),
+                        channel: c,
+                        w,
+                        h,
+                        mean: mean[(c, w, h)],
+                        std: std[(c, w, h)],
+                        min: min[(c, w, h)],
+                        max: max[(c, w, h)],
+                        sat_frac: sat_count[(c, w, h)] as f32 / batch_size as f32,
+                    });
+                }
+            }
+        }
+
+        // for `AutoMinMax`, find the actual value/std range with a pass over the data before the pixel
+        // loop, so far-out-of-range activations (e.g. pre-activation logits) don't all clip to one color
+        let (value_min, value_max) = match config.normalization {
+            Normalization::AutoMinMax => (
+                data.iter().cloned().fold(f32::INFINITY, f32::min),
+                data.iter().cloned().fold(f32::NEG_INFINITY, f32::max),
+            ),
+            Normalization::Fixed | Normalization::ZScore => (-1.0, 1.0),
+        };
+        let std_max = match config.normalization {
+            Normalization::AutoMinMax => std.iter().cloned().fold(0.0f32, f32::max),
+            Normalization::Fixed | Normalization::ZScore => 2.0,
+        };
 
         for b in 0..batch_size {
             for c in 0..channels {
@@ -115,12 +286,29 @@ pub fn visualize_graph_activations(
                     for h in 0..height {
                         let y = details.start_y + h;
 
-                        let s_norm = std[(c, w, h)].clamp(0.0, 2.0) / 1.0;
-
                         let f = data[(b, c, w, h)];
-                        let f_norm = (f.clamp(-1.0, 1.0) + 1.0) / 2.0;
 
-                        let p = Rgb([(s_norm * 255.0) as u8, (f_norm * 255.0) as u8, (f_norm * 255.0) as u8]);
+                        let (f_norm, s_norm) = match config.normalization {
+                            Normalization::Fixed => (
+                                (f.clamp(-1.0, 1.0) + 1.0) / 2.0,
+                                std[(c, w, h)].clamp(0.0, 2.0) / 1.0,
+                            ),
+                            Normalization::AutoMinMax => {
+                                let f_norm = if value_max > value_min {
+                                    (f - value_min) / (value_max - value_min)
+                                } else {
+                                    0.5
+                                };
+                                let s_norm = if std_max > 0.0 { std[(c, w, h)] / std_max } else { 0.0 };
+                                (f_norm, s_norm)
+                            }
+                            Normalization::ZScore => {
+                                let z = (f - mean[(c, w, h)]) / std[(c, w, h)].max(1e-6);
+                                ((z.clamp(-2.0, 2.0) + 2.0) / 4.0, 1.0)
+                            }
+                        };
+
+                        let p = colormap_pixel(config.colormap, f_norm, s_norm);
                         images[b].put_pixel(x as u32, y as u32, p);
                     }
                 }
@@ -128,7 +316,7 @@ pub fn visualize_graph_activations(
         }
     }
 
-    images
+    (images, stats)
 }
 
 fn should_skip_value(_: &Graph, _: Value) -> bool {
@@ -137,6 +325,7 @@ fn should_skip_value(_: &Graph, _: Value) -> bool {
 
 #[derive(Debug)]
 struct Details {
+    name: String,
     start_y: usize,
     data: WrapDebug<Tensor4>,
 }
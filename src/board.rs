@@ -1,10 +1,14 @@
 #![allow(dead_code)]
 
 use std::fmt::{self, Debug, Write};
+use std::iter::FromIterator;
+use std::ops;
 
 use itertools::Itertools;
+use lazy_static::lazy_static;
 use rand::distributions::{Distribution, Standard};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Player {
@@ -98,92 +102,190 @@ impl Debug for Coord {
     }
 }
 
-//TODO implement simpler partialeq again
-#[derive(Clone, Eq, PartialEq, Debug, Hash)]
-pub struct Board {
-    //TODO try u16 here, that makes Board a lot smaller and maybe even feasible to store in the tree?
-    grids: [u32; 9],
-    main_grid: u32,
+/// A set of board cells, in the style of a chess bitboard. Backed by a `u128` with one bit per
+/// `Coord::o()` (81 cells don't fit in a `u64`); only the low 81 bits are ever meaningful.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct TileSet(u128);
 
-    pub last_move: Option<Coord>,
-    pub next_player: Player,
-    pub won_by: Option<Player>,
+impl TileSet {
+    pub const EMPTY: TileSet = TileSet(0);
+    const FULL_MASK: u128 = (1 << 81) - 1;
 
-    macro_mask: u32,
-    macro_open: u32,
+    pub fn single(coord: Coord) -> TileSet {
+        TileSet(1 << coord.o())
+    }
+
+    pub fn contains(self, coord: Coord) -> bool {
+        self.0 & (1 << coord.o()) != 0
+    }
+
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// The lowest-indexed `Coord` in this set, if any.
+    pub fn first(self) -> Option<Coord> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(Coord::from_o(self.0.trailing_zeros() as u8))
+        }
+    }
+}
+
+impl ops::BitOr for TileSet {
+    type Output = TileSet;
+    fn bitor(self, rhs: TileSet) -> TileSet {
+        TileSet(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign for TileSet {
+    fn bitor_assign(&mut self, rhs: TileSet) {
+        self.0 |= rhs.0;
+    }
 }
 
-//TODO implement a size hint
-//TODO look into other iterator speedup functions that can be implemented
-pub struct BoardMoveIterator<'a> {
-    board: &'a Board,
-    macro_left: u32,
-    curr_om: u32,
-    grid_left: u32,
+impl ops::BitAnd for TileSet {
+    type Output = TileSet;
+    fn bitand(self, rhs: TileSet) -> TileSet {
+        TileSet(self.0 & rhs.0)
+    }
 }
 
-impl<'a> BoardMoveIterator<'a> {
-    fn empty(board: &Board) -> BoardMoveIterator {
-        BoardMoveIterator { board, macro_left: 0, curr_om: 0, grid_left: 0 }
+impl ops::BitXor for TileSet {
+    type Output = TileSet;
+    fn bitxor(self, rhs: TileSet) -> TileSet {
+        TileSet(self.0 ^ rhs.0)
     }
-    fn new(board: &Board) -> BoardMoveIterator {
-        BoardMoveIterator { board, macro_left: board.macro_mask, curr_om: 0, grid_left: 0 }
+}
+
+impl ops::Not for TileSet {
+    type Output = TileSet;
+    fn not(self) -> TileSet {
+        TileSet(!self.0 & TileSet::FULL_MASK)
     }
 }
 
-impl<'a> Iterator for BoardMoveIterator<'a> {
+/// Set difference: the cells in `self` that are not in `rhs`.
+impl ops::Sub for TileSet {
+    type Output = TileSet;
+    fn sub(self, rhs: TileSet) -> TileSet {
+        TileSet(self.0 & !rhs.0)
+    }
+}
+
+pub struct TileSetIter(u128);
+
+impl Iterator for TileSetIter {
     type Item = Coord;
 
     fn next(&mut self) -> Option<Coord> {
-        if self.grid_left == 0 {
-            if self.macro_left == 0 {
-                return None;
-            } else {
-                self.curr_om = self.macro_left.trailing_zeros();
-                self.macro_left &= self.macro_left - 1;
-                self.grid_left = !compact_grid(self.board.grids[self.curr_om as usize]) & Board::FULL_MASK;
-            }
+        if self.0 == 0 {
+            None
+        } else {
+            let o = self.0.trailing_zeros();
+            self.0 &= self.0 - 1;
+            Some(Coord::from_o(o as u8))
         }
+    }
+}
 
-        let os = self.grid_left.trailing_zeros();
-        self.grid_left &= self.grid_left - 1;
+impl IntoIterator for TileSet {
+    type Item = Coord;
+    type IntoIter = TileSetIter;
+
+    fn into_iter(self) -> TileSetIter {
+        TileSetIter(self.0)
+    }
+}
+
+impl FromIterator<Coord> for TileSet {
+    fn from_iter<T: IntoIterator<Item=Coord>>(iter: T) -> TileSet {
+        let mut set = TileSet::EMPTY;
+        for coord in iter {
+            set |= TileSet::single(coord);
+        }
+        set
+    }
+}
+
+impl fmt::Display for TileSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..9 {
+            if y == 3 || y == 6 {
+                f.write_str("---+---+---\n")?;
+            }
+
+            for x in 0..9 {
+                if x == 3 || x == 6 {
+                    f.write_char('|')?;
+                }
+                f.write_char(if self.contains(Coord::from_xy(x, y)) { '.' } else { ' ' })?;
+            }
 
-        Some(Coord::from_oo(self.curr_om as u8, os as u8))
+            f.write_char('\n')?;
+        }
+
+        Ok(())
     }
 }
 
+//TODO implement simpler partialeq again
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+pub struct Board {
+    //TODO try u16 here, that makes Board a lot smaller and maybe even feasible to store in the tree?
+    grids: [u32; 9],
+    main_grid: u32,
+
+    pub last_move: Option<Coord>,
+    pub next_player: Player,
+    pub won_by: Option<Player>,
+
+    macro_mask: u32,
+    macro_open: u32,
+
+    // incrementally maintained by `set_tile_and_update`/`play`, see `ZOBRIST`
+    zobrist: u64,
+}
+
 /// A symmetry group element for Board transformations. Can represent any combination of
-/// flips, rotating and transposing, which result in 8 distinct elements.
+/// flips, rotating, transposing and swapping colors, which result in 16 distinct elements:
+/// the 8 spatial ones combined with whether X/O are also swapped.
 ///
 /// The `Default::default()` value means no transformation.
 ///
 /// The internal representation is such that first x and y are transposed,
-/// then each axis is flipped separately.
+/// then each axis is flipped separately, then colors are swapped.
 #[derive(Debug, Copy, Clone)]
 pub struct Symmetry {
     pub transpose: bool,
     pub flip_x: bool,
     pub flip_y: bool,
+    /// Whether X and O are swapped. Combined with the 8 spatial elements this completes a 16-element
+    /// augmentation group; a value produced against the transformed board must be negated when this is set.
+    pub color_swap: bool,
 }
 
 impl Default for Symmetry {
     fn default() -> Self {
-        Symmetry { transpose: false, flip_x: false, flip_y: false }
+        Symmetry { transpose: false, flip_x: false, flip_y: false, color_swap: false }
     }
 }
 
 impl Distribution<Symmetry> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Symmetry {
-        Symmetry { transpose: rng.gen(), flip_x: rng.gen(), flip_y: rng.gen() }
+        Symmetry { transpose: rng.gen(), flip_x: rng.gen(), flip_y: rng.gen(), color_swap: rng.gen() }
     }
 }
 
 impl Symmetry {
     pub fn all() -> impl Iterator<Item=Symmetry> {
-        (0..8).map(|i| Symmetry {
-            transpose: i & 0b100 != 0,
-            flip_x: i & 0b010 != 0,
-            flip_y: i & 0b001 != 0,
+        (0..16).map(|i| Symmetry {
+            transpose: i & 0b1000 != 0,
+            flip_x: i & 0b0100 != 0,
+            flip_y: i & 0b0010 != 0,
+            color_swap: i & 0b0001 != 0,
         })
     }
 
@@ -192,6 +294,7 @@ impl Symmetry {
             transpose: self.transpose,
             flip_x: if self.transpose { self.flip_y } else { self.flip_x },
             flip_y: if self.transpose { self.flip_x } else { self.flip_y },
+            color_swap: self.color_swap,
         }
     }
 
@@ -218,13 +321,63 @@ impl Symmetry {
     }
 }
 
+struct ZobristKeys {
+    // indexed by `Coord::o()` and `Player::index()`
+    tile: [[u64; 2]; 81],
+    // one key per bit of `macro_mask`, indexed by `om`
+    macro_bit: [u64; 9],
+    next_player: u64,
+}
+
+lazy_static! {
+    static ref ZOBRIST: ZobristKeys = {
+        let mut rng = StdRng::seed_from_u64(0x5A0B_8157_u64);
+        let mut tile = [[0u64; 2]; 81];
+        for slot in &mut tile {
+            slot[0] = rng.gen();
+            slot[1] = rng.gen();
+        }
+        let mut macro_bit = [0u64; 9];
+        for key in &mut macro_bit {
+            *key = rng.gen();
+        }
+        ZobristKeys { tile, macro_bit, next_player: rng.gen() }
+    };
+}
+
+/// Why [`Board::play_checked`] refused to play a move.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MoveError {
+    /// The board is already finished (won or drawn), so no more moves can be played.
+    BoardDone,
+    /// `coord` is not one of the currently available moves.
+    MoveNotAvailable,
+}
+
+/// Captures exactly the fields a single [`Board::play_checked`] call mutates, so [`Board::unplay`] can
+/// restore them without a full `Board` clone. `set_tile_and_update` only ever touches one `grids` slot
+/// plus a handful of scalars, so this stays a few bytes regardless of how full the board is, letting a
+/// solver push/pop moves on a single board instead of cloning at every node.
+#[derive(Debug, Copy, Clone)]
+pub struct MoveUndo {
+    om: u8,
+    prev_grid: u32,
+    prev_main_grid: u32,
+    prev_macro_mask: u32,
+    prev_macro_open: u32,
+    prev_won_by: Option<Player>,
+    prev_last_move: Option<Coord>,
+    prev_next_player: Player,
+    prev_zobrist: u64,
+}
+
 impl Board {
     pub const MAX_AVAILABLE_MOVES: u32 = 9 * 9;
 
     const FULL_MASK: u32 = 0b111_111_111;
 
     pub fn new() -> Board {
-        Board {
+        let mut board = Board {
             grids: [0; 9],
             main_grid: 0,
             last_move: None,
@@ -232,7 +385,10 @@ impl Board {
             won_by: None,
             macro_mask: Board::FULL_MASK,
             macro_open: Board::FULL_MASK,
-        }
+            zobrist: 0,
+        };
+        board.zobrist = board.compute_zobrist();
+        board
     }
 
     pub fn is_done(&self) -> bool {
@@ -248,13 +404,19 @@ impl Board {
         get_player(self.main_grid, om)
     }
 
+    /// Whether the player to move can play in any open macro board, because the macro their last
+    /// opponent move sent them to was already won or full.
+    pub fn has_free_macro_choice(&self) -> bool {
+        self.macro_mask == self.macro_open
+    }
+
     pub fn map_symmetry(&self, sym: Symmetry) -> Board {
         let mut grids = [0; 9];
         for oo in 0..9 {
             grids[sym.map_oo(oo) as usize] = sym.map_grid(self.grids[oo as usize])
         }
 
-        Board {
+        let mapped = Board {
             grids,
             main_grid: 0,
             last_move: self.last_move.map(|c| sym.map_coord(c)),
@@ -262,55 +424,146 @@ impl Board {
             won_by: self.won_by,
             macro_mask: sym.map_grid(self.macro_mask),
             macro_open: sym.map_grid(self.macro_open),
+            zobrist: 0,
+        };
+
+        if sym.color_swap {
+            // `swap_colors` recomputes the hash itself, no need to do it again below
+            mapped.swap_colors()
+        } else {
+            let mut mapped = mapped;
+            // built directly instead of through `set_tile_and_update`/`play`, so the incremental
+            // hash has to be recomputed from scratch here instead of carried over
+            mapped.zobrist = mapped.compute_zobrist();
+            mapped
         }
     }
 
-    /// Return the number of non-empty tiles.
-    pub fn count_tiles(&self) -> u32 {
-        self.grids.iter().map(|tile| tile.count_ones()).sum()
-    }
+    /// Exchanges X and O everywhere: the low 9 bits and high 9 bits of each `grids[i]` and of
+    /// `main_grid`, and flips `next_player`/`won_by`. `macro_mask`/`macro_open` are left untouched
+    /// since which macro boards are playable doesn't depend on whose turn it is. A value computed
+    /// against the returned board must be negated to apply to `self`.
+    pub fn swap_colors(&self) -> Board {
+        let mut grids = [0; 9];
+        for (i, grid) in grids.iter_mut().enumerate() {
+            *grid = swap_color_halves(self.grids[i]);
+        }
 
-    pub fn available_moves(&self) -> impl Iterator<Item=Coord> + '_ {
-        return if self.is_done() {
-            BoardMoveIterator::empty(&self)
-        } else {
-            BoardMoveIterator::new(&self)
+        let mut swapped = Board {
+            grids,
+            main_grid: swap_color_halves(self.main_grid),
+            last_move: self.last_move,
+            next_player: self.next_player.other(),
+            won_by: self.won_by.map(Player::other),
+            macro_mask: self.macro_mask,
+            macro_open: self.macro_open,
+            zobrist: 0,
         };
+        swapped.zobrist = swapped.compute_zobrist();
+        swapped
     }
 
-    pub fn random_available_move<R: Rng>(&self, rand: &mut R) -> Option<Coord> {
-        if self.is_done() {
-            return None;
-        }
+    /// The incrementally-maintained Zobrist hash of this exact position.
+    pub fn hash(&self) -> u64 {
+        self.zobrist
+    }
 
-        let mut count = 0;
+    /// The minimum [`Board::hash`] over all 16 symmetric (spatial + color-swap) images of this
+    /// position, so that positions related by rotation/reflection/transpose/color-swap share a single
+    /// transposition table key.
+    pub fn canonical_hash(&self) -> u64 {
+        Symmetry::all().map(|sym| self.hash_under_symmetry(sym)).min().unwrap()
+    }
+
+    /// Equivalent to `self.map_symmetry(sym).hash()`, but remaps `grids`/`macro_mask`/`next_player`
+    /// directly into the Zobrist sum instead of building a whole symmetric `Board` (with its own
+    /// `last_move`/`won_by`/`macro_open`) just to immediately call `compute_zobrist` on it and
+    /// throw the rest away. Called once per symmetry by `canonical_hash`, so this matters.
+    fn hash_under_symmetry(&self, sym: Symmetry) -> u64 {
+        let mut hash = 0;
+        for c in Coord::all() {
+            let player = self.tile(c);
+            if player != Player::Neutral {
+                let player = if sym.color_swap { player.other() } else { player };
+                hash ^= ZOBRIST.tile[sym.map_coord(c).o() as usize][player.index() as usize];
+            }
+        }
+        for om in BitIter::of(sym.map_grid(self.macro_mask)) {
+            hash ^= ZOBRIST.macro_bit[om as usize];
+        }
+        let next_player = if sym.color_swap { self.next_player.other() } else { self.next_player };
+        if next_player == Player::O {
+            hash ^= ZOBRIST.next_player;
+        }
+        hash
+    }
+
+    /// The lexicographically-minimal board among the 16 symmetric (spatial + color-swap) images of this
+    /// position, along with the `Symmetry` that produced it, so a policy vector computed against the
+    /// canonical board can be mapped back onto this one with `sym.inverse()` (negating the value if
+    /// `sym.color_swap`).
+    ///
+    /// Compares images through `board_to_compact_string`, since `Board` itself has no ordering (it only
+    /// derives `Eq`/`Hash`). The compact string encodes tiles, playable cells and the last move; which
+    /// macro boards are won follows from the tiles, so `map_symmetry` leaving `main_grid` at `0` on every
+    /// image does not affect the comparison.
+    pub fn canonicalize(&self) -> (Board, Symmetry) {
+        Symmetry::all()
+            .map(|sym| (self.map_symmetry(sym), sym))
+            .min_by_key(|(board, _)| board_to_compact_string(board))
+            .unwrap()
+    }
+
+    fn compute_zobrist(&self) -> u64 {
+        let mut hash = 0;
+        for c in Coord::all() {
+            let player = self.tile(c);
+            if player != Player::Neutral {
+                hash ^= ZOBRIST.tile[c.o() as usize][player.index() as usize];
+            }
+        }
         for om in BitIter::of(self.macro_mask) {
-            count += 9 - self.grids[om as usize].count_ones();
+            hash ^= ZOBRIST.macro_bit[om as usize];
         }
+        if self.next_player == Player::O {
+            hash ^= ZOBRIST.next_player;
+        }
+        hash
+    }
 
-        let mut index = rand.gen_range(0..count);
+    /// Return the number of non-empty tiles.
+    pub fn count_tiles(&self) -> u32 {
+        self.grids.iter().map(|tile| tile.count_ones()).sum()
+    }
 
-        for om in BitIter::of(self.macro_mask) {
-            let grid = self.grids[om as usize];
-            let grid_count = 9 - grid.count_ones();
+    pub fn available_moves(&self) -> TileSet {
+        if self.is_done() {
+            return TileSet::EMPTY;
+        }
 
-            if index < grid_count {
-                let os = get_nth_set_bit(!compact_grid(grid), index as u32);
-                return Some(Coord::from_oo(om as u8, os as u8));
+        let mut moves = TileSet::EMPTY;
+        for om in BitIter::of(self.macro_mask) {
+            let free = !compact_grid(self.grids[om as usize]) & Board::FULL_MASK;
+            for os in BitIter::of(free) {
+                moves |= TileSet::single(Coord::from_oo(om as u8, os as u8));
             }
+        }
+        moves
+    }
 
-            index -= grid_count;
+    pub fn random_available_move<R: Rng>(&self, rand: &mut R) -> Option<Coord> {
+        let moves = self.available_moves();
+        let count = moves.count();
+        if count == 0 {
+            return None;
         }
 
-        //todo try unchecked here
-        unreachable!()
+        let index = rand.gen_range(0..count);
+        moves.into_iter().nth(index as usize)
     }
 
     pub fn is_available_move(&self, coord: Coord) -> bool {
-        let om = coord.om();
-        let os = coord.os();
-        has_bit(self.macro_mask, om) &&
-            !has_bit(compact_grid(self.grids[om as usize]), os)
+        self.available_moves().contains(coord)
     }
 
     pub fn clone_and_play(&self, coord: Coord) -> Board {
@@ -319,6 +572,49 @@ impl Board {
         next
     }
 
+    /// Play `coord`, returning a [`MoveUndo`] that [`Board::unplay`] can later use to restore this
+    /// exact position, instead of the caller having to keep a cloned `Board` around. Checks the
+    /// preconditions `play` only `debug_assert!`s, returning a [`MoveError`] instead of panicking.
+    pub fn play_checked(&mut self, coord: Coord) -> Result<MoveUndo, MoveError> {
+        if self.is_done() {
+            return Err(MoveError::BoardDone);
+        }
+        if !self.is_available_move(coord) {
+            return Err(MoveError::MoveNotAvailable);
+        }
+
+        let om = coord.om();
+        let undo = MoveUndo {
+            om,
+            prev_grid: self.grids[om as usize],
+            prev_main_grid: self.main_grid,
+            prev_macro_mask: self.macro_mask,
+            prev_macro_open: self.macro_open,
+            prev_won_by: self.won_by,
+            prev_last_move: self.last_move,
+            prev_next_player: self.next_player,
+            prev_zobrist: self.zobrist,
+        };
+
+        self.play(coord);
+
+        Ok(undo)
+    }
+
+    /// Undo a move previously played through [`Board::play_checked`], restoring the exact position it
+    /// was played from. `undo` must be the value that call returned; passing one from a different board
+    /// or a different move leaves the board in a nonsensical state.
+    pub fn unplay(&mut self, undo: MoveUndo) {
+        self.grids[undo.om as usize] = undo.prev_grid;
+        self.main_grid = undo.prev_main_grid;
+        self.macro_mask = undo.prev_macro_mask;
+        self.macro_open = undo.prev_macro_open;
+        self.won_by = undo.prev_won_by;
+        self.last_move = undo.prev_last_move;
+        self.next_player = undo.prev_next_player;
+        self.zobrist = undo.prev_zobrist;
+    }
+
     pub fn play(&mut self, coord: Coord) -> bool {
         debug_assert!(!self.is_done(), "can't play on done board");
         debug_assert!(self.is_available_move(coord), "move not available");
@@ -329,6 +625,7 @@ impl Board {
         //update for next player
         self.last_move = Some(coord);
         self.next_player = self.next_player.other();
+        self.zobrist ^= ZOBRIST.next_player;
 
         won_grid
     }
@@ -341,6 +638,7 @@ impl Board {
         //set tile and macro, check win
         let new_grid = self.grids[om as usize] | (1 << (os + p));
         self.grids[om as usize] = new_grid;
+        self.zobrist ^= ZOBRIST.tile[coord.o() as usize][player.index() as usize];
 
         let grid_win = is_win_grid((new_grid >> p) & Board::FULL_MASK);
         if grid_win {
@@ -359,7 +657,12 @@ impl Board {
                 self.won_by = Some(Player::Neutral);
             }
         }
+
+        let old_macro_mask = self.macro_mask;
         self.macro_mask = self.calc_macro_mask(os);
+        for om in BitIter::of(old_macro_mask ^ self.macro_mask) {
+            self.zobrist ^= ZOBRIST.macro_bit[om as usize];
+        }
 
         grid_win
     }
@@ -399,17 +702,14 @@ fn has_mask(x: u32, mask: u32) -> bool {
     x & mask == mask
 }
 
-fn get_nth_set_bit(mut x: u32, n: u32) -> u32 {
-    for _ in 0..n {
-        x &= x.wrapping_sub(1);
-    }
-    x.trailing_zeros()
-}
-
 fn compact_grid(grid: u32) -> u32 {
     (grid | grid >> 9) & Board::FULL_MASK
 }
 
+fn swap_color_halves(grid: u32) -> u32 {
+    ((grid & Board::FULL_MASK) << 9) | ((grid >> 9) & Board::FULL_MASK)
+}
+
 fn get_player(grid: u32, index: u8) -> Player {
     if has_bit(grid, index) {
         Player::X
@@ -541,7 +841,7 @@ mod test {
         let mut rand = SmallRng::seed_from_u64(0);
 
         while !board.is_done() {
-            let moves: Vec<Coord> = board.available_moves().collect();
+            let moves: Vec<Coord> = board.available_moves().into_iter().collect();
 
             let mut counts: [i32; 81] = [0; 81];
             for _ in 0..1_000_000 {
@@ -569,11 +869,12 @@ mod test {
         let board = random_board_with_moves(10, &mut rng);
         println!("Original:\n{}", board);
 
-        for i in 0..8 {
+        for i in 0..16 {
             let sym = Symmetry {
-                transpose: i & 0b001 != 0,
-                flip_x: i & 0b010 != 0,
-                flip_y: i & 0b100 != 0,
+                transpose: i & 0b0001 != 0,
+                flip_x: i & 0b0010 != 0,
+                flip_y: i & 0b0100 != 0,
+                color_swap: i & 0b1000 != 0,
             };
             let sym_inv = sym.inverse();
 
@@ -592,8 +893,8 @@ mod test {
             }
             assert_eq!(board, back);
 
-            let expected_moves = board.available_moves().map(|c| sym.map_coord(c)).sorted_by_key(|c| c.o()).collect_vec();
-            let actual_moves = mapped.available_moves().sorted_by_key(|c| c.o()).collect_vec();
+            let expected_moves = board.available_moves().into_iter().map(|c| sym.map_coord(c)).sorted_by_key(|c| c.o()).collect_vec();
+            let actual_moves = mapped.available_moves().into_iter().sorted_by_key(|c| c.o()).collect_vec();
             assert_eq!(expected_moves, actual_moves);
         }
     }
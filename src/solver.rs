@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use crate::board::{Board, Coord, Player};
+
+/// The game-theoretic result of a position, as far as [`Solver::solve`] managed to determine within
+/// its node budget.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SolveResult {
+    Win(Player),
+    Draw,
+    /// The node budget ran out before the position could be fully resolved.
+    Unknown,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct TTEntry {
+    value: f32,
+    bound: Bound,
+}
+
+/// Exact negamax alpha-beta search over [`Board`], for positions close enough to the end to be
+/// tractable. Uses the make/unmake [`Board::play_checked`]/[`Board::unplay`] pair instead of cloning at
+/// every node, and a transposition table keyed on [`Board::canonical_hash`] so transposed and
+/// symmetry-equivalent positions are only solved once.
+pub struct Solver {
+    table: HashMap<u64, TTEntry>,
+    nodes_left: u64,
+}
+
+impl Solver {
+    pub fn new(node_budget: u64) -> Solver {
+        Solver { table: HashMap::new(), nodes_left: node_budget }
+    }
+
+    /// Solve `board`, from the perspective of `board.next_player`.
+    pub fn solve(&mut self, board: &mut Board) -> SolveResult {
+        let next_player = board.next_player;
+        match self.negamax(board, -1.0, 1.0) {
+            None => SolveResult::Unknown,
+            Some(value) if value > 0.0 => SolveResult::Win(next_player),
+            Some(value) if value < 0.0 => SolveResult::Win(next_player.other()),
+            Some(_) => SolveResult::Draw,
+        }
+    }
+
+    /// Negamax over `board`, from the perspective of whoever is to move there. Returns `None` if the
+    /// node budget ran out before this subtree could be resolved. Values are in [`Player::sign`] units.
+    fn negamax(&mut self, board: &mut Board, mut alpha: f32, beta: f32) -> Option<f32> {
+        if board.is_done() {
+            return Some(leaf_value(board));
+        }
+        if self.nodes_left == 0 {
+            return None;
+        }
+        self.nodes_left -= 1;
+
+        let orig_alpha = alpha;
+        let hash = board.canonical_hash();
+        if let Some(entry) = self.table.get(&hash) {
+            match entry.bound {
+                Bound::Exact => return Some(entry.value),
+                Bound::Lower if entry.value >= beta => return Some(entry.value),
+                Bound::Upper if entry.value <= alpha => return Some(entry.value),
+                _ => {}
+            }
+        }
+
+        let mut moves: Vec<Coord> = board.available_moves().into_iter().collect();
+        order_moves(board, &mut moves);
+
+        let mut best_value = f32::NEG_INFINITY;
+        for coord in moves {
+            let undo = board.play_checked(coord).expect("coord came from available_moves");
+            let child = self.negamax(board, -beta, -alpha);
+            board.unplay(undo);
+
+            let value = match child {
+                Some(value) => -value,
+                None => return None,
+            };
+
+            if value > best_value {
+                best_value = value;
+            }
+            if value > alpha {
+                alpha = value;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best_value <= orig_alpha {
+            Bound::Upper
+        } else if best_value >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(hash, TTEntry { value: best_value, bound });
+
+        Some(best_value)
+    }
+}
+
+fn leaf_value(board: &Board) -> f32 {
+    let outcome = board.won_by.expect("a done board always has a result");
+    outcome.sign() * board.next_player.sign()
+}
+
+/// Cheap move ordering: try moves that win a sub-grid first, then moves that hand the opponent a free
+/// choice of macro (because the macro they're sent to is already won or full), then everything else.
+fn order_moves(board: &mut Board, moves: &mut [Coord]) {
+    moves.sort_by_key(|&coord| {
+        let undo = board.play_checked(coord).expect("coord came from available_moves");
+        let wins_subgrid = board.macr(coord.om()) != Player::Neutral;
+        let frees_opponent = board.has_free_macro_choice();
+        board.unplay(undo);
+
+        match (wins_subgrid, frees_opponent) {
+            (true, _) => 0,
+            (false, true) => 1,
+            (false, false) => 2,
+        }
+    });
+}